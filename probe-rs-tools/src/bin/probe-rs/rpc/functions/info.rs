@@ -9,7 +9,7 @@ use probe_rs::{
     architecture::{
         arm::{
             self, ApAddress, ApV2Address, ArmDebugInterface,
-            ap::{ApClass, ApRegister, IDR},
+            ap::ApClass,
             component::Scs,
             dp::{self, Ctrl, DLPIDR, DPIDR, DpRegister, TARGETID},
             memory::{
@@ -518,14 +518,22 @@ async fn show_arm_info(
     )
     .await?;
 
+    ctx.publish::<TargetInfoDataTopic>(
+        VarSeq::Seq2(0),
+        &InfoEvent::Message(format!("Debug sequence: {}", interface.sequence_name())),
+    )
+    .await?;
+
     if dp_info.version != dp::DebugPortVersion::DPv3 {
-        let access_ports = interface.access_ports(dp)?;
-        for ap_address in access_ports {
+        let access_ports = interface.access_ports_detailed(dp)?;
+        for arm::ApInfo {
+            address: ap_address,
+            idr,
+            ..
+        } in access_ports
+        {
             match ap_address.ap() {
                 ApAddress::V1(_) => {
-                    let raw_idr = interface.read_raw_ap_register(&ap_address, IDR::ADDRESS)?;
-                    let idr: IDR = raw_idr.try_into()?;
-
                     let ap_info = if idr.CLASS == ApClass::MemAp {
                         let mut ap_nodes = ComponentTreeNode::new(format!(
                             "{} MemoryAP ({:?})",
@@ -548,7 +556,7 @@ async fn show_arm_info(
                                 dp: ap_address.dp().into(),
                                 ap: ap_address.ap().to_string(),
                             },
-                            idr: raw_idr,
+                            idr: idr.into(),
                         }
                     };
 
@@ -704,6 +712,14 @@ fn coresight_component_tree(
                 desc
             )));
         }
+
+        Component::Custom(id, custom) => {
+            parent.push(ComponentTreeNode::new(format!(
+                "{:#06x} {} (Custom component)",
+                id.component_address(),
+                custom.name
+            )));
+        }
     };
 
     Ok(())
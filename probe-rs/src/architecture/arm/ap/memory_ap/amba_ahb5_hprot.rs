@@ -3,13 +3,13 @@ use crate::architecture::arm::{
     ap::{AccessPortType, ApAccess, ApRegAccess, ApRegister, CFG, define_ap_register},
 };
 
-use super::{AddressIncrement, DataSize};
+use super::{AddressIncrement, DataSize, MemApCfg};
 
 /// Memory AP
 ///
 /// The memory AP can be used to access a memory-mapped
 /// set of debug resources of the attached system.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct AmbaAhb5Hprot {
     address: FullyQualifiedApAddress,
     csw: CSW,
@@ -51,6 +51,15 @@ impl super::MemoryApType for AmbaAhb5Hprot {
         Ok(self.csw)
     }
 
+    fn cached_status(&self) -> u32 {
+        self.csw.into()
+    }
+
+    fn set_cached_status(&mut self, csw: u32) -> Result<(), ArmError> {
+        self.csw = csw.try_into()?;
+        Ok(())
+    }
+
     fn try_set_datasize<P: ApAccess + ?Sized>(
         &mut self,
         probe: &mut P,
@@ -83,6 +92,10 @@ impl super::MemoryApType for AmbaAhb5Hprot {
         self.cfg.LD
     }
 
+    fn cfg(&self) -> MemApCfg {
+        self.cfg.into()
+    }
+
     fn supports_only_32bit_data_size(&self) -> bool {
         // Amba AHB5 must support word, half-word and byte size transfers.
         false
@@ -2,7 +2,7 @@ use crate::architecture::arm::{
     ArmError, DapAccess, FullyQualifiedApAddress, RegisterParseError,
     ap::{
         AccessPortType, AddressIncrement, ApAccess, ApRegAccess, ApRegister, CFG, DataSize,
-        define_ap_register,
+        MemApCfg, define_ap_register,
     },
 };
 
@@ -10,7 +10,7 @@ use crate::architecture::arm::{
 ///
 /// The memory AP can be used to access a memory-mapped
 /// set of debug resources of the attached system.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct AmbaApb4Apb5 {
     address: FullyQualifiedApAddress,
     csw: CSW,
@@ -48,6 +48,15 @@ impl super::MemoryApType for AmbaApb4Apb5 {
         Ok(self.csw)
     }
 
+    fn cached_status(&self) -> u32 {
+        self.csw.into()
+    }
+
+    fn set_cached_status(&mut self, csw: u32) -> Result<(), ArmError> {
+        self.csw = csw.try_into()?;
+        Ok(())
+    }
+
     fn try_set_datasize<P: ApAccess + ?Sized>(
         &mut self,
         _probe: &mut P,
@@ -67,6 +76,10 @@ impl super::MemoryApType for AmbaApb4Apb5 {
         self.cfg.LD
     }
 
+    fn cfg(&self) -> MemApCfg {
+        self.cfg.into()
+    }
+
     fn supports_only_32bit_data_size(&self) -> bool {
         // APB4 and APB5 AP only support 32bit accesses
         true
@@ -7,11 +7,26 @@ use crate::architecture::arm::{
     communication_interface::DapProbe,
     dp::{DpAddress, DpRegisterAddress},
 };
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 
 #[derive(Debug)]
 pub struct MockMemoryAp {
     pub memory: Vec<u8>,
+    /// Memory which is accessed instead of `memory` while CSW.HNONSEC is cleared, if set.
+    pub secure_memory: Option<Vec<u8>>,
+    /// 32-bit words outside of `memory`, e.g. the memory mapped registers of a core.
+    ///
+    /// 32-bit writes outside of `memory` are stored here, other words outside of `memory` read
+    /// as zero.
+    pub registers: HashMap<u64, u32>,
+    /// Words outside of `memory` which read as the given value, regardless of the values written
+    /// to them, e.g. to emulate status bits.
+    pub read_only_registers: HashMap<u64, u32>,
+    /// Values which are read from words outside of `memory` before `read_only_registers` and
+    /// `registers`, one per read, e.g. to emulate status bits which change over time.
+    pub register_reads: HashMap<u64, VecDeque<u32>>,
+    /// All 32-bit writes outside of `memory`, in order.
+    pub register_writes: Vec<(u64, u32)>,
     store: HashMap<u64, u32>,
 }
 
@@ -46,9 +61,24 @@ impl MockMemoryAp {
         store.insert(DRW::ADDRESS, 0);
         Self {
             memory: std::iter::repeat(1..=255).flatten().take(size).collect(),
+            secure_memory: None,
+            registers: HashMap::new(),
+            read_only_registers: HashMap::new(),
+            register_reads: HashMap::new(),
+            register_writes: Vec::new(),
             store,
         }
     }
+
+    /// Returns the memory accessed with the given CSW value.
+    fn memory_view(&mut self, csw: u32) -> &mut Vec<u8> {
+        const HNONSEC: u32 = 1 << 30;
+
+        match &mut self.secure_memory {
+            Some(secure) if csw & HNONSEC == 0 => secure,
+            _ => &mut self.memory,
+        }
+    }
 }
 
 impl DapAccess for MockMemoryAp {
@@ -84,21 +114,37 @@ impl DapAccess for MockMemoryAp {
             let drw = self.store[&DRW::ADDRESS];
             let bit_offset = (address % 4) * 8;
             let offset = address as usize;
+            let register = self
+                .register_reads
+                .get_mut(&u64::from(address))
+                .and_then(VecDeque::pop_front)
+                .or_else(|| self.read_only_registers.get(&u64::from(address)).copied())
+                .or_else(|| self.registers.get(&u64::from(address)).copied())
+                .unwrap_or(0);
+            let memory = self.memory_view(csw);
             let csw = CSW::try_from(csw).unwrap();
 
             let (new_drw, offset) = match csw.Size {
+                // A packed transfer reads all bytes or halfwords of the addressed word at once.
+                DataSize::U8 | DataSize::U16 if csw.AddrInc == AddressIncrement::Packed => {
+                    let offset = offset & !3;
+                    let bytes: [u8; 4] = memory
+                        .get(offset..offset + 4)
+                        .map(|v| v.try_into().unwrap())
+                        .unwrap_or([0; 4]);
+
+                    (u32::from_le_bytes(bytes), 4)
+                }
                 DataSize::U32 => {
-                    let bytes: [u8; 4] = self
-                        .memory
+                    let bytes: [u8; 4] = memory
                         .get(offset..offset + 4)
                         .map(|v| v.try_into().unwrap())
-                        .unwrap_or([0u8; 4]);
+                        .unwrap_or(register.to_le_bytes());
 
                     (u32::from_le_bytes(bytes), 4)
                 }
                 DataSize::U16 => {
-                    let bytes = self
-                        .memory
+                    let bytes = memory
                         .get(offset..offset + 2)
                         .map(|v| v.try_into().unwrap())
                         .unwrap_or([0u8; 2]);
@@ -109,7 +155,7 @@ impl DapAccess for MockMemoryAp {
                     )
                 }
                 DataSize::U8 => {
-                    let value = *self.memory.get(offset).unwrap_or(&0u8);
+                    let value = *memory.get(offset).unwrap_or(&0u8);
                     (
                         drw & !(0xff << bit_offset) | (u32::from(value) << bit_offset),
                         1,
@@ -121,13 +167,10 @@ impl DapAccess for MockMemoryAp {
             self.store.insert(DRW::ADDRESS, new_drw);
 
             match csw.AddrInc {
-                AddressIncrement::Single => {
+                AddressIncrement::Single | AddressIncrement::Packed => {
                     self.store.insert(TAR::ADDRESS, address + offset);
                 }
                 AddressIncrement::Off => (),
-                AddressIncrement::Packed => {
-                    unimplemented!();
-                }
             }
             tracing::debug!("Reading: new store: {:x?}", self.store);
 
@@ -155,32 +198,40 @@ impl DapAccess for MockMemoryAp {
 
         match addr {
             DRW::ADDRESS => {
+                let memory = self.memory_view(csw);
                 let csw = CSW::try_from(csw).unwrap();
                 tracing::debug!("csw: {:x?}", csw);
 
                 let access_width = csw.Size.to_byte_count() as u32;
 
-                if (address + access_width) as usize > self.memory.len() {
-                    // Ignore out-of-bounds write
+                if (address + access_width) as usize > memory.len() {
+                    if csw.Size == DataSize::U32 {
+                        self.registers.insert(u64::from(address), value);
+                        self.register_writes.push((u64::from(address), value));
+                        if csw.AddrInc == AddressIncrement::Single {
+                            self.store.insert(TAR::ADDRESS, address + 4);
+                        }
+                    }
+                    // Ignore other out-of-bounds writes
                     return Ok(());
                 }
 
                 let bit_offset = (address % 4) * 8;
                 match csw.Size {
                     DataSize::U32 => {
-                        self.memory[address as usize..address as usize + 4]
+                        memory[address as usize..address as usize + 4]
                             .copy_from_slice(&value.to_le_bytes());
                         Ok(4)
                     }
                     DataSize::U16 => {
                         let value = value >> bit_offset;
-                        self.memory[address as usize] = value as u8;
-                        self.memory[address as usize + 1] = (value >> 8) as u8;
+                        memory[address as usize] = value as u8;
+                        memory[address as usize + 1] = (value >> 8) as u8;
                         Ok(2)
                     }
                     DataSize::U8 => {
                         let value = value >> bit_offset;
-                        self.memory[address as usize] = value as u8;
+                        memory[address as usize] = value as u8;
                         Ok(1)
                     }
                     _ => panic!("MockMemoryAp: unknown width"),
@@ -13,7 +13,8 @@ mod amba_axi3_axi4;
 mod amba_axi5;
 
 use crate::architecture::arm::ap::{
-    AddressIncrement, ApRegister, BASE, BASE2, BaseAddrFormat, DRW, DataSize, TAR, TAR2,
+    AddressIncrement, ApRegister, BASE, BASE2, BD0, BD1, BD2, BD3, BaseAddrFormat, CFG, DRW,
+    DataSize, IDR, MBT, TAR, TAR2,
 };
 
 use super::{AccessPortError, AccessPortType, ApAccess, ApRegAccess};
@@ -51,6 +52,106 @@ macro_rules! attached_regs_to_mem_ap {
 // Re-export the macro so that it can be used in this crate.
 pub(crate) use attached_regs_to_mem_ap;
 
+/// The registers of a memory access port, addressed by name.
+///
+/// Use this together with [`DapAccess::read_mem_ap_register`] and
+/// [`DapAccess::write_mem_ap_register`] instead of raw register offsets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MemApRegister {
+    /// Control and Status Word register.
+    Csw,
+    /// Transfer Address register, lower 32 bits of the address.
+    Tar,
+    /// Transfer Address register, upper 32 bits of the address.
+    ///
+    /// Only available on memory APs with the large address extension.
+    Tar2,
+    /// Data Read/Write register.
+    Drw,
+    /// Banked Data register 0.
+    Bd0,
+    /// Banked Data register 1.
+    Bd1,
+    /// Banked Data register 2.
+    Bd2,
+    /// Banked Data register 3.
+    Bd3,
+    /// Memory Barrier Transfer register.
+    Mbt,
+    /// Debug Base Address register, upper 32 bits.
+    Base2,
+    /// Configuration register.
+    Cfg,
+    /// Debug Base Address register, lower 32 bits.
+    Base,
+    /// Identification register.
+    Idr,
+}
+
+impl MemApRegister {
+    /// The address of the register, as used by [`DapAccess::read_raw_ap_register`].
+    pub const fn address(self) -> u64 {
+        match self {
+            MemApRegister::Csw => CSW::ADDRESS,
+            MemApRegister::Tar => TAR::ADDRESS,
+            MemApRegister::Tar2 => TAR2::ADDRESS,
+            MemApRegister::Drw => DRW::ADDRESS,
+            MemApRegister::Bd0 => BD0::ADDRESS,
+            MemApRegister::Bd1 => BD1::ADDRESS,
+            MemApRegister::Bd2 => BD2::ADDRESS,
+            MemApRegister::Bd3 => BD3::ADDRESS,
+            MemApRegister::Mbt => MBT::ADDRESS,
+            MemApRegister::Base2 => BASE2::ADDRESS,
+            MemApRegister::Cfg => CFG::ADDRESS,
+            MemApRegister::Base => BASE::ADDRESS,
+            MemApRegister::Idr => IDR::ADDRESS,
+        }
+    }
+
+    /// The name of the register, as used in the ARM Debug Interface specification.
+    pub const fn name(self) -> &'static str {
+        match self {
+            MemApRegister::Csw => CSW::NAME,
+            MemApRegister::Tar => TAR::NAME,
+            MemApRegister::Tar2 => TAR2::NAME,
+            MemApRegister::Drw => DRW::NAME,
+            MemApRegister::Bd0 => BD0::NAME,
+            MemApRegister::Bd1 => BD1::NAME,
+            MemApRegister::Bd2 => BD2::NAME,
+            MemApRegister::Bd3 => BD3::NAME,
+            MemApRegister::Mbt => MBT::NAME,
+            MemApRegister::Base2 => BASE2::NAME,
+            MemApRegister::Cfg => CFG::NAME,
+            MemApRegister::Base => BASE::NAME,
+            MemApRegister::Idr => IDR::NAME,
+        }
+    }
+}
+
+/// The extensions implemented by a memory access port, as reported by its CFG register.
+///
+/// Use [`DapAccess::read_mem_ap_cfg`] or [`MemoryApType::cfg`] to read it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemApCfg {
+    /// Memory is accessed big-endian. This is deprecated since ADIv5.2.
+    pub big_endian: bool,
+    /// The large address extension is implemented, so TAR2 holds the upper 32 bits of
+    /// the 64-bit transfer address.
+    pub long_address: bool,
+    /// The large data extension is implemented, so 64-bit transfers are supported.
+    pub large_data: bool,
+}
+
+impl From<CFG> for MemApCfg {
+    fn from(cfg: CFG) -> Self {
+        Self {
+            big_endian: cfg.BE,
+            long_address: cfg.LA,
+            large_data: cfg.LD,
+        }
+    }
+}
+
 /// Common trait for all memory access ports.
 pub trait MemoryApType:
     ApRegAccess<BASE> + ApRegAccess<BASE2> + ApRegAccess<TAR> + ApRegAccess<TAR2> + ApRegAccess<DRW>
@@ -68,6 +169,9 @@ pub trait MemoryApType:
     /// With the large data extension, the data size can be up to 64 bits wide.
     fn has_large_data_extension(&self) -> bool;
 
+    /// The extensions implemented by the Memory AP, as read from CFG when it was set up.
+    fn cfg(&self) -> MemApCfg;
+
     /// Returns whether the Memory AP only supports 32 bit data size.
     fn supports_only_32bit_data_size(&self) -> bool;
 
@@ -92,6 +196,14 @@ pub trait MemoryApType:
     /// The current CSW with the memory AP specific fields.
     fn status<I: ApAccess>(&mut self, interface: &mut I) -> Result<Self::CSW, ArmError>;
 
+    /// The CSW value last read from or written to the register, without accessing it.
+    fn cached_status(&self) -> u32;
+
+    /// Updates the cached CSW to `csw`, without accessing the register.
+    ///
+    /// Use this if the value of the CSW register is known from a write made by other means.
+    fn set_cached_status(&mut self, csw: u32) -> Result<(), ArmError>;
+
     /// The base address of this AP which is used to then access all relative control registers.
     fn base_address<I: ApAccess>(&self, interface: &mut I) -> Result<u64, ArmError> {
         let base_register: BASE = interface.read_ap_register(self)?;
@@ -129,7 +241,7 @@ pub trait MemoryApType:
             };
             interface.write_ap_register(self, tar)?;
         } else if address_upper != 0 {
-            return Err(ArmError::OutOfBounds);
+            return Err(ArmError::AddressOutOfRange(address));
         }
 
         let tar = TAR {
@@ -183,7 +295,7 @@ macro_rules! memory_aps {
         ),*
     ) => {
         /// Sum type for all memory access ports.
-        #[derive(Debug)]
+        #[derive(Debug, Clone)]
         pub enum MemoryAp {
             $(
                 $(#[$outer])*
@@ -211,6 +323,13 @@ macro_rules! memory_aps {
                     $(ApType::$variant => <$type>::new(interface, address.clone())?.into(),)*
                 })
             }
+
+            /// The type of this access port, as read from IDR when it was created.
+            pub(crate) fn ap_type(&self) -> $crate::architecture::arm::ap::ApType {
+                match self {
+                    $(Self::$variant(_) => $crate::architecture::arm::ap::ApType::$variant,)*
+                }
+            }
         }
     }
 }
@@ -265,6 +384,10 @@ impl MemoryApType for MemoryAp {
         mem_ap_forward!(self, has_large_data_extension())
     }
 
+    fn cfg(&self) -> MemApCfg {
+        mem_ap_forward!(self, cfg())
+    }
+
     fn supports_only_32bit_data_size(&self) -> bool {
         mem_ap_forward!(self, supports_only_32bit_data_size())
     }
@@ -280,4 +403,12 @@ impl MemoryApType for MemoryAp {
     fn status<I: ApAccess>(&mut self, interface: &mut I) -> Result<Self::CSW, ArmError> {
         mem_ap_forward!(self, generic_status(interface))
     }
+
+    fn cached_status(&self) -> u32 {
+        mem_ap_forward!(self, cached_status())
+    }
+
+    fn set_cached_status(&mut self, csw: u32) -> Result<(), ArmError> {
+        mem_ap_forward!(self, set_cached_status(csw))
+    }
 }
@@ -29,7 +29,7 @@ pub fn access_port_is_valid<AP>(
     access_port: &FullyQualifiedApAddress,
 ) -> Option<IDR>
 where
-    AP: DapAccess,
+    AP: DapAccess + ?Sized,
 {
     let idr_result: Result<IDR, _> = debug_port
         .read_raw_ap_register(access_port, IDR::ADDRESS)
@@ -74,6 +74,43 @@ where
         .collect()
 }
 
+/// Return a Vec of all valid access ports with an APSEL of at most `max_apsel`.
+///
+/// The scan stops after `stop_after_gaps` consecutive APSEL values without an access port.
+/// The ADI specification requires access ports to be numbered without gaps, so a value of 1
+/// is enough for conforming devices.
+///
+/// Can fail silently under the hood testing an ap that doesn't exist and would require cleanup.
+#[tracing::instrument(skip(debug_port))]
+pub(crate) fn valid_access_ports_bounded<DP>(
+    debug_port: &mut DP,
+    dp: DpAddress,
+    max_apsel: u8,
+    stop_after_gaps: usize,
+) -> Vec<FullyQualifiedApAddress>
+where
+    DP: DapAccess + ?Sized,
+{
+    let mut access_ports = Vec::new();
+    let mut gaps = 0;
+
+    for ap in 0..=max_apsel {
+        let address = FullyQualifiedApAddress::v1_with_dp(dp, ap);
+
+        if access_port_is_valid(debug_port, &address).is_some() {
+            access_ports.push(address);
+            gaps = 0;
+        } else {
+            gaps += 1;
+            if gaps >= stop_after_gaps.max(1) {
+                break;
+            }
+        }
+    }
+
+    access_ports
+}
+
 /// Tries to find the first AP with the given idr value, returns `None` if there isn't any
 pub fn get_ap_by_idr<AP, P>(debug_port: &mut AP, dp: DpAddress, f: P) -> Option<GenericAp>
 where
@@ -90,3 +127,52 @@ where
             }
         })
 }
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use super::valid_access_ports_bounded;
+    use crate::{
+        architecture::arm::{
+            FullyQualifiedApAddress,
+            ap::{ApRegister, IDR},
+            dp::DpAddress,
+            sequences::DefaultArmSequence,
+        },
+        probe::{
+            DebugProbe,
+            fake_probe::{FakeProbe, Operation},
+        },
+    };
+
+    #[test]
+    fn bounded_scan_skips_gaps() {
+        // AHB-AP
+        const AHB_AP_IDR: u32 = 0x2477_0011;
+
+        let probe = FakeProbe::new();
+        for (ap, idr) in [(0, AHB_AP_IDR), (1, 0), (2, AHB_AP_IDR), (3, 0), (4, 0)] {
+            probe.expect_operation(Operation::ReadRawApRegister {
+                ap: FullyQualifiedApAddress::v1_with_default_dp(ap),
+                address: IDR::ADDRESS,
+                result: idr,
+            });
+        }
+
+        let mut interface = Box::new(probe)
+            .try_get_arm_debug_interface(DefaultArmSequence::create())
+            .map_err(|(_, error)| error)
+            .unwrap();
+
+        let access_ports = valid_access_ports_bounded(&mut *interface, DpAddress::Default, 255, 2);
+
+        assert_eq!(
+            access_ports,
+            [
+                FullyQualifiedApAddress::v1_with_default_dp(0),
+                FullyQualifiedApAddress::v1_with_default_dp(2),
+            ]
+        );
+    }
+}
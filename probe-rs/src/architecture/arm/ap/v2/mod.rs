@@ -8,7 +8,7 @@ use crate::architecture::arm::{
     dp::DpAddress,
     memory::{
         ADIMemoryInterface, ArmMemoryInterface, Component, PeripheralType,
-        romtable::{CORESIGHT_ROM_TABLE_ARCHID, RomTable},
+        romtable::{CORESIGHT_ROM_TABLE_ARCHID, RomTable, power_up_domain},
     },
 };
 
@@ -93,9 +93,132 @@ pub fn new_memory_interface<'i>(
         unimplemented!("this is only for APv2 addresses")
     };
 
-    if ap_address.0.is_none() {
-        Ok(Box::new(RootMemoryInterface::new(iface, address.dp())?))
-    } else {
-        Ok(Box::new(ADIMemoryInterface::new(iface, address)?))
+    match ap_address.0 {
+        None => Ok(Box::new(RootMemoryInterface::new(iface, address.dp())?)),
+        Some(ap_base) => {
+            power_up_ap_domain(iface, address, ap_base)?;
+            let memory_ap = iface.memory_ap(address)?;
+            Ok(Box::new(ADIMemoryInterface::with_memory_ap(
+                iface, memory_ap,
+            )))
+        }
+    }
+}
+
+/// Offset of the CIDR1 register of a CoreSight component.
+const CIDR1_OFFSET: u64 = 0xFF4;
+/// Offset of the DEVARCH register of a CoreSight component.
+const DEVARCH_OFFSET: u64 = 0xFBC;
+/// Offset of the DEVID register of a CoreSight component.
+const DEVID_OFFSET: u64 = 0xFC8;
+
+/// The maximum depth of nested ROM tables searched for an AP, to stop on circular references.
+const MAX_ROM_TABLE_DEPTH: usize = 8;
+
+/// Requests power for the power domain of the AP at `ap_base`, and waits until the domain is
+/// powered up.
+///
+/// The ROM tables of the DP are searched for the AP, starting with the root ROM table. Nested
+/// ROM tables are powered up before they are searched. If the ROM table listing the AP doesn't
+/// support power requests, or doesn't assign the AP to a power domain, nothing is done.
+///
+/// Once the domain of an AP is powered up, it is not checked again until the DP is reconnected.
+fn power_up_ap_domain(
+    iface: &mut ArmCommunicationInterface,
+    address: &FullyQualifiedApAddress,
+    ap_base: u64,
+) -> Result<(), ArmError> {
+    if iface.is_ap_domain_powered(address) {
+        return Ok(());
+    }
+
+    let mut root = RootMemoryInterface::new(iface, address.dp())?;
+    let rom_base = root.base_address()?;
+
+    let found = is_class_9_rom_table(&mut root, rom_base)?
+        && power_up_domain_in_rom_table(&mut root, rom_base, ap_base, 0).map_err(|error| {
+            match error {
+                ArmError::Timeout => ArmError::ApPowerDomainUnavailable(address.clone()),
+                other => other,
+            }
+        })?;
+    if !found {
+        tracing::debug!("AP at {ap_base:#x} is not listed in a Class 0x9 ROM table");
+    }
+
+    iface.set_ap_domain_powered(address);
+
+    Ok(())
+}
+
+/// Searches the Class 0x9 ROM table at `rom_base` and the Class 0x9 ROM tables nested in it for
+/// the component at `component_base`, and powers up its power domain.
+///
+/// Returns whether the component was found.
+fn power_up_domain_in_rom_table(
+    root: &mut dyn ArmMemoryInterface,
+    rom_base: u64,
+    component_base: u64,
+    depth: usize,
+) -> Result<bool, ArmError> {
+    if depth > MAX_ROM_TABLE_DEPTH {
+        return Ok(false);
+    }
+
+    // DEVID.PRR indicates whether the ROM table implements the power request registers.
+    let power_requests = root.read_word_32(rom_base + DEVID_OFFSET)? & (1 << 5) != 0;
+
+    // The 32-bit entries of a Class 0x9 ROM table occupy 0x000 to 0x7FC.
+    for offset in (0..0x800).step_by(4) {
+        let entry = root.read_word_32(rom_base + offset)?;
+        if entry == 0 {
+            break;
+        }
+        if entry & 0b11 != 0b11 {
+            continue;
+        }
+
+        let component = rom_base.wrapping_add_signed(i64::from(entry as i32 & !0xFFF));
+        // POWERIDVALID
+        let power_domain =
+            (power_requests && entry & (1 << 2) != 0).then_some(((entry >> 4) & 0x1F) as u8);
+
+        if component == component_base {
+            if let Some(power_domain) = power_domain {
+                power_up_domain(root, rom_base, power_domain)?;
+            }
+            return Ok(true);
+        }
+        if component == rom_base {
+            continue;
+        }
+
+        // Components in a powered down domain read as all zeroes, so a nested ROM table can
+        // only be recognized once its domain is powered up.
+        let mut is_rom_table = is_class_9_rom_table(root, component)?;
+        if let Some(power_domain) = power_domain {
+            if !is_rom_table && root.read_word_32(component + CIDR1_OFFSET)? == 0 {
+                power_up_domain(root, rom_base, power_domain)?;
+                is_rom_table = is_class_9_rom_table(root, component)?;
+            }
+        }
+        if is_rom_table && power_up_domain_in_rom_table(root, component, component_base, depth + 1)?
+        {
+            return Ok(true);
+        }
     }
+
+    Ok(false)
+}
+
+/// Returns whether the component at `base` is a Class 0x9 ROM table, the only kind of ROM table
+/// which supports power requests.
+fn is_class_9_rom_table(root: &mut dyn ArmMemoryInterface, base: u64) -> Result<bool, ArmError> {
+    let cidr1 = root.read_word_32(base + CIDR1_OFFSET)?;
+    if (cidr1 >> 4) & 0xF != 0x9 {
+        return Ok(false);
+    }
+
+    let devarch = root.read_word_32(base + DEVARCH_OFFSET)?;
+    Ok(devarch & 0xFFFF == u32::from(CORESIGHT_ROM_TABLE_ARCHID))
 }
@@ -1,24 +1,45 @@
+#[cfg(any(test, feature = "test"))]
+use crate::probe::connect_capture::ConnectCapture;
 use crate::{
     CoreStatus,
     architecture::arm::{
-        ApAddress, ArmError, DapAccess, FullyQualifiedApAddress, RawDapAccess, RegisterAddress,
-        SwoAccess, SwoConfig, ap,
+        ApAddress, ApV2Address, ArmError, DapAccess, FullyQualifiedApAddress, Pins, RawDapAccess,
+        RegisterAddress, SharedArmInterface, SwoAccess, SwoConfig,
+        ap::{
+            self, ApClass, ApRegister, ApType, IDR, MemApCfg, MemApRegister, TAR2,
+            memory_ap::{MemoryAp, MemoryApType},
+        },
         dp::{
-            Ctrl, DPIDR, DebugPortId, DebugPortVersion, DpAccess, DpAddress, DpRegisterAddress,
-            Select1, SelectV1, SelectV3,
+            Abort, Ctrl, DPIDR, DebugPortError, DebugPortId, DebugPortVersion, DpAccess, DpAddress,
+            DpRegister, DpRegisterAddress, Resend, Select1, SelectV1, SelectV3,
+        },
+        memory::{
+            ADIMemoryInterface, ArmMemoryInterface, Component, CoresightComponent,
+            MemAccessAttributes, PeripheralType,
         },
-        memory::{ADIMemoryInterface, ArmMemoryInterface, Component},
-        sequences::ArmDebugSequence,
+        sequences::{ArmDebugSequence, DebugLockStatus, DefaultArmSequence},
+        swo::capture::SwoCapture,
+        traits::{CSW_SIZE_32BIT, CSW_SIZE_MASK},
+    },
+    probe::{
+        DebugProbe, DebugProbeError, JtagAccess, Probe, ProbeInfo, WireProtocol,
+        transfer_log::TransferRecorder,
     },
-    probe::{DebugProbe, DebugProbeError, Probe, WireProtocol},
 };
+use bitvec::vec::BitVec;
 use jep106::JEP106Code;
 
 use std::{
-    collections::{BTreeSet, HashMap, hash_map},
+    collections::{BTreeSet, HashMap, HashSet, hash_map},
     fmt::Debug,
-    sync::Arc,
-    time::Duration,
+    io::Write,
+    ops::Range,
+    path::Path,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
 };
 
 /// An error in the communication with an access port or
@@ -42,6 +63,197 @@ pub enum DapError {
     IncorrectParity,
 }
 
+/// Controls how [`ArmCommunicationInterface`] retries register accesses which
+/// the target answered with a WAIT response.
+///
+/// The delay before retry `n` (starting at zero) is `initial_delay * backoff_factor^n`.
+/// The default policy does not retry at all, the error is returned to the caller immediately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Maximum number of retries before the WAIT response is surfaced as an error.
+    pub max_retries: u32,
+    /// Delay before the first retry.
+    pub initial_delay: Duration,
+    /// Factor by which the delay is multiplied after each retry.
+    pub backoff_factor: u32,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 0,
+            initial_delay: Duration::from_millis(1),
+            backoff_factor: 2,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns the delay to wait before the given retry attempt.
+    fn delay(&self, attempt: u32) -> Duration {
+        self.initial_delay
+            .saturating_mul(self.backoff_factor.saturating_pow(attempt))
+    }
+}
+
+/// Caps the throughput of an [`ArmCommunicationInterface`], see
+/// [`ArmCommunicationInterfaceBuilder::rate_limit`].
+///
+/// The limit is enforced with a token bucket: up to `burst` units are transferred without delay,
+/// further transfers are delayed so that the average rate stays below `per_second`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    /// What is counted against the limit.
+    pub unit: RateUnit,
+    /// Maximum average number of units per second.
+    pub per_second: u32,
+    /// Number of units which can be transferred in a burst, without being delayed.
+    pub burst: u32,
+}
+
+/// What is counted against a [`RateLimit`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateUnit {
+    /// DP and AP register transfers. Block transfers count once per word.
+    Transfers,
+    /// Bytes of register data, 4 per transfer.
+    Bytes,
+}
+
+impl RateLimit {
+    /// Limits the number of register transfers per second, allowing bursts of a tenth of a
+    /// second.
+    pub fn transfers_per_second(per_second: u32) -> Self {
+        Self {
+            unit: RateUnit::Transfers,
+            per_second,
+            burst: (per_second / 10).max(1),
+        }
+    }
+
+    /// Limits the number of bytes per second, allowing bursts of a tenth of a second.
+    pub fn bytes_per_second(per_second: u32) -> Self {
+        Self {
+            unit: RateUnit::Bytes,
+            per_second,
+            burst: (per_second / 10).max(4),
+        }
+    }
+
+    /// Sets the number of units which can be transferred in a burst.
+    pub fn with_burst(mut self, burst: u32) -> Self {
+        self.burst = burst;
+        self
+    }
+}
+
+/// The state of a [`RateLimit`].
+#[derive(Debug)]
+struct TokenBucket {
+    limit: RateLimit,
+    /// Negative while transfers are ahead of the limit.
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            tokens: f64::from(limit.burst),
+            refilled_at: Instant::now(),
+        }
+    }
+
+    /// Takes the tokens for transferring `words` words, and returns how long to wait before
+    /// the transfer to stay within the limit.
+    fn take(&mut self, words: usize) -> Duration {
+        let rate = f64::from(self.limit.per_second.max(1));
+
+        let now = Instant::now();
+        let refill = now.duration_since(self.refilled_at).as_secs_f64() * rate;
+        self.refilled_at = now;
+        self.tokens = (self.tokens + refill).min(f64::from(self.limit.burst));
+
+        self.tokens -= match self.limit.unit {
+            RateUnit::Transfers => words as f64,
+            RateUnit::Bytes => (words * 4) as f64,
+        };
+
+        if self.tokens < 0.0 {
+            // The debt is paid back by the refill of the next call.
+            Duration::from_secs_f64(-self.tokens / rate)
+        } else {
+            Duration::ZERO
+        }
+    }
+}
+
+/// Statistics about the transfers performed by an [`ArmCommunicationInterface`].
+///
+/// These help diagnosing signal integrity problems. For example, a high number of
+/// parity errors usually means the clock speed is too high for the wiring.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TransferStats {
+    /// Number of DP and AP register transfers, including retries.
+    pub transfers: u64,
+    /// Number of transfers which failed with a parity error.
+    pub parity_errors: u64,
+    /// Number of WAIT responses.
+    pub wait_responses: u64,
+    /// Number of FAULT responses.
+    pub fault_responses: u64,
+    /// Number of transfers which were retried.
+    pub retries: u64,
+    /// Number of register writes which were skipped, because the register already held the
+    /// written value. See [`ArmCommunicationInterfaceBuilder::combine_writes`].
+    pub combined_writes: u64,
+}
+
+/// The counters backing [`TransferStats`].
+#[derive(Debug, Default)]
+struct TransferCounters {
+    transfers: AtomicU64,
+    parity_errors: AtomicU64,
+    wait_responses: AtomicU64,
+    fault_responses: AtomicU64,
+    retries: AtomicU64,
+    combined_writes: AtomicU64,
+}
+
+impl TransferCounters {
+    fn record<T>(&self, result: &Result<T, ArmError>) {
+        self.transfers.fetch_add(1, Ordering::Relaxed);
+
+        let counter = match result {
+            Err(ArmError::Dap(DapError::IncorrectParity)) => &self.parity_errors,
+            Err(ArmError::Dap(DapError::WaitResponse)) => &self.wait_responses,
+            Err(ArmError::Dap(DapError::FaultResponse)) => &self.fault_responses,
+            _ => return,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn record_retry(&self) {
+        self.retries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn record_combined_write(&self) {
+        self.combined_writes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> TransferStats {
+        TransferStats {
+            transfers: self.transfers.load(Ordering::Relaxed),
+            parity_errors: self.parity_errors.load(Ordering::Relaxed),
+            wait_responses: self.wait_responses.load(Ordering::Relaxed),
+            fault_responses: self.fault_responses.load(Ordering::Relaxed),
+            retries: self.retries.load(Ordering::Relaxed),
+            combined_writes: self.combined_writes.load(Ordering::Relaxed),
+        }
+    }
+}
+
 /// To be implemented by debug probe drivers that support the ARM debug interface.
 pub trait ArmDebugInterface: DapAccess + SwdSequence + SwoAccess + Send {
     /// Reinitialize the communication interface (in place).
@@ -57,15 +269,184 @@ pub trait ArmDebugInterface: DapAccess + SwdSequence + SwoAccess + Send {
     /// If you're a chip-specific debug sequence, you're expected to handle this yourself.
     fn reinitialize(&mut self) -> Result<(), ArmError>;
 
+    /// Re-establishes the connection to a debug port after a recoverable protocol error,
+    /// without powering it down.
+    ///
+    /// Unlike [`ArmDebugInterface::reinitialize`], this doesn't run the `debug_port_stop`
+    /// sequence. Only `debug_port_connect` and `debug_port_start` are run again, which resync
+    /// the wire protocol, re-read DPIDR and CTRL/STAT and clear any sticky errors. The cores
+    /// keep their halt state and breakpoints.
+    ///
+    /// This is enough after e.g. a lost SWD line synchronization or a burst of FAULT responses.
+    /// If the target was reset, or a chip-specific sequence disabled the debug port, use
+    /// `reinitialize` instead.
+    ///
+    /// The default implementation falls back to `reinitialize`.
+    fn resync(&mut self, _dp: DpAddress) -> Result<(), ArmError> {
+        self.reinitialize()
+    }
+
     /// Returns a vector of all the access ports the current debug port has.
     ///
     /// If the target device has multiple debug ports, this will switch the active debug port
     /// if necessary.
+    ///
+    /// If the debug port doesn't respond, [`ArmError::NoDebugPort`] is returned. A debug port
+    /// which responds but has no access ports results in an empty set.
     fn access_ports(
         &mut self,
         dp: DpAddress,
     ) -> Result<BTreeSet<FullyQualifiedApAddress>, ArmError>;
 
+    /// Returns the access ports of the given debug port, scanning at most up to APSEL `max_apsel`.
+    ///
+    /// The scan stops after `stop_after_gaps` consecutive APSEL values without an access
+    /// port. This is faster than [`ArmDebugInterface::access_ports`] on devices with a known
+    /// topology, and allows finding access ports on devices which leave gaps between them.
+    ///
+    /// Access ports on ADIv6 debug ports are not addressed by APSEL, so all of them are returned.
+    fn access_ports_bounded(
+        &mut self,
+        dp: DpAddress,
+        max_apsel: u8,
+        stop_after_gaps: usize,
+    ) -> Result<BTreeSet<FullyQualifiedApAddress>, ArmError> {
+        self.select_debug_port(dp)?;
+
+        Ok(
+            ap::v1::valid_access_ports_bounded(self, dp, max_apsel, stop_after_gaps)
+                .into_iter()
+                .collect(),
+        )
+    }
+
+    /// Returns all access ports of the given debug port, together with their decoded IDR and,
+    /// for MEM-APs, the base address of their debug components.
+    fn access_ports_detailed(&mut self, dp: DpAddress) -> Result<Vec<ApInfo>, ArmError> {
+        let mut access_ports = Vec::new();
+
+        for address in self.access_ports(dp)? {
+            let idr = IDR::try_from(self.read_raw_ap_register(&address, IDR::ADDRESS)?)?;
+
+            let base_address = if idr.CLASS == ApClass::MemAp {
+                match self
+                    .memory_interface(&address)
+                    .and_then(|mut memory| memory.base_address())
+                {
+                    Ok(base_address) => Some(base_address),
+                    Err(e) => {
+                        tracing::debug!("Failed to read base address of {:x?}: {}", address, e);
+                        None
+                    }
+                }
+            } else {
+                None
+            };
+
+            access_ports.push(ApInfo {
+                address,
+                idr,
+                base_address,
+            });
+        }
+
+        Ok(access_ports)
+    }
+
+    /// Returns the access ports of all debug ports, see
+    /// [`ArmDebugInterface::access_ports_detailed`], sorted by debug port address.
+    ///
+    /// Multidrop debug ports only respond once they are selected with their target ID, so
+    /// they can't be discovered blindly. Each of the `multidrop_targets` which wasn't connected
+    /// yet is selected once, and skipped if no debug port responds to it. The debug ports
+    /// connected through this interface before are always scanned. If there are neither, the
+    /// default debug port is scanned.
+    ///
+    /// A debug port whose access ports can't be enumerated doesn't stop the scan, the error is
+    /// returned as part of its [`DpTopology`]. Only losing the probe aborts the scan.
+    fn full_topology(&mut self, multidrop_targets: &[u32]) -> Result<Vec<DpTopology>, ArmError> {
+        let mut topology = Vec::new();
+        let known_dps = self.known_debug_ports();
+
+        for &target in multidrop_targets {
+            let dp = DpAddress::Multidrop(target);
+            if known_dps.contains(&dp) {
+                continue;
+            }
+
+            match self.select_debug_port(dp) {
+                // Scan the access ports right away, while the debug port is selected.
+                Ok(()) => topology.push(scan_debug_port(self, dp)?),
+                Err(error) if is_probe_disconnect(&error) => return Err(error),
+                Err(ArmError::NoDebugPort { .. } | ArmError::WireProtocolMismatch { .. }) => {
+                    tracing::debug!("No debug port responded to target ID {target:#010x}");
+                }
+                Err(error) => {
+                    tracing::debug!("Failed to connect to {dp:x?}: {error}");
+                    topology.push(DpTopology {
+                        dp,
+                        access_ports: Err(error),
+                    });
+                }
+            }
+        }
+
+        let mut dps = self.known_debug_ports();
+        dps.retain(|dp| !topology.iter().any(|entry| entry.dp == *dp));
+        if dps.is_empty() && multidrop_targets.is_empty() {
+            dps.push(self.current_debug_port().unwrap_or_default());
+        }
+
+        // Scan the current debug port first, so that every debug port is selected only once.
+        let current_dp = self.current_debug_port();
+        dps.sort_by_key(|dp| (Some(*dp) != current_dp, *dp));
+
+        for dp in dps {
+            topology.push(scan_debug_port(self, dp)?);
+        }
+
+        topology.sort_by_key(|entry| entry.dp);
+
+        Ok(topology)
+    }
+
+    /// Returns the addresses of all MEM-APs of the given debug port.
+    ///
+    /// Other access ports, such as JTAG-APs or vendor specific access ports, can't be
+    /// accessed using [`ArmDebugInterface::memory_interface`] and are skipped.
+    fn memory_access_ports(
+        &mut self,
+        dp: DpAddress,
+    ) -> Result<Vec<FullyQualifiedApAddress>, ArmError> {
+        let mut memory_access_ports = Vec::new();
+
+        for address in self.access_ports(dp)? {
+            let raw_idr = self.read_raw_ap_register(&address, IDR::ADDRESS)?;
+
+            // Vendor specific access ports may not have a valid IDR.
+            let is_mem_ap = IDR::try_from(raw_idr)
+                .is_ok_and(|idr| idr.CLASS == ApClass::MemAp && idr.TYPE != ApType::JtagComAp);
+
+            if is_mem_ap {
+                memory_access_ports.push(address);
+            }
+        }
+
+        Ok(memory_access_ports)
+    }
+
+    /// Checks whether an access port exists at the given address, by reading its IDR.
+    ///
+    /// An IDR of zero means that there is no access port, as does a FAULT response to the
+    /// read. Unlike [`ArmDebugInterface::access_ports`], only the given address is accessed.
+    fn ap_exists(&mut self, ap: &FullyQualifiedApAddress) -> Result<bool, ArmError> {
+        match self.read_raw_ap_register(ap, IDR::ADDRESS) {
+            Ok(idr) => Ok(idr != 0),
+            Err(ArmError::Dap(DapError::FaultResponse)) => Ok(false),
+            Err(error) => Err(error),
+        }
+    }
+
     /// Closes the interface and returns back the generic probe it consumed.
     fn close(self: Box<Self>) -> Probe;
 
@@ -76,560 +457,4915 @@ pub trait ArmDebugInterface: DapAccess + SwdSequence + SwoAccess + Send {
     /// over the point in time where the DP is selected.
     fn select_debug_port(&mut self, dp: DpAddress) -> Result<(), ArmError>;
 
+    /// Disconnects from all debug ports, by running the `debug_port_stop` sequence for each of them.
+    ///
+    /// Unlike dropping or closing the interface, this returns any error which occurred, so that
+    /// callers can check whether the target was released cleanly. Afterwards, the interface can
+    /// be used again by selecting a debug port with [`ArmDebugInterface::select_debug_port`].
+    ///
+    /// Interfaces which don't run any debug port sequences do nothing.
+    fn disconnect_gracefully(&mut self) -> Result<(), ArmError> {
+        Ok(())
+    }
+
     /// Return the currently connected debug port.
     ///
     /// None if the interface is not connected to a DP.
     fn current_debug_port(&self) -> Option<DpAddress>;
 
-    /// Returns a memory interface to access the target's memory.
-    fn memory_interface(
-        &mut self,
-        access_port: &FullyQualifiedApAddress,
-    ) -> Result<Box<dyn ArmMemoryInterface + '_>, ArmError>;
-}
+    /// Returns all debug ports which were connected through this interface, sorted by address.
+    ///
+    /// On multidrop targets this can include debug ports besides the current one.
+    fn known_debug_ports(&self) -> Vec<DpAddress> {
+        self.current_debug_port().into_iter().collect()
+    }
 
-/// Read chip information from the ROM tables
-pub fn read_chip_info_from_rom_table(
-    probe: &mut dyn ArmDebugInterface,
-    dp: DpAddress,
-) -> Result<Option<ArmChipInfo>, ArmError> {
-    for ap in probe.access_ports(dp)? {
-        if let Ok(mut memory) = probe.memory_interface(&ap) {
-            let base_address = memory.base_address()?;
-            let component = Component::try_parse(&mut *memory, base_address)?;
+    /// Returns the version of the given debug port, or `None` if it was not connected yet.
+    fn debug_port_version(&self, _dp: DpAddress) -> Option<DebugPortVersion> {
+        None
+    }
 
-            if let Component::Class1RomTable(component_id, _) = component {
-                if let Some(jep106) = component_id.peripheral_id().jep106() {
-                    return Ok(Some(ArmChipInfo {
-                        manufacturer: jep106,
-                        part: component_id.peripheral_id().part(),
-                    }));
-                }
-            }
-        }
+    /// Returns the ID of the given debug port, as read from DPIDR while connecting.
+    ///
+    /// No transfers are made. Returns `None` if the debug port was not connected through this
+    /// interface.
+    fn debug_port_id(&self, _dp: DpAddress) -> Option<DebugPortId> {
+        None
     }
 
-    Ok(None)
-}
+    /// Reads the RESEND register of the given debug port, which returns the result of the last
+    /// AP read or RDBUFF read again without accessing the AP.
+    ///
+    /// RESEND is only available on SW-DPs. AP register reads use it automatically to recover
+    /// from parity errors.
+    fn read_resend(&mut self, _dp: DpAddress) -> Result<u32, ArmError> {
+        Err(ArmError::NotImplemented("read_resend"))
+    }
 
-// TODO: Rename trait!
-pub trait SwdSequence {
-    /// Corresponds to the DAP_SWJ_Sequence function from the ARM Debug sequences
-    fn swj_sequence(&mut self, bit_len: u8, bits: u64) -> Result<(), DebugProbeError>;
+    /// Returns the cached SELECT state of the given debug port, or `None` if the debug port
+    /// is not connected.
+    ///
+    /// Debug sequences which write SELECT directly can use this together with
+    /// [`ArmDebugInterface::restore_select_state`] to avoid desynchronizing the cached
+    /// register bank selection.
+    fn save_select_state(&self, _dp: DpAddress) -> Option<SelectCache> {
+        None
+    }
 
-    /// Corresponds to the DAP_SWJ_Pins function from the ARM Debug sequences
-    fn swj_pins(
+    /// Writes a SELECT state saved with [`ArmDebugInterface::save_select_state`] back to the
+    /// given debug port.
+    fn restore_select_state(
         &mut self,
-        pin_out: u32,
-        pin_select: u32,
-        pin_wait: u32,
-    ) -> Result<u32, DebugProbeError>;
-}
+        _dp: DpAddress,
+        _state: SelectCache,
+    ) -> Result<(), ArmError> {
+        Err(ArmError::NotImplemented("restore_select_state"))
+    }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub(crate) enum SelectCache {
-    DPv1(SelectV1),
-    DPv3(SelectV3, Select1),
-}
-impl SelectCache {
-    pub fn dp_bank_sel(&self) -> u8 {
-        match self {
-            SelectCache::DPv1(s) => s.dp_bank_sel(),
-            SelectCache::DPv3(s, _) => s.dp_bank_sel(),
-        }
+    /// Brings the cached SELECT state of the given debug port back in sync with the hardware,
+    /// e.g. after SELECT was changed by another debugger or by a target reset.
+    ///
+    /// Where SELECT can be read, i.e. on a JTAG-DP before DPv3, the cache is updated with the
+    /// value read from the debug port. Otherwise, the cached value is written to the debug port.
+    fn resync_select(&mut self, _dp: DpAddress) -> Result<(), ArmError> {
+        Err(ArmError::NotImplemented("resync_select"))
     }
-    pub fn set_dp_bank_sel(&mut self, bank: u8) {
-        match self {
-            SelectCache::DPv1(s) => s.set_dp_bank_sel(bank),
-            SelectCache::DPv3(s, _) => s.set_dp_bank_sel(bank),
-        }
+
+    /// Returns information about the probe used by this interface, such as its firmware
+    /// version and capabilities.
+    fn probe_info(&self) -> ProbeInfo {
+        ProbeInfo::default()
     }
-}
-#[derive(Debug)]
-pub(crate) struct DpState {
-    pub debug_port_version: DebugPortVersion,
 
-    pub(crate) current_select: SelectCache,
-}
+    /// Returns the name of the debug sequence used by this interface.
+    ///
+    /// This helps to tell whether a family-specific sequence is in effect.
+    fn sequence_name(&self) -> &str {
+        "unknown"
+    }
 
-impl DpState {
-    pub fn new() -> Self {
-        Self {
-            debug_port_version: DebugPortVersion::Unsupported(0xFF),
-            current_select: SelectCache::DPv1(SelectV1(0)),
-        }
+    /// Checks whether debug access to the device behind the debug port `dp` is locked, e.g. by
+    /// readout protection.
+    ///
+    /// Locked devices often fail to connect with unclear errors, or return garbage for all
+    /// memory reads. If [`DebugLockStatus::Locked`] is returned, tools can tell the user to
+    /// mass-erase the device instead. Detecting the lock state requires a family-specific
+    /// debug sequence, see [`ArmDebugSequence::is_debug_locked`].
+    fn check_debug_lock(&mut self, _dp: DpAddress) -> Result<DebugLockStatus, ArmError> {
+        Ok(DebugLockStatus::Unknown)
     }
-}
 
-/// An implementation of the communication protocol between probe and target.
-/// Can be used to perform all sorts of generic debug access on ARM targets with probes that support low level access.
-/// (E.g. CMSIS-DAP and J-Link support this, ST-Link does not)
-#[derive(Debug)]
-pub struct ArmCommunicationInterface {
-    probe: Option<Box<dyn DapProbe>>,
+    /// Sets the number of idle clock cycles which the probe appends after each SWD transfer.
+    ///
+    /// This is a workaround for targets which intermittently don't acknowledge transfers, see
+    /// [`RawDapAccess::set_swd_idle_cycles`]. It costs throughput, so only use it if necessary.
+    /// The setting is not kept if the probe has to be re-acquired.
+    fn set_idle_cycles(&mut self, _cycles: u8) -> Result<(), ArmError> {
+        Err(ArmError::NotImplemented("set_idle_cycles"))
+    }
 
-    /// Currently selected debug port. For targets without multidrop,
-    /// this will always be the single, default debug port in the system.
+    /// Returns details about the last failed attempt to connect to a debug port.
     ///
-    /// If this is None, the interface is in an uninitialized state.
-    current_dp: Option<DpAddress>,
-    dps: HashMap<DpAddress, DpState>,
-    use_overrun_detect: bool,
-    sequence: Arc<dyn ArmDebugSequence>,
-}
+    /// This is cleared once a debug port is connected successfully.
+    fn last_connect_diagnostics(&self) -> Option<ConnectDiagnostics> {
+        None
+    }
 
-impl Drop for ArmCommunicationInterface {
-    fn drop(&mut self) {
-        if self.probe.is_some() {
-            self.disconnect();
+    /// Returns statistics about the transfers performed since the interface was created,
+    /// or since the last call to [`ArmDebugInterface::reset_transfer_stats`].
+    fn transfer_stats(&self) -> Result<TransferStats, ArmError> {
+        Err(ArmError::NotImplemented("transfer_stats"))
+    }
 
-            // Ensure we don't disconnect twice
-            self.probe = None;
-        }
+    /// Resets the transfer statistics to zero.
+    fn reset_transfer_stats(&mut self) -> Result<(), ArmError> {
+        Err(ArmError::NotImplemented("reset_transfer_stats"))
     }
-}
 
-impl ArmCommunicationInterface {
-    pub(crate) fn probe_mut(&mut self) -> &mut dyn DapProbe {
-        // Unwrap: Probe is only taken when the struct is dropped
-        self.probe.as_deref_mut().expect("ArmCommunicationInterface is in an inconsistent state. This is a bug, please report it.")
+    /// Returns the DPs whose `debug_port_stop` sequence failed during the last disconnect, e.g.
+    /// while reinitializing, together with the errors. The errors are only returned once.
+    ///
+    /// Always empty unless enabled with
+    /// [`ArmCommunicationInterfaceBuilder::collect_disconnect_errors`].
+    fn take_disconnect_errors(&mut self) -> Result<Vec<(DpAddress, ArmError)>, ArmError> {
+        Err(ArmError::NotImplemented("take_disconnect_errors"))
     }
 
-    fn close(mut self) -> Probe {
-        self.disconnect();
+    /// Forgets all values written to registers, so that the next write to each register is
+    /// performed even if write combining is enabled.
+    ///
+    /// This has to be called after registers were changed without going through this interface,
+    /// e.g. by accessing the probe directly. Interfaces which don't combine writes have nothing
+    /// to forget.
+    fn invalidate_write_cache(&mut self) {}
 
-        let probe = self.probe.take().unwrap();
+    /// Reads the unique ID of the target through the memory access port `ap`.
+    ///
+    /// The location of the ID is provided by the debug sequence of the target, see
+    /// [`ArmDebugSequence::read_unique_id`]. Returns `None` if the sequence doesn't know it.
+    fn read_target_uid(
+        &mut self,
+        _ap: &FullyQualifiedApAddress,
+    ) -> Result<Option<Vec<u8>>, ArmError> {
+        Err(ArmError::NotImplemented("read_target_uid"))
+    }
 
-        Probe::from_attached_probe(RawDapAccess::into_probe(probe))
+    /// Selects the wire protocol used to talk to the target.
+    ///
+    /// Returns [`ArmError::ProtocolNotSupported`] if the probe can't use `protocol`. Once
+    /// selected, connecting doesn't fall back to the other protocol anymore.
+    ///
+    /// If a debug port is connected already, it has to be stopped using the protocol it was set
+    /// up with, so a different protocol only takes effect on the next call to
+    /// [`ArmDebugInterface::reinitialize`]. This is reported with
+    /// [`ArmError::ReinitializeRequired`], and the current protocol is used until then.
+    fn set_wire_protocol(&mut self, _protocol: WireProtocol) -> Result<(), ArmError> {
+        Err(ArmError::NotImplemented("set_wire_protocol"))
     }
 
-    /// Disconnect from all debug ports, by calling `debug_port_stop` on all DPs which we
-    /// are connected to.
-    fn disconnect(&mut self) {
-        let probe = self.probe.as_deref_mut().unwrap();
+    /// Returns a memory interface to access the target's memory.
+    fn memory_interface(
+        &mut self,
+        access_port: &FullyQualifiedApAddress,
+    ) -> Result<Box<dyn ArmMemoryInterface + '_>, ArmError>;
 
-        if let Some(current_dp) = self.current_dp.take() {
-            let _stop_span = tracing::debug_span!("debug_port_stop").entered();
+    /// Reads a single 32-bit word from the memory behind `access_port`.
+    ///
+    /// Interfaces with raw access to the access port registers program TAR and read DRW
+    /// directly, without setting up a [`ArmDebugInterface::memory_interface`] first, which reads
+    /// several registers of the access port. Use it for polling loops in debug sequences. Other
+    /// interfaces read the word through a memory interface.
+    fn read_word_32(
+        &mut self,
+        access_port: &FullyQualifiedApAddress,
+        address: u64,
+    ) -> Result<u32, ArmError> {
+        self.memory_interface(access_port)?.read_word_32(address)
+    }
 
-            // Stop the current DP, which may not be one of the known ones (i.e. RP2040 rescue DP).
-            self.sequence.debug_port_stop(probe, current_dp).ok();
+    /// Returns whether SWO tracing can be enabled with the current probe.
+    ///
+    /// This requires the probe to have a SWO interface, and to be connected using SWD,
+    /// as the SWO pin is shared with the JTAG TDO pin.
+    fn supports_swo(&self) -> bool {
+        self.try_dap_probe().is_some_and(|probe| {
+            probe.get_swo_interface().is_some()
+                && probe.active_protocol() == Some(WireProtocol::Swd)
+        })
+    }
 
-            drop(_stop_span);
+    /// Changes the SWD clock of the underlying probe to `hz`, and returns the clock
+    /// frequency actually achieved, in Hz.
+    ///
+    /// This can be called between transfers, without reconnecting to the target.
+    /// Probes which can't change the clock while connected keep the current clock,
+    /// which is then returned.
+    fn set_swd_clock(&mut self, hz: u32) -> Result<u32, DebugProbeError> {
+        let Some(probe) = self.try_dap_probe_mut() else {
+            return Err(DebugProbeError::CommandNotSupportedByProbe {
+                command_name: "set_swd_clock",
+            });
+        };
 
-            // Stop all intentionally-connected DPs.
-            for dp in self.dps.keys().filter(|dp| **dp != current_dp) {
-                // Try to select the debug port we want to shut down.
-                if self.sequence.debug_port_connect(probe, *dp).is_ok() {
-                    self.sequence.debug_port_stop(probe, *dp).ok();
-                } else {
-                    tracing::warn!("Failed to stop DP {:x?}", dp);
-                }
+        // Queued transfers have to be performed with the clock they were queued with.
+        probe
+            .raw_flush()
+            .map_err(|error| DebugProbeError::Other(error.to_string()))?;
+
+        let speed_khz = (hz / 1000).max(1);
+        match probe.set_speed(speed_khz) {
+            Ok(actual_khz) => Ok(actual_khz * 1000),
+            Err(
+                error @ (DebugProbeError::CommandNotSupportedByProbe { .. }
+                | DebugProbeError::NotImplemented { .. }),
+            ) => {
+                let current_khz = probe.speed_khz();
+                tracing::warn!(
+                    "Unable to change the SWD clock to {} kHz, keeping {} kHz: {}",
+                    speed_khz,
+                    current_khz,
+                    error
+                );
+                Ok(current_khz * 1000)
             }
-        };
+            Err(error) => Err(error),
+        }
+    }
 
-        probe.raw_flush().ok();
+    /// Returns a memory interface which programs the given CSW once and keeps it fixed
+    /// for its whole lifetime, instead of reprogramming it on each access.
+    ///
+    /// This is useful for tight polling loops which repeatedly access memory with the same
+    /// access width. Callers must not mix access widths on the returned interface, as
+    /// every access with a width different from `csw` reprograms the CSW register.
+    ///
+    /// The original CSW value is restored when the returned interface is dropped.
+    fn memory_interface_with_csw(
+        &mut self,
+        _access_port: &FullyQualifiedApAddress,
+        _csw: ap::CSW,
+    ) -> Result<Box<dyn ArmMemoryInterface + '_>, ArmError> {
+        Err(ArmError::NotImplemented("memory_interface_with_csw"))
     }
-}
 
-/// Helper trait for probes which offer access to ARM DAP (Debug Access Port).
-///
-/// This is used to combine the traits, because it cannot be done in the ArmCommunicationInterface
-/// struct itself.
-pub trait DapProbe: RawDapAccess + DebugProbe {}
+    /// Returns whether the probe supports raw JTAG IR and DR scans, using
+    /// [`ArmDebugInterface::jtag_scan_ir`] and [`ArmDebugInterface::jtag_scan_dr`].
+    fn supports_jtag_scan(&mut self) -> bool {
+        jtag_access(self).is_ok()
+    }
 
-impl ArmDebugInterface for ArmCommunicationInterface {
-    fn reinitialize(&mut self) -> Result<(), ArmError> {
-        let current_dp = self.current_dp;
+    /// Shifts `len` bits of `bits` into the instruction register of the selected TAP,
+    /// and returns the bits shifted out.
+    ///
+    /// This bypasses the debug port, so the DAP state of the interface is not updated.
+    fn jtag_scan_ir(&mut self, bits: &[u8], len: u32) -> Result<BitVec, ArmError> {
+        Ok(jtag_access(self)?.write_ir(bits, len)?)
+    }
 
-        // Simulate the drop / close of the initialized communication interface.
-        self.disconnect();
+    /// Shifts `len` bits of `bits` into the data register of the selected TAP,
+    /// and returns the bits shifted out.
+    ///
+    /// This bypasses the debug port, so the DAP state of the interface is not updated.
+    fn jtag_scan_dr(&mut self, bits: &[u8], len: u32) -> Result<BitVec, ArmError> {
+        Ok(jtag_access(self)?.write_dr(bits, len)?)
+    }
 
-        // This should be set to None by the disconnect call above.
-        assert!(self.current_dp.is_none());
+    /// Reads `len` bytes of memory starting at `start` through the given access port,
+    /// and writes them to `out`.
+    ///
+    /// The memory is read in chunks which don't cross the 1 KiB TAR auto-increment boundary,
+    /// so only a single chunk is kept in memory at any time.
+    fn dump_memory(
+        &mut self,
+        access_port: &FullyQualifiedApAddress,
+        start: u64,
+        len: u64,
+        out: &mut dyn Write,
+    ) -> Result<(), ArmError> {
+        self.dump_memory_with_progress(access_port, start, len, out, &mut |_| {})
+    }
 
-        // Reconnect to the DP again
-        if let Some(dp) = current_dp {
-            self.select_dp(dp)?;
+    /// Like [`ArmDebugInterface::dump_memory`], but calls `progress` with the total number
+    /// of bytes dumped so far after every chunk.
+    fn dump_memory_with_progress(
+        &mut self,
+        access_port: &FullyQualifiedApAddress,
+        start: u64,
+        len: u64,
+        out: &mut dyn Write,
+        progress: &mut dyn FnMut(u64),
+    ) -> Result<(), ArmError> {
+        const TAR_AUTOINCREMENT_LIMIT: u64 = 0x400;
+
+        let end = start.checked_add(len).ok_or(ArmError::OutOfBounds)?;
+        let mut memory = self.memory_interface(access_port)?;
+        let mut buffer = vec![0; TAR_AUTOINCREMENT_LIMIT as usize];
+
+        let mut address = start;
+        while address < end {
+            let boundary = (address + 1).next_multiple_of(TAR_AUTOINCREMENT_LIMIT);
+            let chunk = &mut buffer[..(boundary.min(end) - address) as usize];
+
+            memory.read(address, chunk)?;
+            out.write_all(chunk)
+                .map_err(|e| ArmError::Other(format!("Failed to write memory dump: {e}")))?;
+
+            address += chunk.len() as u64;
+            progress(address - start);
         }
 
         Ok(())
     }
 
-    fn memory_interface(
+    /// Reads a block of 32 bit words at `address`, tolerating addresses which fault.
+    ///
+    /// The block is read in one go first. If that fails with a FAULT response, it is read
+    /// again word by word, and every faulting word is filled with `sentinel`. The faulting
+    /// address ranges are returned, so that e.g. memory map discovery can skip them.
+    fn read_32_with_fallback(
         &mut self,
-        access_port_address: &FullyQualifiedApAddress,
-    ) -> Result<Box<dyn ArmMemoryInterface + '_>, ArmError> {
-        let memory_interface = match access_port_address.ap() {
-            ApAddress::V1(_) => Box::new(ADIMemoryInterface::new(self, access_port_address)?)
-                as Box<dyn ArmMemoryInterface + '_>,
-            ApAddress::V2(_) => ap::v2::new_memory_interface(self, access_port_address)?,
-        };
-        Ok(memory_interface)
-    }
+        access_port: &FullyQualifiedApAddress,
+        address: u64,
+        data: &mut [u32],
+        sentinel: u32,
+    ) -> Result<Vec<FaultedRange>, ArmError> {
+        let mut memory = self.memory_interface(access_port)?;
 
-    fn current_debug_port(&self) -> Option<DpAddress> {
-        self.current_dp
+        match memory.read_32(address, data) {
+            Err(ArmError::Dap(DapError::FaultResponse)) => {
+                tracing::debug!(
+                    "Block read at {:#010x} faulted, reading word by word",
+                    address
+                );
+            }
+            result => return result.map(|()| Vec::new()),
+        }
+
+        let mut faulted: Vec<FaultedRange> = Vec::new();
+        for (word_address, word) in (address..).step_by(4).zip(data.iter_mut()) {
+            match memory.read_word_32(word_address) {
+                Ok(value) => *word = value,
+                Err(ArmError::Dap(DapError::FaultResponse)) => {
+                    *word = sentinel;
+
+                    match faulted.last_mut() {
+                        Some(range) if range.end == word_address => range.end += 4,
+                        _ => faulted.push(FaultedRange {
+                            start: word_address,
+                            end: word_address + 4,
+                        }),
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(faulted)
     }
 
-    fn close(self: Box<Self>) -> Probe {
-        ArmCommunicationInterface::close(*self)
+    /// Resets the debug logic behind the given debug port, without resetting the rest of the system.
+    ///
+    /// This asserts CDBGRSTREQ in CTRL/STAT, waits for CDBGRSTACK, and then releases the request
+    /// again. Returns [`ArmError::Timeout`] if the reset is not acknowledged.
+    fn debug_reset(&mut self, dp: DpAddress) -> Result<(), ArmError> {
+        const ACK_TIMEOUT: Duration = Duration::from_millis(100);
+        const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+        fn wait_for_ack<D: DpAccess + ?Sized>(
+            interface: &mut D,
+            dp: DpAddress,
+            ack: bool,
+        ) -> Result<(), ArmError> {
+            let start = Instant::now();
+            while interface.read_dp_register::<Ctrl>(dp)?.cdbgrstack() != ack {
+                if start.elapsed() >= ACK_TIMEOUT {
+                    return Err(ArmError::Timeout);
+                }
+                std::thread::sleep(POLL_INTERVAL);
+            }
+            Ok(())
+        }
+
+        let mut ctrl: Ctrl = self.read_dp_register(dp)?;
+
+        tracing::debug!("Setting CDBGRSTREQ");
+        ctrl.set_c_dbg_rst_req(true);
+        self.write_dp_register(dp, ctrl.clone())?;
+        let requested = wait_for_ack(self, dp, true);
+
+        // Always release the reset request, even if it was never acknowledged.
+        tracing::debug!("Clearing CDBGRSTREQ");
+        ctrl.set_c_dbg_rst_req(false);
+        let released = self
+            .write_dp_register(dp, ctrl)
+            .and_then(|_| wait_for_ack(self, dp, false));
+
+        requested.and(released)
     }
 
-    fn access_ports(
+    /// Returns a memory interface which performs all accesses with the given bus attributes,
+    /// e.g. to access secure memory.
+    ///
+    /// The CSW of the access port is restored when the memory interface is dropped.
+    fn memory_interface_with_attributes(
         &mut self,
-        dp: DpAddress,
-    ) -> Result<BTreeSet<FullyQualifiedApAddress>, ArmError> {
-        match self.select_dp(dp).map(|state| state.debug_port_version)? {
-            DebugPortVersion::DPv0 | DebugPortVersion::DPv1 | DebugPortVersion::DPv2 => {
-                Ok(ap::v1::valid_access_ports(self, dp).into_iter().collect())
-            }
-            DebugPortVersion::DPv3 => ap::v2::enumerate_access_ports(self, dp),
-            DebugPortVersion::Unsupported(_) => unreachable!(),
+        access_port: &FullyQualifiedApAddress,
+        attributes: MemAccessAttributes,
+    ) -> Result<Box<dyn ArmMemoryInterface + '_>, ArmError> {
+        if attributes == MemAccessAttributes::default() {
+            return self.memory_interface(access_port);
         }
+
+        let idr = IDR::try_from(self.read_raw_ap_register(access_port, IDR::ADDRESS)?)?;
+        let csw = ap::CSW::try_from(self.read_raw_ap_register(access_port, ap::CSW::ADDRESS)?)?;
+        let csw = attributes.apply(idr.TYPE, csw)?;
+
+        self.memory_interface_with_csw(access_port, csw)
     }
 
-    fn select_debug_port(&mut self, dp: DpAddress) -> Result<(), ArmError> {
-        let _ = self.select_dp(dp)?;
-        Ok(())
+    /// Walks the ROM table behind the given access port, including all nested ROM tables,
+    /// and returns the base address of every component of the requested [`PeripheralType`].
+    ///
+    /// This allows locating components like the ITM or TPIU without hardcoding their
+    /// addresses for each chip.
+    fn find_all_components(
+        &mut self,
+        access_port: &FullyQualifiedApAddress,
+        peripheral_type: PeripheralType,
+    ) -> Result<Vec<(u64, PeripheralType)>, ArmError> {
+        let mut memory = self.memory_interface(access_port)?;
+        let base_address = memory.base_address()?;
+        let component = Component::try_parse(&mut *memory, base_address)?;
+        drop(memory);
+
+        let root = CoresightComponent::new(component, access_port.clone());
+        let components = root
+            .iter()
+            .filter_map(|component| {
+                let id = component.component.id();
+                let found = id.peripheral_id().determine_part()?.peripheral_type();
+
+                (found == peripheral_type).then_some((id.component_address(), found))
+            })
+            .collect();
+
+        Ok(components)
     }
 }
 
-impl SwdSequence for ArmCommunicationInterface {
-    fn swj_sequence(&mut self, bit_len: u8, bits: u64) -> Result<(), DebugProbeError> {
-        self.probe_mut().swj_sequence(bit_len, bits)?;
+/// Read chip information from the ROM tables
+pub fn read_chip_info_from_rom_table(
+    probe: &mut dyn ArmDebugInterface,
+    dp: DpAddress,
+) -> Result<Option<ArmChipInfo>, ArmError> {
+    for ap in probe.memory_access_ports(dp)? {
+        if let Ok(mut memory) = probe.memory_interface(&ap) {
+            let base_address = memory.base_address()?;
+            let component = Component::try_parse(&mut *memory, base_address)?;
 
-        Ok(())
+            if let Component::Class1RomTable(component_id, _) = component {
+                if let Some(jep106) = component_id.peripheral_id().jep106() {
+                    return Ok(Some(ArmChipInfo {
+                        manufacturer: jep106,
+                        part: component_id.peripheral_id().part(),
+                        ap,
+                        rom_base: base_address,
+                    }));
+                }
+            }
+        }
     }
 
+    Ok(None)
+}
+
+/// Connects to the debug port `dp`, reads the chip information from the ROM tables and
+/// disconnects again.
+///
+/// Errors while connecting to the debug port are returned as they are, e.g. as
+/// [`ArmError::NoDebugPort`] if the debug port did not respond. `Ok(None)` means that the
+/// debug port could be used, but none of its access ports has a ROM table identifying the chip.
+pub fn identify(
+    probe: Box<dyn DapProbe>,
+    sequence: Arc<dyn ArmDebugSequence>,
+    dp: DpAddress,
+) -> Result<Option<ArmChipInfo>, ArmError> {
+    let mut interface = ArmCommunicationInterfaceBuilder::new()
+        .sequence(sequence)
+        .build(probe);
+
+    let result = interface
+        .select_debug_port(dp)
+        .and_then(|_| read_chip_info_from_rom_table(interface.as_mut(), dp));
+
+    interface.close();
+
+    result
+}
+
+// TODO: Rename trait!
+pub trait SwdSequence {
+    /// Corresponds to the DAP_SWJ_Sequence function from the ARM Debug sequences
+    fn swj_sequence(&mut self, bit_len: u8, bits: u64) -> Result<(), DebugProbeError>;
+
+    /// Corresponds to the DAP_SWJ_Pins function from the ARM Debug sequences
     fn swj_pins(
         &mut self,
         pin_out: u32,
         pin_select: u32,
         pin_wait: u32,
-    ) -> Result<u32, DebugProbeError> {
-        self.probe_mut().swj_pins(pin_out, pin_select, pin_wait)
+    ) -> Result<u32, DebugProbeError>;
+
+    /// Clocks out a sequence on SWD in which SWDIO is driven or captured, depending on the phase.
+    ///
+    /// Returns the bits captured in all [`SwdPhase::Input`] phases, in order. This can be used
+    /// to implement custom protocols on the SWD pins. It corresponds to the DAP_SWD_Sequence
+    /// command of CMSIS-DAP.
+    fn swd_sequence(&mut self, _phases: &[SwdPhase]) -> Result<Vec<bool>, DebugProbeError> {
+        Err(DebugProbeError::NotImplemented {
+            function_name: "swd_sequence",
+        })
+    }
+}
+
+/// One phase of a sequence sent with [`SwdSequence::swd_sequence`].
+///
+/// Each phase is 1 to 64 clock cycles long.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwdPhase {
+    /// Drive the lowest `bit_len` bits of `bits` on SWDIO, LSB first.
+    Output {
+        /// The number of clock cycles.
+        bit_len: u8,
+        /// The bits to drive.
+        bits: u64,
+    },
+    /// Release SWDIO, and capture it for `bit_len` clock cycles.
+    Input {
+        /// The number of clock cycles.
+        bit_len: u8,
+    },
+}
+
+impl SwdPhase {
+    /// Returns the number of clock cycles of the phase.
+    pub fn bit_len(&self) -> u8 {
+        match self {
+            SwdPhase::Output { bit_len, .. } | SwdPhase::Input { bit_len } => *bit_len,
+        }
+    }
+}
+
+/// The cached value of the SELECT register(s) of a debug port.
+///
+/// See [`ArmDebugInterface::save_select_state`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectCache {
+    /// SELECT of a DPv0 to DPv2 debug port.
+    DPv1(SelectV1),
+    /// SELECT and SELECT1 of a DPv3 debug port.
+    DPv3(SelectV3, Select1),
+}
+impl SelectCache {
+    /// The selected DP register bank.
+    pub fn dp_bank_sel(&self) -> u8 {
+        match self {
+            SelectCache::DPv1(s) => s.dp_bank_sel(),
+            SelectCache::DPv3(s, _) => s.dp_bank_sel(),
+        }
+    }
+    /// Selects a DP register bank.
+    pub fn set_dp_bank_sel(&mut self, bank: u8) {
+        match self {
+            SelectCache::DPv1(s) => s.set_dp_bank_sel(bank),
+            SelectCache::DPv3(s, _) => s.set_dp_bank_sel(bank),
+        }
+    }
+}
+#[derive(Debug)]
+pub(crate) struct DpState {
+    pub debug_port_version: DebugPortVersion,
+
+    /// The contents of DPIDR, read while connecting.
+    ///
+    /// This is `None` for debug ports which were adopted, instead of connected by this interface.
+    pub(crate) debug_port_id: Option<DebugPortId>,
+
+    pub(crate) current_select: SelectCache,
+
+    /// Set when SELECT was written directly with a value that doesn't match `current_select`,
+    /// which means the cache is out of sync until the state is restored.
+    ///
+    /// This is only tracked in debug builds.
+    pub(crate) select_written_directly: bool,
+
+    /// The TAR auto-increment state of the MEM-APs of this debug port, as far as it is known
+    /// from the register writes issued through this interface.
+    pub(crate) auto_increment: HashMap<ApAddress, AutoIncrementState>,
+
+    /// The access ports discovered through the ROM tables of an ADIv6 debug port,
+    /// including their resolved base addresses.
+    ///
+    /// This is `None` until the access ports have been enumerated.
+    pub(crate) access_ports: Option<BTreeSet<FullyQualifiedApAddress>>,
+
+    /// The values last written to registers without side effects, used to skip writing the
+    /// same value again.
+    pub(crate) last_writes: HashMap<CombinableRegister, u32>,
+
+    /// The access ports which are known to be MEM-APs, because their IDR was read.
+    pub(crate) mem_aps: HashSet<ApAddress>,
+
+    /// The APv2 access ports whose power domain was powered up, or which don't have a power
+    /// domain which can be controlled.
+    pub(crate) powered_aps: HashSet<ApAddress>,
+
+    /// The MEM-APs which were set up for memory accesses, so that opening another memory
+    /// interface for them doesn't need to read their configuration again.
+    pub(crate) opened_memory_aps: HashMap<ApAddress, MemoryAp>,
+}
+
+impl DpState {
+    pub fn new() -> Self {
+        Self {
+            debug_port_version: DebugPortVersion::Unsupported(0xFF),
+            debug_port_id: None,
+            current_select: SelectCache::DPv1(SelectV1(0)),
+            select_written_directly: false,
+            auto_increment: HashMap::new(),
+            access_ports: None,
+            last_writes: HashMap::new(),
+            mem_aps: HashSet::new(),
+            powered_aps: HashSet::new(),
+            opened_memory_aps: HashMap::new(),
+        }
+    }
+}
+
+/// A register which can be written repeatedly without side effects, so that writing the value
+/// it already holds can be skipped.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum CombinableRegister {
+    /// CSW of a MEM-AP.
+    Csw(ApAddress),
+    /// TAR of a MEM-AP. It is forgotten on every DRW access, as these may increment TAR.
+    Tar(ApAddress),
+}
+
+/// TAR is only guaranteed to auto-increment within a block of this size.
+const AUTO_INCREMENT_LIMIT: u64 = 0x400;
+
+/// The default time the target is given to settle after the reset line is released.
+const DEFAULT_RESET_SETTLE_DELAY: Duration = Duration::from_millis(1);
+
+/// Returns whether `address` refers to the register of `ap` at `register_address`.
+///
+/// APv1 registers are only addressed by the lower 8 bits, while APv2 registers are addressed by
+/// their full offset, which includes the 0xD00 of the MEM-AP registers.
+fn is_ap_register(ap: &FullyQualifiedApAddress, address: u64, register_address: u64) -> bool {
+    match ap.ap() {
+        ApAddress::V1(_) => address & 0xFF == register_address & 0xFF,
+        ApAddress::V2(_) => address == register_address,
+    }
+}
+
+/// Returns whether writing `value` to the DP register `address` clears sticky error flags, either
+/// through ABORT, or through the write-one-to-clear bits of CTRL/STAT on JTAG-DPs.
+fn clears_sticky_errors(address: DpRegisterAddress, value: u32) -> bool {
+    /// STICKYORUN, STICKYCMP, STICKYERR and WDATAERR.
+    const CTRL_STICKY_FLAGS: u32 = (1 << 1) | (1 << 4) | (1 << 5) | (1 << 7);
+
+    address == Abort::ADDRESS || (address == Ctrl::ADDRESS && value & CTRL_STICKY_FLAGS != 0)
+}
+
+/// Returns the MEM-AP register at `address` of `ap`, if it influences TAR auto-increment.
+fn auto_increment_register(ap: &FullyQualifiedApAddress, address: u64) -> Option<MemApRegister> {
+    [MemApRegister::Csw, MemApRegister::Tar, MemApRegister::Drw]
+        .into_iter()
+        .find(|register| is_ap_register(ap, address, register.address()))
+}
+
+/// The TAR auto-increment state of a MEM-AP.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct AutoIncrementState {
+    /// By how much TAR is incremented on each DRW access, or `None` if it isn't, or the
+    /// configuration is unknown.
+    increment: Option<u64>,
+    /// The current value of TAR, if known.
+    tar: Option<u64>,
+    /// The last value written to or read from CSW, if any.
+    csw: Option<u32>,
+    /// The last value written to TAR2.
+    tar2: u32,
+}
+
+impl AutoIncrementState {
+    fn update_csw(&mut self, csw: u32) {
+        self.csw = Some(csw);
+        let size = csw & 0x7;
+        let addr_inc = (csw >> 4) & 0x3;
+
+        self.increment = match (addr_inc, size) {
+            // Single increment, by the transfer size
+            (0b01, 0..=2) => Some(1 << size),
+            // Packed increment, by one word
+            (0b10, 0..=2) => Some(4),
+            _ => None,
+        };
     }
+
+    /// Advances TAR by `count` DRW accesses.
+    fn advance(&mut self, count: usize) {
+        if let (Some(increment), Some(tar)) = (self.increment, self.tar) {
+            let tar = tar + count as u64 * increment;
+            // Whether TAR wraps at the end of the block is implementation defined.
+            self.tar = (tar % AUTO_INCREMENT_LIMIT != 0).then_some(tar);
+        }
+    }
+}
+
+/// An implementation of the communication protocol between probe and target.
+/// Can be used to perform all sorts of generic debug access on ARM targets with probes that support low level access.
+/// (E.g. CMSIS-DAP and J-Link support this, ST-Link does not)
+#[derive(Debug)]
+pub struct ArmCommunicationInterface {
+    probe: Option<Box<dyn DapProbe>>,
+
+    /// Currently selected debug port. For targets without multidrop,
+    /// this will always be the single, default debug port in the system.
+    ///
+    /// If this is None, the interface is in an uninitialized state.
+    current_dp: Option<DpAddress>,
+    dps: HashMap<DpAddress, DpState>,
+    use_overrun_detect: bool,
+    connect_timeout: Option<Duration>,
+    sequence: Arc<dyn ArmDebugSequence>,
+    retry_policy: RetryPolicy,
+    transfer_counters: TransferCounters,
+    probe_reacquirer: Option<ProbeReacquirer>,
+    pre_connect_sequence: Vec<(u8, u64)>,
+    verify_writes: bool,
+    combine_writes: bool,
+    lenient_overrun_detect: bool,
+    reset_settle_delay: Duration,
+    /// When the reset line was last released through [`SwdSequence::swj_pins`], if the target
+    /// was not given time to settle since then.
+    reset_released_at: Option<Instant>,
+    /// Set when the wire protocol was selected with [`ArmDebugInterface::set_wire_protocol`],
+    /// which disables falling back to the other protocol.
+    fixed_wire_protocol: bool,
+    /// The wire protocol selected with [`ArmDebugInterface::set_wire_protocol`] while a debug
+    /// port was connected, which is switched to on the next reinitialize.
+    pending_wire_protocol: Option<WireProtocol>,
+    /// Details about the connect in progress, or the last failed one.
+    last_connect_diagnostics: Option<ConnectDiagnostics>,
+    collect_disconnect_errors: bool,
+    /// The DPs which could not be stopped by the last disconnect.
+    last_disconnect_errors: Vec<(DpAddress, ArmError)>,
+    /// Receives a copy of all SWO data, see [`SwoAccess::start_swo_capture`].
+    swo_capture: Option<SwoCapture>,
+    rate_limiter: Option<TokenBucket>,
+    post_connect_validation: Option<PostConnectValidation>,
+    #[cfg(any(test, feature = "test"))]
+    connect_capture: Option<ConnectCapture>,
 }
 
-impl ArmCommunicationInterface {
-    /// Create a new instance of the communication interface,
-    /// which is not yet connected to a debug port.
-    pub fn create(
-        probe: Box<dyn DapProbe>,
-        sequence: Arc<dyn ArmDebugSequence>,
-        use_overrun_detect: bool,
-    ) -> Box<dyn ArmDebugInterface> {
-        let interface = ArmCommunicationInterface {
-            probe: Some(probe),
-            current_dp: None,
-            dps: Default::default(),
-            use_overrun_detect,
-            sequence,
-        };
+impl Drop for ArmCommunicationInterface {
+    fn drop(&mut self) {
+        if self.probe.is_some() {
+            self.disconnect();
+
+            // Ensure we don't disconnect twice
+            self.probe = None;
+        }
+    }
+}
+
+impl ArmCommunicationInterface {
+    pub(crate) fn probe_mut(&mut self) -> &mut dyn DapProbe {
+        // Unwrap: Probe is only taken when the struct is dropped
+        self.probe.as_deref_mut().expect("ArmCommunicationInterface is in an inconsistent state. This is a bug, please report it.")
+    }
+
+    /// Returns whether the power domain of the APv2 access port `ap` was already powered up since
+    /// its DP was selected.
+    pub(crate) fn is_ap_domain_powered(&self, ap: &FullyQualifiedApAddress) -> bool {
+        self.dps
+            .get(&ap.dp())
+            .is_some_and(|state| state.powered_aps.contains(ap.ap()))
+    }
+
+    /// Remembers that the power domain of the APv2 access port `ap` is powered up.
+    pub(crate) fn set_ap_domain_powered(&mut self, ap: &FullyQualifiedApAddress) {
+        if let Some(state) = self.dps.get_mut(&ap.dp()) {
+            state.powered_aps.insert(ap.ap().clone());
+        }
+    }
+
+    /// Selects `protocol` on the probe, and disables falling back to the other protocol.
+    fn select_wire_protocol(&mut self, protocol: WireProtocol) -> Result<(), ArmError> {
+        self.probe_mut()
+            .select_protocol(protocol)
+            .map_err(|error| match error {
+                DebugProbeError::UnsupportedProtocol(protocol) => {
+                    ArmError::ProtocolNotSupported(protocol)
+                }
+                other => other.into(),
+            })?;
+        self.fixed_wire_protocol = true;
+
+        Ok(())
+    }
+
+    /// Returns the MEM-AP `ap`, set up for memory accesses.
+    ///
+    /// The access port is only set up the first time. Afterwards, a copy of its state is
+    /// returned, with the CSW value last written through this interface.
+    pub(crate) fn memory_ap(&mut self, ap: &FullyQualifiedApAddress) -> Result<MemoryAp, ArmError> {
+        let cached = self
+            .dps
+            .get(&ap.dp())
+            .and_then(|state| state.opened_memory_aps.get(ap.ap()))
+            .cloned();
+
+        let memory_ap = match cached {
+            Some(mut memory_ap) => {
+                match self.auto_increment_state(ap).and_then(|state| state.csw) {
+                    Some(csw) => memory_ap.set_cached_status(csw)?,
+                    None => {
+                        memory_ap.status(self)?;
+                    }
+                }
+                memory_ap
+            }
+            None => MemoryAp::new(self, ap)?,
+        };
+
+        if let Some(state) = self.dps.get_mut(&ap.dp()) {
+            state
+                .opened_memory_aps
+                .insert(ap.ap().clone(), memory_ap.clone());
+        }
+
+        Ok(memory_ap)
+    }
+
+    fn close(mut self) -> Probe {
+        self.disconnect();
+
+        let probe = self.probe.take().unwrap();
+
+        Probe::from_attached_probe(RawDapAccess::into_probe(probe))
+    }
+
+    /// Replaces the probe with a freshly opened one, configured like the old one.
+    ///
+    /// All debug port state is discarded, so the debug ports are set up again on the next access.
+    fn reacquire_probe(&mut self) -> Result<(), ArmError> {
+        let Some(reacquirer) = self.probe_reacquirer.clone() else {
+            return Err(ArmError::ProbeDisconnected);
+        };
+
+        let old_probe = self.probe_mut();
+        let protocol = old_probe.active_protocol();
+        let speed_khz = old_probe.speed_khz();
+
+        let reopen = || {
+            let mut probe = (reacquirer.0)()?;
+            if let Some(protocol) = protocol {
+                probe.select_protocol(protocol)?;
+            }
+            probe.set_speed(speed_khz)?;
+            probe.attach()?;
+
+            Ok::<_, DebugProbeError>(probe)
+        };
+
+        let probe = reopen().map_err(|error| {
+            tracing::warn!("Failed to re-acquire the probe: {}", error);
+            ArmError::ProbeDisconnected
+        })?;
+
+        self.probe = Some(probe);
+        self.current_dp = None;
+        self.dps.clear();
+
+        Ok(())
+    }
+
+    /// Disconnect from all debug ports, by calling `debug_port_stop` on all DPs which we
+    /// are connected to.
+    ///
+    /// Errors are only logged, and kept for [`ArmDebugInterface::take_disconnect_errors`]
+    /// if enabled.
+    fn disconnect(&mut self) {
+        let (errors, flushed) = self.stop_each_debug_port();
+
+        for (dp, error) in &errors {
+            tracing::debug!("Error while disconnecting from DP {:x?}: {}", dp, error);
+        }
+        if let Err(error) = flushed {
+            tracing::debug!("Error while disconnecting: {}", error);
+        }
+
+        if self.collect_disconnect_errors {
+            self.last_disconnect_errors = errors;
+        }
+    }
+
+    /// Calls `debug_port_stop` on all DPs which we are connected to.
+    ///
+    /// All DPs are stopped, even if stopping one of them fails. The first error is returned.
+    fn stop_debug_ports(&mut self) -> Result<(), ArmError> {
+        let (errors, flushed) = self.stop_each_debug_port();
+
+        match errors.into_iter().next() {
+            Some((_, error)) => Err(error),
+            None => flushed,
+        }
+    }
+
+    /// Calls `debug_port_stop` on all DPs which we are connected to, and flushes the probe.
+    ///
+    /// Returns the errors of the DPs which could not be stopped, and the result of the flush.
+    fn stop_each_debug_port(&mut self) -> (Vec<(DpAddress, ArmError)>, Result<(), ArmError>) {
+        // Stopping powers down the DPs, which resets their registers.
+        self.invalidate_write_cache();
+
+        let probe = self.probe.as_deref_mut().unwrap();
+        let mut errors = Vec::new();
+
+        if let Some(current_dp) = self.current_dp.take() {
+            let _stop_span = tracing::debug_span!("debug_port_stop").entered();
+
+            // Stop the current DP, which may not be one of the known ones (i.e. RP2040 rescue DP).
+            if let Err(error) = self.sequence.debug_port_stop(probe, current_dp) {
+                errors.push((current_dp, error));
+            }
+
+            drop(_stop_span);
+
+            // Stop all intentionally-connected DPs.
+            for dp in self.dps.keys().filter(|dp| **dp != current_dp) {
+                // Try to select the debug port we want to shut down.
+                let stopped = self
+                    .sequence
+                    .debug_port_connect(probe, *dp)
+                    .and_then(|()| self.sequence.debug_port_stop(probe, *dp));
+
+                if let Err(error) = stopped {
+                    tracing::warn!("Failed to stop DP {:x?}: {}", dp, error);
+                    errors.push((*dp, error));
+                }
+            }
+        };
+
+        let flushed = probe.raw_flush();
+
+        (errors, flushed)
+    }
+}
+
+/// Returns the JTAG interface of the probe behind `interface`, after flushing queued transfers.
+fn jtag_access<T: ArmDebugInterface + ?Sized>(
+    interface: &mut T,
+) -> Result<&mut dyn JtagAccess, ArmError> {
+    let probe = interface
+        .try_dap_probe_mut()
+        .ok_or(DebugProbeError::InterfaceNotAvailable {
+            interface_name: "JTAG",
+        })?;
+    probe.raw_flush()?;
+
+    probe
+        .try_as_jtag_probe()
+        .ok_or(ArmError::Probe(DebugProbeError::InterfaceNotAvailable {
+            interface_name: "JTAG",
+        }))
+}
+
+/// Helper trait for probes which offer access to ARM DAP (Debug Access Port).
+///
+/// This is used to combine the traits, because it cannot be done in the ArmCommunicationInterface
+/// struct itself.
+pub trait DapProbe: RawDapAccess + DebugProbe {}
+
+impl ArmDebugInterface for ArmCommunicationInterface {
+    fn reinitialize(&mut self) -> Result<(), ArmError> {
+        let current_dp = self.current_dp;
+
+        // Simulate the drop / close of the initialized communication interface.
+        self.disconnect();
+
+        // This should be set to None by the disconnect call above.
+        assert!(self.current_dp.is_none());
+
+        // The access ports may be powered or configured differently after reconnecting.
+        for state in self.dps.values_mut() {
+            state.access_ports = None;
+            state.opened_memory_aps.clear();
+        }
+
+        // Reconnect using the previous protocol if the new one can't be selected.
+        let selected = match self.pending_wire_protocol.take() {
+            Some(protocol) => self.select_wire_protocol(protocol),
+            None => Ok(()),
+        };
+
+        // Reconnect to the DP again
+        if let Some(dp) = current_dp {
+            let result = self.select_dp(dp).map(|_| ());
+
+            match result {
+                Err(error) if is_probe_disconnect(&error) => {
+                    tracing::warn!(
+                        "Lost the connection to the probe, re-acquiring it: {}",
+                        error
+                    );
+                    self.reacquire_probe()?;
+                    self.select_dp(dp)?;
+                }
+                result => result?,
+            }
+
+            self.resync_select(dp)?;
+        }
+
+        selected
+    }
+
+    fn memory_interface(
+        &mut self,
+        access_port_address: &FullyQualifiedApAddress,
+    ) -> Result<Box<dyn ArmMemoryInterface + '_>, ArmError> {
+        let memory_interface = match access_port_address.ap() {
+            ApAddress::V1(_) => {
+                let memory_ap = self.memory_ap(access_port_address)?;
+                Box::new(ADIMemoryInterface::with_memory_ap(self, memory_ap))
+                    as Box<dyn ArmMemoryInterface + '_>
+            }
+            ApAddress::V2(_) => ap::v2::new_memory_interface(self, access_port_address)?,
+        };
+        Ok(memory_interface)
+    }
+
+    fn read_word_32(
+        &mut self,
+        access_port: &FullyQualifiedApAddress,
+        address: u64,
+    ) -> Result<u32, ArmError> {
+        if address % 4 != 0 {
+            return Err(ArmError::alignment_error(address, 4));
+        }
+
+        // CSW is only written if it is not set up for 32-bit transfers already, and is left
+        // like that.
+        let known_csw = self
+            .auto_increment_state(access_port)
+            .and_then(|state| state.csw);
+        let csw = match known_csw {
+            Some(csw) => csw,
+            None => {
+                let csw = self.read_mem_ap_register(access_port, MemApRegister::Csw)?;
+                if let Some(state) = self.auto_increment_state(access_port) {
+                    state.update_csw(csw);
+                }
+                csw
+            }
+        };
+        if csw & CSW_SIZE_MASK != CSW_SIZE_32BIT {
+            self.write_mem_ap_register(
+                access_port,
+                MemApRegister::Csw,
+                (csw & !CSW_SIZE_MASK) | CSW_SIZE_32BIT,
+            )?;
+        }
+
+        // TAR2 is assumed to be zero, unless a non-zero value was written through this interface.
+        let tar2_set = self
+            .auto_increment_state(access_port)
+            .is_some_and(|state| state.tar2 != 0);
+        if address > u64::from(u32::MAX) || tar2_set {
+            self.write_mem_ap_tar64(access_port, address)?;
+        } else {
+            self.write_mem_ap_register(access_port, MemApRegister::Tar, address as u32)?;
+        }
+        self.read_mem_ap_register(access_port, MemApRegister::Drw)
+    }
+
+    fn memory_interface_with_csw(
+        &mut self,
+        access_port_address: &FullyQualifiedApAddress,
+        csw: ap::CSW,
+    ) -> Result<Box<dyn ArmMemoryInterface + '_>, ArmError> {
+        if let ApAddress::V2(ApV2Address(None)) = access_port_address.ap() {
+            // The root memory interface is accessed through the DP and has no CSW.
+            return Err(ArmError::WrongApType);
+        }
+
+        Ok(Box::new(ADIMemoryInterface::new_with_csw(
+            self,
+            access_port_address,
+            csw,
+        )?))
+    }
+
+    fn current_debug_port(&self) -> Option<DpAddress> {
+        self.current_dp
+    }
+
+    fn known_debug_ports(&self) -> Vec<DpAddress> {
+        let mut dps: Vec<_> = self.dps.keys().copied().collect();
+        dps.sort();
+        dps
+    }
+
+    fn debug_port_version(&self, dp: DpAddress) -> Option<DebugPortVersion> {
+        self.dps.get(&dp).map(|state| state.debug_port_version)
+    }
+
+    fn debug_port_id(&self, dp: DpAddress) -> Option<DebugPortId> {
+        self.dps.get(&dp)?.debug_port_id.clone()
+    }
+
+    fn read_resend(&mut self, dp: DpAddress) -> Result<u32, ArmError> {
+        self.select_dp(dp)?;
+
+        if !self.supports_resend(dp) {
+            return Err(DebugPortError::Unsupported(
+                "RESEND is only available on SW-DPs".to_string(),
+            )
+            .into());
+        }
+
+        self.retry_transfer(dp, |this| {
+            this.probe_mut()
+                .raw_read_register(RegisterAddress::DpRegister(Resend::ADDRESS))
+        })
+    }
+
+    fn close(self: Box<Self>) -> Probe {
+        ArmCommunicationInterface::close(*self)
+    }
+
+    fn access_ports(
+        &mut self,
+        dp: DpAddress,
+    ) -> Result<BTreeSet<FullyQualifiedApAddress>, ArmError> {
+        let state = self.select_dp(dp)?;
+        let debug_port_version = state.debug_port_version;
+        let known_access_ports = state.access_ports.clone();
+
+        match debug_port_version {
+            DebugPortVersion::DPv0 | DebugPortVersion::DPv1 | DebugPortVersion::DPv2 => {
+                Ok(ap::v1::valid_access_ports(self, dp).into_iter().collect())
+            }
+            DebugPortVersion::DPv3 => {
+                // The base addresses of APv2 access ports are only known after walking the
+                // ROM tables, so the result is kept to avoid re-enumerating the DP.
+                if let Some(access_ports) = known_access_ports {
+                    return Ok(access_ports);
+                }
+
+                let access_ports = ap::v2::enumerate_access_ports(self, dp)?;
+
+                let state = self
+                    .dps
+                    .get_mut(&dp)
+                    .expect("This DP State was inserted by select_dp");
+                state.access_ports = Some(access_ports.clone());
+
+                Ok(access_ports)
+            }
+            DebugPortVersion::Unsupported(_) => unreachable!(),
+        }
+    }
+
+    fn select_debug_port(&mut self, dp: DpAddress) -> Result<(), ArmError> {
+        let _ = self.select_dp(dp)?;
+        Ok(())
+    }
+
+    fn access_ports_bounded(
+        &mut self,
+        dp: DpAddress,
+        max_apsel: u8,
+        stop_after_gaps: usize,
+    ) -> Result<BTreeSet<FullyQualifiedApAddress>, ArmError> {
+        let state = self.select_dp(dp)?;
+
+        if state.debug_port_version == DebugPortVersion::DPv3 {
+            return self.access_ports(dp);
+        }
+
+        Ok(
+            ap::v1::valid_access_ports_bounded(self, dp, max_apsel, stop_after_gaps)
+                .into_iter()
+                .collect(),
+        )
+    }
+
+    fn resync(&mut self, dp: DpAddress) -> Result<(), ArmError> {
+        if self.current_dp != Some(dp) {
+            // There's no connection to preserve.
+            return self.select_debug_port(dp);
+        }
+
+        tracing::debug!("Resynchronizing DP {dp:x?}");
+
+        if let Err(error) = self.probe_mut().raw_flush() {
+            tracing::debug!("Discarding pending transfers failed: {error}");
+        }
+
+        let sequence = self.sequence.clone();
+        sequence.debug_port_connect(&mut *self.probe_mut(), dp)?;
+
+        // Forget the cached state of the DP, so that `debug_port_start` runs again and the
+        // SELECT cache is rebuilt.
+        self.dps.remove(&dp);
+        self.select_debug_port(dp)
+    }
+
+    fn disconnect_gracefully(&mut self) -> Result<(), ArmError> {
+        self.stop_debug_ports()
+    }
+
+    fn save_select_state(&self, dp: DpAddress) -> Option<SelectCache> {
+        self.dps.get(&dp).map(|state| state.current_select)
+    }
+
+    fn probe_info(&self) -> ProbeInfo {
+        self.probe
+            .as_deref()
+            .map(|probe| probe.probe_info())
+            .unwrap_or_default()
+    }
+
+    fn sequence_name(&self) -> &str {
+        self.sequence.name()
+    }
+
+    fn set_idle_cycles(&mut self, cycles: u8) -> Result<(), ArmError> {
+        self.probe_mut().set_swd_idle_cycles(cycles)
+    }
+
+    fn check_debug_lock(&mut self, dp: DpAddress) -> Result<DebugLockStatus, ArmError> {
+        let sequence = self.sequence.clone();
+
+        Ok(match sequence.is_debug_locked(self, dp)? {
+            Some(true) => DebugLockStatus::Locked,
+            Some(false) => DebugLockStatus::Unlocked,
+            None => DebugLockStatus::Unknown,
+        })
+    }
+
+    fn last_connect_diagnostics(&self) -> Option<ConnectDiagnostics> {
+        self.last_connect_diagnostics.clone()
+    }
+
+    fn transfer_stats(&self) -> Result<TransferStats, ArmError> {
+        Ok(self.transfer_counters.snapshot())
+    }
+
+    fn reset_transfer_stats(&mut self) -> Result<(), ArmError> {
+        self.transfer_counters = TransferCounters::default();
+        Ok(())
+    }
+
+    fn take_disconnect_errors(&mut self) -> Result<Vec<(DpAddress, ArmError)>, ArmError> {
+        Ok(std::mem::take(&mut self.last_disconnect_errors))
+    }
+
+    fn invalidate_write_cache(&mut self) {
+        for state in self.dps.values_mut() {
+            state.last_writes.clear();
+            state.opened_memory_aps.clear();
+        }
+    }
+
+    fn read_target_uid(
+        &mut self,
+        ap: &FullyQualifiedApAddress,
+    ) -> Result<Option<Vec<u8>>, ArmError> {
+        let sequence = self.sequence.clone();
+        let mut memory = self.memory_interface(ap)?;
+        sequence.read_unique_id(&mut *memory)
+    }
+
+    fn set_wire_protocol(&mut self, protocol: WireProtocol) -> Result<(), ArmError> {
+        if self.current_dp.is_none() {
+            return self.select_wire_protocol(protocol);
+        }
+
+        if !self.probe_mut().supports_protocol(protocol) {
+            return Err(ArmError::ProtocolNotSupported(protocol));
+        }
+
+        if self.probe_mut().active_protocol() == Some(protocol) {
+            self.pending_wire_protocol = None;
+            self.fixed_wire_protocol = true;
+            return Ok(());
+        }
+
+        // The debug ports have to be stopped using the protocol they were set up with.
+        self.pending_wire_protocol = Some(protocol);
+        Err(ArmError::ReinitializeRequired(protocol))
+    }
+
+    fn restore_select_state(&mut self, dp: DpAddress, state: SelectCache) -> Result<(), ArmError> {
+        // Update the cache first, so that the writes below match it.
+        let dp_state = self.select_dp(dp)?;
+        dp_state.current_select = state;
+        dp_state.select_written_directly = false;
+
+        match state {
+            SelectCache::DPv1(select) => self.write_dp_register(dp, select)?,
+            SelectCache::DPv3(select, select1) => {
+                self.write_dp_register(dp, select)?;
+                self.write_dp_register(dp, select1)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn resync_select(&mut self, dp: DpAddress) -> Result<(), ArmError> {
+        let dp_state = self.select_dp(dp)?;
+        let cached = dp_state.current_select;
+
+        // SELECT is write-only on SW-DPs, and on all DPv3 debug ports.
+        let readable = dp_state.debug_port_version != DebugPortVersion::DPv3
+            && self.probe_mut().active_protocol() == Some(WireProtocol::Jtag);
+
+        if !readable {
+            tracing::debug!("SELECT of {dp:x?} is write-only, writing the cached value");
+            return self.restore_select_state(dp, cached);
+        }
+
+        let select: SelectV1 = self.read_dp_register(dp)?;
+        tracing::debug!("Read SELECT of {dp:x?}: {select:x?}");
+
+        let dp_state = self.select_dp(dp)?;
+        dp_state.current_select = SelectCache::DPv1(select);
+        dp_state.select_written_directly = false;
+
+        Ok(())
+    }
+}
+
+impl SwdSequence for ArmCommunicationInterface {
+    fn swj_sequence(&mut self, bit_len: u8, bits: u64) -> Result<(), DebugProbeError> {
+        self.probe_mut().swj_sequence(bit_len, bits)?;
+
+        Ok(())
+    }
+
+    fn swd_sequence(&mut self, phases: &[SwdPhase]) -> Result<Vec<bool>, DebugProbeError> {
+        self.probe_mut().swd_sequence(phases)
+    }
+
+    fn swj_pins(
+        &mut self,
+        pin_out: u32,
+        pin_select: u32,
+        pin_wait: u32,
+    ) -> Result<u32, DebugProbeError> {
+        let mut n_reset = Pins(0);
+        n_reset.set_nreset(true);
+        let n_reset = u32::from(n_reset.0);
+
+        let pins = self.probe_mut().swj_pins(pin_out, pin_select, pin_wait)?;
+        if pin_select & n_reset != 0 && pin_out & n_reset != 0 {
+            self.reset_released_at = Some(Instant::now());
+        }
+
+        Ok(pins)
+    }
+}
+
+/// Builder for an [`ArmCommunicationInterface`].
+///
+/// ```no_run
+/// # use probe_rs::architecture::arm::{ArmCommunicationInterfaceBuilder, DapProbe, RetryPolicy};
+/// # fn build(probe: Box<dyn DapProbe>) {
+/// let interface = ArmCommunicationInterfaceBuilder::new()
+///     .overrun_detect(true)
+///     .retry_policy(RetryPolicy {
+///         max_retries: 5,
+///         ..Default::default()
+///     })
+///     .build(probe);
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ArmCommunicationInterfaceBuilder {
+    sequence: Arc<dyn ArmDebugSequence>,
+    use_overrun_detect: bool,
+    connect_timeout: Option<Duration>,
+    retry_policy: RetryPolicy,
+    probe_reacquirer: Option<ProbeReacquirer>,
+    transfer_recorder: Option<TransferRecorder>,
+    pre_connect_sequence: Vec<(u8, u64)>,
+    verify_writes: bool,
+    combine_writes: bool,
+    lenient_overrun_detect: bool,
+    reset_settle_delay: Duration,
+    keep_alive: Option<Duration>,
+    rate_limit: Option<RateLimit>,
+    collect_disconnect_errors: bool,
+    post_connect_validation: Option<PostConnectValidation>,
+    #[cfg(any(test, feature = "test"))]
+    connect_capture: Option<ConnectCapture>,
+}
+
+/// Opens a debug probe again after it was disconnected.
+#[derive(Clone)]
+struct ProbeReacquirer(Arc<dyn Fn() -> Result<Box<dyn DapProbe>, DebugProbeError> + Send + Sync>);
+
+impl Debug for ProbeReacquirer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProbeReacquirer").finish_non_exhaustive()
+    }
+}
+
+/// A check run against a memory AP, see [`PostConnectValidation`].
+type ValidateFn = dyn Fn(&mut dyn ArmMemoryInterface) -> Result<(), ArmError> + Send + Sync;
+
+/// Checks the target after connecting to a debug port, see
+/// [`ArmCommunicationInterfaceBuilder::post_connect_validate`].
+#[derive(Clone)]
+struct PostConnectValidation {
+    ap: FullyQualifiedApAddress,
+    validate: Arc<ValidateFn>,
+}
+
+impl Debug for PostConnectValidation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PostConnectValidation")
+            .field("ap", &self.ap)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Turns an error while connecting to `dp` into [`ArmError::NoDebugPort`] if the debug port
+/// did not respond at all.
+///
+/// Other errors, e.g. FAULT responses or a failing probe, are returned unchanged.
+fn no_debug_port(dp: DpAddress, error: ArmError) -> ArmError {
+    match error {
+        ArmError::Dap(DapError::NoAcknowledge | DapError::Protocol(_)) => ArmError::NoDebugPort {
+            dp,
+            source: Box::new(error),
+        },
+        other => other,
+    }
+}
+
+/// Returns `true` if `error` means that the probe itself is gone, e.g. because the USB cable was unplugged.
+fn is_probe_disconnect(error: &ArmError) -> bool {
+    matches!(
+        error,
+        ArmError::Probe(DebugProbeError::Usb(_) | DebugProbeError::ProbeCouldNotBeCreated(_))
+    )
+}
+
+/// Enumerates the access ports of `dp` for [`ArmDebugInterface::full_topology`].
+///
+/// Only an error caused by losing the probe is returned, any other error is part of the
+/// returned [`DpTopology`].
+fn scan_debug_port<I: ArmDebugInterface + ?Sized>(
+    interface: &mut I,
+    dp: DpAddress,
+) -> Result<DpTopology, ArmError> {
+    let access_ports = match interface.access_ports_detailed(dp) {
+        Err(error) if is_probe_disconnect(&error) => return Err(error),
+        Err(error) => {
+            tracing::debug!("Failed to scan the access ports of {dp:x?}: {error}");
+            Err(error)
+        }
+        access_ports => access_ports,
+    };
+
+    Ok(DpTopology { dp, access_ports })
+}
+
+impl Default for ArmCommunicationInterfaceBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ArmCommunicationInterfaceBuilder {
+    /// Creates a new builder, using the default ARM debug sequence and
+    /// overrun detection disabled.
+    pub fn new() -> Self {
+        Self {
+            sequence: DefaultArmSequence::create(),
+            use_overrun_detect: false,
+            connect_timeout: None,
+            retry_policy: RetryPolicy::default(),
+            probe_reacquirer: None,
+            transfer_recorder: None,
+            pre_connect_sequence: Vec::new(),
+            verify_writes: false,
+            combine_writes: false,
+            lenient_overrun_detect: false,
+            reset_settle_delay: DEFAULT_RESET_SETTLE_DELAY,
+            keep_alive: None,
+            rate_limit: None,
+            collect_disconnect_errors: false,
+            post_connect_validation: None,
+            #[cfg(any(test, feature = "test"))]
+            connect_capture: None,
+        }
+    }
+
+    /// Sets the debug sequence used to connect to and disconnect from debug ports.
+    pub fn sequence(mut self, sequence: Arc<dyn ArmDebugSequence>) -> Self {
+        self.sequence = sequence;
+        self
+    }
+
+    /// Enables or disables the overrun detection mode of the debug port.
+    ///
+    /// "Bit-banging" probes, such as J-Link or FTDI, rely on it for stable communication.
+    pub fn overrun_detect(mut self, enable: bool) -> Self {
+        self.use_overrun_detect = enable;
+        self
+    }
+
+    /// Continues connecting if the debug port answers with a FAULT while overrun detection is
+    /// configured, instead of failing the connect.
+    ///
+    /// Some minimal debug ports don't implement configuring overrun detection in CTRL/STAT. By
+    /// default, such a FAULT is returned as an error.
+    pub fn lenient_overrun_detect(mut self, enable: bool) -> Self {
+        self.lenient_overrun_detect = enable;
+        self
+    }
+
+    /// Keeps retrying to set up a debug port until the given timeout expires.
+    ///
+    /// By default, setting up the debug port is only attempted once.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets how long the target is given to settle after the reset line is released, before the
+    /// debug port is accessed again.
+    ///
+    /// Boards with slow power supplies or external oscillators may need tens of milliseconds
+    /// before the debug port responds. By default, 1 ms is used.
+    ///
+    /// The delay applies when the reset line is released through [`SwdSequence::swj_pins`],
+    /// e.g. by [`ArmDebugSequence::reset_hardware_deassert`] when connecting under reset.
+    pub fn reset_settle_delay(mut self, delay: Duration) -> Self {
+        self.reset_settle_delay = delay;
+        self
+    }
+
+    /// Caps the throughput of register transfers.
+    ///
+    /// Some bus-powered boards brown out while flashing at full speed. With a rate limit, short
+    /// sleeps are inserted before transfers once the limit is exceeded. By default, the
+    /// throughput is not limited.
+    pub fn rate_limit(mut self, limit: RateLimit) -> Self {
+        self.rate_limit = Some(limit);
+        self
+    }
+
+    /// Keeps the errors of DPs which could not be stopped while disconnecting, see
+    /// [`ArmDebugInterface::take_disconnect_errors`].
+    ///
+    /// By default, these errors are only logged.
+    pub fn collect_disconnect_errors(mut self, enable: bool) -> Self {
+        self.collect_disconnect_errors = enable;
+        self
+    }
+
+    /// Sets the [`RetryPolicy`] used for WAIT responses.
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Sets a function which opens the probe again, e.g. by its serial number.
+    ///
+    /// If the probe is disconnected briefly, [`ArmDebugInterface::reinitialize`] uses it to
+    /// re-acquire the probe, and connects to the target again.
+    pub fn reacquire_probe(
+        mut self,
+        reacquire: impl Fn() -> Result<Box<dyn DapProbe>, DebugProbeError> + Send + Sync + 'static,
+    ) -> Self {
+        self.probe_reacquirer = Some(ProbeReacquirer(Arc::new(reacquire)));
+        self
+    }
+
+    /// Sets a function which checks the target whenever the debug port of `ap` is connected,
+    /// e.g. by comparing a memory location against a known value to detect a wrong target.
+    ///
+    /// The function is called with a memory interface for `ap`, after the `debug_port_start`
+    /// sequence ran. If it returns an error, connecting fails with this error, and the next
+    /// access connects to the debug port again.
+    pub fn post_connect_validate(
+        mut self,
+        ap: FullyQualifiedApAddress,
+        validate: impl Fn(&mut dyn ArmMemoryInterface) -> Result<(), ArmError> + Send + Sync + 'static,
+    ) -> Self {
+        self.post_connect_validation = Some(PostConnectValidation {
+            ap,
+            validate: Arc::new(validate),
+        });
+        self
+    }
+
+    /// Sets SWJ sequences which are sent before the `debug_port_setup` sequence, as
+    /// `(bit_len, bits)` pairs.
+    ///
+    /// This can be used for boards which need a custom preamble, e.g. to switch an SWD mux,
+    /// before the standard JTAG-to-SWD switch sequence.
+    pub fn pre_connect_sequence(mut self, sequence: impl IntoIterator<Item = (u8, u64)>) -> Self {
+        self.pre_connect_sequence = sequence.into_iter().collect();
+        self
+    }
+
+    /// Enables or disables verification of access port register writes.
+    ///
+    /// If enabled, every register written with [`DapAccess::write_raw_ap_register`] is read back,
+    /// and [`ArmError::WriteVerifyMismatch`] is returned if the value differs. This doubles the
+    /// number of transfers, but helps to diagnose signal integrity issues which only affect writes.
+    ///
+    /// Only TAR, and the Size and AddrInc fields of CSW of MEM-APs are verified, as other
+    /// registers can't be read back without side effects, or contain read-only bits.
+    pub fn verify_writes(mut self, enable: bool) -> Self {
+        self.verify_writes = enable;
+        self
+    }
+
+    /// Enables or disables skipping register writes which would not change the register.
+    ///
+    /// If enabled, a write to CSW or TAR of a MEM-AP is skipped if the register was last written
+    /// with the same value through this interface. Registers with side effects, like CTRL/STAT,
+    /// ABORT or DRW, are always written. The written values are forgotten when sticky errors
+    /// are cleared, and when the target is reset or the connection is reinitialized. This saves transfers
+    /// in debug sequences which repeat idempotent setup writes. The number of skipped writes is
+    /// reported in [`TransferStats::combined_writes`].
+    ///
+    /// The interface can't know about registers changed by the target, or by accessing the
+    /// probe directly. Use [`ArmDebugInterface::invalidate_write_cache`] afterwards.
+    pub fn combine_writes(mut self, enable: bool) -> Self {
+        self.combine_writes = enable;
+        self
+    }
+
+    /// Reads DPIDR whenever the interface was idle for `interval`, to keep the probe from being
+    /// suspended by the host.
+    ///
+    /// This only applies to interfaces created with
+    /// [`ArmCommunicationInterfaceBuilder::build_shared`], see
+    /// [`SharedArmInterface::start_keep_alive`].
+    pub fn keep_alive(mut self, interval: Duration) -> Self {
+        self.keep_alive = Some(interval);
+        self
+    }
+
+    /// Records all transfers of the probe with the given [`TransferRecorder`].
+    ///
+    /// The recorded log can be replayed with a
+    /// [`TransferReplayer`](crate::probe::transfer_log::TransferReplayer).
+    pub fn record_transfers(mut self, recorder: TransferRecorder) -> Self {
+        self.transfer_recorder = Some(recorder);
+        self
+    }
+
+    /// Captures all transfers used to connect to a debug port with the given [`ConnectCapture`].
+    ///
+    /// This is meant for regression tests of debug sequences, which compare the captured
+    /// transfers against a trace recorded earlier.
+    #[cfg(any(test, feature = "test"))]
+    pub fn capture_connect(mut self, capture: ConnectCapture) -> Self {
+        self.connect_capture = Some(capture);
+        self
+    }
+
+    /// Creates the communication interface, which is not yet connected to a debug port.
+    ///
+    /// [`ArmCommunicationInterfaceBuilder::keep_alive`] is ignored, as only a
+    /// [`SharedArmInterface`] can run the keep-alive. Use
+    /// [`ArmCommunicationInterfaceBuilder::build_shared`] instead.
+    pub fn build(self, probe: Box<dyn DapProbe>) -> Box<dyn ArmDebugInterface> {
+        if self.keep_alive.is_some() {
+            tracing::warn!("The keep-alive is only started for interfaces created by build_shared");
+        }
+
+        Box::new(self.build_interface(probe))
+    }
+
+    /// Creates the communication interface, which is not yet connected to a debug port, as a
+    /// [`SharedArmInterface`].
+    ///
+    /// If [`ArmCommunicationInterfaceBuilder::keep_alive`] was set, the keep-alive is started.
+    pub fn build_shared(self, probe: Box<dyn DapProbe>) -> SharedArmInterface {
+        let keep_alive = self.keep_alive;
+        let mut shared = SharedArmInterface::new(Box::new(self.build_interface(probe)));
+
+        if let Some(interval) = keep_alive {
+            shared.start_keep_alive(interval);
+        }
+
+        shared
+    }
+
+    /// Creates the communication interface for the debug port `dp`, which was already connected
+    /// by someone else, e.g. a previous session which handed over the probe.
+    ///
+    /// The `debug_port_setup` and `debug_port_start` sequences are not run for `dp`, so it has
+    /// to be powered up already. Only SELECT is written, as its current value is not known.
+    ///
+    /// If writing SELECT fails, the probe is returned together with the error.
+    pub fn adopt_connected(
+        self,
+        probe: Box<dyn DapProbe>,
+        dp: DpAddress,
+        debug_port_version: DebugPortVersion,
+    ) -> Result<Box<dyn ArmDebugInterface>, (Box<dyn DapProbe>, ArmError)> {
+        let mut interface = self.build_interface(probe);
+
+        let mut state = DpState::new();
+        state.debug_port_version = debug_port_version;
+        if debug_port_version == DebugPortVersion::DPv3 {
+            state.current_select = SelectCache::DPv3(SelectV3(0), Select1(0));
+        }
+        let select = state.current_select;
+
+        interface.dps.insert(dp, state);
+        interface.current_dp = Some(dp);
+
+        if let Err(error) = interface.restore_select_state(dp, select) {
+            // Don't stop the debug port when the interface is dropped, it was never ours.
+            let probe = interface.probe.take().unwrap();
+            return Err((probe, error));
+        }
+
+        Ok(Box::new(interface))
+    }
+
+    fn build_interface(self, mut probe: Box<dyn DapProbe>) -> ArmCommunicationInterface {
+        let mut probe_reacquirer = self.probe_reacquirer;
+
+        if let Some(recorder) = self.transfer_recorder {
+            probe = recorder.wrap(probe);
+
+            // Keep recording if the probe has to be re-acquired.
+            probe_reacquirer = probe_reacquirer.map(|reacquirer| {
+                ProbeReacquirer(Arc::new(move || {
+                    (reacquirer.0)().map(|probe| recorder.wrap(probe))
+                }))
+            });
+        }
+
+        #[cfg(any(test, feature = "test"))]
+        if let Some(capture) = self.connect_capture.clone() {
+            probe = capture.wrap(probe);
+
+            probe_reacquirer = probe_reacquirer.map(|reacquirer| {
+                ProbeReacquirer(Arc::new(move || {
+                    (reacquirer.0)().map(|probe| capture.wrap(probe))
+                }))
+            });
+        }
+
+        ArmCommunicationInterface {
+            probe: Some(probe),
+            current_dp: None,
+            dps: Default::default(),
+            use_overrun_detect: self.use_overrun_detect,
+            connect_timeout: self.connect_timeout,
+            sequence: self.sequence,
+            retry_policy: self.retry_policy,
+            transfer_counters: TransferCounters::default(),
+            probe_reacquirer,
+            pre_connect_sequence: self.pre_connect_sequence,
+            verify_writes: self.verify_writes,
+            combine_writes: self.combine_writes,
+            lenient_overrun_detect: self.lenient_overrun_detect,
+            reset_settle_delay: self.reset_settle_delay,
+            reset_released_at: None,
+            fixed_wire_protocol: false,
+            pending_wire_protocol: None,
+            last_connect_diagnostics: None,
+            collect_disconnect_errors: self.collect_disconnect_errors,
+            last_disconnect_errors: Vec::new(),
+            swo_capture: None,
+            rate_limiter: self.rate_limit.map(TokenBucket::new),
+            post_connect_validation: self.post_connect_validation,
+            #[cfg(any(test, feature = "test"))]
+            connect_capture: self.connect_capture,
+        }
+    }
+}
+
+impl ArmCommunicationInterface {
+    /// Create a new instance of the communication interface,
+    /// which is not yet connected to a debug port.
+    #[deprecated = "Use `ArmCommunicationInterfaceBuilder` instead"]
+    pub fn create(
+        probe: Box<dyn DapProbe>,
+        sequence: Arc<dyn ArmDebugSequence>,
+        use_overrun_detect: bool,
+    ) -> Box<dyn ArmDebugInterface> {
+        ArmCommunicationInterfaceBuilder::new()
+            .sequence(sequence)
+            .overrun_detect(use_overrun_detect)
+            .build(probe)
+    }
+
+    /// Waits as long as necessary to stay within the rate limit, before transferring `words`
+    /// words.
+    fn throttle(&mut self, words: usize) {
+        if let Some(bucket) = &mut self.rate_limiter {
+            let delay = bucket.take(words);
+            if !delay.is_zero() {
+                std::thread::sleep(delay);
+            }
+        }
+    }
+
+    /// Runs a single transfer `op` on `dp`.
+    ///
+    /// The transfer is retried according to the [`RetryPolicy`] of this interface as long as
+    /// the target answers with a WAIT response. If overrun detection is enabled and the transfer
+    /// fails because of a sticky overrun, the overrun is cleared and the transfer is retried once.
+    fn retry_transfer<T>(
+        &mut self,
+        dp: DpAddress,
+        op: impl FnMut(&mut Self) -> Result<T, ArmError>,
+    ) -> Result<T, ArmError> {
+        self.retry_transfer_with(dp, |_| Ok(true), op)
+    }
+
+    /// Runs a transfer `op` on `dp` like [`Self::retry_transfer`], calling `prepare_retry` before
+    /// each retry.
+    ///
+    /// `prepare_retry` returns whether the transfer can be retried. If it can't, the error of the
+    /// failed attempt is returned.
+    fn retry_transfer_with<T>(
+        &mut self,
+        dp: DpAddress,
+        mut prepare_retry: impl FnMut(&mut Self) -> Result<bool, ArmError>,
+        mut op: impl FnMut(&mut Self) -> Result<T, ArmError>,
+    ) -> Result<T, ArmError> {
+        let mut attempt = 0;
+        let mut cleared_overrun = false;
+        loop {
+            let result = op(self);
+            self.transfer_counters.record(&result);
+
+            match result {
+                Err(ArmError::Dap(DapError::WaitResponse))
+                    if attempt < self.retry_policy.max_retries =>
+                {
+                    let delay = self.retry_policy.delay(attempt);
+                    tracing::debug!(
+                        "WAIT response, retrying in {:?} ({}/{})",
+                        delay,
+                        attempt + 1,
+                        self.retry_policy.max_retries
+                    );
+                    std::thread::sleep(delay);
+                    attempt += 1;
+                }
+                Err(ArmError::Dap(DapError::FaultResponse))
+                    if self.use_overrun_detect && !cleared_overrun =>
+                {
+                    if !self.clear_sticky_overrun(dp)? {
+                        return Err(DapError::FaultResponse.into());
+                    }
+                    cleared_overrun = true;
+                }
+                Err(error) => {
+                    self.forget_last_writes(dp);
+                    return Err(error);
+                }
+                result => return result,
+            }
+
+            if !prepare_retry(self)? {
+                tracing::debug!("Transfer cannot be repeated safely, not retrying");
+                self.forget_last_writes(dp);
+                return result;
+            }
+            self.transfer_counters.record_retry();
+        }
+    }
+
+    /// Forgets the values last written to the registers of `dp`, as a failed write may or may
+    /// not have reached its register.
+    fn forget_last_writes(&mut self, dp: DpAddress) {
+        if let Some(state) = self.dps.get_mut(&dp) {
+            state.last_writes.clear();
+            state.opened_memory_aps.clear();
+        }
+    }
+
+    /// Returns whether the RESEND register of `dp` can be read, which is the case for SW-DPs.
+    fn supports_resend(&self, dp: DpAddress) -> bool {
+        let protocol = self
+            .probe
+            .as_deref()
+            .and_then(|probe| probe.active_protocol());
+
+        protocol == Some(WireProtocol::Swd)
+            && self
+                .dps
+                .get(&dp)
+                .is_some_and(|state| state.debug_port_version != DebugPortVersion::DPv0)
+    }
+
+    /// Enables or disables the overrun detect mode of `dp`, as requested.
+    fn configure_overrun_detect(&mut self, dp: DpAddress) -> Result<(), ArmError> {
+        // For "bit-banging" probes, such as JLink or FTDI, we rely on it for good, stable communication.
+        // This is required as the default sequence (and most special implementations) does not do this.
+        let mut ctrl_reg: Ctrl = self.read_dp_register(dp)?;
+        if ctrl_reg.orun_detect() != self.use_overrun_detect {
+            tracing::debug!("Setting orun_detect: {}", self.use_overrun_detect);
+            // only write if there’s a need for it.
+            ctrl_reg.set_orun_detect(self.use_overrun_detect);
+            self.write_dp_register(dp, ctrl_reg)?;
+        }
+
+        Ok(())
+    }
+
+    /// Checks CTRL/STAT of the given DP for a sticky overrun, and clears it using ABORT.
+    ///
+    /// Returns whether an overrun was cleared.
+    fn clear_sticky_overrun(&mut self, dp: DpAddress) -> Result<bool, ArmError> {
+        // Talk to the probe directly, so that a FAULT here does not end up in `retry_transfer` again.
+        self.select_dp_and_dp_bank(dp, &Ctrl::ADDRESS)?;
+        let ctrl = Ctrl(self.probe_mut().raw_read_register(Ctrl::ADDRESS.into())?);
+
+        if !ctrl.sticky_orun() {
+            return Ok(false);
+        }
+
+        tracing::debug!("Clearing sticky overrun on DP {:x?}", dp);
+
+        let mut abort = Abort(0);
+        abort.set_orunerrclr(true);
+        self.probe_mut()
+            .raw_write_register(Abort::ADDRESS.into(), abort.into())?;
+        self.forget_last_writes(dp);
+
+        Ok(true)
+    }
+
+    /// Inform the probe of the [`CoreStatus`] of the chip attached to the probe.
+    pub fn core_status_notification(&mut self, state: CoreStatus) {
+        self.probe_mut().core_status_notification(state).ok();
+    }
+
+    /// Sets up the given DP.
+    ///
+    /// If the target does not respond to the configured wire protocol, it might be in the
+    /// other mode, so setting up the DP is attempted once more using the alternative protocol.
+    fn setup_debug_port(&mut self, dp: DpAddress) -> Result<(), ArmError> {
+        let error = match self.run_debug_port_setup(dp) {
+            Err(ArmError::Dap(error @ (DapError::Protocol(_) | DapError::NoAcknowledge)))
+                if !self.fixed_wire_protocol =>
+            {
+                error
+            }
+            other => return other,
+        };
+
+        let Some(configured) = self.probe_mut().active_protocol() else {
+            return Err(error.into());
+        };
+        let alternative = match configured {
+            WireProtocol::Swd => WireProtocol::Jtag,
+            WireProtocol::Jtag => WireProtocol::Swd,
+        };
+
+        tracing::warn!(
+            "Failed to set up DP {:x?} using {}: {}. Trying {} instead.",
+            dp,
+            configured,
+            error,
+            alternative
+        );
+
+        if let Err(e) = self.probe_mut().select_protocol(alternative) {
+            tracing::debug!("Probe can't switch to {}: {}", alternative, e);
+            return Err(error.into());
+        }
+        if let Some(diagnostics) = &mut self.last_connect_diagnostics {
+            diagnostics.wire_protocols.push(alternative);
+        }
+
+        match self.run_debug_port_setup(dp) {
+            Ok(()) => {
+                tracing::info!("Set up DP {:x?} using {}", dp, alternative);
+                Ok(())
+            }
+            Err(e) => {
+                tracing::warn!("Failed to set up DP {:x?} using {}: {}", dp, alternative, e);
+
+                // Leave the probe in the configured state.
+                self.probe_mut().select_protocol(configured).ok();
+
+                Err(ArmError::WireProtocolMismatch {
+                    configured,
+                    alternative,
+                })
+            }
+        }
+    }
+
+    /// Sends the SWJ sequences configured with
+    /// [`ArmCommunicationInterfaceBuilder::pre_connect_sequence`].
+    fn send_pre_connect_sequence(&mut self) -> Result<(), ArmError> {
+        for (bit_len, bits) in self.pre_connect_sequence.clone() {
+            self.probe_mut().swj_sequence(bit_len, bits)?;
+        }
+
+        Ok(())
+    }
+
+    /// Runs the `debug_port_setup` sequence, retrying it until the connect timeout expires.
+    fn run_debug_port_setup(&mut self, dp: DpAddress) -> Result<(), ArmError> {
+        let sequence = self.sequence.clone();
+        let deadline = self.connect_timeout.map(|timeout| Instant::now() + timeout);
+
+        loop {
+            let result = self
+                .send_pre_connect_sequence()
+                .and_then(|()| sequence.debug_port_setup(&mut *self.probe_mut(), dp));
+
+            match result {
+                Err(e) if deadline.is_some_and(|deadline| Instant::now() < deadline) => {
+                    tracing::debug!("Failed to set up DP {:x?}, retrying: {}", dp, e);
+                    if let Some(diagnostics) = &mut self.last_connect_diagnostics {
+                        diagnostics.retries += 1;
+                    }
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn auto_increment_state(
+        &mut self,
+        ap: &FullyQualifiedApAddress,
+    ) -> Option<&mut AutoIncrementState> {
+        let state = self.dps.get_mut(&ap.dp())?;
+        Some(state.auto_increment.entry(ap.ap().clone()).or_default())
+    }
+
+    /// Updates the TAR auto-increment state after `count` accesses to the register `address`.
+    ///
+    /// `value` is the value written to the register, or `None` for reads.
+    fn track_mem_ap_access(
+        &mut self,
+        ap: &FullyQualifiedApAddress,
+        address: u64,
+        value: Option<u32>,
+        count: usize,
+    ) {
+        if is_ap_register(ap, address, TAR2::ADDRESS) {
+            if let (Some(tar2), Some(state)) = (value, self.auto_increment_state(ap)) {
+                state.tar2 = tar2;
+            }
+            return;
+        }
+
+        let Some(register) = auto_increment_register(ap, address) else {
+            return;
+        };
+        let Some(state) = self.auto_increment_state(ap) else {
+            return;
+        };
+
+        match (register, value) {
+            (MemApRegister::Csw, Some(csw)) => state.update_csw(csw),
+            (MemApRegister::Tar, Some(tar)) => state.tar = Some(tar as u64),
+            (MemApRegister::Drw, _) => state.advance(count),
+            _ => {}
+        }
+    }
+
+    /// Returns the bits of the register at `address` of `ap` which can be read back to verify a
+    /// write, or `None` if the register can't be verified.
+    ///
+    /// Only TAR, and the Size and AddrInc fields of CSW of MEM-APs are verified. The data
+    /// registers of a MEM-AP access memory when read, the other fields of CSW contain read-only
+    /// status bits, and the registers of other access ports can have side effects when read.
+    fn verifiable_ap_register_bits(
+        &self,
+        ap: &FullyQualifiedApAddress,
+        address: u64,
+    ) -> Option<u32> {
+        /// The Size and AddrInc fields of CSW.
+        const CSW_TRANSFER_FIELDS: u32 = 0x37;
+
+        let state = self.dps.get(&ap.dp())?;
+        if !state.mem_aps.contains(ap.ap()) {
+            return None;
+        }
+
+        [
+            (MemApRegister::Csw, CSW_TRANSFER_FIELDS),
+            (MemApRegister::Tar, u32::MAX),
+            (MemApRegister::Tar2, u32::MAX),
+        ]
+        .into_iter()
+        .find(|(register, _)| is_ap_register(ap, address, register.address()))
+        .map(|(_, mask)| mask)
+    }
+
+    /// Returns the register at `address` of `ap` if writes to it can be combined.
+    fn combinable_ap_register(
+        &self,
+        ap: &FullyQualifiedApAddress,
+        address: u64,
+    ) -> Option<CombinableRegister> {
+        if !self.combine_writes {
+            return None;
+        }
+
+        // Other access ports may have registers with side effects at the same offsets.
+        let state = self.dps.get(&ap.dp())?;
+        if !state.mem_aps.contains(ap.ap()) {
+            return None;
+        }
+
+        match auto_increment_register(ap, address)? {
+            MemApRegister::Csw => Some(CombinableRegister::Csw(ap.ap().clone())),
+            MemApRegister::Tar => Some(CombinableRegister::Tar(ap.ap().clone())),
+            _ => None,
+        }
+    }
+
+    /// Returns whether `value` was the last value written to `register`, so that writing it
+    /// again can be skipped.
+    fn is_redundant_write(
+        &self,
+        dp: DpAddress,
+        register: Option<&CombinableRegister>,
+        value: u32,
+    ) -> bool {
+        let Some(register) = register else {
+            return false;
+        };
+
+        let last_value = self
+            .dps
+            .get(&dp)
+            .and_then(|state| state.last_writes.get(register));
+
+        if last_value != Some(&value) {
+            return false;
+        }
+
+        tracing::trace!("Skipping write of {value:#010x} to {register:x?}, it is unchanged");
+        self.transfer_counters.record_combined_write();
+        true
+    }
+
+    /// Remembers `value` as the last value written to `register`.
+    fn track_combinable_write(
+        &mut self,
+        dp: DpAddress,
+        register: Option<CombinableRegister>,
+        value: u32,
+    ) {
+        if let (Some(register), Some(state)) = (register, self.dps.get_mut(&dp)) {
+            state.last_writes.insert(register, value);
+        }
+    }
+
+    /// Updates the write cache after an access to the register `address` of `ap`.
+    ///
+    /// Reading the IDR tells whether the access port is a MEM-AP, and a DRW access may change TAR.
+    fn track_combinable_access(
+        &mut self,
+        ap: &FullyQualifiedApAddress,
+        address: u64,
+        read_value: Option<u32>,
+    ) {
+        let Some(state) = self.dps.get_mut(&ap.dp()) else {
+            return;
+        };
+
+        // MEM-APs are also tracked without write combining, to verify writes to them.
+        let is_mem_ap = is_ap_register(ap, address, IDR::ADDRESS)
+            && read_value
+                .is_some_and(|idr| IDR::try_from(idr).is_ok_and(|idr| idr.CLASS == ApClass::MemAp));
+        if is_mem_ap {
+            state.mem_aps.insert(ap.ap().clone());
+        }
+
+        if !self.combine_writes {
+            return;
+        }
+
+        if auto_increment_register(ap, address) == Some(MemApRegister::Drw) {
+            state
+                .last_writes
+                .remove(&CombinableRegister::Tar(ap.ap().clone()));
+        }
+    }
+
+    /// Runs a repeated access to the register `address` of `ap` as `transfer`, which is called
+    /// with the range of the values to transfer, and the value of TAR at its start, if known.
+    ///
+    /// If the register is DRW and the access would cross a TAR auto-increment boundary, the
+    /// access is split at the boundary and TAR is written before each further part, as the
+    /// value of TAR is not guaranteed after crossing it.
+    fn split_at_auto_increment_boundary(
+        &mut self,
+        ap: &FullyQualifiedApAddress,
+        address: u64,
+        len: usize,
+        mut transfer: impl FnMut(&mut Self, Range<usize>, Option<u64>) -> Result<(), ArmError>,
+    ) -> Result<(), ArmError> {
+        let state = match auto_increment_register(ap, address) {
+            Some(MemApRegister::Drw) => self.auto_increment_state(ap).map(|state| *state),
+            _ => None,
+        };
+
+        let Some(AutoIncrementState {
+            increment: Some(increment),
+            tar: Some(mut tar),
+            ..
+        }) = state
+        else {
+            transfer(self, 0..len, None)?;
+            self.track_mem_ap_access(ap, address, None, len);
+            return Ok(());
+        };
+
+        let mut start = 0;
+        while start < len {
+            if start > 0 {
+                tracing::debug!("Rewriting TAR to {tar:#010x} at auto-increment boundary");
+                // TAR is located 8 bytes before DRW.
+                self.write_raw_ap_register(ap, address - 0x8, tar as u32)?;
+            }
+
+            let room = (AUTO_INCREMENT_LIMIT - tar % AUTO_INCREMENT_LIMIT) / increment;
+            let end = len.min(start + room.max(1) as usize);
+
+            if let Err(error) = transfer(self, start..end, Some(tar)) {
+                if let Some(state) = self.auto_increment_state(ap) {
+                    state.tar = None;
+                }
+                return Err(error);
+            }
+
+            tar += (end - start) as u64 * increment;
+            start = end;
+        }
+
+        if let Some(state) = self.auto_increment_state(ap) {
+            state.tar = (tar % AUTO_INCREMENT_LIMIT != 0).then_some(tar);
+        }
+
+        Ok(())
+    }
+
+    /// Prepares repeating a block of `len` transfers to the register `address` of `ap` after it
+    /// failed, returning whether it can be repeated.
+    ///
+    /// Part of the block may already have been transferred, which incremented TAR. For DRW,
+    /// TAR is written again with `tar`, the address at the start of the block, so that the
+    /// block is repeated from the start. Blocks for which this address is not known are only
+    /// repeated if they consist of a single transfer, which did not take place.
+    fn prepare_block_retry(
+        &mut self,
+        ap: &FullyQualifiedApAddress,
+        address: u64,
+        len: usize,
+        tar: Option<u64>,
+    ) -> Result<bool, ArmError> {
+        if len == 1 {
+            return Ok(true);
+        }
+        let Some(tar) = tar else {
+            return Ok(false);
+        };
+
+        tracing::debug!("Rewriting TAR to {tar:#010x} to repeat the block");
+        // The cached value of TAR is stale, as TAR was incremented by the failed transfer.
+        if let Some(state) = self.dps.get_mut(&ap.dp()) {
+            state
+                .last_writes
+                .remove(&CombinableRegister::Tar(ap.ap().clone()));
+        }
+        // TAR is located 8 bytes before DRW.
+        self.write_raw_ap_register(ap, address - 0x8, tar as u32)?;
+
+        Ok(true)
+    }
+
+    /// Marks the SELECT cache of `dp` as out of sync if `value` was written to SELECT or SELECT1
+    /// and differs from the cached value.
+    ///
+    /// Writes issued through the cache always match it, so a mismatch means that the register
+    /// was written directly, e.g. by a debug sequence.
+    fn track_select_write(&mut self, dp: DpAddress, address: DpRegisterAddress, value: u32) {
+        if !cfg!(debug_assertions) {
+            return;
+        }
+
+        let Some(state) = self.dps.get_mut(&dp) else {
+            return;
+        };
+
+        let cached = match (state.current_select, address.address, address.bank) {
+            (SelectCache::DPv1(select), 0x8, _) => u32::from(select),
+            (SelectCache::DPv3(select, _), 0x8, _) => u32::from(select),
+            (SelectCache::DPv3(_, select1), 0x4, Some(5)) => u32::from(select1),
+            _ => return,
+        };
+
+        if value != cached {
+            state.select_written_directly = true;
+        }
+    }
+
+    /// Debug sequences may write SELECT while connecting to a debug port. Writes the cached
+    /// value back afterwards, so that the cache matches the debug port again.
+    fn resync_select_after_sequence(&mut self, dp: DpAddress) -> Result<(), ArmError> {
+        let Some(state) = self.dps.get(&dp) else {
+            return Ok(());
+        };
+
+        if state.select_written_directly {
+            tracing::debug!("SELECT of {dp:x?} was changed by the debug sequence, restoring it");
+            let select = state.current_select;
+            self.restore_select_state(dp, select)?;
+        }
+
+        Ok(())
+    }
+
+    fn select_dp(&mut self, dp: DpAddress) -> Result<&mut DpState, ArmError> {
+        // Transfers only happen in here while connecting to the DP.
+        #[cfg(any(test, feature = "test"))]
+        let _capture = self.connect_capture.as_ref().map(ConnectCapture::capture);
+
+        if let Some(released_at) = self.reset_released_at.take() {
+            let remaining = self
+                .reset_settle_delay
+                .saturating_sub(released_at.elapsed());
+            if !remaining.is_zero() {
+                tracing::debug!(
+                    "Waiting {:?} for the target to settle after reset",
+                    remaining
+                );
+                std::thread::sleep(remaining);
+            }
+        }
+
+        let connecting = self.current_dp != Some(dp) || !self.dps.contains_key(&dp);
+        if connecting {
+            self.last_connect_diagnostics = Some(ConnectDiagnostics {
+                dp,
+                dpidr: None,
+                ctrl_stat: None,
+                retries: 0,
+                wire_protocols: self.probe_mut().active_protocol().into_iter().collect(),
+            });
+        }
+
+        match self.connect_dp(dp) {
+            Ok(()) if connecting => self.last_connect_diagnostics = None,
+            Ok(()) => {}
+            Err(error) => {
+                self.finish_connect_diagnostics();
+                return Err(error);
+            }
+        }
+
+        // note(unwrap): Entry gets inserted by connect_dp
+        Ok(self.dps.get_mut(&dp).unwrap())
+    }
+
+    /// Reads DPIDR and CTRL/STAT after a failed connect, to complete
+    /// [`ArmDebugInterface::last_connect_diagnostics`].
+    ///
+    /// The registers are read directly from the probe, as the state of the debug port is unknown.
+    fn finish_connect_diagnostics(&mut self) {
+        let probe = self.probe_mut();
+        let dpidr = probe
+            .raw_read_register(RegisterAddress::DpRegister(DPIDR::ADDRESS))
+            .ok();
+        let ctrl_stat = probe
+            .raw_read_register(RegisterAddress::DpRegister(Ctrl::ADDRESS))
+            .ok();
+
+        if let Some(diagnostics) = &mut self.last_connect_diagnostics {
+            diagnostics.dpidr = dpidr;
+            diagnostics.ctrl_stat = ctrl_stat;
+        }
+    }
+
+    /// Connects to `dp` if necessary, and makes it the current debug port.
+    fn connect_dp(&mut self, dp: DpAddress) -> Result<(), ArmError> {
+        let mut switched_dp = false;
+
+        let sequence = self.sequence.clone();
+
+        if self.current_dp != Some(dp) {
+            tracing::debug!("Selecting DP {:x?}", dp);
+
+            switched_dp = true;
+
+            self.probe_mut().raw_flush()?;
+
+            // We are not currently connected to any DP,
+            // so we need to run the debug_port_setup sequence.
+            if self.current_dp.is_none() {
+                self.setup_debug_port(dp)
+                    .map_err(|error| no_debug_port(dp, error))?;
+            } else {
+                // Try to switch to the new DP.
+                if let Err(e) = sequence.debug_port_connect(&mut *self.probe_mut(), dp) {
+                    tracing::warn!("Failed to switch to DP {:x?}: {}", dp, e);
+
+                    // Try the more involved debug_port_setup sequence, which also handles dormant mode.
+                    if let Err(error) = sequence.debug_port_setup(&mut *self.probe_mut(), dp) {
+                        // The line was reset, so the previous DP isn't selected anymore either.
+                        self.current_dp = None;
+                        return Err(no_debug_port(dp, error));
+                    }
+                }
+            }
+
+            self.current_dp = Some(dp);
+
+            // The DP may have lost power since it was last selected.
+            if let Some(state) = self.dps.get_mut(&dp) {
+                state.last_writes.clear();
+                state.powered_aps.clear();
+                state.auto_increment.clear();
+                state.opened_memory_aps.clear();
+            }
+        }
+
+        // If we don't have  a state for this DP, this means that we haven't run the necessary init sequence yet.
+        if let hash_map::Entry::Vacant(entry) = self.dps.entry(dp) {
+            let sequence = self.sequence.clone();
+
+            entry.insert(DpState::new());
+
+            let start_span = tracing::debug_span!("debug_port_start").entered();
+            sequence
+                .debug_port_start(self, dp)
+                .map_err(|error| no_debug_port(dp, error))?;
+            drop(start_span);
+            self.resync_select_after_sequence(dp)?;
+
+            match self.configure_overrun_detect(dp) {
+                Err(ArmError::Dap(DapError::FaultResponse)) if self.lenient_overrun_detect => {
+                    tracing::warn!(
+                        "DP {:x?} faulted while configuring overrun detection, continuing without it",
+                        dp
+                    );
+
+                    let mut abort = Abort(0);
+                    abort.set_orunerrclr(true);
+                    abort.set_wderrclr(true);
+                    abort.set_stkerrclr(true);
+                    abort.set_stkcmpclr(true);
+                    self.write_dp_register(dp, abort)?;
+                }
+                result => result?,
+            }
+
+            let idr: DebugPortId = self
+                .read_dp_register::<DPIDR>(dp)
+                .map_err(|error| no_debug_port(dp, error))?
+                .into();
+            tracing::info!(
+                "Debug Port version: {} MinDP: {:?}",
+                idr.version,
+                idr.min_dp_support
+            );
+
+            let state = self
+                .dps
+                .get_mut(&dp)
+                .expect("This DP State was inserted earlier in this function");
+            state.debug_port_version = idr.version;
+            if idr.version == DebugPortVersion::DPv3 {
+                state.current_select = SelectCache::DPv3(SelectV3(0), Select1(0));
+            }
+            state.debug_port_id = Some(idr);
+
+            self.validate_connection(dp)?;
+        } else if switched_dp {
+            let sequence = self.sequence.clone();
+
+            let start_span = tracing::debug_span!("debug_port_start").entered();
+            sequence.debug_port_start(self, dp)?;
+            drop(start_span);
+            self.resync_select_after_sequence(dp)?;
+        }
+
+        Ok(())
+    }
+
+    /// Runs the [`ArmCommunicationInterfaceBuilder::post_connect_validate`] function, if it was
+    /// set for `dp`.
+    fn validate_connection(&mut self, dp: DpAddress) -> Result<(), ArmError> {
+        let Some(validation) = self.post_connect_validation.clone() else {
+            return Ok(());
+        };
+        if validation.ap.dp() != dp {
+            return Ok(());
+        }
+
+        let result = self
+            .memory_interface(&validation.ap)
+            .and_then(|mut memory| (validation.validate)(&mut *memory));
+
+        if let Err(error) = result {
+            tracing::warn!("Validating DP {:x?} after connecting failed: {}", dp, error);
+
+            // Forget the DP, so that the next access connects and validates again.
+            self.dps.remove(&dp);
+            return Err(error);
+        }
+
+        Ok(())
+    }
+
+    fn select_dp_and_dp_bank(
+        &mut self,
+        dp: DpAddress,
+        dp_register_address: &DpRegisterAddress,
+    ) -> Result<(), ArmError> {
+        let dp_state = self.select_dp(dp)?;
+
+        // DP register addresses are 4 bank bits, 4 address bits. Lowest 2 address bits are
+        // always 0, so this leaves only 4 possible addresses: 0x0, 0x4, 0x8, 0xC.
+        // On ADIv5, only address 0x4 is banked, the rest are don't care.
+        // On ADIv6, address 0x0 and 0x4 are banked, the rest are don't care.
+
+        let &DpRegisterAddress {
+            bank,
+            address: addr,
+        } = dp_register_address;
+
+        if addr != 0 && addr != 4 {
+            return Ok(());
+        }
+
+        let bank = bank.unwrap_or(0);
+
+        // DPv0 does not implement DP banking, and some old parts misbehave if SELECT is written
+        // just to change the bank, so never change it.
+        if dp_state.debug_port_version == DebugPortVersion::DPv0 {
+            if bank != 0 {
+                return Err(DebugPortError::Unsupported(format!(
+                    "DP register bank {bank} does not exist on DPv0"
+                ))
+                .into());
+            }
+
+            return Ok(());
+        }
+
+        assert_select_cache_in_sync(dp_state, dp);
+
+        if bank != dp_state.current_select.dp_bank_sel() {
+            dp_state.current_select.set_dp_bank_sel(bank);
+
+            tracing::debug!("Changing DP_BANK_SEL to {:x?}", dp_state.current_select);
+
+            match dp_state.current_select {
+                SelectCache::DPv1(select) => self.write_dp_register(dp, select)?,
+                SelectCache::DPv3(select, _) => self.write_dp_register(dp, select)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn select_ap_and_ap_bank(
+        &mut self,
+        ap: &FullyQualifiedApAddress,
+        ap_register_address: u64,
+    ) -> Result<(), ArmError> {
+        let dp_state = self.select_dp(ap.dp())?;
+
+        assert_select_cache_in_sync(dp_state, ap.dp());
+
+        let previous_select = dp_state.current_select;
+        match (ap.ap(), &mut dp_state.current_select) {
+            (ApAddress::V1(port), SelectCache::DPv1(s)) => {
+                let ap_register_address = (ap_register_address & 0xFF) as u8;
+                let ap_bank = ap_register_address >> 4;
+                s.set_ap_sel(*port);
+                s.set_ap_bank_sel(ap_bank);
+            }
+            (ApAddress::V2(base), SelectCache::DPv3(s, s1)) => {
+                let address = base.0.unwrap_or(0) + ap_register_address;
+                s.set_addr(((address >> 4) & 0xFFFF_FFFF) as u32);
+                s1.set_addr((address >> 32) as u32);
+            }
+            _ => {
+                return Err(ArmError::ApVersionMismatch {
+                    ap: ap.clone(),
+                    debug_port_version: dp_state.debug_port_version,
+                });
+            }
+        }
+
+        if previous_select != dp_state.current_select {
+            tracing::debug!("Changing SELECT to {:x?}", dp_state.current_select);
+
+            match dp_state.current_select {
+                SelectCache::DPv1(select) => {
+                    self.write_dp_register(ap.dp(), select)?;
+                }
+                SelectCache::DPv3(select, select1) => {
+                    self.write_dp_register(ap.dp(), select)?;
+                    self.write_dp_register(ap.dp(), select1)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Panics in debug builds if SELECT of `dp` was written directly and not restored since,
+/// as any access relying on the cached SELECT value would then go to the wrong register.
+fn assert_select_cache_in_sync(dp_state: &DpState, dp: DpAddress) {
+    debug_assert!(
+        !dp_state.select_written_directly,
+        "SELECT of {dp:x?} was overwritten while its cached value was still in use. \
+         Wrap direct SELECT writes in `save_select_state` and `restore_select_state`."
+    );
+}
+
+/// Hints at the authentication interface when an access port access is answered with a FAULT.
+fn hint_authentication(error: &ArmError) {
+    if matches!(error, ArmError::Dap(DapError::FaultResponse)) {
+        tracing::debug!(
+            "Access port access faulted, debug may be disabled by authentication. Check `read_auth_status`."
+        );
+    }
+}
+
+impl SwoAccess for ArmCommunicationInterface {
+    fn enable_swo(&mut self, config: &SwoConfig) -> Result<(), ArmError> {
+        match self.probe_mut().get_swo_interface_mut() {
+            Some(interface) => interface.enable_swo(config),
+            None => Err(ArmError::ArchitectureRequired(&["ARMv7", "ARMv8"])),
+        }
+    }
+
+    fn disable_swo(&mut self) -> Result<(), ArmError> {
+        match self.probe_mut().get_swo_interface_mut() {
+            Some(interface) => interface.disable_swo(),
+            None => Err(ArmError::ArchitectureRequired(&["ARMv7", "ARMv8"])),
+        }
+    }
+
+    fn read_swo_timeout(&mut self, timeout: Duration) -> Result<Vec<u8>, ArmError> {
+        let data = match self.probe_mut().get_swo_interface_mut() {
+            Some(interface) => interface.read_swo_timeout(timeout)?,
+            None => return Err(ArmError::ArchitectureRequired(&["ARMv7", "ARMv8"])),
+        };
+        self.capture_swo(&data);
+
+        Ok(data)
+    }
+
+    fn swo_buffer_available(&mut self) -> Result<usize, ArmError> {
+        match self.probe_mut().get_swo_interface_mut() {
+            Some(interface) => interface.swo_buffer_available(),
+            None => Ok(0),
+        }
+    }
+
+    fn flush_swo(&mut self) -> Result<Vec<u8>, ArmError> {
+        let data = match self.probe_mut().get_swo_interface_mut() {
+            Some(interface) => interface.flush_swo()?,
+            None => return Err(ArmError::ArchitectureRequired(&["ARMv7", "ARMv8"])),
+        };
+        self.capture_swo(&data);
+
+        Ok(data)
+    }
+
+    fn start_swo_capture(&mut self, path: &Path) -> Result<(), ArmError> {
+        self.stop_swo_capture()?;
+        self.swo_capture = Some(SwoCapture::create(path).map_err(ArmError::SwoCapture)?);
+
+        Ok(())
+    }
+
+    fn stop_swo_capture(&mut self) -> Result<(), ArmError> {
+        if let Some(capture) = self.swo_capture.take() {
+            capture.finish().map_err(ArmError::SwoCapture)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl ArmCommunicationInterface {
+    /// Writes SWO data which was read into the running capture, if any.
+    ///
+    /// A failing capture is stopped, so that reading SWO data keeps working.
+    fn capture_swo(&mut self, data: &[u8]) {
+        let Some(capture) = self.swo_capture.as_mut() else {
+            return;
+        };
+
+        if let Err(error) = capture.record(data) {
+            tracing::warn!("Stopping SWO capture after write error: {error}");
+            self.swo_capture = None;
+        }
+    }
+}
+
+impl DapAccess for ArmCommunicationInterface {
+    fn read_raw_dp_register(
+        &mut self,
+        dp: DpAddress,
+        address: DpRegisterAddress,
+    ) -> Result<u32, ArmError> {
+        self.throttle(1);
+        self.retry_transfer(dp, |this| {
+            this.select_dp_and_dp_bank(dp, &address)?;
+            this.probe_mut().raw_read_register(address.into())
+        })
+    }
+
+    fn write_raw_dp_register(
+        &mut self,
+        dp: DpAddress,
+        address: DpRegisterAddress,
+        value: u32,
+    ) -> Result<(), ArmError> {
+        self.throttle(1);
+        self.retry_transfer(dp, |this| {
+            this.select_dp_and_dp_bank(dp, &address)?;
+            this.probe_mut().raw_write_register(address.into(), value)
+        })?;
+        self.track_select_write(dp, address, value);
+        if clears_sticky_errors(address, value) {
+            // Writes which were discarded because of the error may still be cached.
+            self.forget_last_writes(dp);
+        }
+
+        Ok(())
+    }
+
+    #[cfg_attr(
+        feature = "transfer-spans",
+        tracing::instrument(level = "trace", skip(self))
+    )]
+    fn read_raw_ap_register(
+        &mut self,
+        ap: &FullyQualifiedApAddress,
+        address: u64,
+    ) -> Result<u32, ArmError> {
+        self.throttle(1);
+        let result = self.retry_transfer(ap.dp(), |this| {
+            this.select_ap_and_ap_bank(ap, address)?;
+
+            this.probe_mut()
+                .raw_read_register(RegisterAddress::ApRegister((address & 0xFF) as u8))
+        });
+
+        // Reading the register again could repeat side effects of the read, e.g. popping a
+        // FIFO, so fetch the corrupted value again from RESEND instead.
+        let value = match result {
+            Err(ArmError::Dap(DapError::IncorrectParity)) if self.supports_resend(ap.dp()) => {
+                tracing::debug!("Parity error reading AP register {address:#x}, reading RESEND");
+                self.read_resend(ap.dp())
+            }
+            result => result,
+        }
+        .inspect_err(hint_authentication)?;
+        self.track_mem_ap_access(ap, address, None, 1);
+        self.track_combinable_access(ap, address, Some(value));
+
+        Ok(value)
+    }
+
+    #[cfg_attr(
+        feature = "transfer-spans",
+        tracing::instrument(level = "trace", skip(self, values), fields(len = values.len()))
+    )]
+    fn read_raw_ap_register_repeated(
+        &mut self,
+        ap: &FullyQualifiedApAddress,
+        address: u64,
+        values: &mut [u32],
+    ) -> Result<(), ArmError> {
+        self.track_combinable_access(ap, address, None);
+        self.split_at_auto_increment_boundary(ap, address, values.len(), |this, range, tar| {
+            this.throttle(range.len());
+            this.retry_transfer_with(
+                ap.dp(),
+                |this| this.prepare_block_retry(ap, address, range.len(), tar),
+                |this| {
+                    this.select_ap_and_ap_bank(ap, address)?;
+
+                    this.probe_mut().raw_read_block(
+                        RegisterAddress::ApRegister((address & 0xFF) as u8),
+                        &mut values[range.clone()],
+                    )
+                },
+            )
+            .inspect_err(hint_authentication)
+        })?;
+        // TAR may have been rewritten and incremented during the access.
+        self.track_combinable_access(ap, address, None);
+
+        Ok(())
+    }
+
+    #[cfg_attr(
+        feature = "transfer-spans",
+        tracing::instrument(level = "trace", skip(self))
+    )]
+    fn write_raw_ap_register(
+        &mut self,
+        ap: &FullyQualifiedApAddress,
+        address: u64,
+        value: u32,
+    ) -> Result<(), ArmError> {
+        let register = self.combinable_ap_register(ap, address);
+        if self.is_redundant_write(ap.dp(), register.as_ref(), value) {
+            return Ok(());
+        }
+
+        self.throttle(1);
+        self.retry_transfer(ap.dp(), |this| {
+            this.select_ap_and_ap_bank(ap, address)?;
+
+            this.probe_mut()
+                .raw_write_register(RegisterAddress::ApRegister((address & 0xFF) as u8), value)
+        })
+        .inspect_err(hint_authentication)?;
+        self.track_mem_ap_access(ap, address, Some(value), 1);
+        self.track_combinable_access(ap, address, None);
+        self.track_combinable_write(ap.dp(), register, value);
+
+        if let Some(mask) = self
+            .verify_writes
+            .then(|| self.verifiable_ap_register_bits(ap, address))
+            .flatten()
+        {
+            let read = self.read_raw_ap_register(ap, address)?;
+            if read & mask != value & mask {
+                tracing::warn!(
+                    "Write to register {:#x} of AP {:x?} did not stick: wrote {:#010x}, read {:#010x}",
+                    address,
+                    ap,
+                    value,
+                    read
+                );
+                return Err(ArmError::WriteVerifyMismatch { wrote: value, read });
+            }
+        }
+
+        Ok(())
+    }
+
+    #[cfg_attr(
+        feature = "transfer-spans",
+        tracing::instrument(level = "trace", skip(self, values), fields(len = values.len()))
+    )]
+    fn write_raw_ap_register_repeated(
+        &mut self,
+        ap: &FullyQualifiedApAddress,
+        address: u64,
+        values: &[u32],
+    ) -> Result<(), ArmError> {
+        self.track_combinable_access(ap, address, None);
+        self.split_at_auto_increment_boundary(ap, address, values.len(), |this, range, tar| {
+            this.throttle(range.len());
+            this.retry_transfer_with(
+                ap.dp(),
+                |this| this.prepare_block_retry(ap, address, range.len(), tar),
+                |this| {
+                    this.select_ap_and_ap_bank(ap, address)?;
+
+                    this.probe_mut().raw_write_block(
+                        RegisterAddress::ApRegister((address & 0xFF) as u8),
+                        &values[range.clone()],
+                    )
+                },
+            )
+            .inspect_err(hint_authentication)
+        })?;
+        // TAR may have been rewritten and incremented during the access.
+        self.track_combinable_access(ap, address, None);
+
+        Ok(())
+    }
+
+    fn read_mem_ap_cfg(&mut self, ap: &FullyQualifiedApAddress) -> Result<MemApCfg, ArmError> {
+        // CFG is read once when the memory AP is set up, and never changes.
+        let opened = self
+            .dps
+            .get(&ap.dp())
+            .and_then(|state| state.opened_memory_aps.get(ap.ap()))
+            .map(MemoryApType::cfg);
+
+        match opened {
+            Some(cfg) => Ok(cfg),
+            None => Ok(self.memory_ap(ap)?.cfg()),
+        }
+    }
+
+    fn flush(&mut self) -> Result<(), ArmError> {
+        self.probe_mut().raw_flush()
+    }
+
+    fn try_dap_probe(&self) -> Option<&dyn DapProbe> {
+        self.probe.as_deref()
+    }
+
+    fn try_dap_probe_mut(&mut self) -> Option<&mut dyn DapProbe> {
+        self.probe
+            .as_deref_mut()
+            // Need to explicitly coerce lifetimes: https://github.com/rust-lang/rust/issues/108999
+            .map(|p: &mut (dyn DapProbe + 'static)| p as &mut (dyn DapProbe + '_))
+    }
+}
+
+/// Information about the chip target we are currently attached to.
+/// This can be used for discovery, tho, for now it does not work optimally,
+/// as some manufacturers (e.g. ST Microelectronics) violate the spec and thus need special discovery procedures.
+#[derive(Debug, Clone)]
+pub struct ArmChipInfo {
+    /// The JEP106 code of the manufacturer of this chip target.
+    pub manufacturer: JEP106Code,
+    /// The unique part number of the chip target. Unfortunately this only unique in the spec.
+    /// In practice some manufacturers violate the spec and assign a part number to an entire family.
+    ///
+    /// Consider this not unique when working with targets!
+    pub part: u16,
+    /// The access port through which the ROM table was found.
+    pub ap: FullyQualifiedApAddress,
+    /// The base address of the ROM table in the memory space of [`Self::ap`].
+    pub rom_base: u64,
+}
+
+impl std::fmt::Display for ArmChipInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let manu = match self.manufacturer.get() {
+            Some(name) => name.to_string(),
+            None => format!(
+                "<unknown manufacturer (cc={:2x}, id={:2x})>",
+                self.manufacturer.cc, self.manufacturer.id
+            ),
+        };
+        write!(
+            f,
+            "{} 0x{:04x} (ROM table at {:#010x} on {:?})",
+            manu, self.part, self.rom_base, self.ap
+        )
+    }
+}
+
+/// A range of memory which could not be read, as returned by
+/// [`ArmDebugInterface::read_32_with_fallback`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FaultedRange {
+    /// The first address of the range.
+    pub start: u64,
+    /// The first address after the range.
+    pub end: u64,
+}
+
+/// Details about a failed attempt to connect to a debug port, as returned by
+/// [`ArmDebugInterface::last_connect_diagnostics`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectDiagnostics {
+    /// The debug port which could not be connected to.
+    pub dp: DpAddress,
+    /// The value of DPIDR read after the failure, if the debug port responded.
+    pub dpidr: Option<u32>,
+    /// The value of CTRL/STAT read after the failure, if the debug port responded.
+    pub ctrl_stat: Option<u32>,
+    /// How often setting up the debug port was retried.
+    pub retries: usize,
+    /// The wire protocols which were tried, in order.
+    pub wire_protocols: Vec<WireProtocol>,
+}
+
+/// Information about an access port, as returned by [`ArmDebugInterface::access_ports_detailed`].
+#[derive(Debug, Clone)]
+pub struct ApInfo {
+    /// The address of the access port.
+    pub address: FullyQualifiedApAddress,
+    /// The decoded identification register of the access port.
+    pub idr: IDR,
+    /// The base address of the debug components, if the access port is a MEM-AP.
+    pub base_address: Option<u64>,
+}
+
+/// The access ports of a debug port, as returned by [`ArmDebugInterface::full_topology`].
+#[derive(Debug)]
+pub struct DpTopology {
+    /// The address of the debug port.
+    pub dp: DpAddress,
+    /// The access ports of the debug port, or the error which occurred while enumerating them.
+    pub access_ports: Result<Vec<ApInfo>, ArmError>,
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{
+        collections::{BTreeSet, HashMap},
+        sync::{
+            Arc, Mutex,
+            atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering},
+        },
+        time::{Duration, Instant},
+    };
+
+    use test_log::test;
+
+    use super::{
+        ArmCommunicationInterface, ArmCommunicationInterfaceBuilder, ArmDebugInterface, DapError,
+        DapProbe, DpState, RateLimit, RetryPolicy, SelectCache, SwdSequence, TokenBucket,
+        TransferStats, identify,
+    };
+    use crate::{
+        architecture::arm::{
+            ArmError, DapAccess, FullyQualifiedApAddress, Pins, RegisterAddress,
+            ap::{ApRegister, CSW, DRW, IDR, MemApCfg, TAR, memory_ap::mock::MockMemoryAp},
+            dp::{
+                Abort, BASEPTR0, Ctrl, DPIDR, DebugPortVersion, DpAccess, DpAddress, DpRegister,
+                DpRegisterAddress, Resend, Select1, SelectV1, SelectV3,
+            },
+            sequences::{ArmDebugSequence, DebugLockStatus, DefaultArmSequence},
+        },
+        probe::{
+            DebugProbe, DebugProbeError, WireProtocol,
+            fake_probe::FakeProbe,
+            recording_probe::{RecordedTransfer, RecordingProbe},
+        },
+        vendor::nordicsemi::sequences::nrf52::Nrf52,
+    };
+
+    /// DPIDR of a DPv1 debug port.
+    const DPIDR_V1: u32 = 0x2BA0_1477;
+    /// CTRL/STAT of a powered debug port: CSYSPWRUPACK | CSYSPWRUPREQ | CDBGPWRUPACK | CDBGPWRUPREQ
+    const CTRL_POWERED: u32 = 0xF000_0000;
+
+    /// The reads of connecting to a powered DPv1 debug port using the default sequence.
+    ///
+    /// These are DPIDR and CTRL/STAT in `debug_port_connect`, DPIDR and CTRL/STAT in
+    /// `debug_port_start`, and CTRL/STAT and DPIDR when the state of the DP is set up.
+    pub(crate) const CONNECT_READS: [u32; 6] = [
+        DPIDR_V1,
+        CTRL_POWERED,
+        DPIDR_V1,
+        CTRL_POWERED,
+        CTRL_POWERED,
+        DPIDR_V1,
+    ];
+
+    /// Creates an interface using `probe` with the settings of `builder`.
+    ///
+    /// If `debug_port_version` is given, the interface is already connected to the default DP,
+    /// which has that version.
+    fn test_interface(
+        builder: ArmCommunicationInterfaceBuilder,
+        probe: Box<dyn DapProbe>,
+        debug_port_version: Option<DebugPortVersion>,
+    ) -> ArmCommunicationInterface {
+        let mut interface = builder.build_interface(probe);
+
+        if let Some(debug_port_version) = debug_port_version {
+            let mut state = DpState::new();
+            state.debug_port_version = debug_port_version;
+            if debug_port_version == DebugPortVersion::DPv3 {
+                state.current_select = SelectCache::DPv3(SelectV3(0), Select1(0));
+            }
+
+            interface.dps.insert(DpAddress::Default, state);
+            interface.current_dp = Some(DpAddress::Default);
+        }
+
+        interface
+    }
+
+    /// Creates an interface which is connected to the default DP, with a probe that forwards all
+    /// accesses to AP 0 to `memory_ap`.
+    pub(crate) fn mock_memory_interface(
+        memory_ap: Arc<Mutex<MockMemoryAp>>,
+    ) -> ArmCommunicationInterface {
+        let ap = FullyQualifiedApAddress::v1_with_default_dp(0);
+
+        let mut probe = FakeProbe::new();
+        let (read_ap, read_memory_ap) = (ap.clone(), memory_ap.clone());
+        probe.set_dap_register_read_handler(Box::new(move |address| match address {
+            RegisterAddress::ApRegister(register) => read_memory_ap
+                .lock()
+                .unwrap()
+                .read_raw_ap_register(&read_ap, 0xD00 | u64::from(register)),
+            RegisterAddress::DpRegister(_) => Ok(0),
+        }));
+        probe.set_dap_register_write_handler(Box::new(move |address, value| match address {
+            RegisterAddress::ApRegister(register) => memory_ap
+                .lock()
+                .unwrap()
+                .write_raw_ap_register(&ap, 0xD00 | u64::from(register), value),
+            RegisterAddress::DpRegister(_) => Ok(()),
+        }));
+
+        test_interface(
+            ArmCommunicationInterfaceBuilder::new(),
+            Box::new(probe),
+            Some(DebugPortVersion::DPv1),
+        )
+    }
+
+    /// Creates an interface like [`mock_memory_interface`], and returns `memory_ap` as well, to
+    /// check the accesses made through the interface.
+    pub(crate) fn mock_target(
+        memory_ap: MockMemoryAp,
+    ) -> (Arc<Mutex<MockMemoryAp>>, ArmCommunicationInterface) {
+        let memory_ap = Arc::new(Mutex::new(memory_ap));
+        let interface = mock_memory_interface(memory_ap.clone());
+
+        (memory_ap, interface)
+    }
+
+    /// All register writes of a probe, in order.
+    type RecordedWrites = Arc<Mutex<Vec<(RegisterAddress, u32)>>>;
+
+    /// Creates an interface which is already connected to the default DP, with a probe that
+    /// answers the first `faults` AP reads with a FAULT caused by a sticky overrun.
+    fn interface_with_overrun_faults(faults: usize) -> (ArmCommunicationInterface, RecordedWrites) {
+        let remaining_faults = AtomicUsize::new(faults);
+        let writes = Arc::new(Mutex::new(Vec::new()));
+
+        let mut probe = FakeProbe::new();
+        probe.set_dap_register_read_handler(Box::new(move |address| match address {
+            RegisterAddress::ApRegister(_) => {
+                if remaining_faults.load(Ordering::SeqCst) > 0 {
+                    remaining_faults.fetch_sub(1, Ordering::SeqCst);
+                    Err(DapError::FaultResponse.into())
+                } else {
+                    Ok(0x1234_5678)
+                }
+            }
+            RegisterAddress::DpRegister(address) if address == Ctrl::ADDRESS => {
+                // STICKYORUN set
+                Ok(Ctrl(1 << 1).into())
+            }
+            _ => Ok(0),
+        }));
+        let recorded_writes = writes.clone();
+        probe.set_dap_register_write_handler(Box::new(move |address, value| {
+            recorded_writes.lock().unwrap().push((address, value));
+            Ok(())
+        }));
+
+        let interface = test_interface(
+            ArmCommunicationInterfaceBuilder::new().overrun_detect(true),
+            Box::new(probe),
+            Some(DebugPortVersion::DPv1),
+        );
+
+        (interface, writes)
+    }
+
+    fn orun_clear_count(writes: &Mutex<Vec<(RegisterAddress, u32)>>) -> usize {
+        let mut abort = Abort(0);
+        abort.set_orunerrclr(true);
+
+        writes
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|&&write| write == (RegisterAddress::DpRegister(Abort::ADDRESS), abort.into()))
+            .count()
+    }
+
+    #[test]
+    fn sticky_overrun_is_cleared_and_transfer_retried() {
+        let (mut interface, writes) = interface_with_overrun_faults(1);
+        let ap = FullyQualifiedApAddress::v1_with_default_dp(0);
+
+        let value = interface.read_raw_ap_register(&ap, 0xFC).unwrap();
+
+        assert_eq!(value, 0x1234_5678);
+        assert_eq!(orun_clear_count(&writes), 1);
+
+        let stats = interface.transfer_stats().unwrap();
+        assert!(stats.transfers >= 2);
+        assert_eq!(stats.fault_responses, 1);
+        assert_eq!(stats.retries, 1);
+
+        interface.reset_transfer_stats().unwrap();
+        assert_eq!(
+            interface.transfer_stats().unwrap(),
+            TransferStats::default()
+        );
+    }
+
+    #[test]
+    fn recurring_sticky_overrun_is_reported() {
+        let (mut interface, writes) = interface_with_overrun_faults(2);
+        let ap = FullyQualifiedApAddress::v1_with_default_dp(0);
+
+        let result = interface.read_raw_ap_register(&ap, 0xFC);
+
+        assert!(matches!(
+            result,
+            Err(ArmError::Dap(DapError::FaultResponse))
+        ));
+        assert_eq!(orun_clear_count(&writes), 1);
+    }
+
+    #[test]
+    fn debug_reset_request_is_released_if_polling_fails() {
+        const CDBGRSTREQ: u32 = 1 << 26;
+
+        let requested = Arc::new(AtomicBool::new(false));
+        let writes = Arc::new(Mutex::new(Vec::new()));
+
+        let mut probe = FakeProbe::new();
+        let read_requested = requested.clone();
+        probe.set_dap_register_read_handler(Box::new(move |address| match address {
+            RegisterAddress::DpRegister(address)
+                if address == Ctrl::ADDRESS && read_requested.load(Ordering::SeqCst) =>
+            {
+                Err(ArmError::Timeout)
+            }
+            _ => Ok(0),
+        }));
+        let recorded_writes = writes.clone();
+        probe.set_dap_register_write_handler(Box::new(move |address, value| {
+            if address == RegisterAddress::DpRegister(Ctrl::ADDRESS) {
+                requested.store(value & CDBGRSTREQ != 0, Ordering::SeqCst);
+                recorded_writes.lock().unwrap().push(value);
+            }
+            Ok(())
+        }));
+        let mut interface = test_interface(
+            ArmCommunicationInterfaceBuilder::new(),
+            Box::new(probe),
+            Some(DebugPortVersion::DPv1),
+        );
+
+        let result = interface.debug_reset(DpAddress::Default);
+
+        assert!(matches!(result, Err(ArmError::Timeout)));
+        let writes = writes.lock().unwrap();
+        assert_eq!(writes.len(), 2);
+        assert_ne!(writes[0] & CDBGRSTREQ, 0);
+        assert_eq!(writes[1] & CDBGRSTREQ, 0);
+    }
+
+    #[test]
+    fn dp_bank_is_never_selected_on_dpv0() {
+        let (mut interface, writes) = interface_with_overrun_faults(0);
+
+        // Even if the cached bank is stale, no SELECT write may be issued on DPv0.
+        let mut select = SelectV1(0);
+        select.set_dp_bank_sel(1);
+        let state = interface.dps.get_mut(&DpAddress::Default).unwrap();
+        state.debug_port_version = DebugPortVersion::DPv0;
+        state.current_select = SelectCache::DPv1(select);
+
+        interface
+            .read_raw_dp_register(DpAddress::Default, Ctrl::ADDRESS)
+            .unwrap();
+
+        let banked = DpRegisterAddress {
+            bank: Some(2),
+            address: 0x4,
+        };
+        assert!(
+            interface
+                .read_raw_dp_register(DpAddress::Default, banked)
+                .is_err()
+        );
+
+        assert!(
+            !writes
+                .lock()
+                .unwrap()
+                .iter()
+                .any(|(address, _)| *address == RegisterAddress::DpRegister(SelectV1::ADDRESS))
+        );
+    }
+
+    #[test]
+    fn restored_select_state_can_be_used() {
+        let (mut interface, _writes) = interface_with_overrun_faults(0);
+        let ap = FullyQualifiedApAddress::v1_with_default_dp(0);
+
+        let saved = interface.save_select_state(DpAddress::Default).unwrap();
+        interface
+            .write_dp_register(DpAddress::Default, SelectV1(0x0200_0000))
+            .unwrap();
+        interface
+            .restore_select_state(DpAddress::Default, saved)
+            .unwrap();
+
+        interface.read_raw_ap_register(&ap, 0xFC).unwrap();
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "was overwritten while its cached value was still in use")]
+    fn direct_select_write_is_detected() {
+        let (mut interface, _writes) = interface_with_overrun_faults(0);
+        let ap = FullyQualifiedApAddress::v1_with_default_dp(0);
+
+        interface
+            .write_dp_register(DpAddress::Default, SelectV1(0x0200_0000))
+            .unwrap();
+
+        let _ = interface.read_raw_ap_register(&ap, 0xFC);
+    }
+
+    #[test]
+    fn repeated_read_is_split_at_auto_increment_boundary() {
+        let (mut interface, writes) = interface_with_overrun_faults(0);
+        let ap = FullyQualifiedApAddress::v1_with_default_dp(0);
+
+        // 32-bit transfers, single auto-increment
+        interface.write_raw_ap_register(&ap, 0x00, 0x12).unwrap();
+        interface
+            .write_raw_ap_register(&ap, 0x04, 0x2000_03F0)
+            .unwrap();
+
+        let mut values = [0; 8];
+        interface
+            .read_raw_ap_register_repeated(&ap, 0x0C, &mut values)
+            .unwrap();
+        assert_eq!(values, [0x1234_5678; 8]);
+
+        let tar_writes: Vec<u32> = writes
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(address, _)| *address == RegisterAddress::ApRegister(0x04))
+            .map(|&(_, value)| value)
+            .collect();
+        assert_eq!(tar_writes, [0x2000_03F0, 0x2000_0400]);
+
+        // TAR is known to be within the next block, so no further write is needed.
+        interface
+            .read_raw_ap_register_repeated(&ap, 0x0C, &mut values)
+            .unwrap();
+        assert_eq!(
+            writes
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(address, _)| *address == RegisterAddress::ApRegister(0x04))
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn repeated_read_is_split_at_auto_increment_boundary_on_apv2() {
+        let (mut interface, writes) = interface_with_overrun_faults(0);
+        let state = interface.dps.get_mut(&DpAddress::Default).unwrap();
+        state.debug_port_version = DebugPortVersion::DPv3;
+        state.current_select = SelectCache::DPv3(SelectV3(0), Select1(0));
+        let ap = FullyQualifiedApAddress::v2(DpAddress::Default, 0x2000);
+
+        // 32-bit transfers, single auto-increment
+        interface
+            .write_raw_ap_register(&ap, CSW::ADDRESS, 0x12)
+            .unwrap();
+        interface
+            .write_raw_ap_register(&ap, TAR::ADDRESS, 0x2000_03F0)
+            .unwrap();
+
+        let mut values = [0; 8];
+        interface
+            .read_raw_ap_register_repeated(&ap, DRW::ADDRESS, &mut values)
+            .unwrap();
+
+        let tar_writes: Vec<u32> = writes
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(address, _)| *address == RegisterAddress::ApRegister(0x04))
+            .map(|&(_, value)| value)
+            .collect();
+        assert_eq!(tar_writes, [0x2000_03F0, 0x2000_0400]);
+    }
+
+    /// Creates a probe emulating TAR auto-increment, which answers DRW read `wait_at` with a
+    /// WAIT, without accessing memory.
+    ///
+    /// DRW reads return the address they were read from. All values written to TAR are
+    /// recorded.
+    fn auto_increment_probe(wait_at: usize) -> (FakeProbe, Arc<Mutex<Vec<u32>>>) {
+        let tar = Arc::new(Mutex::new(0));
+        let tar_writes = Arc::new(Mutex::new(Vec::new()));
+        let drw_reads = AtomicUsize::new(0);
+
+        let mut probe = FakeProbe::new();
+        let read_tar = tar.clone();
+        probe.set_dap_register_read_handler(Box::new(move |address| match address {
+            RegisterAddress::ApRegister(0x0C) => {
+                if drw_reads.fetch_add(1, Ordering::SeqCst) == wait_at {
+                    return Err(DapError::WaitResponse.into());
+                }
+                let mut tar = read_tar.lock().unwrap();
+                let value = *tar;
+                *tar += 4;
+                Ok(value)
+            }
+            _ => Ok(0),
+        }));
+        let recorded_writes = tar_writes.clone();
+        probe.set_dap_register_write_handler(Box::new(move |address, value| {
+            if address == RegisterAddress::ApRegister(0x04) {
+                *tar.lock().unwrap() = value;
+                recorded_writes.lock().unwrap().push(value);
+            }
+            Ok(())
+        }));
+
+        (probe, tar_writes)
+    }
+
+    #[test]
+    fn wait_within_block_repeats_the_block_from_its_start() {
+        let (probe, tar_writes) = auto_increment_probe(2);
+        let mut interface = test_interface(
+            ArmCommunicationInterfaceBuilder::new().retry_policy(RetryPolicy {
+                max_retries: 1,
+                ..Default::default()
+            }),
+            Box::new(probe),
+            Some(DebugPortVersion::DPv1),
+        );
+        let ap = FullyQualifiedApAddress::v1_with_default_dp(0);
+
+        // 32-bit transfers, single auto-increment
+        interface.write_raw_ap_register(&ap, 0x00, 0x12).unwrap();
+        interface
+            .write_raw_ap_register(&ap, 0x04, 0x2000_0000)
+            .unwrap();
+
+        let mut values = [0; 4];
+        interface
+            .read_raw_ap_register_repeated(&ap, 0x0C, &mut values)
+            .unwrap();
+
+        assert_eq!(values, [0x2000_0000, 0x2000_0004, 0x2000_0008, 0x2000_000C]);
+        assert_eq!(*tar_writes.lock().unwrap(), [0x2000_0000, 0x2000_0000]);
+        assert_eq!(interface.transfer_stats().unwrap().retries, 1);
+    }
+
+    #[test]
+    fn wait_within_block_is_reported_if_tar_is_unknown() {
+        let (probe, tar_writes) = auto_increment_probe(2);
+        let mut interface = test_interface(
+            ArmCommunicationInterfaceBuilder::new().retry_policy(RetryPolicy {
+                max_retries: 1,
+                ..Default::default()
+            }),
+            Box::new(probe),
+            Some(DebugPortVersion::DPv1),
+        );
+        let ap = FullyQualifiedApAddress::v1_with_default_dp(0);
+
+        // Neither CSW nor TAR were written through the interface, so the block can't be repeated.
+        let mut values = [0; 4];
+        let error = interface
+            .read_raw_ap_register_repeated(&ap, 0x0C, &mut values)
+            .unwrap_err();
+
+        assert!(matches!(error, ArmError::Dap(DapError::WaitResponse)));
+        assert!(tar_writes.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn unchanged_csw_write_is_combined_on_apv2() {
+        const IDR_VALUE: u32 = 0x2477_0011;
+
+        let csw_writes = Arc::new(AtomicUsize::new(0));
+        let mut probe = FakeProbe::new();
+        probe.set_dap_register_read_handler(Box::new(|address| match address {
+            RegisterAddress::ApRegister(0xFC) => Ok(IDR_VALUE),
+            _ => Ok(0),
+        }));
+        let counted_writes = csw_writes.clone();
+        probe.set_dap_register_write_handler(Box::new(move |address, _| {
+            if address == RegisterAddress::ApRegister(0x00) {
+                counted_writes.fetch_add(1, Ordering::SeqCst);
+            }
+            Ok(())
+        }));
+
+        let mut interface = test_interface(
+            ArmCommunicationInterfaceBuilder::new().combine_writes(true),
+            Box::new(probe),
+            Some(DebugPortVersion::DPv3),
+        );
+        let ap = FullyQualifiedApAddress::v2(DpAddress::Default, 0x2000);
+
+        // Reading the IDR marks the access port as a MEM-AP.
+        interface.read_raw_ap_register(&ap, IDR::ADDRESS).unwrap();
+        for csw in [0x2300_0052, 0x2300_0052] {
+            interface
+                .write_raw_ap_register(&ap, CSW::ADDRESS, csw)
+                .unwrap();
+        }
+
+        assert_eq!(csw_writes.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn write_verification_detects_mismatch() {
+        const IDR_VALUE: u32 = 0x2477_0011;
+
+        // All other AP registers read as 0x1234_5678.
+        let mut probe = FakeProbe::new();
+        probe.set_dap_register_read_handler(Box::new(|address| match address {
+            RegisterAddress::ApRegister(0xFC) => Ok(IDR_VALUE),
+            _ => Ok(0x1234_5678),
+        }));
+        probe.set_dap_register_write_handler(Box::new(|_, _| Ok(())));
+        let mut interface = test_interface(
+            ArmCommunicationInterfaceBuilder::new().verify_writes(true),
+            Box::new(probe),
+            Some(DebugPortVersion::DPv1),
+        );
+        let ap = FullyQualifiedApAddress::v1_with_default_dp(0);
+
+        // Reading the IDR marks the access port as a MEM-AP.
+        interface.read_raw_ap_register(&ap, IDR::ADDRESS).unwrap();
+
+        let result = interface.write_raw_ap_register(&ap, TAR::ADDRESS, 0x2000_0000);
+        assert!(matches!(
+            result,
+            Err(ArmError::WriteVerifyMismatch {
+                wrote: 0x2000_0000,
+                read: 0x1234_5678
+            })
+        ));
+
+        // Only the Size and AddrInc fields of CSW are compared.
+        interface
+            .write_raw_ap_register(&ap, CSW::ADDRESS, 0x2300_0070)
+            .unwrap();
+        assert!(
+            interface
+                .write_raw_ap_register(&ap, CSW::ADDRESS, 0x2300_0052)
+                .is_err()
+        );
+
+        // DRW is not read back, as reading it would access memory.
+        interface
+            .write_raw_ap_register(&ap, DRW::ADDRESS, 0x2000_0000)
+            .unwrap();
+    }
+
+    #[test]
+    fn write_verification_skips_other_access_ports() {
+        // The IDR doesn't describe a MEM-AP, so TAR may be a register with side effects.
+        let (mut interface, _writes) = interface_with_overrun_faults(0);
+        interface.verify_writes = true;
+        let ap = FullyQualifiedApAddress::v1_with_default_dp(0);
+
+        interface.read_raw_ap_register(&ap, IDR::ADDRESS).unwrap();
+        interface
+            .write_raw_ap_register(&ap, TAR::ADDRESS, 0x2000_0000)
+            .unwrap();
+    }
+
+    /// Creates an interface connected to the default DP, whose AP 0 is an AMBA AHB3 memory AP
+    /// with the given CFG.
+    fn interface_with_mem_ap_cfg(cfg: u32) -> (RecordingProbe, ArmCommunicationInterface) {
+        const IDR_AHB3: u32 = 0x2477_0011;
+        const CSW_VALUE: u32 = 0x2300_0002;
+
+        let recorder = RecordingProbe::new([IDR_AHB3, CSW_VALUE, cfg]);
+        let interface = test_interface(
+            ArmCommunicationInterfaceBuilder::new(),
+            Box::new(recorder.clone()),
+            Some(DebugPortVersion::DPv1),
+        );
+
+        (recorder, interface)
+    }
+
+    #[test]
+    fn large_tar_requires_long_address_extension() {
+        let (recorder, mut interface) = interface_with_mem_ap_cfg(0);
+        let ap = FullyQualifiedApAddress::v1_with_default_dp(0);
+
+        assert_eq!(interface.read_mem_ap_cfg(&ap).unwrap(), MemApCfg::default());
+        recorder.clear_transfers();
+
+        assert!(matches!(
+            interface.write_mem_ap_tar64(&ap, 0x1_0000_0000),
+            Err(ArmError::AddressOutOfRange(0x1_0000_0000))
+        ));
+        // CFG is not read again, and neither TAR nor TAR2 was written.
+        assert!(recorder.transfers().is_empty());
+    }
+
+    #[test]
+    fn large_tar_uses_the_cached_cfg() {
+        // CFG.LA
+        let (recorder, mut interface) = interface_with_mem_ap_cfg(0b10);
+        let ap = FullyQualifiedApAddress::v1_with_default_dp(0);
+
+        // The memory AP, and with it CFG, is set up by the first access.
+        interface.write_mem_ap_tar64(&ap, 0x1_2000_0000).unwrap();
+        interface.write_mem_ap_tar64(&ap, 0x2_2000_0000).unwrap();
+
+        let cfg_reads = recorder
+            .transfers()
+            .into_iter()
+            .filter(|transfer| {
+                matches!(
+                    transfer,
+                    RecordedTransfer::Read {
+                        address: RegisterAddress::ApRegister(0xF4),
+                        ..
+                    }
+                )
+            })
+            .count();
+        assert_eq!(cfg_reads, 1);
+    }
+
+    /// Connects to the default DP through the default sequence with write combining enabled,
+    /// and reads the IDR of AP 0, which marks it as a MEM-AP.
+    fn connected_combining_interface() -> (ArmCommunicationInterface, RecordingProbe) {
+        // An AHB-AP IDR.
+        const IDR_VALUE: u32 = 0x2477_0011;
+
+        let recorder = RecordingProbe::new(CONNECT_READS.into_iter().chain([IDR_VALUE]));
+        let mut interface = ArmCommunicationInterfaceBuilder::new()
+            .combine_writes(true)
+            .build_interface(Box::new(recorder.clone()));
+
+        interface.select_debug_port(DpAddress::Default).unwrap();
+        let ap = FullyQualifiedApAddress::v1_with_default_dp(0);
+        assert_eq!(
+            interface.read_raw_ap_register(&ap, IDR::ADDRESS).unwrap(),
+            IDR_VALUE
+        );
+        recorder.clear_transfers();
+
+        (interface, recorder)
+    }
+
+    fn csw_writes(recorder: &RecordingProbe) -> usize {
+        recorder
+            .transfers()
+            .into_iter()
+            .filter(|transfer| {
+                matches!(
+                    transfer,
+                    RecordedTransfer::Write {
+                        address: RegisterAddress::ApRegister(0x00),
+                        ..
+                    }
+                )
+            })
+            .count()
+    }
+
+    #[test]
+    fn unchanged_csw_write_is_combined() {
+        let (mut interface, recorder) = connected_combining_interface();
+        let ap = FullyQualifiedApAddress::v1_with_default_dp(0);
+
+        for csw in [0x2300_0052, 0x2300_0052, 0x2300_0042] {
+            interface
+                .write_raw_ap_register(&ap, CSW::ADDRESS, csw)
+                .unwrap();
+        }
+
+        assert_eq!(csw_writes(&recorder), 2);
+        assert_eq!(interface.transfer_stats().unwrap().combined_writes, 1);
+    }
+
+    #[test]
+    fn ctrl_stat_writes_are_not_combined() {
+        let (mut interface, recorder) = connected_combining_interface();
+
+        for _ in 0..2 {
+            interface
+                .write_raw_dp_register(DpAddress::Default, Ctrl::ADDRESS, 0x5000_0000)
+                .unwrap();
+        }
+
+        let ctrl_writes = recorder
+            .transfers()
+            .into_iter()
+            .filter(|transfer| {
+                matches!(
+                    transfer,
+                    RecordedTransfer::Write {
+                        address: RegisterAddress::DpRegister(Ctrl::ADDRESS),
+                        ..
+                    }
+                )
+            })
+            .count();
+        assert_eq!(ctrl_writes, 2);
+    }
+
+    #[test]
+    fn clearing_sticky_errors_forgets_written_values() {
+        let (mut interface, recorder) = connected_combining_interface();
+        let ap = FullyQualifiedApAddress::v1_with_default_dp(0);
+
+        interface
+            .write_raw_ap_register(&ap, CSW::ADDRESS, 0x2300_0052)
+            .unwrap();
+        let mut abort = Abort(0);
+        abort.set_stkerrclr(true);
+        interface
+            .write_dp_register(DpAddress::Default, abort)
+            .unwrap();
+        interface
+            .write_raw_ap_register(&ap, CSW::ADDRESS, 0x2300_0052)
+            .unwrap();
+
+        assert_eq!(csw_writes(&recorder), 2);
+    }
+
+    #[test]
+    fn resync_does_not_power_down_the_debug_port() {
+        let recorder = RecordingProbe::new(CONNECT_READS);
+
+        let mut interface = test_interface(
+            ArmCommunicationInterfaceBuilder::new(),
+            Box::new(recorder.clone()),
+            Some(DebugPortVersion::DPv1),
+        );
+
+        interface.resync(DpAddress::Default).unwrap();
+
+        assert_eq!(interface.current_debug_port(), Some(DpAddress::Default));
+        assert!(!recorder.transfers().contains(&RecordedTransfer::Write {
+            address: RegisterAddress::DpRegister(Ctrl::ADDRESS),
+            value: 0,
+        }));
+    }
+
+    #[test]
+    fn silent_debug_port_is_reported() {
+        let mut probe = FakeProbe::new();
+        probe.set_dap_register_read_handler(Box::new(|_| Err(DapError::NoAcknowledge.into())));
+        probe.set_dap_register_write_handler(Box::new(|_, _| Err(DapError::NoAcknowledge.into())));
+
+        let mut interface = test_interface(
+            ArmCommunicationInterfaceBuilder::new(),
+            Box::new(probe),
+            None,
+        );
+        interface.current_dp = Some(DpAddress::Default);
+
+        let error = interface.access_ports(DpAddress::Default).unwrap_err();
+        assert!(matches!(
+            error,
+            ArmError::NoDebugPort {
+                dp: DpAddress::Default,
+                ..
+            }
+        ));
+
+        let diagnostics = interface.last_connect_diagnostics().unwrap();
+        assert_eq!(diagnostics.dp, DpAddress::Default);
+        assert_eq!(diagnostics.dpidr, None);
+        assert_eq!(diagnostics.ctrl_stat, None);
+        assert_eq!(diagnostics.retries, 0);
+    }
+
+    #[test]
+    fn target_settles_after_reset_release() {
+        const SETTLE_DELAY: Duration = Duration::from_millis(20);
+
+        let recorder = RecordingProbe::new(std::iter::empty());
+
+        let mut interface = test_interface(
+            ArmCommunicationInterfaceBuilder::new().reset_settle_delay(SETTLE_DELAY),
+            Box::new(recorder),
+            Some(DebugPortVersion::DPv1),
+        );
+
+        let mut n_reset = Pins(0);
+        n_reset.set_nreset(true);
+        let n_reset = u32::from(n_reset.0);
+
+        let start = Instant::now();
+        interface.swj_pins(n_reset, n_reset, 0).unwrap();
+        interface
+            .read_raw_dp_register(DpAddress::Default, Ctrl::ADDRESS)
+            .unwrap();
+        assert!(start.elapsed() >= SETTLE_DELAY);
+
+        // The delay is only applied once per reset release.
+        let start = Instant::now();
+        interface
+            .read_raw_dp_register(DpAddress::Default, Ctrl::ADDRESS)
+            .unwrap();
+        assert!(start.elapsed() < SETTLE_DELAY);
+    }
+
+    #[test]
+    fn pre_connect_sequence_is_sent_before_setup() {
+        let recorder = RecordingProbe::new(std::iter::empty());
+
+        let mut interface = test_interface(
+            ArmCommunicationInterfaceBuilder::new().pre_connect_sequence([(8, 0xA5), (4, 0x3)]),
+            Box::new(recorder.clone()),
+            None,
+        );
+
+        // The recording probe answers all reads with zero, so the setup itself may fail.
+        interface.run_debug_port_setup(DpAddress::Default).ok();
+
+        assert_eq!(
+            recorder.transfers()[..2],
+            [
+                RecordedTransfer::SwjSequence {
+                    bit_len: 8,
+                    bits: 0xA5
+                },
+                RecordedTransfer::SwjSequence {
+                    bit_len: 4,
+                    bits: 0x3
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn adopted_debug_port_is_not_set_up_again() {
+        // CSYSPWRUPACK | CSYSPWRUPREQ | CDBGPWRUPACK | CDBGPWRUPREQ
+        const CTRL_POWERED: u32 = 0xF000_0000;
+
+        let recorder = RecordingProbe::new([CTRL_POWERED]);
+
+        let mut interface = ArmCommunicationInterfaceBuilder::new()
+            .adopt_connected(
+                Box::new(recorder.clone()),
+                DpAddress::Default,
+                DebugPortVersion::DPv1,
+            )
+            .unwrap();
+
+        let ctrl = interface
+            .read_raw_dp_register(DpAddress::Default, Ctrl::ADDRESS)
+            .unwrap();
+        assert_eq!(ctrl, CTRL_POWERED);
+
+        // Only SELECT is written to get the cache in sync, no sequences are run.
+        assert_eq!(
+            recorder.transfers(),
+            [
+                RecordedTransfer::Write {
+                    address: RegisterAddress::DpRegister(SelectV1::ADDRESS),
+                    value: 0,
+                },
+                RecordedTransfer::Read {
+                    address: RegisterAddress::DpRegister(Ctrl::ADDRESS),
+                    value: CTRL_POWERED,
+                },
+            ]
+        );
+        assert_eq!(interface.current_debug_port(), Some(DpAddress::Default));
+        assert_eq!(interface.known_debug_ports(), [DpAddress::Default]);
+        assert_eq!(
+            interface.debug_port_version(DpAddress::Default),
+            Some(DebugPortVersion::DPv1)
+        );
+    }
+
+    #[test]
+    fn pipelined_ap_reads_are_returned_in_order() {
+        let recorder = RecordingProbe::new([0x11, 0x22, 0x33]);
+        let mut interface = ArmCommunicationInterfaceBuilder::new()
+            .adopt_connected(
+                Box::new(recorder.clone()),
+                DpAddress::Default,
+                DebugPortVersion::DPv1,
+            )
+            .unwrap();
+        recorder.clear_transfers();
+
+        let ap = FullyQualifiedApAddress::v1_with_default_dp(0);
+        let values = interface.read_ap_pipelined(&ap, DRW::ADDRESS, 3).unwrap();
+
+        assert_eq!(values, [0x11, 0x22, 0x33]);
+        assert_eq!(
+            recorder.transfers(),
+            [0x11, 0x22, 0x33].map(|value| RecordedTransfer::Read {
+                address: RegisterAddress::ApRegister(DRW::ADDRESS as u8),
+                value,
+            })
+        );
+    }
+
+    #[test]
+    fn probe_is_returned_if_adopting_fails() {
+        let mut probe = FakeProbe::new();
+        probe.set_dap_register_write_handler(Box::new(|_, _| {
+            Err(ArmError::Dap(DapError::NoAcknowledge))
+        }));
+
+        let Err((probe, error)) = ArmCommunicationInterfaceBuilder::new().adopt_connected(
+            Box::new(probe),
+            DpAddress::Default,
+            DebugPortVersion::DPv1,
+        ) else {
+            panic!("adopting the debug port should fail");
+        };
+
+        assert!(matches!(error, ArmError::Dap(DapError::NoAcknowledge)));
+        assert_eq!(probe.get_name(), "Mock probe for testing");
+    }
+
+    #[test]
+    fn push_compare_reports_mismatch() {
+        // 32 bit transfers with single auto increment.
+        const CSW_VALUE: u32 = 0x2300_0012;
+        const STICKYCMP: u32 = 1 << 4;
+
+        // CSW, CTRL/STAT before and after the compare.
+        let recorder = RecordingProbe::new([CSW_VALUE, 0, STICKYCMP]);
+        let mut interface = ArmCommunicationInterfaceBuilder::new()
+            .adopt_connected(
+                Box::new(recorder.clone()),
+                DpAddress::Default,
+                DebugPortVersion::DPv1,
+            )
+            .unwrap();
+
+        let ap = FullyQualifiedApAddress::v1_with_default_dp(0);
+        let matches = interface
+            .push_compare(&ap, 0x2000_0000, 0xDEAD_BEEF)
+            .unwrap();
+        assert!(!matches);
+
+        let transfers = recorder.transfers();
+        assert!(transfers.contains(&RecordedTransfer::Write {
+            address: RegisterAddress::DpRegister(Abort::ADDRESS),
+            value: 0b10,
+        }));
+
+        // CSW and TRNMODE are restored.
+        let last_write_to = |address| {
+            transfers.iter().rev().find_map(|transfer| match transfer {
+                RecordedTransfer::Write { address: a, value } if *a == address => Some(*value),
+                _ => None,
+            })
+        };
+        assert_eq!(
+            last_write_to(RegisterAddress::ApRegister(0x00)),
+            Some(CSW_VALUE)
+        );
+        assert_eq!(
+            last_write_to(RegisterAddress::DpRegister(Ctrl::ADDRESS)),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn push_compare_clears_stickycmp_over_jtag() {
+        const CSW_VALUE: u32 = 0x2300_0012;
+        const STICKYCMP: u32 = 1 << 4;
+
+        // CSW, CTRL/STAT before and after the compare.
+        let recorder = RecordingProbe::new([CSW_VALUE, 0, STICKYCMP]);
+        let mut probe = recorder.clone();
+        probe.select_protocol(WireProtocol::Jtag).unwrap();
+        let mut interface = ArmCommunicationInterfaceBuilder::new()
+            .adopt_connected(Box::new(probe), DpAddress::Default, DebugPortVersion::DPv1)
+            .unwrap();
+
+        let ap = FullyQualifiedApAddress::v1_with_default_dp(0);
+        let matches = interface
+            .push_compare(&ap, 0x2000_0000, 0xDEAD_BEEF)
+            .unwrap();
+        assert!(!matches);
+
+        // ABORT.STKCMPCLR is reserved on JTAG-DPs.
+        let transfers = recorder.transfers();
+        assert!(!transfers.iter().any(|transfer| matches!(
+            transfer,
+            RecordedTransfer::Write {
+                address: RegisterAddress::DpRegister(address),
+                ..
+            } if *address == Abort::ADDRESS
+        )));
+        assert_eq!(
+            transfers.last(),
+            Some(&RecordedTransfer::Write {
+                address: RegisterAddress::DpRegister(Ctrl::ADDRESS),
+                value: STICKYCMP,
+            })
+        );
+    }
+
+    #[test]
+    fn repeated_push_compare_is_split_at_autoincrement_boundaries() {
+        const CSW_VALUE: u32 = 0x2300_0012;
+        // TRNCNT = 1 | TRNMODE = pushed-compare
+        const COMPARE_CTRL: u32 = (1 << 12) | (0b10 << 2);
+
+        // CSW, CTRL/STAT before and after each block.
+        let recorder = RecordingProbe::new([CSW_VALUE, 0, 0, 0]);
+        let mut interface = ArmCommunicationInterfaceBuilder::new()
+            .adopt_connected(
+                Box::new(recorder.clone()),
+                DpAddress::Default,
+                DebugPortVersion::DPv1,
+            )
+            .unwrap();
+        recorder.clear_transfers();
+
+        let ap = FullyQualifiedApAddress::v1_with_default_dp(0);
+        let matches = interface
+            .push_compare_repeated(&ap, 0x2000_03F8, 0xFFFF_FFFF, 4)
+            .unwrap();
+        assert!(matches);
+
+        let writes_to = |address| {
+            recorder
+                .transfers()
+                .into_iter()
+                .filter_map(|transfer| match transfer {
+                    RecordedTransfer::Write { address: a, value } if a == address => Some(value),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            writes_to(RegisterAddress::ApRegister(0x04)),
+            [0x2000_03F8, 0x2000_0400]
+        );
+        assert_eq!(
+            writes_to(RegisterAddress::ApRegister(0x0C)),
+            [0xFFFF_FFFF, 0xFFFF_FFFF]
+        );
+        // TRNCNT and TRNMODE are reset to 0 afterwards.
+        assert_eq!(
+            writes_to(RegisterAddress::DpRegister(Ctrl::ADDRESS)),
+            [COMPARE_CTRL, COMPARE_CTRL, 0]
+        );
+    }
+
+    #[test]
+    fn debug_port_id_is_cached_while_connecting() {
+        let recorder = RecordingProbe::new(CONNECT_READS);
+        let mut interface =
+            ArmCommunicationInterfaceBuilder::new().build(Box::new(recorder.clone()));
+        assert!(interface.debug_port_id(DpAddress::Default).is_none());
+
+        interface.select_debug_port(DpAddress::Default).unwrap();
+        recorder.clear_transfers();
+
+        let id = interface.debug_port_id(DpAddress::Default).unwrap();
+        assert_eq!(id.version, DebugPortVersion::DPv1);
+        assert_eq!(id.revision, 2);
+        assert!(recorder.transfers().is_empty());
+    }
+
+    #[test]
+    fn resync_select_writes_the_cache_over_swd() {
+        let recorder = RecordingProbe::new([]);
+        let mut interface = ArmCommunicationInterfaceBuilder::new()
+            .adopt_connected(
+                Box::new(recorder.clone()),
+                DpAddress::Default,
+                DebugPortVersion::DPv1,
+            )
+            .unwrap();
+
+        let ap = FullyQualifiedApAddress::v1_with_default_dp(2);
+        interface.read_raw_ap_register(&ap, 0x0C).unwrap();
+        recorder.clear_transfers();
+
+        interface.resync_select(DpAddress::Default).unwrap();
+
+        let mut select = SelectV1(0);
+        select.set_ap_sel(2);
+        assert_eq!(
+            recorder.transfers(),
+            [RecordedTransfer::Write {
+                address: RegisterAddress::DpRegister(SelectV1::ADDRESS),
+                value: select.into(),
+            }]
+        );
+    }
+
+    #[test]
+    fn resync_select_reads_select_over_jtag() {
+        let mut select = SelectV1(0);
+        select.set_ap_sel(3);
+        select.set_ap_bank_sel(0xF);
+
+        let mut recorder = RecordingProbe::new([select.into()]);
+        recorder.select_protocol(WireProtocol::Jtag).unwrap();
+        let mut interface = ArmCommunicationInterfaceBuilder::new()
+            .adopt_connected(
+                Box::new(recorder.clone()),
+                DpAddress::Default,
+                DebugPortVersion::DPv1,
+            )
+            .unwrap();
+
+        interface.resync_select(DpAddress::Default).unwrap();
+        assert_eq!(
+            interface.save_select_state(DpAddress::Default),
+            Some(SelectCache::DPv1(select))
+        );
+
+        // The cache now matches the debug port, so no SELECT write is needed.
+        recorder.clear_transfers();
+        let ap = FullyQualifiedApAddress::v1_with_default_dp(3);
+        interface.read_raw_ap_register(&ap, 0xFC).unwrap();
+        assert!(!recorder.transfers().iter().any(|transfer| matches!(
+            transfer,
+            RecordedTransfer::Write {
+                address: RegisterAddress::DpRegister(_),
+                ..
+            }
+        )));
+    }
+
+    #[test]
+    fn identify_without_rom_table_returns_none() {
+        let recorder = RecordingProbe::new(CONNECT_READS);
+
+        let chip_info = identify(
+            Box::new(recorder.clone()),
+            DefaultArmSequence::create(),
+            DpAddress::Default,
+        )
+        .unwrap();
+        assert!(chip_info.is_none());
+        assert!(!recorder.transfers().is_empty());
+    }
+
+    /// A sequence whose debug port setup only succeeds using the given wire protocol, like a
+    /// target which only responds to that protocol.
+    #[derive(Debug)]
+    struct RespondsTo(Option<WireProtocol>);
+
+    impl ArmDebugSequence for RespondsTo {
+        fn debug_port_setup(
+            &self,
+            interface: &mut dyn DapProbe,
+            _dp: DpAddress,
+        ) -> Result<(), ArmError> {
+            match interface.active_protocol() {
+                protocol if protocol.is_some() && protocol == self.0 => Ok(()),
+                _ => Err(DapError::NoAcknowledge.into()),
+            }
+        }
+    }
+
+    #[test]
+    fn debug_port_setup_falls_back_to_the_alternative_protocol() {
+        let mut interface = ArmCommunicationInterfaceBuilder::new()
+            .sequence(Arc::new(RespondsTo(Some(WireProtocol::Jtag))))
+            .build_interface(Box::new(RecordingProbe::new([])));
+
+        interface.setup_debug_port(DpAddress::Default).unwrap();
+        assert_eq!(
+            interface.probe_mut().active_protocol(),
+            Some(WireProtocol::Jtag)
+        );
+    }
+
+    #[test]
+    fn wire_protocol_mismatch_is_reported() {
+        let mut interface = ArmCommunicationInterfaceBuilder::new()
+            .sequence(Arc::new(RespondsTo(None)))
+            .build_interface(Box::new(RecordingProbe::new([])));
+
+        let error = interface.setup_debug_port(DpAddress::Default).unwrap_err();
+        assert!(matches!(
+            error,
+            ArmError::WireProtocolMismatch {
+                configured: WireProtocol::Swd,
+                alternative: WireProtocol::Jtag,
+            }
+        ));
+
+        // The probe is left using the configured protocol.
+        assert_eq!(
+            interface.probe_mut().active_protocol(),
+            Some(WireProtocol::Swd)
+        );
+    }
+
+    #[test]
+    fn explicit_wire_protocol_disables_the_fallback() {
+        let mut interface = ArmCommunicationInterfaceBuilder::new()
+            .sequence(Arc::new(RespondsTo(Some(WireProtocol::Swd))))
+            .build_interface(Box::new(RecordingProbe::new([])));
+
+        interface.set_wire_protocol(WireProtocol::Jtag).unwrap();
+        assert_eq!(
+            interface.probe_mut().active_protocol(),
+            Some(WireProtocol::Jtag)
+        );
+
+        interface.select_debug_port(DpAddress::Default).unwrap_err();
+        assert_eq!(
+            interface.probe_mut().active_protocol(),
+            Some(WireProtocol::Jtag)
+        );
+    }
+
+    #[test]
+    fn wire_protocol_is_switched_on_reinitialize() {
+        /// A target which responds to both wire protocols.
+        #[derive(Debug)]
+        struct AnyProtocol;
+
+        impl ArmDebugSequence for AnyProtocol {
+            fn debug_port_setup(
+                &self,
+                _interface: &mut dyn DapProbe,
+                _dp: DpAddress,
+            ) -> Result<(), ArmError> {
+                Ok(())
+            }
+
+            fn debug_port_start(
+                &self,
+                _interface: &mut dyn DapAccess,
+                _dp: DpAddress,
+            ) -> Result<(), ArmError> {
+                Ok(())
+            }
+
+            fn debug_port_stop(
+                &self,
+                _interface: &mut dyn DapProbe,
+                _dp: DpAddress,
+            ) -> Result<(), ArmError> {
+                Ok(())
+            }
+        }
+
+        let recorder = RecordingProbe::new([]);
+        let mut interface = ArmCommunicationInterfaceBuilder::new()
+            .sequence(Arc::new(AnyProtocol))
+            .build_interface(Box::new(recorder.clone()));
+        interface.select_debug_port(DpAddress::Default).unwrap();
+        recorder.clear_transfers();
+
+        // The connected debug port keeps using SWD until the interface is reinitialized.
+        let error = interface.set_wire_protocol(WireProtocol::Jtag).unwrap_err();
+        assert!(matches!(
+            error,
+            ArmError::ReinitializeRequired(WireProtocol::Jtag)
+        ));
+        assert_eq!(
+            interface.probe_mut().active_protocol(),
+            Some(WireProtocol::Swd)
+        );
+        assert!(recorder.transfers().is_empty());
 
-        Box::new(interface)
+        interface.reinitialize().unwrap();
+        assert_eq!(
+            interface.probe_mut().active_protocol(),
+            Some(WireProtocol::Jtag)
+        );
+        assert_eq!(interface.current_debug_port(), Some(DpAddress::Default));
     }
 
-    /// Inform the probe of the [`CoreStatus`] of the chip attached to the probe.
-    pub fn core_status_notification(&mut self, state: CoreStatus) {
-        self.probe_mut().core_status_notification(state).ok();
+    #[test]
+    fn unsupported_wire_protocol_is_rejected_while_connected() {
+        let mut probe = FakeProbe::new();
+        probe.set_supported_protocols(&[WireProtocol::Swd]);
+        probe.set_dap_register_read_handler(Box::new(|_| Ok(0)));
+        probe.set_dap_register_write_handler(Box::new(|_, _| Ok(())));
+        let mut interface = test_interface(
+            ArmCommunicationInterfaceBuilder::new(),
+            Box::new(probe),
+            Some(DebugPortVersion::DPv1),
+        );
+
+        let error = interface.set_wire_protocol(WireProtocol::Jtag).unwrap_err();
+        assert!(matches!(
+            error,
+            ArmError::ProtocolNotSupported(WireProtocol::Jtag)
+        ));
+        assert!(interface.pending_wire_protocol.is_none());
+
+        // Selecting the protocol which is already used doesn't need a reinitialize.
+        interface.set_wire_protocol(WireProtocol::Swd).unwrap();
+        assert!(interface.pending_wire_protocol.is_none());
+        assert!(interface.fixed_wire_protocol);
     }
 
-    fn select_dp(&mut self, dp: DpAddress) -> Result<&mut DpState, ArmError> {
-        let mut switched_dp = false;
+    #[test]
+    fn disconnect_errors_are_collected() {
+        #[derive(Debug)]
+        struct FailingStop;
 
-        let sequence = self.sequence.clone();
+        impl ArmDebugSequence for FailingStop {
+            fn debug_port_stop(
+                &self,
+                _interface: &mut dyn DapProbe,
+                _dp: DpAddress,
+            ) -> Result<(), ArmError> {
+                Err(ArmError::Timeout)
+            }
+        }
 
-        if self.current_dp != Some(dp) {
-            tracing::debug!("Selecting DP {:x?}", dp);
+        let recorder = RecordingProbe::new(CONNECT_READS);
+        let mut interface = ArmCommunicationInterfaceBuilder::new()
+            .sequence(Arc::new(FailingStop))
+            .collect_disconnect_errors(true)
+            .build_interface(Box::new(recorder));
 
-            switched_dp = true;
+        interface.select_debug_port(DpAddress::Default).unwrap();
+        assert!(interface.take_disconnect_errors().unwrap().is_empty());
 
-            self.probe_mut().raw_flush()?;
+        interface.disconnect();
 
-            // We are not currently connected to any DP,
-            // so we need to run the debug_port_setup sequence.
-            if self.current_dp.is_none() {
-                sequence.debug_port_setup(&mut *self.probe_mut(), dp)?;
-            } else {
-                // Try to switch to the new DP.
-                if let Err(e) = sequence.debug_port_connect(&mut *self.probe_mut(), dp) {
-                    tracing::warn!("Failed to switch to DP {:x?}: {}", dp, e);
+        let errors = interface.take_disconnect_errors().unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, DpAddress::Default);
+        assert!(matches!(errors[0].1, ArmError::Timeout));
+        assert!(interface.take_disconnect_errors().unwrap().is_empty());
+    }
 
-                    // Try the more involved debug_port_setup sequence, which also handles dormant mode.
-                    sequence.debug_port_setup(&mut *self.probe_mut(), dp)?;
-                }
-            }
+    #[test]
+    fn ap_exists_checks_the_idr() {
+        // An AHB-AP IDR.
+        const IDR_VALUE: u32 = 0x2477_0011;
 
-            self.current_dp = Some(dp);
-        }
+        let mut interface = ArmCommunicationInterfaceBuilder::new()
+            .adopt_connected(
+                Box::new(RecordingProbe::new([IDR_VALUE, 0])),
+                DpAddress::Default,
+                DebugPortVersion::DPv1,
+            )
+            .unwrap();
 
-        // If we don't have  a state for this DP, this means that we haven't run the necessary init sequence yet.
-        if let hash_map::Entry::Vacant(entry) = self.dps.entry(dp) {
-            let sequence = self.sequence.clone();
+        let ap0 = FullyQualifiedApAddress::v1_with_default_dp(0);
+        let ap1 = FullyQualifiedApAddress::v1_with_default_dp(1);
+        assert!(interface.ap_exists(&ap0).unwrap());
+        assert!(!interface.ap_exists(&ap1).unwrap());
+    }
 
-            entry.insert(DpState::new());
+    #[test]
+    fn failed_post_connect_validation_aborts_the_connect() {
+        // An AHB-AP IDR.
+        const IDR_VALUE: u32 = 0x2477_0011;
 
-            let start_span = tracing::debug_span!("debug_port_start").entered();
-            sequence.debug_port_start(self, dp)?;
-            drop(start_span);
+        let recorder = RecordingProbe::new(CONNECT_READS.into_iter().chain([IDR_VALUE]));
+        let validations = Arc::new(AtomicUsize::new(0));
 
-            // Make sure we enable the overrun detect mode when requested.
-            // For "bit-banging" probes, such as JLink or FTDI, we rely on it for good, stable communication.
-            // This is required as the default sequence (and most special implementations) does not do this.
-            let mut ctrl_reg: Ctrl = self.read_dp_register(dp)?;
-            if ctrl_reg.orun_detect() != self.use_overrun_detect {
-                tracing::debug!("Setting orun_detect: {}", self.use_overrun_detect);
-                // only write if there’s a need for it.
-                ctrl_reg.set_orun_detect(self.use_overrun_detect);
-                self.write_dp_register(dp, ctrl_reg)?;
-            }
+        let mut interface = ArmCommunicationInterfaceBuilder::new()
+            .post_connect_validate(FullyQualifiedApAddress::v1_with_default_dp(0), {
+                let validations = validations.clone();
+                move |_memory| {
+                    validations.fetch_add(1, Ordering::Relaxed);
+                    Err(ArmError::Other("wrong target".to_string()))
+                }
+            })
+            .build(Box::new(recorder));
 
-            let idr: DebugPortId = self.read_dp_register::<DPIDR>(dp)?.into();
-            tracing::info!(
-                "Debug Port version: {} MinDP: {:?}",
-                idr.version,
-                idr.min_dp_support
-            );
+        let error = interface.select_debug_port(DpAddress::Default).unwrap_err();
+        assert!(matches!(error, ArmError::Other(_)));
+        assert_eq!(validations.load(Ordering::Relaxed), 1);
+        assert!(interface.debug_port_version(DpAddress::Default).is_none());
+    }
 
-            let state = self
-                .dps
-                .get_mut(&dp)
-                .expect("This DP State was inserted earlier in this function");
-            state.debug_port_version = idr.version;
-            if idr.version == DebugPortVersion::DPv3 {
-                state.current_select = SelectCache::DPv3(SelectV3(0), Select1(0));
+    #[test]
+    fn parity_errors_are_recovered_using_resend() {
+        const VALUE: u32 = 0xCAFE_F00D;
+
+        let ap_reads = Arc::new(AtomicUsize::new(0));
+
+        let mut probe = FakeProbe::new();
+        probe.set_dap_register_read_handler(Box::new({
+            let ap_reads = ap_reads.clone();
+            move |address| match address {
+                RegisterAddress::ApRegister(_) => {
+                    ap_reads.fetch_add(1, Ordering::SeqCst);
+                    Err(DapError::IncorrectParity.into())
+                }
+                RegisterAddress::DpRegister(address) if address == Resend::ADDRESS => Ok(VALUE),
+                _ => Ok(0),
             }
-        } else if switched_dp {
-            let sequence = self.sequence.clone();
+        }));
+        probe.set_dap_register_write_handler(Box::new(|_, _| Ok(())));
 
-            let start_span = tracing::debug_span!("debug_port_start").entered();
-            sequence.debug_port_start(self, dp)?;
-            drop(start_span);
-        }
+        let mut interface = ArmCommunicationInterfaceBuilder::new()
+            .adopt_connected(Box::new(probe), DpAddress::Default, DebugPortVersion::DPv1)
+            .unwrap();
 
-        // note(unwrap): Entry gets inserted above
-        Ok(self.dps.get_mut(&dp).unwrap())
+        let ap = FullyQualifiedApAddress::v1_with_default_dp(0);
+        assert_eq!(interface.read_raw_ap_register(&ap, 0x0C).unwrap(), VALUE);
+        assert_eq!(ap_reads.load(Ordering::SeqCst), 1);
     }
 
-    fn select_dp_and_dp_bank(
-        &mut self,
-        dp: DpAddress,
-        dp_register_address: &DpRegisterAddress,
-    ) -> Result<(), ArmError> {
-        let dp_state = self.select_dp(dp)?;
+    #[test]
+    fn full_topology_scans_the_connected_debug_port() {
+        let mut interface = ArmCommunicationInterfaceBuilder::new()
+            .adopt_connected(
+                Box::new(RecordingProbe::new([])),
+                DpAddress::Multidrop(0x0100_2927),
+                DebugPortVersion::DPv2,
+            )
+            .unwrap();
 
-        // DP register addresses are 4 bank bits, 4 address bits. Lowest 2 address bits are
-        // always 0, so this leaves only 4 possible addresses: 0x0, 0x4, 0x8, 0xC.
-        // On ADIv5, only address 0x4 is banked, the rest are don't care.
-        // On ADIv6, address 0x0 and 0x4 are banked, the rest are don't care.
+        let topology = interface.full_topology(&[]).unwrap();
+        assert_eq!(topology.len(), 1);
+        assert_eq!(topology[0].dp, DpAddress::Multidrop(0x0100_2927));
+        assert!(topology[0].access_ports.as_ref().unwrap().is_empty());
+    }
 
-        let &DpRegisterAddress {
-            bank,
-            address: addr,
-        } = dp_register_address;
+    #[test]
+    fn full_topology_scans_the_multidrop_targets() {
+        const PRESENT: u32 = 0x0100_2927;
+        const FAILING: u32 = 0x1100_2927;
+        const ABSENT: u32 = 0x2100_2927;
 
-        if addr != 0 && addr != 4 {
-            return Ok(());
+        /// A multidrop bus on which the debug port with the `FAILING` target ID responds, but
+        /// can't be started.
+        #[derive(Debug)]
+        struct MultidropBus;
+
+        impl MultidropBus {
+            fn respond(dp: DpAddress) -> Result<(), ArmError> {
+                match dp {
+                    DpAddress::Multidrop(PRESENT | FAILING) => Ok(()),
+                    _ => Err(DapError::NoAcknowledge.into()),
+                }
+            }
         }
 
-        let bank = bank.unwrap_or(0);
+        impl ArmDebugSequence for MultidropBus {
+            fn debug_port_setup(
+                &self,
+                _interface: &mut dyn DapProbe,
+                dp: DpAddress,
+            ) -> Result<(), ArmError> {
+                Self::respond(dp)
+            }
 
-        if bank != dp_state.current_select.dp_bank_sel() {
-            dp_state.current_select.set_dp_bank_sel(bank);
+            fn debug_port_connect(
+                &self,
+                _interface: &mut dyn DapProbe,
+                dp: DpAddress,
+            ) -> Result<(), ArmError> {
+                Self::respond(dp)
+            }
 
-            tracing::debug!("Changing DP_BANK_SEL to {:x?}", dp_state.current_select);
+            fn debug_port_start(
+                &self,
+                _interface: &mut dyn DapAccess,
+                dp: DpAddress,
+            ) -> Result<(), ArmError> {
+                match dp {
+                    DpAddress::Multidrop(FAILING) => Err(ArmError::Timeout),
+                    _ => Ok(()),
+                }
+            }
 
-            match dp_state.current_select {
-                SelectCache::DPv1(select) => self.write_dp_register(dp, select)?,
-                SelectCache::DPv3(select, _) => self.write_dp_register(dp, select)?,
+            fn debug_port_stop(
+                &self,
+                _interface: &mut dyn DapProbe,
+                _dp: DpAddress,
+            ) -> Result<(), ArmError> {
+                Ok(())
             }
         }
 
-        Ok(())
+        let mut interface = ArmCommunicationInterfaceBuilder::new()
+            .sequence(Arc::new(MultidropBus))
+            .build_interface(Box::new(RecordingProbe::new([])));
+
+        let topology = interface
+            .full_topology(&[ABSENT, FAILING, PRESENT])
+            .unwrap();
+
+        assert_eq!(topology.len(), 2);
+        assert_eq!(topology[0].dp, DpAddress::Multidrop(PRESENT));
+        assert!(topology[0].access_ports.as_ref().unwrap().is_empty());
+        assert_eq!(topology[1].dp, DpAddress::Multidrop(FAILING));
+        assert!(matches!(topology[1].access_ports, Err(ArmError::Timeout)));
     }
 
-    fn select_ap_and_ap_bank(
-        &mut self,
-        ap: &FullyQualifiedApAddress,
-        ap_register_address: u64,
-    ) -> Result<(), ArmError> {
-        let dp_state = self.select_dp(ap.dp())?;
+    #[test]
+    fn lenient_overrun_detect_tolerates_a_fault() {
+        const DPIDR_VALUE: u32 = 0x2BA0_1477;
+        const ORUNDETECT: u32 = 1;
 
-        let previous_select = dp_state.current_select;
-        match (ap.ap(), &mut dp_state.current_select) {
-            (ApAddress::V1(port), SelectCache::DPv1(s)) => {
-                let ap_register_address = (ap_register_address & 0xFF) as u8;
-                let ap_bank = ap_register_address >> 4;
-                s.set_ap_sel(*port);
-                s.set_ap_bank_sel(ap_bank);
+        /// A debug port which needs no setup.
+        #[derive(Debug)]
+        struct Started;
+
+        impl ArmDebugSequence for Started {
+            fn debug_port_setup(
+                &self,
+                _interface: &mut dyn DapProbe,
+                _dp: DpAddress,
+            ) -> Result<(), ArmError> {
+                Ok(())
             }
-            (ApAddress::V2(base), SelectCache::DPv3(s, s1)) => {
-                let address = base.0.unwrap_or(0) + ap_register_address;
-                s.set_addr(((address >> 4) & 0xFFFF_FFFF) as u32);
-                s1.set_addr((address >> 32) as u32);
+
+            fn debug_port_start(
+                &self,
+                _interface: &mut dyn DapAccess,
+                _dp: DpAddress,
+            ) -> Result<(), ArmError> {
+                Ok(())
             }
-            _ => unreachable!(
-                "Did not expect to be called with {ap:x?}. This is a bug, please report it."
-            ),
         }
 
-        if previous_select != dp_state.current_select {
-            tracing::debug!("Changing SELECT to {:x?}", dp_state.current_select);
+        for lenient in [false, true] {
+            let abort_writes = Arc::new(Mutex::new(Vec::new()));
 
-            match dp_state.current_select {
-                SelectCache::DPv1(select) => {
-                    self.write_dp_register(ap.dp(), select)?;
+            // A minimal debug port which faults when overrun detection is enabled.
+            let mut probe = FakeProbe::new();
+            probe.set_dap_register_read_handler(Box::new(|address| match address {
+                RegisterAddress::DpRegister(address) if address == DPIDR::ADDRESS => {
+                    Ok(DPIDR_VALUE)
                 }
-                SelectCache::DPv3(select, select1) => {
-                    self.write_dp_register(ap.dp(), select)?;
-                    self.write_dp_register(ap.dp(), select1)?;
+                _ => Ok(0),
+            }));
+            probe.set_dap_register_write_handler(Box::new({
+                let abort_writes = abort_writes.clone();
+                move |address, value| match address {
+                    RegisterAddress::DpRegister(address)
+                        if address == Ctrl::ADDRESS && value & ORUNDETECT != 0 =>
+                    {
+                        Err(DapError::FaultResponse.into())
+                    }
+                    RegisterAddress::DpRegister(address) if address == Abort::ADDRESS => {
+                        abort_writes.lock().unwrap().push(value);
+                        Ok(())
+                    }
+                    _ => Ok(()),
                 }
-            }
-        }
+            }));
 
-        Ok(())
-    }
-}
+            let mut interface = ArmCommunicationInterfaceBuilder::new()
+                .sequence(Arc::new(Started))
+                .overrun_detect(true)
+                .lenient_overrun_detect(lenient)
+                .build_interface(Box::new(probe));
 
-impl SwoAccess for ArmCommunicationInterface {
-    fn enable_swo(&mut self, config: &SwoConfig) -> Result<(), ArmError> {
-        match self.probe_mut().get_swo_interface_mut() {
-            Some(interface) => interface.enable_swo(config),
-            None => Err(ArmError::ArchitectureRequired(&["ARMv7", "ARMv8"])),
+            let result = interface.select_debug_port(DpAddress::Default);
+            if lenient {
+                result.unwrap();
+                assert_eq!(
+                    interface.debug_port_version(DpAddress::Default),
+                    Some(DebugPortVersion::DPv1)
+                );
+                // All sticky errors are cleared after the fault.
+                assert_eq!(*abort_writes.lock().unwrap(), [0x1E]);
+            } else {
+                assert!(matches!(
+                    result,
+                    Err(ArmError::Dap(DapError::FaultResponse))
+                ));
+            }
         }
     }
 
-    fn disable_swo(&mut self) -> Result<(), ArmError> {
-        match self.probe_mut().get_swo_interface_mut() {
-            Some(interface) => interface.disable_swo(),
-            None => Err(ArmError::ArchitectureRequired(&["ARMv7", "ARMv8"])),
-        }
+    #[test]
+    fn idle_cycles_are_set_by_the_probe() {
+        let mut interface = ArmCommunicationInterfaceBuilder::new()
+            .adopt_connected(
+                Box::new(RecordingProbe::new([])),
+                DpAddress::Default,
+                DebugPortVersion::DPv1,
+            )
+            .unwrap();
+
+        // The recording probe doesn't support idle cycles, so the error comes from the probe.
+        let error = interface.set_idle_cycles(8).unwrap_err();
+        assert!(matches!(
+            error,
+            ArmError::Probe(DebugProbeError::CommandNotSupportedByProbe {
+                command_name: "set_swd_idle_cycles"
+            })
+        ));
     }
 
-    fn read_swo_timeout(&mut self, timeout: Duration) -> Result<Vec<u8>, ArmError> {
-        match self.probe_mut().get_swo_interface_mut() {
-            Some(interface) => interface.read_swo_timeout(timeout),
-            None => Err(ArmError::ArchitectureRequired(&["ARMv7", "ARMv8"])),
-        }
+    #[test]
+    fn rate_limit_allows_bursts_within_budget() {
+        let mut bucket = TokenBucket::new(RateLimit::transfers_per_second(1000).with_burst(4));
+
+        assert_eq!(bucket.take(4), Duration::ZERO);
+
+        // Two transfers over budget take 2 ms at 1000 transfers per second.
+        let delay = bucket.take(2);
+        assert!(delay > Duration::from_millis(1), "{delay:?}");
+        assert!(delay <= Duration::from_millis(2), "{delay:?}");
     }
-}
 
-impl DapAccess for ArmCommunicationInterface {
-    fn read_raw_dp_register(
-        &mut self,
-        dp: DpAddress,
-        address: DpRegisterAddress,
-    ) -> Result<u32, ArmError> {
-        self.select_dp_and_dp_bank(dp, &address)?;
-        let result = self.probe_mut().raw_read_register(address.into())?;
-        Ok(result)
+    #[test]
+    fn ap_version_mismatch_is_an_error() {
+        let mut interface = ArmCommunicationInterfaceBuilder::new()
+            .adopt_connected(
+                Box::new(RecordingProbe::new([])),
+                DpAddress::Default,
+                DebugPortVersion::DPv1,
+            )
+            .unwrap();
+
+        let ap = FullyQualifiedApAddress::v2(DpAddress::Default, 0xD000);
+        let error = interface.read_raw_ap_register(&ap, 0x0C).unwrap_err();
+        assert!(matches!(
+            error,
+            ArmError::ApVersionMismatch {
+                debug_port_version: DebugPortVersion::DPv1,
+                ..
+            }
+        ));
     }
 
-    fn write_raw_dp_register(
-        &mut self,
-        dp: DpAddress,
-        address: DpRegisterAddress,
-        value: u32,
-    ) -> Result<(), ArmError> {
-        self.select_dp_and_dp_bank(dp, &address)?;
-        self.probe_mut().raw_write_register(address.into(), value)?;
-        Ok(())
+    #[test]
+    fn debug_lock_is_detected_by_the_sequence() {
+        // APPROTECTSTATUS of the CTRL-AP reads 0 while the device is protected.
+        let recorder = RecordingProbe::new([0]);
+        let mut interface = ArmCommunicationInterfaceBuilder::new()
+            .sequence(Nrf52::create())
+            .adopt_connected(
+                Box::new(recorder.clone()),
+                DpAddress::Default,
+                DebugPortVersion::DPv1,
+            )
+            .unwrap();
+        assert_eq!(
+            interface.check_debug_lock(DpAddress::Default).unwrap(),
+            DebugLockStatus::Locked
+        );
+
+        // The default sequence can't tell.
+        let mut interface = ArmCommunicationInterfaceBuilder::new()
+            .adopt_connected(
+                Box::new(RecordingProbe::new([])),
+                DpAddress::Default,
+                DebugPortVersion::DPv1,
+            )
+            .unwrap();
+        assert_eq!(
+            interface.check_debug_lock(DpAddress::Default).unwrap(),
+            DebugLockStatus::Unknown
+        );
     }
 
-    fn read_raw_ap_register(
-        &mut self,
-        ap: &FullyQualifiedApAddress,
-        address: u64,
-    ) -> Result<u32, ArmError> {
-        self.select_ap_and_ap_bank(ap, address)?;
+    #[test]
+    fn single_word_read_skips_memory_interface_setup() {
+        // 32 bit transfers, so CSW doesn't have to be written.
+        const CSW_VALUE: u32 = 0x2300_0002;
+        const WORD: u32 = 0x1234_5678;
 
-        let result = self
-            .probe_mut()
-            .raw_read_register(RegisterAddress::ApRegister((address & 0xFF) as u8))?;
+        let recorder = RecordingProbe::new([CSW_VALUE, WORD, WORD]);
+        let mut interface = ArmCommunicationInterfaceBuilder::new()
+            .adopt_connected(
+                Box::new(recorder.clone()),
+                DpAddress::Default,
+                DebugPortVersion::DPv1,
+            )
+            .unwrap();
+        recorder.clear_transfers();
 
-        Ok(result)
-    }
+        let ap = FullyQualifiedApAddress::v1_with_default_dp(0);
+        assert_eq!(interface.read_word_32(&ap, 0xE000_EDF0).unwrap(), WORD);
 
-    fn read_raw_ap_register_repeated(
-        &mut self,
-        ap: &FullyQualifiedApAddress,
-        address: u64,
-        values: &mut [u32],
-    ) -> Result<(), ArmError> {
-        self.select_ap_and_ap_bank(ap, address)?;
+        // CSW, TAR and DRW only. TAR2 is not needed for a 32-bit address.
+        assert_eq!(
+            recorder.transfers(),
+            [
+                RecordedTransfer::Read {
+                    address: RegisterAddress::ApRegister(0x00),
+                    value: CSW_VALUE,
+                },
+                RecordedTransfer::Write {
+                    address: RegisterAddress::ApRegister(0x04),
+                    value: 0xE000_EDF0,
+                },
+                RecordedTransfer::Read {
+                    address: RegisterAddress::ApRegister(0x0C),
+                    value: WORD,
+                },
+            ]
+        );
+        recorder.clear_transfers();
 
-        self.probe_mut()
-            .raw_read_block(RegisterAddress::ApRegister((address & 0xFF) as u8), values)?;
-        Ok(())
+        // The value of CSW is known now.
+        assert_eq!(interface.read_word_32(&ap, 0xE000_EDF0).unwrap(), WORD);
+        assert_eq!(
+            recorder.transfers(),
+            [
+                RecordedTransfer::Write {
+                    address: RegisterAddress::ApRegister(0x04),
+                    value: 0xE000_EDF0,
+                },
+                RecordedTransfer::Read {
+                    address: RegisterAddress::ApRegister(0x0C),
+                    value: WORD,
+                },
+            ]
+        );
     }
 
-    fn write_raw_ap_register(
-        &mut self,
-        ap: &FullyQualifiedApAddress,
-        address: u64,
-        value: u32,
-    ) -> Result<(), ArmError> {
-        self.select_ap_and_ap_bank(ap, address)?;
+    /// Writes the identification registers of a CoreSight component at `base`.
+    fn insert_component_id(rom: &mut HashMap<u64, u32>, base: u64, class: u32, devarch: u32) {
+        rom.insert(base + 0xFBC, devarch);
+        // ARM Ltd: JEP106 continuation code 4, identity code 0x3B
+        let pidr = [0x00, 0xB0, 0x0B, 0, 0x04];
+        for (offset, value) in [0xFE0, 0xFE4, 0xFE8, 0xFEC, 0xFD0].into_iter().zip(pidr) {
+            rom.insert(base + offset, value);
+        }
+        for (offset, value) in [
+            (0xFF0, 0x0D),
+            (0xFF4, class << 4),
+            (0xFF8, 0x05),
+            (0xFFC, 0xB1),
+        ] {
+            rom.insert(base + offset, value);
+        }
+    }
 
-        self.probe_mut()
-            .raw_write_register(RegisterAddress::ApRegister((address & 0xFF) as u8), value)?;
+    /// Emulates the address space of an ADIv6 debug port, which contains `rom` and has its
+    /// root ROM table at address 0. Writes are stored in `rom`, and addresses for which
+    /// `powered` returns `false` read as zero.
+    ///
+    /// All AP register writes of an [`adiv6_probe`], by address.
+    type Adiv6Writes = Arc<Mutex<Vec<(u64, u32)>>>;
 
-        Ok(())
-    }
+    /// Returns the probe, the number of AP register reads and all AP register writes.
+    fn adiv6_probe(
+        rom: HashMap<u64, u32>,
+        powered: fn(&HashMap<u64, u32>, u64) -> bool,
+    ) -> (FakeProbe, Arc<AtomicUsize>, Adiv6Writes) {
+        let rom = Arc::new(Mutex::new(rom));
+        let select = Arc::new(AtomicU32::new(0));
+        let ap_reads = Arc::new(AtomicUsize::new(0));
+        let ap_writes = Arc::new(Mutex::new(Vec::new()));
 
-    fn write_raw_ap_register_repeated(
-        &mut self,
-        ap: &FullyQualifiedApAddress,
-        address: u64,
-        values: &[u32],
-    ) -> Result<(), ArmError> {
-        self.select_ap_and_ap_bank(ap, address)?;
+        let address_of = |select: &AtomicU32, register: u8| {
+            u64::from(select.load(Ordering::SeqCst) & !0xF) | u64::from(register & 0xF)
+        };
 
-        self.probe_mut()
-            .raw_write_block(RegisterAddress::ApRegister((address & 0xFF) as u8), values)?;
-        Ok(())
-    }
+        let mut probe = FakeProbe::new();
+        let (read_rom, read_select, counted_reads) =
+            (rom.clone(), select.clone(), ap_reads.clone());
+        probe.set_dap_register_read_handler(Box::new(move |address| match address {
+            // Valid, pointing to address 0
+            RegisterAddress::DpRegister(address) if address == BASEPTR0::ADDRESS => Ok(1),
+            RegisterAddress::DpRegister(_) => Ok(0),
+            RegisterAddress::ApRegister(register) => {
+                counted_reads.fetch_add(1, Ordering::SeqCst);
+                let address = address_of(&read_select, register);
+                let rom = read_rom.lock().unwrap();
+                Ok(powered(&rom, address)
+                    .then(|| rom.get(&address).copied())
+                    .flatten()
+                    .unwrap_or(0))
+            }
+        }));
+        let recorded_writes = ap_writes.clone();
+        probe.set_dap_register_write_handler(Box::new(move |address, value| {
+            match address {
+                RegisterAddress::DpRegister(address) if address == SelectV3::ADDRESS => {
+                    select.store(value, Ordering::SeqCst);
+                }
+                RegisterAddress::ApRegister(register) => {
+                    let address = address_of(&select, register);
+                    rom.lock().unwrap().insert(address, value);
+                    recorded_writes.lock().unwrap().push((address, value));
+                }
+                RegisterAddress::DpRegister(_) => {}
+            }
+            Ok(())
+        }));
 
-    fn flush(&mut self) -> Result<(), ArmError> {
-        self.probe_mut().raw_flush()
+        (probe, ap_reads, ap_writes)
     }
 
-    fn try_dap_probe(&self) -> Option<&dyn DapProbe> {
-        self.probe.as_deref()
+    /// The DP address space of the nRF54 series: a Class 0x1 root ROM table, which lists two
+    /// MEM-APs at 0x2000 and 0x3000.
+    fn nrf54_rom() -> HashMap<u64, u32> {
+        let mut rom = HashMap::new();
+        insert_component_id(&mut rom, 0x0000, 0x1, 0);
+        // Present, 32-bit format
+        rom.insert(0x0000, 0x2003);
+        rom.insert(0x0004, 0x3003);
+        // DEVARCH.PRESENT, Memory Access Port v2
+        insert_component_id(&mut rom, 0x2000, 0x9, (1 << 20) | 0x0A17);
+        insert_component_id(&mut rom, 0x3000, 0x9, (1 << 20) | 0x0A17);
+
+        rom
     }
 
-    fn try_dap_probe_mut(&mut self) -> Option<&mut dyn DapProbe> {
-        self.probe
-            .as_deref_mut()
-            // Need to explicitly coerce lifetimes: https://github.com/rust-lang/rust/issues/108999
-            .map(|p: &mut (dyn DapProbe + 'static)| p as &mut (dyn DapProbe + '_))
+    #[test]
+    fn adiv6_access_ports_are_enumerated_once() {
+        let (probe, ap_reads, _) = adiv6_probe(nrf54_rom(), |_, _| true);
+        let mut interface = test_interface(
+            ArmCommunicationInterfaceBuilder::new(),
+            Box::new(probe),
+            Some(DebugPortVersion::DPv3),
+        );
+
+        let expected = BTreeSet::from([
+            FullyQualifiedApAddress::v2(DpAddress::Default, 0x2000),
+            FullyQualifiedApAddress::v2(DpAddress::Default, 0x3000),
+        ]);
+        assert_eq!(
+            interface.access_ports(DpAddress::Default).unwrap(),
+            expected
+        );
+        assert_ne!(ap_reads.load(Ordering::SeqCst), 0);
+
+        ap_reads.store(0, Ordering::SeqCst);
+        assert_eq!(
+            interface.access_ports(DpAddress::Default).unwrap(),
+            expected
+        );
+        assert_eq!(ap_reads.load(Ordering::SeqCst), 0);
     }
-}
 
-/// Information about the chip target we are currently attached to.
-/// This can be used for discovery, tho, for now it does not work optimally,
-/// as some manufacturers (e.g. ST Microelectronics) violate the spec and thus need special discovery procedures.
-#[derive(Debug, Clone, Copy)]
-pub struct ArmChipInfo {
-    /// The JEP106 code of the manufacturer of this chip target.
-    pub manufacturer: JEP106Code,
-    /// The unique part number of the chip target. Unfortunately this only unique in the spec.
-    /// In practice some manufacturers violate the spec and assign a part number to an entire family.
-    ///
-    /// Consider this not unique when working with targets!
-    pub part: u16,
-}
+    #[test]
+    fn apv2_power_domains_are_powered_up_once() {
+        const ROM_TABLE: u32 = (1 << 20) | 0x0AF7;
+        // The power domain of the nested ROM table, which also contains the AP.
+        const DBGPCR1: u64 = 0x0A04;
+        // The power domain of the AP, in the nested ROM table.
+        const DBGPCR2: u64 = 0x4A08;
 
-impl std::fmt::Display for ArmChipInfo {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let manu = match self.manufacturer.get() {
-            Some(name) => name.to_string(),
-            None => format!(
-                "<unknown manufacturer (cc={:2x}, id={:2x})>",
-                self.manufacturer.cc, self.manufacturer.id
-            ),
+        let mut rom = HashMap::new();
+        // The root ROM table supports power requests (DEVID.PRR).
+        insert_component_id(&mut rom, 0x0000, 0x9, ROM_TABLE);
+        rom.insert(0x0FC8, 1 << 5);
+        // Present, POWERIDVALID, power domain 1
+        rom.insert(0x0000, 0x4017);
+        // Both DBGPCR.PRESENT and DBGPSR.PS
+        rom.insert(DBGPCR1, 1);
+        rom.insert(0x0A84, 1);
+
+        insert_component_id(&mut rom, 0x4000, 0x9, ROM_TABLE);
+        rom.insert(0x4FC8, 1 << 5);
+        // Offset 0x1000, present, POWERIDVALID, power domain 2
+        rom.insert(0x4000, 0x1027);
+        rom.insert(DBGPCR2, 1);
+        rom.insert(0x4A88, 1);
+
+        // AHB-AP IDR
+        rom.insert(0x5DFC, 0x2477_0011);
+
+        // The nested ROM table and the AP read as zero until power domain 1 is requested.
+        let (probe, _, ap_writes) = adiv6_probe(rom, |rom, address| {
+            address < 0x4000 || rom[&DBGPCR1] & (1 << 1) != 0
+        });
+        let mut interface = test_interface(
+            ArmCommunicationInterfaceBuilder::new(),
+            Box::new(probe),
+            Some(DebugPortVersion::DPv3),
+        );
+        let ap = FullyQualifiedApAddress::v2(DpAddress::Default, 0x5000);
+
+        let power_requests = || {
+            ap_writes
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(address, _)| [DBGPCR1, DBGPCR2].contains(address))
+                .copied()
+                .collect::<Vec<_>>()
         };
-        write!(f, "{} 0x{:04x}", manu, self.part)
+
+        drop(interface.memory_interface(&ap).unwrap());
+        assert_eq!(power_requests(), [(DBGPCR1, 1 << 1), (DBGPCR2, 1 << 1)]);
+
+        drop(interface.memory_interface(&ap).unwrap());
+        assert_eq!(power_requests().len(), 2);
     }
 }
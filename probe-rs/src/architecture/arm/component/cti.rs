@@ -0,0 +1,214 @@
+//! Arm Cross Trigger Interface CoreSight Component
+//!
+//! # Description
+//! The Cross Trigger Interface (CTI) connects the debug events of a core to the channels of the
+//! Cross Trigger Matrix (CTM), which broadcasts them to the CTIs of the other cores. This module
+//! provides the configuration needed to halt and restart multiple cores synchronously.
+//!
+//! The trigger numbers follow the ARMv8-A and ARMv7-A architectural assignment: output trigger 0
+//! is the debug (halt) request, output trigger 1 the restart request, and input trigger 0 signals
+//! that the core halted.
+use crate::architecture::arm::memory::romtable::CoresightComponent;
+use crate::architecture::arm::{ArmDebugInterface, ArmError};
+
+const REGISTER_OFFSET_CTICONTROL: u32 = 0x000;
+const REGISTER_OFFSET_CTIINTACK: u32 = 0x010;
+const REGISTER_OFFSET_CTIAPPPULSE: u32 = 0x01C;
+const REGISTER_OFFSET_CTIINEN: u32 = 0x020;
+const REGISTER_OFFSET_CTIOUTEN: u32 = 0x0A0;
+const REGISTER_OFFSET_CTIGATE: u32 = 0x140;
+const REGISTER_OFFSET_ACCESS: u32 = 0xFB0;
+const REGISTER_OFFSET_CTIDEVID: u32 = 0xFC8;
+
+/// The input trigger which signals that the core halted.
+const TRIGGER_IN_HALTED: u32 = 0;
+/// The output trigger which requests the core to halt.
+const TRIGGER_OUT_DEBUG_REQUEST: u32 = 0;
+/// The output trigger which requests the core to restart.
+const TRIGGER_OUT_RESTART: u32 = 1;
+
+/// Cross Trigger Interface unit
+pub struct Cti<'a> {
+    component: &'a CoresightComponent,
+    interface: &'a mut dyn ArmDebugInterface,
+}
+
+impl<'a> Cti<'a> {
+    /// Construct a new CTI component.
+    pub fn new(
+        interface: &'a mut dyn ArmDebugInterface,
+        component: &'a CoresightComponent,
+    ) -> Self {
+        Cti {
+            component,
+            interface,
+        }
+    }
+
+    /// Unlock the CTI registers for writing.
+    pub fn unlock(&mut self) -> Result<(), ArmError> {
+        self.component
+            .write_reg(self.interface, REGISTER_OFFSET_ACCESS, 0xC5AC_CE55)?;
+
+        Ok(())
+    }
+
+    /// Read the number of CTM channels the CTI is connected to.
+    pub fn channel_count(&mut self) -> Result<u32, ArmError> {
+        let devid = self
+            .component
+            .read_reg(self.interface, REGISTER_OFFSET_CTIDEVID)?;
+
+        Ok((devid >> 16) & 0x3F)
+    }
+
+    /// Program the CTI to broadcast a halt of its core on `channel`, and to halt its core when
+    /// any other core broadcasts a halt on `channel`.
+    ///
+    /// # Note
+    /// This has to be done for the CTI of every core which should halt synchronously. Previously
+    /// configured channels are kept.
+    pub fn cti_halt_channel(&mut self, channel: u32) -> Result<(), ArmError> {
+        let mask = self.channel_mask(channel)?;
+
+        self.enable()?;
+        self.set_bits(REGISTER_OFFSET_CTIINEN + 4 * TRIGGER_IN_HALTED, mask)?;
+        self.set_bits(
+            REGISTER_OFFSET_CTIOUTEN + 4 * TRIGGER_OUT_DEBUG_REQUEST,
+            mask,
+        )?;
+        self.set_bits(REGISTER_OFFSET_CTIGATE, mask)
+    }
+
+    /// Program the CTI to restart its core when an event is generated on `channel`.
+    ///
+    /// The restart is started with [`Cti::pulse_channel`] on any of the CTIs. The halt request
+    /// must have been acknowledged with [`Cti::acknowledge_halt`] before, otherwise the core
+    /// halts again immediately.
+    pub fn cti_restart_channel(&mut self, channel: u32) -> Result<(), ArmError> {
+        let mask = self.channel_mask(channel)?;
+
+        self.enable()?;
+        self.set_bits(REGISTER_OFFSET_CTIOUTEN + 4 * TRIGGER_OUT_RESTART, mask)?;
+        self.set_bits(REGISTER_OFFSET_CTIGATE, mask)
+    }
+
+    /// Generate an event on `channel`, which is broadcast to all CTIs listening on it.
+    pub fn pulse_channel(&mut self, channel: u32) -> Result<(), ArmError> {
+        let mask = self.channel_mask(channel)?;
+
+        self.component
+            .write_reg(self.interface, REGISTER_OFFSET_CTIAPPPULSE, mask)
+    }
+
+    /// Acknowledge the halt request of the core, so it can be restarted.
+    pub fn acknowledge_halt(&mut self) -> Result<(), ArmError> {
+        self.component.write_reg(
+            self.interface,
+            REGISTER_OFFSET_CTIINTACK,
+            1 << TRIGGER_OUT_DEBUG_REQUEST,
+        )
+    }
+
+    /// Enable the mapping of triggers to channels.
+    fn enable(&mut self) -> Result<(), ArmError> {
+        self.component
+            .write_reg(self.interface, REGISTER_OFFSET_CTICONTROL, 1)
+    }
+
+    fn channel_mask(&mut self, channel: u32) -> Result<u32, ArmError> {
+        let available = self.channel_count()?;
+        if channel >= available {
+            return Err(ArmError::CtiChannelIndexOutOfRange {
+                index: channel as usize,
+                available,
+            });
+        }
+
+        Ok(1 << channel)
+    }
+
+    fn set_bits(&mut self, offset: u32, mask: u32) -> Result<(), ArmError> {
+        let value = self.component.read_reg(self.interface, offset)?;
+        self.component
+            .write_reg(self.interface, offset, value | mask)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Cti;
+    use crate::architecture::arm::{ArmError, component::tests::mock_component};
+
+    const CTI_BASE: u64 = 0xE004_2000;
+    /// CTIDEVID.NUMCHAN: 4 channels
+    const CTIDEVID_FOUR_CHANNELS: (u32, u32) = (0xFC8, 4 << 16);
+
+    #[test]
+    fn halt_channel_is_added_to_the_configured_channels() {
+        // CTIGATE already passes channel 0.
+        let (memory_ap, mut interface, component) =
+            mock_component(CTI_BASE, &[CTIDEVID_FOUR_CHANNELS, (0x140, 0b1)]);
+
+        Cti::new(&mut interface, &component)
+            .cti_halt_channel(2)
+            .unwrap();
+
+        assert_eq!(
+            memory_ap.lock().unwrap().register_writes,
+            [
+                // CTICONTROL.GLBEN
+                (CTI_BASE, 1),
+                // CTIINEN0: the halted trigger
+                (CTI_BASE + 0x20, 1 << 2),
+                // CTIOUTEN0: the debug request trigger
+                (CTI_BASE + 0xA0, 1 << 2),
+                // CTIGATE
+                (CTI_BASE + 0x140, 0b101),
+            ]
+        );
+    }
+
+    #[test]
+    fn restart_channel_uses_the_restart_trigger() {
+        let (memory_ap, mut interface, component) =
+            mock_component(CTI_BASE, &[CTIDEVID_FOUR_CHANNELS]);
+
+        let mut cti = Cti::new(&mut interface, &component);
+        cti.cti_restart_channel(1).unwrap();
+        cti.acknowledge_halt().unwrap();
+        cti.pulse_channel(1).unwrap();
+
+        assert_eq!(
+            memory_ap.lock().unwrap().register_writes,
+            [
+                (CTI_BASE, 1),
+                // CTIOUTEN1: the restart trigger
+                (CTI_BASE + 0xA4, 1 << 1),
+                (CTI_BASE + 0x140, 1 << 1),
+                // CTIINTACK of the debug request trigger
+                (CTI_BASE + 0x10, 1),
+                // CTIAPPPULSE
+                (CTI_BASE + 0x1C, 1 << 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn channel_is_checked_against_ctidevid() {
+        let (memory_ap, mut interface, component) =
+            mock_component(CTI_BASE, &[CTIDEVID_FOUR_CHANNELS]);
+
+        let mut cti = Cti::new(&mut interface, &component);
+        assert_eq!(cti.channel_count().unwrap(), 4);
+        assert_eq!(cti.channel_mask(3).unwrap(), 1 << 3);
+        assert!(matches!(
+            cti.cti_halt_channel(4),
+            Err(ArmError::CtiChannelIndexOutOfRange {
+                index: 4,
+                available: 4
+            })
+        ));
+        assert!(memory_ap.lock().unwrap().register_writes.is_empty());
+    }
+}
@@ -8,8 +8,14 @@
 
 use super::super::memory::romtable::CoresightComponent;
 use super::DebugComponentInterface;
-use crate::architecture::arm::{ArmDebugInterface, ArmError};
-use crate::{Error, memory_mapped_bitfield_register};
+use super::itm::enable_dwt_forwarding;
+use crate::architecture::arm::{
+    ArmDebugInterface, ArmError, core::armv6m::Demcr, memory::ArmMemoryInterface,
+};
+use crate::{Error, MemoryMappedRegister, memory_mapped_bitfield_register};
+
+/// The base address of the DWT on Cortex-M cores.
+const DWT_BASE: u64 = 0xE000_1000;
 
 /// A struct representing a DWT unit on target.
 pub struct Dwt<'a> {
@@ -128,6 +134,286 @@ impl<'a> Dwt<'a> {
     }
 }
 
+/// The accesses which trigger a watchpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    /// Reads of the watched address.
+    Read,
+    /// Writes to the watched address.
+    Write,
+    /// Reads and writes of the watched address.
+    Access,
+}
+
+impl WatchKind {
+    /// The value of DWT_FUNCTION which generates a watchpoint debug event for this kind of access,
+    /// see ARMv7-M architecture reference manual C1.8.17.
+    fn function(self) -> u32 {
+        match self {
+            WatchKind::Read => 0b0101,
+            WatchKind::Write => 0b0110,
+            WatchKind::Access => 0b0111,
+        }
+    }
+}
+
+/// The rate at which the DWT emits PC sample packets.
+///
+/// The DWT takes a sample every `divisor` core clock cycles, so the sample frequency is the core
+/// clock divided by the divisor. The divisor is built from a tap on the cycle counter, every 64
+/// or every 1024 cycles (DWT_CTRL.CYCTAP), and a reload value of 1 to 16 taps (POSTPRESET + 1).
+/// Only multiples of 64 up to 1024, and multiples of 1024 up to 16384 can be used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SampleRate {
+    divisor: u32,
+}
+
+impl SampleRate {
+    /// Creates a sample rate of one sample every `divisor` core clock cycles, or `None` if the
+    /// DWT can't sample at this rate.
+    pub fn from_divisor(divisor: u32) -> Option<Self> {
+        let valid = |tap: u32| divisor % tap == 0 && (1..=16).contains(&(divisor / tap));
+
+        (valid(64) || valid(1024)).then_some(Self { divisor })
+    }
+
+    /// The number of core clock cycles between two samples.
+    pub fn divisor(self) -> u32 {
+        self.divisor
+    }
+
+    /// The number of samples per second with a core clock of `core_clock` Hz.
+    pub fn sample_frequency(self, core_clock: u32) -> u32 {
+        core_clock / self.divisor
+    }
+
+    /// Returns the CYCTAP and POSTPRESET values for this rate.
+    fn fields(self) -> (bool, u8) {
+        if self.divisor <= 16 * 64 {
+            (false, (self.divisor / 64 - 1) as u8)
+        } else {
+            (true, (self.divisor / 1024 - 1) as u8)
+        }
+    }
+}
+
+/// The DWT registers of a Cortex-M core which hold its configuration, see [`DwtConfig::read`].
+pub(crate) struct DwtConfig {
+    ctrl: u32,
+    /// COMP, MASK and FUNCTION of each comparator.
+    comparators: Vec<[u32; 3]>,
+}
+
+impl DwtConfig {
+    /// Reads the configuration of the DWT of the Cortex-M core behind `memory`.
+    ///
+    /// DEMCR.TRCENA has to be set, otherwise the DWT can't be accessed.
+    pub(crate) fn read(memory: &mut dyn ArmMemoryInterface) -> Result<Self, ArmError> {
+        let ctrl = memory.read_word_32(Ctrl::get_mmio_address_from_base(DWT_BASE)?)?;
+
+        let comparators = (0..u64::from(Ctrl::from(ctrl).numcomp()))
+            .map(|index| {
+                let base = DWT_BASE + 16 * index;
+                Ok([
+                    memory.read_word_32(Comp::get_mmio_address_from_base(base)?)?,
+                    memory.read_word_32(Mask::get_mmio_address_from_base(base)?)?,
+                    memory.read_word_32(Function::get_mmio_address_from_base(base)?)?,
+                ])
+            })
+            .collect::<Result<Vec<_>, ArmError>>()?;
+
+        Ok(Self { ctrl, comparators })
+    }
+
+    /// Writes the configuration back to the DWT of the Cortex-M core behind `memory`.
+    pub(crate) fn restore(&self, memory: &mut dyn ArmMemoryInterface) -> Result<(), ArmError> {
+        for (index, [comp, mask, function]) in self.comparators.iter().enumerate() {
+            let base = DWT_BASE + 16 * index as u64;
+            let function_address = Function::get_mmio_address_from_base(base)?;
+
+            // Disable the comparator while it is reprogrammed.
+            memory.write_word_32(function_address, 0)?;
+            memory.write_word_32(Comp::get_mmio_address_from_base(base)?, *comp)?;
+            memory.write_word_32(Mask::get_mmio_address_from_base(base)?, *mask)?;
+            memory.write_word_32(function_address, *function)?;
+        }
+
+        memory.write_word_32(Ctrl::get_mmio_address_from_base(DWT_BASE)?, self.ctrl)
+    }
+}
+
+/// Sets DEMCR.TRCENA, which is required to access the DWT.
+fn enable_trace(memory: &mut dyn ArmMemoryInterface) -> Result<(), ArmError> {
+    let mut demcr = Demcr::from(memory.read_word_32(Demcr::get_mmio_address())?);
+    if !demcr.dwtena() {
+        demcr.set_dwtena(true);
+        memory.write_word_32(Demcr::get_mmio_address(), demcr.into())?;
+    }
+
+    Ok(())
+}
+
+/// Returns the base address of the registers of the DWT comparator `index`, after checking
+/// that the comparator exists.
+///
+/// The registers of comparator `n` are 16 bytes after the ones of comparator `n - 1`.
+fn comparator_base(memory: &mut dyn ArmMemoryInterface, index: usize) -> Result<u64, ArmError> {
+    let ctrl = Ctrl::from(memory.read_word_32(Ctrl::get_mmio_address_from_base(DWT_BASE)?)?);
+    let available = u32::from(ctrl.numcomp());
+    if index >= available as usize {
+        return Err(ArmError::WatchpointIndexOutOfRange { index, available });
+    }
+
+    Ok(DWT_BASE + 16 * index as u64)
+}
+
+/// Sets the DWT comparator `index` of the Cortex-M core behind `memory` to halt the core on
+/// `kind` accesses to `address`.
+///
+/// The lowest `mask` bits of the address are ignored, so the watchpoint covers `2^mask` bytes.
+/// `address` has to be aligned to that size.
+pub fn set_watchpoint(
+    memory: &mut dyn ArmMemoryInterface,
+    index: usize,
+    address: u32,
+    mask: u8,
+    kind: WatchKind,
+) -> Result<(), ArmError> {
+    if mask > 31 {
+        return Err(ArmError::Other(format!(
+            "The watchpoint mask {mask} is larger than the address width"
+        )));
+    }
+    if address & ((1 << mask) - 1) != 0 {
+        return Err(ArmError::alignment_error(address.into(), 1 << mask));
+    }
+
+    enable_trace(memory)?;
+
+    let base = comparator_base(memory, index)?;
+    let function_address = Function::get_mmio_address_from_base(base)?;
+
+    // ARMv8-M uses a different encoding of DWT_FUNCTION, which is identified by the ID field.
+    // The field is reserved and reads as zero on ARMv6-M and ARMv7-M.
+    if memory.read_word_32(function_address)? >> 27 != 0 {
+        return Err(ArmError::FeatureNotImplemented(
+            "ARMv7-M style DWT watchpoints",
+        ));
+    }
+
+    // Disable the comparator while it is reprogrammed.
+    memory.write_word_32(function_address, 0)?;
+
+    let mut comp = Comp::from(0);
+    comp.set_comp(address);
+    memory.write_word_32(Comp::get_mmio_address_from_base(base)?, comp.into())?;
+
+    let mut comparator_mask = Mask::from(0);
+    comparator_mask.set_mask(mask.into());
+    memory.write_word_32(
+        Mask::get_mmio_address_from_base(base)?,
+        comparator_mask.into(),
+    )?;
+
+    let mut function = Function::from(0);
+    function.set_function(kind.function());
+    memory.write_word_32(function_address, function.into())?;
+
+    memory.flush()
+}
+
+/// Clears the watchpoint set on the DWT comparator `index` of the Cortex-M core behind `memory`.
+pub fn clear_watchpoint(memory: &mut dyn ArmMemoryInterface, index: usize) -> Result<(), ArmError> {
+    enable_trace(memory)?;
+
+    let base = comparator_base(memory, index)?;
+    memory.write_word_32(Function::get_mmio_address_from_base(base)?, 0)?;
+
+    memory.flush()
+}
+
+/// Makes the DWT of the Cortex-M core behind `memory` emit a PC sample packet at `rate`, and
+/// enables the ITM to forward them.
+///
+/// The packets are only output if the TPIU or SWO is configured as well.
+pub fn enable_pc_sampling(
+    memory: &mut dyn ArmMemoryInterface,
+    rate: SampleRate,
+) -> Result<(), ArmError> {
+    enable_trace(memory)?;
+
+    let ctrl_address = Ctrl::get_mmio_address_from_base(DWT_BASE)?;
+    let mut ctrl = Ctrl::from(memory.read_word_32(ctrl_address)?);
+    if ctrl.notrcpkt() || ctrl.nocyccnt() {
+        return Err(ArmError::FeatureNotImplemented("DWT PC sampling"));
+    }
+
+    // The sample counter may only be reloaded while sampling is disabled.
+    ctrl.set_pcsamplena(false);
+    memory.write_word_32(ctrl_address, ctrl.into())?;
+
+    let (cyctap, postpreset) = rate.fields();
+    ctrl.set_cyctap(cyctap);
+    ctrl.set_postpreset(postpreset.into());
+    ctrl.set_postinit(postpreset);
+    // The sample counter is clocked by the cycle counter, which also drives the
+    // synchronization packets the decoder needs.
+    ctrl.set_cyccntena(true);
+    if ctrl.synctap() == 0 {
+        ctrl.set_synctap(0x01);
+    }
+    memory.write_word_32(ctrl_address, ctrl.into())?;
+
+    ctrl.set_pcsamplena(true);
+    memory.write_word_32(ctrl_address, ctrl.into())?;
+
+    enable_dwt_forwarding(memory)?;
+
+    memory.flush()
+}
+
+/// Enables the cycle counter of the Cortex-M core behind `memory`, if it isn't running already.
+///
+/// This also sets DEMCR.TRCENA, which is required to access the DWT.
+pub fn enable_cycle_counter(memory: &mut dyn ArmMemoryInterface) -> Result<(), ArmError> {
+    enable_trace(memory)?;
+
+    let ctrl_address = Ctrl::get_mmio_address_from_base(DWT_BASE)?;
+    let mut ctrl = Ctrl::from(memory.read_word_32(ctrl_address)?);
+    if ctrl.cyccntena() {
+        return Ok(());
+    }
+    if ctrl.nocyccnt() {
+        return Err(ArmError::FeatureNotImplemented("the DWT cycle counter"));
+    }
+
+    ctrl.set_cyccntena(true);
+    memory.write_word_32(ctrl_address, ctrl.into())?;
+
+    // ARMv6-M cores don't have a cycle counter, but don't report that in NOCYCCNT. CYCCNTENA
+    // reads as zero on them.
+    if !Ctrl::from(memory.read_word_32(ctrl_address)?).cyccntena() {
+        return Err(ArmError::FeatureNotImplemented("the DWT cycle counter"));
+    }
+
+    Ok(())
+}
+
+/// Reads the cycle counter of the Cortex-M core behind `memory`, enabling it first if needed.
+pub fn read_cycle_count(memory: &mut dyn ArmMemoryInterface) -> Result<u32, ArmError> {
+    enable_cycle_counter(memory)?;
+
+    memory.read_word_32(Cyccnt::get_mmio_address_from_base(DWT_BASE)?)
+}
+
+/// Resets the cycle counter of the Cortex-M core behind `memory` to zero, enabling it first if
+/// needed.
+pub fn reset_cycle_counter(memory: &mut dyn ArmMemoryInterface) -> Result<(), ArmError> {
+    enable_cycle_counter(memory)?;
+
+    memory.write_word_32(Cyccnt::get_mmio_address_from_base(DWT_BASE)?, 0)
+}
+
 memory_mapped_bitfield_register! {
     pub struct Ctrl(u32);
     0x00, "DWT/CTRL",
@@ -223,3 +509,161 @@ memory_mapped_bitfield_register! {
 }
 
 impl DebugComponentInterface for Pcsr {}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use super::{
+        WatchKind, clear_watchpoint, enable_cycle_counter, read_cycle_count, reset_cycle_counter,
+        set_watchpoint,
+    };
+    use crate::{
+        MemoryMappedRegister,
+        architecture::arm::{
+            ArmDebugInterface, ArmError, FullyQualifiedApAddress,
+            ap::memory_ap::mock::MockMemoryAp, communication_interface::tests::mock_target,
+            core::armv6m::Demcr,
+        },
+    };
+
+    const DWT_CTRL: u64 = 0xE000_1000;
+    const DWT_CYCCNT: u64 = 0xE000_1004;
+    /// DEMCR.TRCENA
+    const TRCENA: u32 = 1 << 24;
+
+    fn ap() -> FullyQualifiedApAddress {
+        FullyQualifiedApAddress::v1_with_default_dp(0)
+    }
+
+    /// Returns a target whose DWT_CTRL reads `ctrl`.
+    fn dwt(ctrl: u32) -> MockMemoryAp {
+        let mut memory_ap = MockMemoryAp::with_pattern_and_size(0);
+        memory_ap.read_only_registers.insert(DWT_CTRL, ctrl);
+        memory_ap
+    }
+
+    #[test]
+    fn watchpoint_address_has_to_be_aligned_to_the_mask() {
+        const DWT_COMP1: u64 = 0xE000_1030;
+        const DWT_MASK1: u64 = 0xE000_1034;
+        const DWT_FUNCTION1: u64 = 0xE000_1038;
+
+        // Two comparators
+        let (memory_ap, mut interface) = mock_target(dwt(2 << 28));
+
+        let mut memory = interface.memory_interface(&ap()).unwrap();
+        assert!(matches!(
+            set_watchpoint(&mut *memory, 1, 0x2000_0008, 4, WatchKind::Write),
+            Err(ArmError::MemoryNotAligned(error)) if error.alignment == 16
+        ));
+        assert!(matches!(
+            set_watchpoint(&mut *memory, 1, 0, 32, WatchKind::Write),
+            Err(ArmError::Other(_))
+        ));
+        assert!(memory_ap.lock().unwrap().register_writes.is_empty());
+
+        set_watchpoint(&mut *memory, 1, 0x2000_0010, 4, WatchKind::Write).unwrap();
+        assert_eq!(
+            memory_ap.lock().unwrap().register_writes,
+            [
+                (Demcr::get_mmio_address(), TRCENA),
+                // The comparator is disabled while it is reprogrammed.
+                (DWT_FUNCTION1, 0),
+                (DWT_COMP1, 0x2000_0010),
+                (DWT_MASK1, 4),
+                // Write access
+                (DWT_FUNCTION1, 0b0110),
+            ]
+        );
+    }
+
+    #[test]
+    fn watchpoint_index_is_checked_against_numcomp() {
+        let mut memory_ap = dwt(2 << 28);
+        // TRCENA is already set.
+        memory_ap
+            .registers
+            .insert(Demcr::get_mmio_address(), TRCENA);
+        let (memory_ap, mut interface) = mock_target(memory_ap);
+
+        let mut memory = interface.memory_interface(&ap()).unwrap();
+        assert!(matches!(
+            set_watchpoint(&mut *memory, 2, 0x2000_0000, 2, WatchKind::Read),
+            Err(ArmError::WatchpointIndexOutOfRange {
+                index: 2,
+                available: 2
+            })
+        ));
+        assert!(matches!(
+            clear_watchpoint(&mut *memory, 2),
+            Err(ArmError::WatchpointIndexOutOfRange {
+                index: 2,
+                available: 2
+            })
+        ));
+        assert!(memory_ap.lock().unwrap().register_writes.is_empty());
+    }
+
+    #[test]
+    fn cycle_counter_is_enabled_before_it_is_read() {
+        let mut memory_ap = MockMemoryAp::with_pattern_and_size(0);
+        memory_ap.registers.insert(DWT_CYCCNT, 1234);
+        let (memory_ap, mut interface) = mock_target(memory_ap);
+
+        let mut memory = interface.memory_interface(&ap()).unwrap();
+        assert_eq!(read_cycle_count(&mut *memory).unwrap(), 1234);
+
+        assert_eq!(
+            memory_ap.lock().unwrap().register_writes,
+            [
+                (Demcr::get_mmio_address(), TRCENA),
+                // CYCCNTENA
+                (DWT_CTRL, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn cycle_counter_is_not_implemented_on_armv6m() {
+        // Two comparators, CYCCNTENA reads as zero and NOCYCCNT isn't set.
+        let mut memory_ap = dwt(2 << 28);
+        memory_ap.registers.insert(DWT_CYCCNT, 1234);
+        let (memory_ap, mut interface) = mock_target(memory_ap);
+
+        let mut memory = interface.memory_interface(&ap()).unwrap();
+        assert!(matches!(
+            read_cycle_count(&mut *memory),
+            Err(ArmError::FeatureNotImplemented(_))
+        ));
+        assert!(matches!(
+            reset_cycle_counter(&mut *memory),
+            Err(ArmError::FeatureNotImplemented(_))
+        ));
+
+        // CYCCNT is never written.
+        assert!(
+            memory_ap
+                .lock()
+                .unwrap()
+                .register_writes
+                .iter()
+                .all(|&(address, _)| address != DWT_CYCCNT)
+        );
+    }
+
+    #[test]
+    fn cycle_counter_is_not_enabled_if_nocyccnt_is_set() {
+        let (memory_ap, mut interface) = mock_target(dwt(1 << 25));
+
+        let mut memory = interface.memory_interface(&ap()).unwrap();
+        assert!(matches!(
+            enable_cycle_counter(&mut *memory),
+            Err(ArmError::FeatureNotImplemented(_))
+        ));
+        assert_eq!(
+            memory_ap.lock().unwrap().register_writes,
+            [(Demcr::get_mmio_address(), TRCENA)]
+        );
+    }
+}
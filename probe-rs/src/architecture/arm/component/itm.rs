@@ -4,7 +4,7 @@
 
 use super::super::memory::romtable::CoresightComponent;
 use super::DebugComponentInterface;
-use crate::architecture::arm::ArmDebugInterface;
+use crate::architecture::arm::{ArmDebugInterface, ArmError, memory::ArmMemoryInterface};
 use crate::{Error, MemoryMappedRegister};
 
 pub const _ITM_PID: [u8; 8] = [0x1, 0xB0, 0x3b, 0x0, 0x4, 0x0, 0x0, 0x0];
@@ -32,6 +32,12 @@ pub struct Itm<'a> {
 const _REGISTER_OFFSET_ITM_TPR: u32 = 0xE40;
 const REGISTER_OFFSET_ACCESS: u32 = 0xFB0;
 
+/// The base address of the ITM on Cortex-M cores.
+const ITM_BASE: u64 = 0xE000_0000;
+
+/// The trace bus ID used for the ITM, see [`Itm::tx_enable`].
+const TRACE_BUS_ID: u8 = 0b1101;
+
 impl<'a> Itm<'a> {
     /// Create a new ITM interface from a probe and a ROM table component.
     pub fn new(
@@ -67,7 +73,7 @@ impl<'a> Itm<'a> {
         tcr.set_syncena(true); // SYNENA: Enable sync pulses, note DWT_CTRL.SYNCTAP must be configured.
         tcr.set_txena(true); // TXENA: forward DWT packets to ITM
         tcr.set_gtsfreq(0b10); // GTSFREQ: generate global timestamp every 8192 cycles
-        tcr.set_trace_bus_id(0b1101); // 7 bits trace bus ID
+        tcr.set_trace_bus_id(TRACE_BUS_ID); // 7 bits trace bus ID
 
         tcr.store(self.component, self.interface)?;
 
@@ -186,6 +192,23 @@ impl<'a> Itm<'a> {
     }
 }
 
+/// Unlocks the ITM of the Cortex-M core behind `memory`, and enables it to forward the packets
+/// generated by the DWT.
+pub(crate) fn enable_dwt_forwarding(memory: &mut dyn ArmMemoryInterface) -> Result<(), ArmError> {
+    memory.write_word_32(ITM_BASE + u64::from(REGISTER_OFFSET_ACCESS), 0xC5AC_CE55)?;
+
+    let tcr_address = register::ITM_TCR::get_mmio_address_from_base(ITM_BASE)?;
+    let mut tcr = register::ITM_TCR::from(memory.read_word_32(tcr_address)?);
+    tcr.set_itmena(true);
+    tcr.set_syncena(true);
+    tcr.set_txena(true);
+    if tcr.tracebusid() == 0 {
+        tcr.set_trace_bus_id(TRACE_BUS_ID);
+    }
+
+    memory.write_word_32(tcr_address, tcr.into())
+}
+
 mod register {
     use crate::{
         architecture::arm::component::DebugComponentInterface, memory_mapped_bitfield_register,
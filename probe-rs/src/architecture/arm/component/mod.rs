@@ -1,5 +1,6 @@
 //! Types and functions for interacting with CoreSight Components
 
+mod cti;
 mod dwt;
 mod itm;
 mod scs;
@@ -11,16 +12,24 @@ mod trace_funnel;
 use crate::{
     Core, Error, MemoryInterface, MemoryMappedRegister,
     architecture::arm::{
-        ArmDebugInterface, ArmError, SwoConfig, SwoMode,
+        ArmDebugInterface, ArmError, ParallelTraceConfig, SwoConfig, SwoMode,
         core::armv6m::Demcr,
         dp::DpAddress,
-        memory::romtable::{CoresightComponent, PeripheralType, RomTableError},
+        memory::romtable::{ComponentParsers, CoresightComponent, PeripheralType, RomTableError},
     },
 };
 
 pub use self::itm::Itm;
-pub use dwt::Dwt;
-pub use scs::Scs;
+pub use cti::Cti;
+pub(crate) use dwt::DwtConfig;
+pub use dwt::{
+    Dwt, SampleRate, WatchKind, clear_watchpoint, enable_cycle_counter, enable_pc_sampling,
+    read_cycle_count, reset_cycle_counter, set_watchpoint,
+};
+pub use scs::{
+    AuthStatus, CortexMCore, MpuPermission, MpuRegion, Scs, halt_all_cores, read_auth_status,
+    read_mpu_regions, resume_all_cores,
+};
 pub use swo::Swo;
 pub use tmc::TraceMemoryController;
 pub use tpiu::Tpiu;
@@ -30,6 +39,7 @@ use super::memory::Component;
 
 /// Specifies the data sink (destination) for trace data.
 #[derive(Debug, Copy, Clone)]
+#[non_exhaustive]
 pub enum TraceSink {
     /// Trace data should be sent to the SWO peripheral.
     ///
@@ -109,6 +119,16 @@ pub trait DebugComponentInterface:
 pub fn get_arm_components(
     interface: &mut dyn ArmDebugInterface,
     dp: DpAddress,
+) -> Result<Vec<CoresightComponent>, ArmError> {
+    get_arm_components_with(interface, dp, &ComponentParsers::default())
+}
+
+/// Reads all the available ARM CoresightComponents of the currently attached target, like
+/// [`get_arm_components`], recognizing proprietary components with the given `parsers`.
+pub fn get_arm_components_with(
+    interface: &mut dyn ArmDebugInterface,
+    dp: DpAddress,
+    parsers: &ComponentParsers,
 ) -> Result<Vec<CoresightComponent>, ArmError> {
     let mut components = Vec::new();
 
@@ -117,7 +137,8 @@ pub fn get_arm_components(
             match memory.base_address()? {
                 0 => Err(Error::Other("AP has a base address of 0".to_string())),
                 debug_base_address => {
-                    let component = Component::try_parse(&mut *memory, debug_base_address)?;
+                    let component =
+                        Component::try_parse_with(&mut *memory, debug_base_address, parsers)?;
                     Ok(CoresightComponent::new(component, ap_index.clone()))
                 }
             }
@@ -155,6 +176,26 @@ pub fn find_component(
     Ok(component)
 }
 
+/// Goes through every component in the vector and returns all components with the given type.
+///
+/// This is useful for components which exist once per core, like the CTI.
+pub fn find_all_components(
+    components: &[CoresightComponent],
+    peripheral_type: PeripheralType,
+) -> Vec<&CoresightComponent> {
+    components
+        .iter()
+        .flat_map(|component| component.iter())
+        .filter(|component| {
+            component
+                .component
+                .id()
+                .peripheral_id()
+                .is_of_type(peripheral_type)
+        })
+        .collect()
+}
+
 /// Configure the Trace Port Interface Unit
 ///
 /// # Note
@@ -191,6 +232,40 @@ fn configure_tpiu(
     Ok(())
 }
 
+/// Configures the TPIU for parallel trace.
+///
+/// # Note
+/// This configures the TPIU in synchronous mode, where the formatter has to be used.
+///
+/// None of the supported probes can capture the TRACEDATA pins, so this is only useful if the
+/// trace is recorded by external hardware, e.g. a logic analyzer or a dedicated trace probe.
+///
+/// # Args
+/// * `interface` - The interface with the probe.
+/// * `component` - The TPIU CoreSight component found.
+/// * `config` - The parallel trace configuration to use.
+pub fn configure_parallel_tpiu(
+    interface: &mut dyn ArmDebugInterface,
+    component: &CoresightComponent,
+    config: &ParallelTraceConfig,
+) -> Result<(), Error> {
+    let mut tpiu = Tpiu::new(interface, component);
+
+    // CSPSR has one bit per port size, bit n selects a port with n + 1 data pins.
+    let port_size = 1 << (config.port_width().bits() - 1);
+    if tpiu.supported_port_sizes()? & port_size == 0 {
+        return Err(ArmError::FeatureNotImplemented("the configured trace port width").into());
+    }
+
+    tpiu.set_port_size(port_size)?;
+    tpiu.set_pin_protocol(0)?;
+
+    // Formatter: TrigIn and continuous formatting enabled.
+    tpiu.set_formatter(0x102)?;
+
+    Ok(())
+}
+
 /// Sets up all the SWV components.
 ///
 /// Expects to be given a list of all ROM table `components` as the second argument.
@@ -379,3 +454,84 @@ pub fn disable_swv(core: &mut Core) -> Result<(), Error> {
     core.write_word_32(Demcr::get_mmio_address(), demcr.into())?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::configure_parallel_tpiu;
+    use crate::architecture::arm::{
+        ArmCommunicationInterface, ArmDebugInterface, FullyQualifiedApAddress, ParallelTraceConfig,
+        TracePortWidth,
+        ap::memory_ap::mock::MockMemoryAp,
+        communication_interface::tests::mock_memory_interface,
+        memory::romtable::{Component, CoresightComponent},
+    };
+
+    const TPIU_BASE: u64 = 0xE004_0000;
+
+    /// Creates an interface to a Class 0x9 CoreSight component at `base`, whose registers at the
+    /// given offsets read the given values.
+    pub(super) fn mock_component(
+        base: u64,
+        registers: &[(u32, u32)],
+    ) -> (
+        Arc<Mutex<MockMemoryAp>>,
+        ArmCommunicationInterface,
+        CoresightComponent,
+    ) {
+        let mut memory_ap = MockMemoryAp::with_pattern_and_size(0);
+        memory_ap.registers.extend([
+            // CIDR of a Class 0x9 component
+            (base + 0xFF0, 0x0D),
+            (base + 0xFF4, 0x90),
+            (base + 0xFF8, 0x05),
+            (base + 0xFFC, 0xB1),
+        ]);
+        memory_ap.registers.extend(
+            registers
+                .iter()
+                .map(|&(offset, value)| (base + u64::from(offset), value)),
+        );
+        let memory_ap = Arc::new(Mutex::new(memory_ap));
+        let mut interface = mock_memory_interface(memory_ap.clone());
+
+        let ap = FullyQualifiedApAddress::v1_with_default_dp(0);
+        let mut memory = interface.memory_interface(&ap).unwrap();
+        let component = Component::try_parse(&mut *memory, base).unwrap();
+        drop(memory);
+
+        (memory_ap, interface, CoresightComponent::new(component, ap))
+    }
+
+    #[test]
+    fn parallel_tpiu_is_configured_for_the_port_width() {
+        // SSPSR: 1, 2 and 4 data pins
+        let (memory_ap, mut interface, component) = mock_component(TPIU_BASE, &[(0, 0b1011)]);
+
+        let config = ParallelTraceConfig::new().set_port_width(TracePortWidth::Bits4);
+        configure_parallel_tpiu(&mut interface, &component, &config).unwrap();
+
+        assert_eq!(
+            memory_ap.lock().unwrap().register_writes,
+            [
+                // CSPSR: 4 data pins
+                (TPIU_BASE + 0x4, 1 << 3),
+                // SPPR: synchronous trace
+                (TPIU_BASE + 0xF0, 0),
+                // FFCR: TrigIn, continuous formatting
+                (TPIU_BASE + 0x304, 0x102),
+            ]
+        );
+    }
+
+    #[test]
+    fn unsupported_port_width_is_rejected() {
+        // SSPSR: 1 data pin
+        let (memory_ap, mut interface, component) = mock_component(TPIU_BASE, &[(0, 0b0001)]);
+
+        let config = ParallelTraceConfig::new().set_port_width(TracePortWidth::Bits2);
+        assert!(configure_parallel_tpiu(&mut interface, &component, &config).is_err());
+        assert!(memory_ap.lock().unwrap().register_writes.is_empty());
+    }
+}
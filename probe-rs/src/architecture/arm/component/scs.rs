@@ -2,14 +2,326 @@
 //!
 //! SCS = System Control Space
 
-use self::register::CPUID;
+pub use self::register::AuthStatus;
+use self::register::{CPUID, MpuRasr, MpuRbar, MpuRlar, MpuRnr, MpuType};
 
-use super::super::memory::romtable::CoresightComponent;
+use std::time::{Duration, Instant};
+
+use super::super::memory::romtable::{CoresightComponent, PeripheralType};
 use crate::{
-    MemoryMappedRegister,
-    architecture::arm::{ArmDebugInterface, ArmError},
+    CoreType, MemoryMappedRegister,
+    architecture::arm::{
+        ArmDebugInterface, ArmError, FullyQualifiedApAddress, core::armv7m::Dhcsr, dp::DpAddress,
+        memory::ArmMemoryInterface,
+    },
 };
 
+/// The base address of the SCS on Cortex-M cores.
+const SCS_BASE: u64 = 0xE000_E000;
+
+/// A Cortex-M core variant, as identified by the CPUID register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CortexMCore {
+    /// Cortex-M0
+    M0,
+    /// Cortex-M0+
+    M0Plus,
+    /// Cortex-M1
+    M1,
+    /// Cortex-M3
+    M3,
+    /// Cortex-M4
+    M4,
+    /// Cortex-M7
+    M7,
+    /// Cortex-M23
+    M23,
+    /// Cortex-M33
+    M33,
+    /// Cortex-M35P
+    M35P,
+    /// Cortex-M52
+    M52,
+    /// Cortex-M55
+    M55,
+    /// Cortex-M85
+    M85,
+    /// A core which is not known, with the raw CPUID value.
+    Unknown(u32),
+}
+
+impl CortexMCore {
+    /// The address of the CPUID register.
+    pub const CPUID_ADDRESS: u64 = 0xE000_ED00;
+
+    /// Decodes the core from the raw value of the CPUID register.
+    pub fn from_cpuid(cpuid: u32) -> Self {
+        let cpuid = CPUID(cpuid);
+        if cpuid.implementer() != 0x41 {
+            return Self::Unknown(cpuid.0);
+        }
+
+        match cpuid.partno() {
+            0xC20 => Self::M0,
+            0xC21 => Self::M1,
+            0xC23 => Self::M3,
+            0xC24 => Self::M4,
+            0xC27 => Self::M7,
+            0xC60 => Self::M0Plus,
+            0xD20 => Self::M23,
+            0xD21 => Self::M33,
+            0xD22 => Self::M55,
+            0xD23 => Self::M85,
+            0xD24 => Self::M52,
+            0xD31 => Self::M35P,
+            _ => Self::Unknown(cpuid.0),
+        }
+    }
+
+    /// The architecture implemented by the core, or `None` for unknown cores.
+    pub fn core_type(&self) -> Option<CoreType> {
+        match self {
+            Self::M0 | Self::M0Plus | Self::M1 => Some(CoreType::Armv6m),
+            Self::M3 => Some(CoreType::Armv7m),
+            Self::M4 | Self::M7 => Some(CoreType::Armv7em),
+            Self::M23 | Self::M33 | Self::M35P | Self::M52 | Self::M55 | Self::M85 => {
+                Some(CoreType::Armv8m)
+            }
+            Self::Unknown(_) => None,
+        }
+    }
+}
+
+/// The access a privilege level has to an MPU region.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MpuPermission {
+    /// Any access faults.
+    NoAccess,
+    /// Reads are allowed, writes fault.
+    ReadOnly,
+    /// Reads and writes are allowed.
+    ReadWrite,
+}
+
+/// A region of the MPU, as returned by [`read_mpu_regions`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MpuRegion {
+    /// The number of the region.
+    pub number: u8,
+    /// Whether the region is enabled.
+    pub enabled: bool,
+    /// The address of the first byte of the region.
+    pub base_address: u32,
+    /// The size of the region in bytes.
+    pub size: u64,
+    /// The access of privileged software to the region.
+    pub privileged: MpuPermission,
+    /// The access of unprivileged software to the region.
+    pub unprivileged: MpuPermission,
+    /// Whether instruction fetches from the region fault.
+    pub execute_never: bool,
+    /// The disabled subregions, bit `n` is set if subregion `n` is disabled.
+    ///
+    /// Only ARMv6-M and ARMv7-M split regions into eight subregions, this is always zero on
+    /// ARMv8-M.
+    pub disabled_subregions: u8,
+}
+
+impl MpuRegion {
+    /// Decodes a region from the RBAR and RASR registers of an ARMv6-M or ARMv7-M MPU.
+    fn from_armv7m(number: u8, rbar: MpuRbar, rasr: MpuRasr) -> Self {
+        let (privileged, unprivileged) = match rasr.ap() {
+            0b001 => (MpuPermission::ReadWrite, MpuPermission::NoAccess),
+            0b010 => (MpuPermission::ReadWrite, MpuPermission::ReadOnly),
+            0b011 => (MpuPermission::ReadWrite, MpuPermission::ReadWrite),
+            0b101 => (MpuPermission::ReadOnly, MpuPermission::NoAccess),
+            0b110 | 0b111 => (MpuPermission::ReadOnly, MpuPermission::ReadOnly),
+            // 0b100 is reserved.
+            _ => (MpuPermission::NoAccess, MpuPermission::NoAccess),
+        };
+
+        Self {
+            number,
+            enabled: rasr.enable(),
+            base_address: rbar.0 & !0x1F,
+            size: 1 << (rasr.size() + 1),
+            privileged,
+            unprivileged,
+            execute_never: rasr.xn(),
+            disabled_subregions: rasr.srd(),
+        }
+    }
+
+    /// Decodes a region from the RBAR and RLAR registers of an ARMv8-M MPU.
+    fn from_armv8m(number: u8, rbar: MpuRbar, rlar: MpuRlar) -> Self {
+        let base_address = rbar.0 & !0x1F;
+        // The limit address is the last byte of the region.
+        let limit_address = rlar.0 | 0x1F;
+
+        let (privileged, unprivileged) = match rbar.ap() {
+            0b00 => (MpuPermission::ReadWrite, MpuPermission::NoAccess),
+            0b01 => (MpuPermission::ReadWrite, MpuPermission::ReadWrite),
+            0b10 => (MpuPermission::ReadOnly, MpuPermission::NoAccess),
+            _ => (MpuPermission::ReadOnly, MpuPermission::ReadOnly),
+        };
+
+        Self {
+            number,
+            enabled: rlar.enable(),
+            base_address,
+            size: (u64::from(limit_address) + 1).saturating_sub(u64::from(base_address)),
+            privileged,
+            unprivileged,
+            execute_never: rbar.xn(),
+            disabled_subregions: 0,
+        }
+    }
+}
+
+/// Reads the configuration of all regions of the MPU of the Cortex-M core behind `memory`.
+///
+/// An empty list is returned if the core has no MPU. RNR is restored afterwards, so that the
+/// firmware isn't disturbed if it is halted while programming the MPU.
+pub fn read_mpu_regions(
+    memory: &mut dyn ArmMemoryInterface,
+    core_type: CoreType,
+) -> Result<Vec<MpuRegion>, ArmError> {
+    let armv8m = match core_type {
+        CoreType::Armv8m => true,
+        CoreType::Armv6m | CoreType::Armv7m | CoreType::Armv7em => false,
+        _ => {
+            return Err(ArmError::ArchitectureRequired(&[
+                "ARMv6-M", "ARMv7-M", "ARMv8-M",
+            ]));
+        }
+    };
+
+    let mpu_type = MpuType(memory.read_word_32(MpuType::get_mmio_address_from_base(SCS_BASE)?)?);
+    if mpu_type.dregion() == 0 {
+        return Ok(Vec::new());
+    }
+
+    let rnr_address = MpuRnr::get_mmio_address_from_base(SCS_BASE)?;
+    let rbar_address = MpuRbar::get_mmio_address_from_base(SCS_BASE)?;
+    // RASR and RLAR are at the same address.
+    let attributes_address = MpuRasr::get_mmio_address_from_base(SCS_BASE)?;
+
+    let original_rnr = memory.read_word_32(rnr_address)?;
+
+    let mut regions = Vec::with_capacity(mpu_type.dregion().into());
+    for number in 0..mpu_type.dregion() {
+        memory.write_word_32(rnr_address, number.into())?;
+        let rbar = MpuRbar(memory.read_word_32(rbar_address)?);
+        let attributes = memory.read_word_32(attributes_address)?;
+
+        regions.push(if armv8m {
+            MpuRegion::from_armv8m(number, rbar, MpuRlar(attributes))
+        } else {
+            MpuRegion::from_armv7m(number, rbar, MpuRasr(attributes))
+        });
+    }
+
+    memory.write_word_32(rnr_address, original_rnr)?;
+
+    Ok(regions)
+}
+
+/// Reads the debug authentication status of the core behind `access_port`.
+///
+/// On secured devices, the authentication interface can block halting the core or accessing
+/// secure memory, which otherwise shows up as FAULT responses.
+pub fn read_auth_status(
+    interface: &mut dyn ArmDebugInterface,
+    access_port: &FullyQualifiedApAddress,
+) -> Result<AuthStatus, ArmError> {
+    let (scs_address, _) = interface
+        .find_all_components(access_port, PeripheralType::Scs)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| ArmError::Other("No SCS found in the ROM table".to_string()))?;
+
+    let mut memory = interface.memory_interface(access_port)?;
+    let value = memory.read_word_32(scs_address + AuthStatus::ADDRESS_OFFSET)?;
+
+    Ok(AuthStatus(value))
+}
+
+/// Halts all Cortex-M cores behind the debug port `dp`, and waits until they are halted.
+///
+/// The cores are found by walking the ROM tables of all access ports. All cores are requested
+/// to halt before waiting for any of them, so that they stop as close together as possible.
+pub fn halt_all_cores(
+    interface: &mut dyn ArmDebugInterface,
+    dp: DpAddress,
+) -> Result<(), ArmError> {
+    set_all_cores_halted(interface, dp, true)
+}
+
+/// Resumes all Cortex-M cores behind the debug port `dp`, see [`halt_all_cores`].
+pub fn resume_all_cores(
+    interface: &mut dyn ArmDebugInterface,
+    dp: DpAddress,
+) -> Result<(), ArmError> {
+    set_all_cores_halted(interface, dp, false)
+}
+
+/// Halts or resumes all Cortex-M cores behind `dp` using their DHCSR.
+fn set_all_cores_halted(
+    interface: &mut dyn ArmDebugInterface,
+    dp: DpAddress,
+    halt: bool,
+) -> Result<(), ArmError> {
+    const TIMEOUT: Duration = Duration::from_millis(100);
+    let dhcsr_offset = Dhcsr::ADDRESS_OFFSET & 0xFFF;
+
+    let mut cores = Vec::new();
+    for access_port in interface.access_ports(dp)? {
+        match interface.find_all_components(&access_port, PeripheralType::Scs) {
+            Ok(components) => cores.extend(
+                components
+                    .into_iter()
+                    .map(|(address, _)| (access_port.clone(), address + dhcsr_offset)),
+            ),
+            Err(e) => tracing::debug!("Not searching {:x?} for cores: {}", access_port, e),
+        }
+    }
+
+    for (access_port, dhcsr_address) in &cores {
+        let mut dhcsr = Dhcsr(0);
+        dhcsr.enable_write();
+        dhcsr.set_c_debugen(true);
+        dhcsr.set_c_halt(halt);
+
+        let mut memory = interface.memory_interface(access_port)?;
+        memory.write_word_32(*dhcsr_address, dhcsr.into())?;
+        memory.flush()?;
+    }
+
+    let start = Instant::now();
+    while !cores.is_empty() {
+        let mut pending = Vec::new();
+        for (access_port, dhcsr_address) in cores {
+            let mut memory = interface.memory_interface(&access_port)?;
+            let dhcsr = Dhcsr(memory.read_word_32(dhcsr_address)?);
+            drop(memory);
+
+            if dhcsr.s_halt() != halt {
+                pending.push((access_port, dhcsr_address));
+            }
+        }
+
+        if !pending.is_empty() && start.elapsed() >= TIMEOUT {
+            return Err(ArmError::CoresNotResponding {
+                action: if halt { "halt" } else { "resume" },
+                cores: pending,
+            });
+        }
+        cores = pending;
+    }
+
+    Ok(())
+}
+
 /// An interface to control the SCS (System Control Space) of a MCU.
 pub struct Scs<'a> {
     component: &'a CoresightComponent,
@@ -34,6 +346,13 @@ impl<'a> Scs<'a> {
             .read_reg(self.interface, CPUID::ADDRESS_OFFSET as u32)
             .map(CPUID)
     }
+
+    /// D1.2.5 DAUTHSTATUS, Debug Authentication Status Register
+    pub fn auth_status(&mut self) -> Result<AuthStatus, ArmError> {
+        self.component
+            .read_reg(self.interface, AuthStatus::ADDRESS_OFFSET as u32)
+            .map(AuthStatus)
+    }
 }
 
 mod register {
@@ -82,4 +401,133 @@ mod register {
             }
         }
     }
+
+    memory_mapped_bitfield_register! {
+        /// D1.2.5 DAUTHSTATUS, Debug Authentication Status Register
+        ///
+        /// Each field reads as `0b00` if the security state is not implemented,
+        /// `0b10` if the debug feature is disabled and `0b11` if it is enabled.
+        pub struct AuthStatus(u32);
+        0xFB8, "DAUTHSTATUS",
+        impl From;
+        /// Secure non-invasive debug.
+        pub u8, snid, _: 7, 6;
+        /// Secure invasive debug.
+        pub u8, sid, _: 5, 4;
+        /// Non-secure non-invasive debug.
+        pub u8, nsnid, _: 3, 2;
+        /// Non-secure invasive debug.
+        pub u8, nsid, _: 1, 0;
+    }
+
+    impl AuthStatus {
+        /// Whether invasive debug is enabled in the non-secure state (DBGEN).
+        pub fn dbgen(&self) -> bool {
+            self.nsid() == 0b11
+        }
+
+        /// Whether non-invasive debug is enabled in the non-secure state (NIDEN).
+        pub fn niden(&self) -> bool {
+            self.nsnid() == 0b11
+        }
+
+        /// Whether invasive debug is enabled in the secure state (SPIDEN).
+        pub fn spiden(&self) -> bool {
+            self.sid() == 0b11
+        }
+
+        /// Whether non-invasive debug is enabled in the secure state (SPNIDEN).
+        pub fn spniden(&self) -> bool {
+            self.snid() == 0b11
+        }
+    }
+
+    memory_mapped_bitfield_register! {
+        /// B3.5.5 MPU Type Register
+        pub struct MpuType(u32);
+        0xD90, "MPU_TYPE",
+        impl From;
+        pub u8, dregion, _: 15, 8;
+    }
+
+    memory_mapped_bitfield_register! {
+        /// B3.5.7 MPU Region Number Register
+        pub struct MpuRnr(u32);
+        0xD98, "MPU_RNR",
+        impl From;
+    }
+
+    memory_mapped_bitfield_register! {
+        /// B3.5.8 MPU Region Base Address Register
+        ///
+        /// The AP and XN fields only exist on ARMv8-M.
+        pub struct MpuRbar(u32);
+        0xD9C, "MPU_RBAR",
+        impl From;
+        pub u8, ap, _: 2, 1;
+        pub xn, _: 0;
+    }
+
+    memory_mapped_bitfield_register! {
+        /// B3.5.9 MPU Region Attribute and Size Register, ARMv6-M and ARMv7-M only
+        pub struct MpuRasr(u32);
+        0xDA0, "MPU_RASR",
+        impl From;
+        pub xn, _: 28;
+        pub u8, ap, _: 26, 24;
+        pub u8, srd, _: 15, 8;
+        pub u8, size, _: 5, 1;
+        pub enable, _: 0;
+    }
+
+    memory_mapped_bitfield_register! {
+        /// MPU Region Limit Address Register, ARMv8-M only
+        pub struct MpuRlar(u32);
+        0xDA0, "MPU_RLAR",
+        impl From;
+        pub enable, _: 0;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        MpuPermission, MpuRegion,
+        register::{MpuRasr, MpuRbar, MpuRlar},
+    };
+
+    #[test]
+    fn mpu_regions_are_decoded() {
+        // 32 KiB of read-only memory at 0x0800_0000, with the last subregion disabled.
+        let region = MpuRegion::from_armv7m(1, MpuRbar(0x0800_0011), MpuRasr(0x0600_801D));
+        assert_eq!(
+            region,
+            MpuRegion {
+                number: 1,
+                enabled: true,
+                base_address: 0x0800_0000,
+                size: 0x8000,
+                privileged: MpuPermission::ReadOnly,
+                unprivileged: MpuPermission::ReadOnly,
+                execute_never: false,
+                disabled_subregions: 0x80,
+            }
+        );
+
+        // 4 KiB of privileged, non-executable memory at 0x2000_0000.
+        let region = MpuRegion::from_armv8m(0, MpuRbar(0x2000_0001), MpuRlar(0x2000_0FE1));
+        assert_eq!(
+            region,
+            MpuRegion {
+                number: 0,
+                enabled: true,
+                base_address: 0x2000_0000,
+                size: 0x1000,
+                privileged: MpuPermission::ReadWrite,
+                unprivileged: MpuPermission::NoAccess,
+                execute_never: true,
+                disabled_subregions: 0,
+            }
+        );
+    }
 }
@@ -4,7 +4,7 @@ use crate::architecture::arm::ArmDebugInterface;
 
 pub const _TPIU_PID: [u8; 8] = [0xA1, 0xB9, 0x0B, 0x0, 0x4, 0x0, 0x0, 0x0];
 
-const _REGISTER_OFFSET_TPIU_SSPSR: u32 = 0x0;
+const REGISTER_OFFSET_TPIU_SSPSR: u32 = 0x0;
 const REGISTER_OFFSET_TPIU_CSPSR: u32 = 0x4;
 const REGISTER_OFFSET_TPIU_ACPR: u32 = 0x10;
 const REGISTER_OFFSET_TPIU_SPPR: u32 = 0xF0;
@@ -30,6 +30,16 @@ impl<'a> Tpiu<'a> {
         }
     }
 
+    /// Read the port sizes supported by the TPIU.
+    ///
+    /// Bit `n` is set if a port with `n + 1` data pins is supported.
+    pub fn supported_port_sizes(&mut self) -> Result<u32, Error> {
+        let value = self
+            .component
+            .read_reg(self.interface, REGISTER_OFFSET_TPIU_SSPSR)?;
+        Ok(value)
+    }
+
     /// Set the port size of the TPIU.
     pub fn set_port_size(&mut self, value: u32) -> Result<(), Error> {
         self.component
@@ -1,14 +1,27 @@
 //! Common functions and data types for Cortex-M core variants
 
 use crate::{
-    CoreInterface, Error, MemoryMappedRegister,
-    architecture::arm::{ArmError, memory::ArmMemoryInterface},
+    CoreInterface, CoreRegister, CoreType, Error, HaltReason, MemoryMappedRegister,
+    architecture::arm::{
+        ArmError,
+        component::{self, CortexMCore},
+        core::{
+            CortexMState, Dfsr,
+            armv7m::{Armv7m, Demcr, FpCtrl, FpRev1CompX, FpRev2CompX},
+        },
+        memory::ArmMemoryInterface,
+        sequences::{ArmDebugSequence, DefaultArmSequence},
+        valid_32bit_arm_address,
+    },
     core::RegisterId,
     memory_mapped_bitfield_register,
     semihosting::SemihostingCommand,
     semihosting::decode_semihosting_syscall,
 };
-use std::time::{Duration, Instant};
+use std::{
+    mem::size_of,
+    time::{Duration, Instant},
+};
 
 memory_mapped_bitfield_register! {
     pub struct Vtor(u32);
@@ -129,6 +142,27 @@ impl IdPfr1 {
     }
 }
 
+/// Checks that `register` can be accessed through DCRSR/DCRDR right now.
+///
+/// This requires the core to be halted, and an FPU for the floating point registers.
+pub(crate) fn check_core_reg_access(
+    memory: &mut dyn ArmMemoryInterface,
+    register: RegisterId,
+) -> Result<(), ArmError> {
+    if !Dhcsr(memory.read_word_32(Dhcsr::get_mmio_address())?).s_halt() {
+        return Err(ArmError::CoreNotHalted);
+    }
+
+    // FPSCR and S0-S31.
+    if (register.0 == 33 || (64..=95).contains(&register.0))
+        && !Mvfr0(memory.read_word_32(Mvfr0::get_mmio_address())?).fp_present()
+    {
+        return Err(ArmError::ExtensionRequired(&["FPU"]));
+    }
+
+    Ok(())
+}
+
 pub(crate) fn read_core_reg(
     memory: &mut dyn ArmMemoryInterface,
     addr: RegisterId,
@@ -224,3 +258,1009 @@ fn wait_for_core_register_transfer(
     }
     Err(ArmError::Timeout)
 }
+
+/// The capabilities of a Flash Patch and Breakpoint unit, as returned by
+/// [`CortexMDebug::read_breakpoint_unit_info`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BreakpointUnitInfo {
+    /// The version of the FPB architecture, 1 or 2.
+    ///
+    /// Version 1 only supports breakpoints in the code region, below `0x2000_0000`.
+    pub version: u32,
+    /// The number of instruction address comparators, which is the number of hardware
+    /// breakpoints.
+    pub code_comparators: u32,
+    /// The number of literal address comparators.
+    pub literal_comparators: u32,
+}
+
+impl From<FpCtrl> for BreakpointUnitInfo {
+    fn from(fp_ctrl: FpCtrl) -> Self {
+        Self {
+            version: fp_ctrl.rev() + 1,
+            // NUM_CODE is split into two fields in both versions. ARMv6-M cores don't implement
+            // the upper field, it reads as zero there.
+            code_comparators: fp_ctrl.num_code(),
+            literal_comparators: fp_ctrl.num_lit(),
+        }
+    }
+}
+
+/// The initial state of a Cortex-M core after reset, as returned by
+/// [`CortexMDebug::read_reset_vector`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResetVector {
+    /// The initial value of the main stack pointer.
+    pub stack_pointer: u32,
+    /// The address of the reset handler, where execution starts.
+    pub program_counter: u32,
+}
+
+/// The exceptions which halt a Cortex-M core, see [`CortexMDebug::set_vector_catch`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VectorCatchFlags {
+    /// Halt on reset (VC_CORERESET).
+    pub core_reset: bool,
+    /// Halt on a HardFault (VC_HARDERR).
+    pub hard_fault: bool,
+    /// Halt on a fault during exception entry or return (VC_INTERR).
+    pub exception_entry_fault: bool,
+    /// Halt on a BusFault (VC_BUSERR).
+    pub bus_fault: bool,
+    /// Halt on a UsageFault caused by a state information error, e.g. an undefined instruction
+    /// (VC_STATERR).
+    pub state_error: bool,
+    /// Halt on a UsageFault caused by a checking error, e.g. an alignment check (VC_CHKERR).
+    pub checking_error: bool,
+    /// Halt on a UsageFault caused by an access to a coprocessor (VC_NOCPERR).
+    pub coprocessor_error: bool,
+    /// Halt on a MemManage fault (VC_MMERR).
+    pub mem_manage: bool,
+}
+
+impl VectorCatchFlags {
+    /// Sets the VC_* bits of `demcr` to these flags, leaving the other bits unchanged.
+    fn apply(self, demcr: &mut Demcr) {
+        demcr.set_vc_corereset(self.core_reset);
+        demcr.set_vc_harderr(self.hard_fault);
+        demcr.set_vc_interr(self.exception_entry_fault);
+        demcr.set_vc_buserr(self.bus_fault);
+        demcr.set_vc_staterr(self.state_error);
+        demcr.set_vc_chkerr(self.checking_error);
+        demcr.set_vc_nocperr(self.coprocessor_error);
+        demcr.set_vc_mmerr(self.mem_manage);
+    }
+}
+
+impl From<Demcr> for VectorCatchFlags {
+    fn from(demcr: Demcr) -> Self {
+        Self {
+            core_reset: demcr.vc_corereset(),
+            hard_fault: demcr.vc_harderr(),
+            exception_entry_fault: demcr.vc_interr(),
+            bus_fault: demcr.vc_buserr(),
+            state_error: demcr.vc_staterr(),
+            checking_error: demcr.vc_chkerr(),
+            coprocessor_error: demcr.vc_nocperr(),
+            mem_manage: demcr.vc_mmerr(),
+        }
+    }
+}
+
+/// Debug functions of Cortex-M cores, which access the core through its memory mapped debug
+/// registers.
+///
+/// This is implemented for the memory interface of the access port the core is behind, see
+/// [`ArmDebugInterface::memory_interface`](crate::architecture::arm::ArmDebugInterface::memory_interface).
+/// Unlike [`Core`](crate::Core), no session or target description is needed.
+pub trait CortexMDebug {
+    /// Reads the CPUID register, and decodes the Cortex-M core variant from it.
+    fn read_core_type(&mut self) -> Result<CortexMCore, ArmError>;
+
+    /// Reads the capabilities of the Flash Patch and Breakpoint unit (FPB) from the FP_CTRL
+    /// register.
+    ///
+    /// The number of code comparators is the number of hardware breakpoints available.
+    fn read_breakpoint_unit_info(&mut self) -> Result<BreakpointUnitInfo, ArmError>;
+
+    /// Reads the address of the vector table from VTOR.
+    ///
+    /// Cores which don't implement VTOR, like most ARMv6-M cores, read it as zero, which is
+    /// also where their vector table is fixed.
+    fn read_vector_table_base(&mut self) -> Result<u64, ArmError>;
+
+    /// Reads the initial stack pointer and program counter from the first two entries of the
+    /// vector table.
+    ///
+    /// These are the values the core loads on reset, see
+    /// [`CortexMDebug::read_vector_table_base`].
+    fn read_reset_vector(&mut self) -> Result<ResetVector, ArmError>;
+
+    /// Sets the hardware breakpoint `index` to `address`, and enables the FPB if it isn't enabled
+    /// yet.
+    ///
+    /// The FP_COMP register is encoded as required by the version of the FPB, see
+    /// [`CortexMDebug::read_breakpoint_unit_info`]. Version 1 units only support breakpoints
+    /// below `0x2000_0000`.
+    fn set_hw_breakpoint(&mut self, index: usize, address: u64) -> Result<(), ArmError>;
+
+    /// Clears the hardware breakpoint `index`.
+    ///
+    /// The FPB stays enabled, so other breakpoints keep working.
+    fn clear_hw_breakpoint(&mut self, index: usize) -> Result<(), ArmError>;
+
+    /// Reads the core register `register`, using DCRSR and DCRDR.
+    ///
+    /// The core has to be halted, otherwise [`ArmError::CoreNotHalted`] is returned. The
+    /// floating point registers can only be read on cores with an FPU.
+    fn read_core_register(&mut self, register: &CoreRegister) -> Result<u32, ArmError>;
+
+    /// Writes `value` to the core register `register`, using DCRSR and DCRDR.
+    ///
+    /// The core has to be halted, otherwise [`ArmError::CoreNotHalted`] is returned. The
+    /// floating point registers can only be written on cores with an FPU.
+    fn write_core_register(&mut self, register: &CoreRegister, value: u32) -> Result<(), ArmError>;
+
+    /// Executes a single instruction on the halted core, using DHCSR.C_STEP, and waits until it
+    /// is halted again.
+    ///
+    /// If `mask_interrupts` is set, pending interrupts are not taken during the step. Otherwise
+    /// the step may end in an interrupt handler. The setting is kept after the step, and has to
+    /// be cleared before the core is resumed.
+    ///
+    /// Returns [`ArmError::CoreNotHalted`] if the core is running. If the step puts the core
+    /// into lockup, it is halted again.
+    fn step_core(&mut self, mask_interrupts: bool) -> Result<(), ArmError>;
+
+    /// Reads why the core halted from DFSR, and clears the reported bits.
+    ///
+    /// If multiple reasons are reported, the most specific one is returned: breakpoints are
+    /// reported before watchpoints, vector catches, external debug requests and halt requests.
+    /// [`HaltReason::Unknown`] is returned if no reason is reported, e.g. because it was cleared
+    /// already.
+    fn read_halt_reason(&mut self) -> Result<HaltReason, ArmError>;
+
+    /// Decodes the semihosting call the core is halted at.
+    ///
+    /// Returns `None` if the core is not halted at a breakpoint, or the breakpoint is not a
+    /// semihosting call (`BKPT 0xAB`). Otherwise, the operation in R0 and its parameter block
+    /// in R1 are decoded. The caller has to answer the call, and set the return value in R0
+    /// before resuming the core. DFSR is cleared.
+    fn handle_semihosting(&mut self) -> Result<Option<SemihostingCommand>, ArmError>;
+
+    /// Configures which exceptions halt the core, by writing the VC_* bits of DEMCR.
+    ///
+    /// Returns the previous configuration, so it can be restored later. ARMv6-M cores only
+    /// support [`VectorCatchFlags::core_reset`] and [`VectorCatchFlags::hard_fault`].
+    fn set_vector_catch(&mut self, catches: VectorCatchFlags)
+    -> Result<VectorCatchFlags, ArmError>;
+
+    /// Resets the core and halts it at the reset vector, also known as "reset catch".
+    ///
+    /// Debug is enabled in DHCSR, and the `ResetCatchSet` and `ResetSystem` functions of
+    /// `sequence` are run. Returns [`ArmError::Timeout`] if the core does not report S_HALT
+    /// within `timeout`. Reset catch is cleared again in any case.
+    fn reset_and_halt(
+        &mut self,
+        sequence: &dyn ArmDebugSequence,
+        timeout: Duration,
+    ) -> Result<(), ArmError>;
+
+    /// Resets the core with the `ResetSystem` function of `sequence`, and restores the
+    /// configuration of its debug components afterwards.
+    ///
+    /// On some devices, a system reset also resets DEMCR, the FPB and the DWT, which clears all
+    /// hardware breakpoints and watchpoints. Their registers are saved before the reset, and
+    /// written back once it is complete. If halting debug is enabled, the core is held at the
+    /// reset vector until then, so that no breakpoint is missed. It is resumed afterwards,
+    /// unless reset vector catch was enabled before.
+    fn reset_preserving_debug(&mut self, sequence: &dyn ArmDebugSequence) -> Result<(), ArmError>;
+}
+
+impl CortexMDebug for dyn ArmMemoryInterface + '_ {
+    fn read_core_type(&mut self) -> Result<CortexMCore, ArmError> {
+        let cpuid = self.read_word_32(CortexMCore::CPUID_ADDRESS)?;
+
+        Ok(CortexMCore::from_cpuid(cpuid))
+    }
+
+    fn read_breakpoint_unit_info(&mut self) -> Result<BreakpointUnitInfo, ArmError> {
+        let fp_ctrl = FpCtrl::from(self.read_word_32(FpCtrl::get_mmio_address())?);
+
+        Ok(BreakpointUnitInfo::from(fp_ctrl))
+    }
+
+    fn read_vector_table_base(&mut self) -> Result<u64, ArmError> {
+        let vtor = Vtor::from(self.read_word_32(Vtor::get_mmio_address())?);
+
+        Ok(u64::from(vtor.tbloff()) << 7)
+    }
+
+    fn read_reset_vector(&mut self) -> Result<ResetVector, ArmError> {
+        let base = self.read_vector_table_base()?;
+
+        let mut entries = [0; 2];
+        self.read_32(base, &mut entries)?;
+
+        Ok(ResetVector {
+            stack_pointer: entries[0],
+            // The reset handler has the Thumb bit set, which is not part of the address.
+            program_counter: entries[1] & !1,
+        })
+    }
+
+    fn set_hw_breakpoint(&mut self, index: usize, address: u64) -> Result<(), ArmError> {
+        let address = valid_32bit_arm_address(address)?;
+        if address % 2 != 0 {
+            return Err(ArmError::alignment_error(address.into(), 2));
+        }
+
+        let fp_ctrl = FpCtrl::from(self.read_word_32(FpCtrl::get_mmio_address())?);
+        let comparator_address = fp_comp_address(fp_ctrl, index)?;
+
+        let comparator: u32 = match fp_ctrl.rev() {
+            0 => FpRev1CompX::breakpoint_configuration(address)?.into(),
+            1 => FpRev2CompX::breakpoint_configuration(address).into(),
+            rev => {
+                return Err(ArmError::Other(format!(
+                    "FPB revision {rev} is not supported"
+                )));
+            }
+        };
+        self.write_word_32(comparator_address, comparator)?;
+
+        if !fp_ctrl.enable() {
+            let mut fp_ctrl = FpCtrl::from(0);
+            fp_ctrl.set_key(true);
+            fp_ctrl.set_enable(true);
+            self.write_word_32(FpCtrl::get_mmio_address(), fp_ctrl.into())?;
+        }
+
+        self.flush()
+    }
+
+    fn clear_hw_breakpoint(&mut self, index: usize) -> Result<(), ArmError> {
+        let fp_ctrl = FpCtrl::from(self.read_word_32(FpCtrl::get_mmio_address())?);
+        let comparator_address = fp_comp_address(fp_ctrl, index)?;
+
+        // The enable bit is bit 0 for both versions of the FPB.
+        self.write_word_32(comparator_address, 0)?;
+
+        self.flush()
+    }
+
+    fn read_core_register(&mut self, register: &CoreRegister) -> Result<u32, ArmError> {
+        check_core_reg_access(self, register.id())?;
+        read_core_reg(self, register.id())
+    }
+
+    fn write_core_register(&mut self, register: &CoreRegister, value: u32) -> Result<(), ArmError> {
+        check_core_reg_access(self, register.id())?;
+        write_core_reg(self, register.id(), value)
+    }
+
+    fn step_core(&mut self, mask_interrupts: bool) -> Result<(), ArmError> {
+        /// The S_HALT bit in DHCSR.
+        const S_HALT: u32 = 1 << 17;
+        const STEP_TIMEOUT: Duration = Duration::from_millis(100);
+
+        let mut dhcsr = Dhcsr(self.read_word_32(Dhcsr::get_mmio_address())?);
+        if !dhcsr.s_halt() {
+            return Err(ArmError::CoreNotHalted);
+        }
+
+        dhcsr.set_c_debugen(true);
+
+        // C_MASKINTS may only be changed while the core is halted, so it can't be changed
+        // together with clearing C_HALT.
+        if dhcsr.c_maskints() != mask_interrupts {
+            dhcsr.set_c_maskints(mask_interrupts);
+            dhcsr.set_c_halt(true);
+            dhcsr.enable_write();
+            self.write_word_32(Dhcsr::get_mmio_address(), dhcsr.into())?;
+        }
+
+        dhcsr.set_c_step(true);
+        dhcsr.set_c_halt(false);
+        dhcsr.enable_write();
+        self.write_word_32(Dhcsr::get_mmio_address(), dhcsr.into())?;
+        self.flush()?;
+
+        let halted = self.wait_for_bit(Dhcsr::get_mmio_address(), S_HALT, true, STEP_TIMEOUT);
+
+        // Lockup isn't reported as halted, the core has to be halted explicitly.
+        if halted.is_ok() || !Dhcsr(self.read_word_32(Dhcsr::get_mmio_address())?).s_lockup() {
+            return halted;
+        }
+
+        tracing::debug!("The core locked up during the step, halting it");
+        dhcsr.set_c_step(false);
+        dhcsr.set_c_halt(true);
+        dhcsr.enable_write();
+        self.write_word_32(Dhcsr::get_mmio_address(), dhcsr.into())?;
+
+        self.wait_for_bit(Dhcsr::get_mmio_address(), S_HALT, true, STEP_TIMEOUT)
+    }
+
+    fn read_halt_reason(&mut self) -> Result<HaltReason, ArmError> {
+        let dfsr = Dfsr(self.read_word_32(Dfsr::get_mmio_address())?);
+
+        // The bits are write-one-to-clear. Only clear the bits which were read, so debug events
+        // which happen in between are not lost.
+        self.write_word_32(Dfsr::get_mmio_address(), dfsr.0)?;
+        self.flush()?;
+
+        Ok(dfsr.prioritized_halt_reason())
+    }
+
+    fn handle_semihosting(&mut self) -> Result<Option<SemihostingCommand>, ArmError> {
+        let dhcsr = Dhcsr(self.read_word_32(Dhcsr::get_mmio_address())?);
+        let dfsr = Dfsr(self.read_word_32(Dfsr::get_mmio_address())?);
+        if !dhcsr.s_halt() || !dfsr.bkpt() {
+            return Ok(None);
+        }
+
+        // The call is decoded the same way as for the cores of a session. Decoding works the
+        // same for all Cortex-M cores, and doesn't use the debug sequence.
+        let access_port = self.fully_qualified_address();
+        let memory = self
+            .get_arm_debug_interface()?
+            .memory_interface(&access_port)?;
+        let mut state = CortexMState::new();
+        let mut core =
+            Armv7m::new(memory, &mut state, DefaultArmSequence::create()).map_err(arm_error)?;
+
+        check_for_semihosting(None, &mut core).map_err(arm_error)
+    }
+
+    fn set_vector_catch(
+        &mut self,
+        catches: VectorCatchFlags,
+    ) -> Result<VectorCatchFlags, ArmError> {
+        let mut demcr = Demcr(self.read_word_32(Demcr::get_mmio_address())?);
+        let previous = VectorCatchFlags::from(demcr);
+
+        catches.apply(&mut demcr);
+        self.write_word_32(Demcr::get_mmio_address(), demcr.into())?;
+        self.flush()?;
+
+        Ok(previous)
+    }
+
+    fn reset_and_halt(
+        &mut self,
+        sequence: &dyn ArmDebugSequence,
+        timeout: Duration,
+    ) -> Result<(), ArmError> {
+        /// The S_HALT bit in DHCSR.
+        const S_HALT: u32 = 1 << 17;
+
+        let core_type = known_core_type(self)?;
+
+        // Vector catch only takes effect when halting debug is enabled.
+        let mut dhcsr = Dhcsr(0);
+        dhcsr.set_c_debugen(true);
+        dhcsr.enable_write();
+        self.write_word_32(Dhcsr::get_mmio_address(), dhcsr.into())?;
+
+        let halted = sequence
+            .reset_catch_set(self, core_type, None)
+            .and_then(|()| sequence.reset_system(self, core_type, None))
+            .and_then(|()| {
+                // The reset may have reset the access port registers as well.
+                self.get_arm_debug_interface()?.invalidate_write_cache();
+                self.wait_for_bit(Dhcsr::get_mmio_address(), S_HALT, true, timeout)
+            });
+
+        // Don't leave reset catch armed, even if the reset failed.
+        let cleared = sequence.reset_catch_clear(self, core_type, None);
+
+        halted.and(cleared)
+    }
+
+    fn reset_preserving_debug(&mut self, sequence: &dyn ArmDebugSequence) -> Result<(), ArmError> {
+        /// The S_HALT bit in DHCSR.
+        const S_HALT: u32 = 1 << 17;
+
+        let core_type = known_core_type(self)?;
+
+        let demcr = Demcr(self.read_word_32(Demcr::get_mmio_address())?);
+        let fp_ctrl = FpCtrl::from(self.read_word_32(FpCtrl::get_mmio_address())?);
+        // The literal comparators follow the instruction address comparators.
+        let fp_comp_addresses = (0..(fp_ctrl.num_code() + fp_ctrl.num_lit()) as u64)
+            .map(|index| FpRev1CompX::get_mmio_address() + index * size_of::<u32>() as u64)
+            .collect::<Vec<_>>();
+        let fp_comps = fp_comp_addresses
+            .iter()
+            .map(|address| self.read_word_32(*address))
+            .collect::<Result<Vec<_>, ArmError>>()?;
+        // The DWT can only be accessed with TRCENA set, it isn't in use otherwise.
+        let dwt = demcr
+            .trcena()
+            .then(|| component::DwtConfig::read(self))
+            .transpose()?;
+
+        let catch_reset = Dhcsr(self.read_word_32(Dhcsr::get_mmio_address())?).c_debugen();
+        let mut reset_demcr = demcr;
+        reset_demcr.set_vc_corereset(catch_reset || demcr.vc_corereset());
+        self.write_word_32(Demcr::get_mmio_address(), reset_demcr.into())?;
+
+        let restored = (|| {
+            sequence.reset_system(self, core_type, None)?;
+            // The reset may have reset the access port registers as well.
+            self.get_arm_debug_interface()?.invalidate_write_cache();
+
+            if catch_reset {
+                match self.wait_for_bit(
+                    Dhcsr::get_mmio_address(),
+                    S_HALT,
+                    true,
+                    Duration::from_millis(100),
+                ) {
+                    Ok(()) => {}
+                    // The reset cleared the vector catch as well, the core is already running.
+                    Err(ArmError::Timeout) => {
+                        tracing::debug!("The core didn't halt at the reset vector");
+                    }
+                    Err(error) => return Err(error),
+                }
+            }
+
+            // DEMCR comes first, as TRCENA is needed to access the DWT.
+            self.write_word_32(Demcr::get_mmio_address(), reset_demcr.into())?;
+            for (address, comp) in fp_comp_addresses.iter().zip(&fp_comps) {
+                self.write_word_32(*address, *comp)?;
+            }
+            let mut restored_fp_ctrl = FpCtrl::from(0);
+            restored_fp_ctrl.set_key(true);
+            restored_fp_ctrl.set_enable(fp_ctrl.enable());
+            self.write_word_32(FpCtrl::get_mmio_address(), restored_fp_ctrl.into())?;
+            if let Some(dwt) = dwt {
+                dwt.restore(self)?;
+            }
+
+            Ok(())
+        })();
+
+        // Don't leave reset vector catch armed, even if the reset or the restore failed.
+        let demcr_restored = self.write_word_32(Demcr::get_mmio_address(), demcr.into());
+        restored.and(demcr_restored)?;
+
+        if catch_reset && !demcr.vc_corereset() {
+            let mut dhcsr = Dhcsr(0);
+            dhcsr.set_c_debugen(true);
+            dhcsr.enable_write();
+            self.write_word_32(Dhcsr::get_mmio_address(), dhcsr.into())?;
+        }
+
+        self.flush()
+    }
+}
+
+/// Returns the architecture of the core behind `memory`, which has to be a Cortex-M core.
+fn known_core_type(memory: &mut dyn ArmMemoryInterface) -> Result<CoreType, ArmError> {
+    memory
+        .read_core_type()?
+        .core_type()
+        .ok_or(ArmError::ArchitectureRequired(&[
+            "ARMv6-M", "ARMv7-M", "ARMv8-M",
+        ]))
+}
+
+/// Returns the address of the FP_COMP register of the hardware breakpoint `index`.
+fn fp_comp_address(fp_ctrl: FpCtrl, index: usize) -> Result<u64, ArmError> {
+    let available = fp_ctrl.num_code();
+    if index >= available as usize {
+        return Err(ArmError::BreakpointIndexOutOfRange { index, available });
+    }
+
+    // FP_COMPn is at the same address for both versions of the FPB.
+    Ok(FpRev1CompX::get_mmio_address() + (index * size_of::<u32>()) as u64)
+}
+
+/// Converts an error of a core operation back into the underlying [`ArmError`].
+fn arm_error(error: Error) -> ArmError {
+    match error {
+        Error::Arm(error) => error,
+        other => ArmError::Other(other.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use test_log::test;
+
+    use super::{BreakpointUnitInfo, CortexMDebug, Dhcsr, VectorCatchFlags};
+    use crate::{
+        CoreType, HaltReason, MemoryMappedRegister,
+        architecture::arm::{
+            ArmDebugInterface, ArmError, FullyQualifiedApAddress,
+            ap::memory_ap::mock::MockMemoryAp,
+            communication_interface::tests::mock_target,
+            component::CortexMCore,
+            core::{
+                Dfsr,
+                armv7m::{Aircr, Demcr, FpCtrl},
+                registers::cortex_m::{CORTEX_M_WITH_FP_CORE_REGISTERS, PC},
+            },
+            memory::ArmMemoryInterface,
+            sequences::{ArmDebugSequence, DefaultArmSequence},
+        },
+        core::BreakpointCause,
+        semihosting::SemihostingCommand,
+    };
+
+    const CPUID_M0_PLUS: u32 = 0x410C_C601;
+    const CPUID_M4: u32 = 0x410F_C241;
+    const FP_COMP0: u64 = 0xE000_2008;
+    const FP_COMP1: u64 = 0xE000_200C;
+    const FP_COMP2: u64 = 0xE000_2010;
+    /// S_HALT and S_REGRDY
+    const DHCSR_HALTED: u32 = (1 << 17) | (1 << 16);
+    const DCRSR: u64 = 0xE000_EDF4;
+    const DCRDR: u64 = 0xE000_EDF8;
+    /// DFSR.BKPT
+    const DFSR_BKPT: u32 = 1 << 1;
+
+    fn ap() -> FullyQualifiedApAddress {
+        FullyQualifiedApAddress::v1_with_default_dp(0)
+    }
+
+    /// Returns a target whose registers at the given addresses read the given values, regardless
+    /// of the values written to them.
+    fn with_read_only_registers(registers: &[(u64, u32)]) -> MockMemoryAp {
+        let mut memory_ap = MockMemoryAp::with_pattern_and_size(256);
+        memory_ap
+            .read_only_registers
+            .extend(registers.iter().copied());
+        memory_ap
+    }
+
+    /// Returns the values written to the register at `address`, in order.
+    fn writes_to(memory_ap: &MockMemoryAp, address: u64) -> Vec<u32> {
+        memory_ap
+            .register_writes
+            .iter()
+            .filter(|&&(written, _)| written == address)
+            .map(|&(_, value)| value)
+            .collect()
+    }
+
+    #[test]
+    fn breakpoint_unit_info_is_decoded_from_fp_ctrl() {
+        // FPB version 2 with 18 code comparators, which uses both NUM_CODE fields.
+        let (_memory_ap, mut interface) = mock_target(with_read_only_registers(&[(
+            FpCtrl::get_mmio_address(),
+            0x1000_1020,
+        )]));
+
+        let mut memory = interface.memory_interface(&ap()).unwrap();
+        assert_eq!(
+            memory.read_breakpoint_unit_info().unwrap(),
+            BreakpointUnitInfo {
+                version: 2,
+                code_comparators: 18,
+                literal_comparators: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn vector_table_base_ignores_reserved_vtor_bits() {
+        let (_memory_ap, mut interface) =
+            mock_target(with_read_only_registers(&[(0xE000_ED08, 0x0800_407F)]));
+
+        let mut memory = interface.memory_interface(&ap()).unwrap();
+        assert_eq!(memory.read_vector_table_base().unwrap(), 0x0800_4000);
+    }
+
+    #[test]
+    fn halt_reason_prefers_breakpoints_over_halt_requests() {
+        // A step which ended on a breakpoint.
+        assert_eq!(
+            Dfsr(0b00011).prioritized_halt_reason(),
+            HaltReason::Breakpoint(BreakpointCause::Unknown)
+        );
+        assert_eq!(
+            Dfsr(0b11001).prioritized_halt_reason(),
+            HaltReason::Exception
+        );
+        assert_eq!(
+            Dfsr(0b10001).prioritized_halt_reason(),
+            HaltReason::External
+        );
+        assert_eq!(Dfsr(0b00001).prioritized_halt_reason(), HaltReason::Request);
+        assert_eq!(Dfsr(0).prioritized_halt_reason(), HaltReason::Unknown);
+    }
+
+    #[test]
+    fn vector_catch_flags_only_change_the_vc_bits() {
+        // TRCENA, VC_HARDERR and VC_CORERESET
+        let mut demcr = Demcr(0x0100_0401);
+        let previous = VectorCatchFlags::from(demcr);
+        assert_eq!(
+            previous,
+            VectorCatchFlags {
+                core_reset: true,
+                hard_fault: true,
+                ..Default::default()
+            }
+        );
+
+        VectorCatchFlags {
+            bus_fault: true,
+            mem_manage: true,
+            ..Default::default()
+        }
+        .apply(&mut demcr);
+        assert_eq!(u32::from(demcr), 0x0100_0110);
+
+        previous.apply(&mut demcr);
+        assert_eq!(u32::from(demcr), 0x0100_0401);
+    }
+
+    #[test]
+    fn reset_and_halt_clears_reset_catch_on_timeout() {
+        // The core never reports S_HALT.
+        let mut memory_ap = with_read_only_registers(&[(Dhcsr::get_mmio_address(), 0)]);
+        memory_ap
+            .registers
+            .insert(CortexMCore::CPUID_ADDRESS, CPUID_M0_PLUS);
+        let (memory_ap, mut interface) = mock_target(memory_ap);
+
+        let mut memory = interface.memory_interface(&ap()).unwrap();
+        let sequence = DefaultArmSequence::create();
+        assert!(matches!(
+            memory.reset_and_halt(&*sequence, Duration::from_millis(10)),
+            Err(ArmError::Timeout)
+        ));
+
+        let memory_ap = memory_ap.lock().unwrap();
+        // VC_CORERESET is set before the reset, and cleared again afterwards.
+        assert_eq!(writes_to(&memory_ap, Demcr::get_mmio_address()), [1, 0]);
+        assert!(!writes_to(&memory_ap, Aircr::get_mmio_address()).is_empty());
+    }
+
+    #[test]
+    fn reset_and_halt_requires_a_known_core() {
+        // CPUID reads as zero.
+        let (memory_ap, mut interface) = mock_target(with_read_only_registers(&[]));
+
+        let mut memory = interface.memory_interface(&ap()).unwrap();
+        let sequence = DefaultArmSequence::create();
+        assert!(matches!(
+            memory.reset_and_halt(&*sequence, Duration::from_millis(10)),
+            Err(ArmError::ArchitectureRequired(_))
+        ));
+        assert!(memory_ap.lock().unwrap().register_writes.is_empty());
+    }
+
+    #[test]
+    fn reset_preserving_debug_restores_breakpoints() {
+        // Two code comparators and one literal comparator, enabled.
+        const FP_CTRL_ENABLED_THREE_COMPARATORS: u32 = (1 << 8) | (2 << 4) | 1;
+
+        let mut memory_ap = with_read_only_registers(&[(
+            FpCtrl::get_mmio_address(),
+            FP_CTRL_ENABLED_THREE_COMPARATORS,
+        )]);
+        memory_ap.registers.extend([
+            (CortexMCore::CPUID_ADDRESS, CPUID_M4),
+            (FP_COMP0, 0x0000_1001),
+            (FP_COMP1, 0x0000_2001),
+            (FP_COMP2, 0x0000_3001),
+        ]);
+        let (memory_ap, mut interface) = mock_target(memory_ap);
+
+        let mut memory = interface.memory_interface(&ap()).unwrap();
+        memory
+            .reset_preserving_debug(&*DefaultArmSequence::create())
+            .unwrap();
+
+        let memory_ap = memory_ap.lock().unwrap();
+        let reset = memory_ap
+            .register_writes
+            .iter()
+            .position(|&(address, _)| address == Aircr::get_mmio_address())
+            .expect("the core was not reset");
+        let restored = &memory_ap.register_writes[reset + 1..];
+        assert!(restored.contains(&(FP_COMP0, 0x0000_1001)));
+        assert!(restored.contains(&(FP_COMP1, 0x0000_2001)));
+        assert!(restored.contains(&(FP_COMP2, 0x0000_3001)));
+        // KEY and ENABLE
+        assert!(restored.contains(&(FpCtrl::get_mmio_address(), 0b11)));
+        assert_eq!(
+            restored.last(),
+            Some(&(Demcr::get_mmio_address(), 0)),
+            "DEMCR is restored last"
+        );
+    }
+
+    #[test]
+    fn reset_preserving_debug_restores_demcr_if_the_reset_fails() {
+        const DEMCR_TRCENA: u32 = 1 << 24;
+
+        #[derive(Debug)]
+        struct FailingReset;
+
+        impl ArmDebugSequence for FailingReset {
+            fn reset_system(
+                &self,
+                _interface: &mut dyn ArmMemoryInterface,
+                _core_type: CoreType,
+                _debug_base: Option<u64>,
+            ) -> Result<(), ArmError> {
+                Err(ArmError::NotImplemented("reset_system"))
+            }
+        }
+
+        // Halting debug is enabled, so reset vector catch is armed for the reset.
+        let mut memory_ap = with_read_only_registers(&[(Dhcsr::get_mmio_address(), 1)]);
+        memory_ap.registers.extend([
+            (CortexMCore::CPUID_ADDRESS, CPUID_M4),
+            (Demcr::get_mmio_address(), DEMCR_TRCENA),
+        ]);
+        let (memory_ap, mut interface) = mock_target(memory_ap);
+
+        let mut memory = interface.memory_interface(&ap()).unwrap();
+        assert!(matches!(
+            memory.reset_preserving_debug(&FailingReset),
+            Err(ArmError::NotImplemented("reset_system"))
+        ));
+
+        // VC_CORERESET is set before the reset, and cleared again afterwards.
+        assert_eq!(
+            writes_to(&memory_ap.lock().unwrap(), Demcr::get_mmio_address()),
+            [DEMCR_TRCENA | 1, DEMCR_TRCENA]
+        );
+    }
+
+    #[test]
+    fn hw_breakpoint_uses_the_rev1_encoding() {
+        // FPB version 1 with two code comparators, disabled.
+        let (memory_ap, mut interface) = mock_target(with_read_only_registers(&[(
+            FpCtrl::get_mmio_address(),
+            2 << 4,
+        )]));
+
+        let mut memory = interface.memory_interface(&ap()).unwrap();
+        memory.set_hw_breakpoint(1, 0x0800_0102).unwrap();
+
+        assert_eq!(
+            memory_ap.lock().unwrap().register_writes,
+            [
+                // REPLACE: upper halfword, COMP and ENABLE
+                (FP_COMP1, 0x8800_0101),
+                // KEY and ENABLE
+                (FpCtrl::get_mmio_address(), 0b11),
+            ]
+        );
+
+        // Version 1 units can only match the code region.
+        assert!(matches!(
+            memory.set_hw_breakpoint(0, 0x2000_0000),
+            Err(ArmError::UnsupportedBreakpointAddress(0x2000_0000))
+        ));
+    }
+
+    #[test]
+    fn hw_breakpoint_uses_the_rev2_encoding() {
+        // FPB version 2 with two code comparators, enabled.
+        let (memory_ap, mut interface) = mock_target(with_read_only_registers(&[(
+            FpCtrl::get_mmio_address(),
+            0x1000_0000 | (2 << 4) | 1,
+        )]));
+
+        let mut memory = interface.memory_interface(&ap()).unwrap();
+        memory.set_hw_breakpoint(1, 0x2000_0102).unwrap();
+        memory.clear_hw_breakpoint(1).unwrap();
+
+        // BPADDR and ENABLE, FP_CTRL is already enabled.
+        assert_eq!(
+            memory_ap.lock().unwrap().register_writes,
+            [(FP_COMP1, 0x2000_0103), (FP_COMP1, 0)]
+        );
+    }
+
+    #[test]
+    fn hw_breakpoint_index_is_checked() {
+        let (memory_ap, mut interface) = mock_target(with_read_only_registers(&[(
+            FpCtrl::get_mmio_address(),
+            0x1000_0000 | (2 << 4) | 1,
+        )]));
+
+        let mut memory = interface.memory_interface(&ap()).unwrap();
+        assert!(matches!(
+            memory.set_hw_breakpoint(2, 0x0800_0000),
+            Err(ArmError::BreakpointIndexOutOfRange {
+                index: 2,
+                available: 2
+            })
+        ));
+        assert!(matches!(
+            memory.clear_hw_breakpoint(2),
+            Err(ArmError::BreakpointIndexOutOfRange {
+                index: 2,
+                available: 2
+            })
+        ));
+        assert!(memory_ap.lock().unwrap().register_writes.is_empty());
+    }
+
+    #[test]
+    fn core_register_is_read_through_dcrsr_and_dcrdr() {
+        let mut memory_ap = with_read_only_registers(&[(Dhcsr::get_mmio_address(), DHCSR_HALTED)]);
+        memory_ap.registers.insert(DCRDR, 0x0800_1234);
+        let (memory_ap, mut interface) = mock_target(memory_ap);
+
+        let mut memory = interface.memory_interface(&ap()).unwrap();
+        assert_eq!(memory.read_core_register(&PC).unwrap(), 0x0800_1234);
+
+        // REGSEL of the PC, REGWnR cleared.
+        assert_eq!(memory_ap.lock().unwrap().register_writes, [(DCRSR, 15)]);
+    }
+
+    #[test]
+    fn core_register_requires_a_halted_core() {
+        let (memory_ap, mut interface) =
+            mock_target(with_read_only_registers(&[(Dhcsr::get_mmio_address(), 0)]));
+
+        let mut memory = interface.memory_interface(&ap()).unwrap();
+        assert!(matches!(
+            memory.read_core_register(&PC),
+            Err(ArmError::CoreNotHalted)
+        ));
+        assert!(memory_ap.lock().unwrap().register_writes.is_empty());
+    }
+
+    #[test]
+    fn fpu_register_requires_an_fpu() {
+        // MVFR0 reads as zero.
+        let (memory_ap, mut interface) = mock_target(with_read_only_registers(&[(
+            Dhcsr::get_mmio_address(),
+            DHCSR_HALTED,
+        )]));
+
+        let mut memory = interface.memory_interface(&ap()).unwrap();
+        let s0 = CORTEX_M_WITH_FP_CORE_REGISTERS.fpu_register(0);
+        assert!(matches!(
+            memory.read_core_register(s0),
+            Err(ArmError::ExtensionRequired(&["FPU"]))
+        ));
+        assert!(memory_ap.lock().unwrap().register_writes.is_empty());
+    }
+
+    #[test]
+    fn step_changes_maskints_before_releasing_the_core() {
+        // S_HALT and C_DEBUGEN
+        let (memory_ap, mut interface) = mock_target(with_read_only_registers(&[(
+            Dhcsr::get_mmio_address(),
+            (1 << 17) | 1,
+        )]));
+
+        let mut memory = interface.memory_interface(&ap()).unwrap();
+        memory.step_core(true).unwrap();
+
+        assert_eq!(
+            memory_ap.lock().unwrap().register_writes,
+            [
+                // C_MASKINTS is set while C_HALT stays set.
+                (Dhcsr::get_mmio_address(), 0xA05F_000B),
+                // C_STEP
+                (Dhcsr::get_mmio_address(), 0xA05F_000D),
+            ]
+        );
+    }
+
+    #[test]
+    fn step_keeps_maskints_if_unchanged() {
+        // S_HALT, C_MASKINTS and C_DEBUGEN
+        let (memory_ap, mut interface) = mock_target(with_read_only_registers(&[(
+            Dhcsr::get_mmio_address(),
+            (1 << 17) | (1 << 3) | 1,
+        )]));
+
+        let mut memory = interface.memory_interface(&ap()).unwrap();
+        memory.step_core(true).unwrap();
+
+        assert_eq!(
+            memory_ap.lock().unwrap().register_writes,
+            [(Dhcsr::get_mmio_address(), 0xA05F_000D)]
+        );
+    }
+
+    #[test]
+    fn step_requires_a_halted_core() {
+        let (memory_ap, mut interface) =
+            mock_target(with_read_only_registers(&[(Dhcsr::get_mmio_address(), 0)]));
+
+        let mut memory = interface.memory_interface(&ap()).unwrap();
+        assert!(matches!(
+            memory.step_core(false),
+            Err(ArmError::CoreNotHalted)
+        ));
+        assert!(memory_ap.lock().unwrap().register_writes.is_empty());
+    }
+
+    #[test]
+    fn step_into_lockup_halts_the_core() {
+        // The core is halted before the step, and locked up afterwards.
+        let mut memory_ap = with_read_only_registers(&[(Dhcsr::get_mmio_address(), (1 << 19) | 1)]);
+        memory_ap
+            .register_reads
+            .insert(Dhcsr::get_mmio_address(), [(1 << 17) | 1].into());
+        let (memory_ap, mut interface) = mock_target(memory_ap);
+
+        // The mock core stays locked up, so waiting for the halt times out as well.
+        let mut memory = interface.memory_interface(&ap()).unwrap();
+        assert!(matches!(memory.step_core(false), Err(ArmError::Timeout)));
+
+        assert_eq!(
+            memory_ap.lock().unwrap().register_writes,
+            [
+                // C_STEP
+                (Dhcsr::get_mmio_address(), 0xA05F_0005),
+                // C_HALT
+                (Dhcsr::get_mmio_address(), 0xA05F_0003),
+            ]
+        );
+    }
+
+    /// Returns a Cortex-M core which is halted with `dfsr`, and whose core registers read
+    /// `registers` in order, e.g. PC, R0 and R1 of a semihosting call.
+    fn semihosting_core(dfsr: u32, registers: &[u32]) -> MockMemoryAp {
+        let mut memory_ap = with_read_only_registers(&[
+            (Dhcsr::get_mmio_address(), DHCSR_HALTED),
+            (Dfsr::get_mmio_address(), dfsr),
+        ]);
+        memory_ap
+            .register_reads
+            .insert(DCRDR, registers.iter().copied().collect());
+        memory_ap
+    }
+
+    #[test]
+    fn semihosting_call_is_decoded() {
+        const SYS_EXIT_EXTENDED: u32 = 0x20;
+        const ADP_STOPPED_APPLICATION_EXIT: u32 = 0x20026;
+
+        let mut memory_ap = semihosting_core(DFSR_BKPT, &[0x40, SYS_EXIT_EXTENDED, 0x80]);
+        // BKPT 0xAB
+        memory_ap.memory[0x40..0x42].copy_from_slice(&[0xAB, 0xBE]);
+        // The parameter block: the reason and the exit status.
+        memory_ap.memory[0x80..0x84].copy_from_slice(&ADP_STOPPED_APPLICATION_EXIT.to_le_bytes());
+        memory_ap.memory[0x84..0x88].copy_from_slice(&0u32.to_le_bytes());
+        let (memory_ap, mut interface) = mock_target(memory_ap);
+
+        let mut memory = interface.memory_interface(&ap()).unwrap();
+        assert!(matches!(
+            memory.handle_semihosting(),
+            Ok(Some(SemihostingCommand::ExitSuccess))
+        ));
+
+        // PC, R0 and R1
+        assert_eq!(writes_to(&memory_ap.lock().unwrap(), DCRSR), [15, 0, 1]);
+    }
+
+    #[test]
+    fn other_breakpoints_are_not_semihosting_calls() {
+        // The memory at the PC doesn't contain BKPT 0xAB.
+        let (memory_ap, mut interface) = mock_target(semihosting_core(DFSR_BKPT, &[0x40]));
+
+        let mut memory = interface.memory_interface(&ap()).unwrap();
+        assert!(matches!(memory.handle_semihosting(), Ok(None)));
+        assert_eq!(writes_to(&memory_ap.lock().unwrap(), DCRSR), [15]);
+    }
+
+    #[test]
+    fn semihosting_requires_a_breakpoint() {
+        // DFSR.HALTED, the core was halted by a request.
+        let (memory_ap, mut interface) = mock_target(semihosting_core(1, &[0x40]));
+
+        let mut memory = interface.memory_interface(&ap()).unwrap();
+        assert!(matches!(memory.handle_semihosting(), Ok(None)));
+        assert!(writes_to(&memory_ap.lock().unwrap(), DCRSR).is_empty());
+    }
+}
@@ -111,6 +111,27 @@ impl Dfsr {
             panic!("This should not happen. Please open a bug report.")
         }
     }
+
+    /// Returns the most specific halt reason, also if multiple bits are set.
+    ///
+    /// The bits are checked in this order: breakpoint, watchpoint, vector catch, external debug
+    /// request and halt request. A step which ends on a breakpoint sets both the breakpoint and
+    /// the halt request bit, so the halt request has the lowest priority.
+    pub(crate) fn prioritized_halt_reason(&self) -> HaltReason {
+        if self.bkpt() {
+            HaltReason::Breakpoint(BreakpointCause::Unknown)
+        } else if self.dwttrap() {
+            HaltReason::Watchpoint
+        } else if self.vcatch() {
+            HaltReason::Exception
+        } else if self.external() {
+            HaltReason::External
+        } else if self.halted() {
+            HaltReason::Request
+        } else {
+            HaltReason::Unknown
+        }
+    }
 }
 
 impl From<u32> for Dfsr {
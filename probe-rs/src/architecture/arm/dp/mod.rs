@@ -135,6 +135,11 @@ pub trait DpAccess {
         dp: DpAddress,
         register: R,
     ) -> Result<(), ArmError>;
+
+    /// Reads and decodes the CTRL/STAT register of a debug port.
+    fn read_ctrl_stat(&mut self, dp: DpAddress) -> Result<CtrlStat, ArmError> {
+        self.read_dp_register::<Ctrl>(dp).map(CtrlStat::from)
+    }
 }
 
 impl<T: ?Sized + DapAccess> DpAccess for T {
@@ -309,7 +314,7 @@ bitfield! {
     /// It is IMPLEMENTATION DEFINED whether this field is implemented.
     ///
     /// TRNMODE is not supported in MINDP configuration. In MINDP configuration, the effect of writing a value other than zero to TRNCNT or TRNMODE is UNPREDICTABLE. See also MINDP, Minimal DP extension on page B1-40.
-    pub u8, trn_mode, _: 3, 2;
+    pub u8, trn_mode, set_trn_mode: 3, 2;
     /// If overrun detection is enabled, this bit is set to 0b1 when an overrun occurs. See `bit[0]` of this register for details of enabling overrun detection.
     /// Access to and how to clear this field are DATA LINK DEFINED:
     ///
@@ -338,6 +343,65 @@ bitfield! {
 }
 impl_dpregister!(Ctrl, DebugPortVersion::DPv1, 0x4, "CTRL/STAT");
 
+/// A decoded view of the status flags in the CTRL/STAT register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CtrlStat {
+    /// An overrun occurred, with overrun detection enabled.
+    pub stickyorun: bool,
+    /// An AP transaction returned an error.
+    pub stickyerr: bool,
+    /// A pushed-compare or pushed-verify operation matched.
+    pub stickycmp: bool,
+    /// The response to the last AP read or RDBUFF read was OK.
+    pub readok: bool,
+    /// The debug power domain is powered up.
+    pub cdbgpwrupack: bool,
+    /// The system power domain is powered up.
+    pub csyspwrupack: bool,
+    /// The transaction counter.
+    pub trncnt: u16,
+}
+
+impl From<Ctrl> for CtrlStat {
+    fn from(ctrl: Ctrl) -> CtrlStat {
+        CtrlStat {
+            stickyorun: ctrl.sticky_orun(),
+            stickyerr: ctrl.sticky_err(),
+            stickycmp: ctrl.stick_cmp(),
+            readok: ctrl.read_ok(),
+            cdbgpwrupack: ctrl.cdbgpwrupack(),
+            csyspwrupack: ctrl.csyspwrupack(),
+            trncnt: ctrl.trn_cnt(),
+        }
+    }
+}
+
+impl Display for CtrlStat {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let flags = [
+            (self.stickyorun, "STICKYORUN"),
+            (self.stickyerr, "STICKYERR"),
+            (self.stickycmp, "STICKYCMP"),
+            (self.readok, "READOK"),
+            (self.cdbgpwrupack, "CDBGPWRUPACK"),
+            (self.csyspwrupack, "CSYSPWRUPACK"),
+        ];
+
+        let mut set_flags = flags.iter().filter(|(set, _)| *set).map(|(_, name)| *name);
+        match set_flags.next() {
+            Some(first) => {
+                write!(f, "{first}")?;
+                for name in set_flags {
+                    write!(f, " | {name}")?;
+                }
+            }
+            None => write!(f, "<no flags set>")?,
+        }
+
+        write!(f, ", TRNCNT={}", self.trncnt)
+    }
+}
+
 bitfield! {
     /// SELECT, AP Select register (see ADI v5.2 B2.2.9)
     #[derive(Clone, Copy, PartialEq, Eq)]
@@ -546,7 +610,7 @@ bitfield! {
 impl_dpregister!(BASEPTR1, DebugPortVersion::DPv3, 0x0, Some(0x3), "BASEPTR1");
 
 /// The ID of a debug port. Can be used to detect and select devices in a multidrop setup.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct DebugPortId {
     /// The revision of the debug port (implementation defined). This is what the designer of the debug port chooses.
     pub revision: u8,
@@ -593,8 +657,17 @@ impl From<DPIDR> for DebugPortId {
 pub struct RdBuff(pub u32);
 impl_dpregister!(RdBuff, DebugPortVersion::DPv1, 0x0C, "RDBUFF");
 
+/// RESEND, Read Resend register (see ADI v5.2 B2.2.8)
+///
+/// Only available on SW-DPs, where it shares its address with SELECT. Reading it returns the
+/// value of the last AP read or RDBUFF read again, without accessing the AP. This allows
+/// recovering from a corrupted read response without repeating side effects of the read.
+#[derive(Debug, Clone)]
+pub struct Resend(pub u32);
+impl_dpregister!(Resend, DebugPortVersion::DPv1, 0x08, "RESEND");
+
 /// Specifies if pushed-find operations are implemented or not.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MinDpSupport {
     /// Pushed-find operations are **not** implemented.
     NotImplemented,
@@ -5,10 +5,10 @@ use crate::{
     architecture::arm::{
         ArmDebugInterface, ArmError, DapAccess, FullyQualifiedApAddress,
         ap::{
-            AccessPortType, ApAccess, CSW, DataSize,
+            AccessPortType, AddressIncrement, ApAccess, ApRegister, CSW, DataSize,
             memory_ap::{MemoryAp, MemoryApType},
         },
-        memory::ArmMemoryInterface,
+        memory::{AddressWidth, ArmMemoryInterface, MemAccessAttributes, SecurityState},
     },
     probe::DebugProbeError,
 };
@@ -22,9 +22,15 @@ fn autoincr_max_bytes(address: u64) -> usize {
 }
 
 /// A struct to give access to a targets memory using a certain DAP.
-pub(crate) struct ADIMemoryInterface<'interface, APA> {
+pub(crate) struct ADIMemoryInterface<'interface, APA>
+where
+    APA: DapAccess,
+{
     interface: &'interface mut APA,
     memory_ap: MemoryAp,
+    /// The CSW value to restore when the interface is dropped, if the CSW was pinned
+    /// with [`ADIMemoryInterface::new_with_csw`].
+    restore_csw: Option<u32>,
 }
 
 impl<'interface, APA> ADIMemoryInterface<'interface, APA>
@@ -37,11 +43,141 @@ where
         access_port_address: &FullyQualifiedApAddress,
     ) -> Result<ADIMemoryInterface<'interface, APA>, ArmError> {
         let memory_ap = MemoryAp::new(interface, access_port_address)?;
+        Ok(Self::with_memory_ap(interface, memory_ap))
+    }
+
+    /// Creates a new MemoryInterface for an AccessPort which was already set up, without
+    /// accessing its registers.
+    pub(crate) fn with_memory_ap(
+        interface: &'interface mut APA,
+        memory_ap: MemoryAp,
+    ) -> ADIMemoryInterface<'interface, APA> {
+        Self {
+            interface,
+            memory_ap,
+            restore_csw: None,
+        }
+    }
+
+    /// Creates a new MemoryInterface for given AccessPort, which programs `csw` once
+    /// and keeps it for the lifetime of the interface.
+    ///
+    /// Accesses with the data size configured in `csw` do not write the CSW register again.
+    /// Callers must not mix access widths, as any other width reprograms the CSW.
+    ///
+    /// The original CSW value is restored when the interface is dropped.
+    pub fn new_with_csw(
+        interface: &'interface mut APA,
+        access_port_address: &FullyQualifiedApAddress,
+        csw: CSW,
+    ) -> Result<ADIMemoryInterface<'interface, APA>, ArmError> {
+        let original = interface.read_raw_ap_register(access_port_address, CSW::ADDRESS)?;
+
+        let mut memory_ap = MemoryAp::new(interface, access_port_address)?;
+        interface.write_raw_ap_register(access_port_address, CSW::ADDRESS, csw.into())?;
+
+        // Refresh the cached CSW, so that the memory AP knows the pinned data size.
+        memory_ap.status(interface)?;
+
         Ok(Self {
             interface,
             memory_ap,
+            restore_csw: Some(original),
         })
     }
+
+    /// Reads word-aligned bytes at `address` using packed 8-bit transfers.
+    ///
+    /// Returns `false` without reading anything if the AP doesn't support packed transfers.
+    fn read_8_packed(&mut self, mut address: u64, mut data: &mut [u8]) -> Result<bool, ArmError> {
+        let ap_address = self.memory_ap.ap_address().clone();
+        let original = self
+            .interface
+            .read_raw_ap_register(&ap_address, CSW::ADDRESS)?;
+        let generic = self.memory_ap.generic_status(self.interface)?;
+
+        // Support for packed transfers is implementation defined, and can only be detected
+        // by checking whether the AddrInc field can be set to packed.
+        let packed = CSW {
+            AddrInc: AddressIncrement::Packed,
+            SIZE: DataSize::U8,
+            ..generic
+        };
+        self.interface
+            .write_raw_ap_register(&ap_address, CSW::ADDRESS, packed.into())?;
+        let supported =
+            self.memory_ap.generic_status(self.interface)?.AddrInc == AddressIncrement::Packed;
+
+        let mut result = Ok(());
+        if supported {
+            let mut words = Vec::new();
+            while !data.is_empty() && result.is_ok() {
+                let chunk_size = data.len().min(autoincr_max_bytes(address));
+                words.resize(chunk_size / 4, 0);
+
+                result = self
+                    .memory_ap
+                    .set_target_address(self.interface, address)
+                    .and_then(|()| self.memory_ap.read_data(self.interface, &mut words));
+
+                data[..chunk_size].copy_from_slice(words.as_bytes());
+                address += chunk_size as u64;
+                data = &mut data[chunk_size..];
+            }
+        }
+
+        // Restore the CSW even if the transfer failed, and update the cached value.
+        self.interface
+            .write_raw_ap_register(&ap_address, CSW::ADDRESS, original)?;
+        self.memory_ap.status(self.interface)?;
+
+        result.map(|()| supported)
+    }
+
+    /// Sets the bus attribute of the CSW which selects the secure or the non-secure view of
+    /// memory, see [`ArmMemoryInterface::set_security`].
+    ///
+    /// CSW is only written if the attribute changes.
+    fn set_security_state(&mut self, security: SecurityState) -> Result<(), ArmError> {
+        let ap_address = self.memory_ap.ap_address().clone();
+        let original = self.memory_ap.cached_status();
+
+        let attributes = MemAccessAttributes {
+            secure: Some(security == SecurityState::Secure),
+            ..MemAccessAttributes::default()
+        };
+        let csw: u32 = attributes
+            .apply(self.memory_ap.ap_type(), CSW::try_from(original)?)?
+            .into();
+        if csw == original {
+            return Ok(());
+        }
+
+        self.interface
+            .write_raw_ap_register(&ap_address, CSW::ADDRESS, csw)?;
+
+        // Keep the cached CSW up to date, so that changing the data size keeps the security
+        // state.
+        self.memory_ap.set_cached_status(csw)
+    }
+}
+
+impl<APA> Drop for ADIMemoryInterface<'_, APA>
+where
+    APA: DapAccess,
+{
+    fn drop(&mut self) {
+        if let Some(csw) = self.restore_csw.take() {
+            let address = self.memory_ap.ap_address().clone();
+
+            if let Err(error) = self
+                .interface
+                .write_raw_ap_register(&address, CSW::ADDRESS, csw)
+            {
+                tracing::warn!("Failed to restore CSW of AP {:x?}: {}", address, error);
+            }
+        }
+    }
 }
 
 impl<AP> MemoryInterface<ArmError> for ADIMemoryInterface<'_, AP>
@@ -509,6 +645,26 @@ impl<APA> ArmMemoryInterface for ADIMemoryInterface<'_, APA>
 where
     APA: ApAccess + ArmDebugInterface,
 {
+    fn read_memory_8_block(&mut self, address: u64, data: &mut [u8]) -> Result<(), ArmError> {
+        let head_len = (address.next_multiple_of(4) - address).min(data.len() as u64) as usize;
+        let (head, rest) = data.split_at_mut(head_len);
+        let body_len = rest.len() / 4 * 4;
+        let (body, tail) = rest.split_at_mut(body_len);
+        let body_address = address + head_len as u64;
+        let tail_address = body_address + body_len as u64;
+
+        self.read(address, head)?;
+        if !body.is_empty()
+            && (self.memory_ap.supports_only_32bit_data_size()
+                || !self.read_8_packed(body_address, body)?)
+        {
+            self.read(body_address, body)?;
+        }
+        self.read(tail_address, tail)?;
+
+        Ok(())
+    }
+
     fn base_address(&mut self) -> Result<u64, ArmError> {
         self.memory_ap.base_address(self.interface)
     }
@@ -517,6 +673,14 @@ where
         self.memory_ap.ap_address().clone()
     }
 
+    fn address_width(&self) -> AddressWidth {
+        if self.memory_ap.has_large_address_extension() {
+            AddressWidth::Bits64
+        } else {
+            AddressWidth::Bits32
+        }
+    }
+
     fn get_arm_debug_interface(&mut self) -> Result<&mut dyn ArmDebugInterface, DebugProbeError> {
         Ok(self.interface)
     }
@@ -525,6 +689,10 @@ where
         self.memory_ap.generic_status(self.interface)
     }
 
+    fn set_security(&mut self, security: SecurityState) -> Result<(), ArmError> {
+        self.set_security_state(security)
+    }
+
     fn update_core_status(&mut self, state: CoreStatus) {
         if let Some(probe) = self.interface.try_dap_probe_mut() {
             // Ignore errors setting the core status
@@ -535,13 +703,18 @@ where
 
 #[cfg(test)]
 mod tests {
+    use std::sync::{Arc, Mutex};
+
     use scroll::Pread;
     use test_log::test;
 
     use crate::{
         MemoryInterface,
         architecture::arm::{
-            FullyQualifiedApAddress, ap::memory_ap::mock::MockMemoryAp, memory::ADIMemoryInterface,
+            ArmDebugInterface, DapAccess, FullyQualifiedApAddress,
+            ap::{AddressIncrement, ApRegister, ApType, CSW, memory_ap::mock::MockMemoryAp},
+            communication_interface::tests::mock_memory_interface,
+            memory::{ADIMemoryInterface, MemAccessAttributes, SecurityState},
         },
     };
 
@@ -772,6 +945,41 @@ mod tests {
         }
     }
 
+    #[test]
+    fn read_memory_8_block() {
+        let memory_ap = Arc::new(Mutex::new(MockMemoryAp::with_pattern_and_size(0x800)));
+        let mut interface = mock_memory_interface(memory_ap.clone());
+        let ap = FullyQualifiedApAddress::v1_with_default_dp(0);
+        let mut mi = interface.memory_interface(&ap).unwrap();
+
+        // The last address crosses the 1 KiB auto-increment boundary.
+        for address in [0, 1, 2, 3, 0x3FA] {
+            for len in 0..14 {
+                let mut data = vec![0u8; len];
+                mi.read_memory_8_block(address, &mut data)
+                    .unwrap_or_else(|_| {
+                        panic!("read_memory_8_block failed, address = {address}, len = {len}")
+                    });
+
+                let range = address as usize..address as usize + len;
+                let expected = memory_ap.lock().unwrap().memory[range].to_vec();
+                assert_eq!(expected, data, "address = {address}, len = {len}");
+            }
+        }
+
+        // The CSW is restored after the packed transfers.
+        mi.read_memory_8_block(0x100, &mut [0; 8]).unwrap();
+        let csw = memory_ap
+            .lock()
+            .unwrap()
+            .read_raw_ap_register(&ap, CSW::ADDRESS)
+            .unwrap();
+        assert_eq!(
+            CSW::try_from(csw).unwrap().AddrInc,
+            AddressIncrement::Single
+        );
+    }
+
     #[test]
     fn write_32() {
         for address in [0, 4] {
@@ -877,4 +1085,77 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn pinned_csw_is_restored_on_drop() {
+        let mut mock = MockMemoryAp::with_pattern_and_size(256);
+        mock.memory[..8].copy_from_slice(&DATA8[..8]);
+        let address = FullyQualifiedApAddress::v1_with_default_dp(0);
+
+        // 32 bit accesses with single address increment.
+        let csw = CSW::try_from(0x0000_0012).unwrap();
+
+        let mut mi = ADIMemoryInterface::new_with_csw(&mut mock, &address, csw).unwrap();
+        for address in [0, 4] {
+            let value = mi.read_word_32(address).expect("read_word_32 failed");
+            assert_eq!(value, DATA32[address as usize / 4]);
+        }
+        drop(mi);
+
+        assert_eq!(
+            mock.read_raw_ap_register(&address, CSW::ADDRESS).unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn access_attributes_are_written_to_csw() {
+        let mut mock = MockMemoryAp::with_pattern_and_size(256);
+        let address = FullyQualifiedApAddress::v1_with_default_dp(0);
+
+        let attributes = MemAccessAttributes {
+            privileged: Some(true),
+            secure: Some(false),
+            cacheable: Some(true),
+        };
+        let csw = CSW::try_from(0x0000_0012).unwrap();
+        let csw = attributes.apply(ApType::AmbaAhb3, csw).unwrap();
+
+        let mut mi = ADIMemoryInterface::new_with_csw(&mut mock, &address, csw).unwrap();
+        mi.read_word_32(0).expect("read_word_32 failed");
+
+        let csw = mi
+            .interface
+            .read_raw_ap_register(&address, CSW::ADDRESS)
+            .unwrap();
+        // HNONSEC, HPROT[3] (cacheable) and HPROT[1] (privileged)
+        assert_eq!(csw & 0x4A00_0000, 0x4A00_0000);
+    }
+
+    #[test]
+    fn security_state_selects_the_memory_view() {
+        const HNONSEC: u32 = 1 << 30;
+
+        let mut mock = MockMemoryAp::with_pattern_and_size(256);
+        mock.secure_memory = Some(vec![0xA5; 256]);
+        let address = FullyQualifiedApAddress::v1_with_default_dp(0);
+        let mut mi = ADIMemoryInterface::new_mock(&mut mock);
+
+        mi.set_security_state(SecurityState::Secure).unwrap();
+        let csw = mi
+            .interface
+            .read_raw_ap_register(&address, CSW::ADDRESS)
+            .unwrap();
+        assert_eq!(csw & HNONSEC, 0);
+        assert_eq!(mi.read_word_32(0).unwrap(), 0xA5A5_A5A5);
+        assert_eq!(mi.read_word_8(1).unwrap(), 0xA5);
+
+        mi.set_security_state(SecurityState::NonSecure).unwrap();
+        let csw = mi
+            .interface
+            .read_raw_ap_register(&address, CSW::ADDRESS)
+            .unwrap();
+        assert_eq!(csw & HNONSEC, HNONSEC);
+        assert_eq!(mi.read_word_32(0).unwrap(), 0x0403_0201);
+    }
 }
@@ -5,10 +5,95 @@ pub mod romtable;
 
 pub(crate) use adi_memory_interface::ADIMemoryInterface;
 
+use std::time::{Duration, Instant};
+
 use crate::{CoreStatus, memory::MemoryInterface, probe::DebugProbeError};
 
-use super::{ArmDebugInterface, ArmError, FullyQualifiedApAddress};
-pub use romtable::{Component, ComponentId, CoresightComponent, PeripheralType, RomTable};
+use super::{
+    ArmDebugInterface, ArmError, FullyQualifiedApAddress,
+    ap::{ApType, CSW},
+};
+pub use romtable::{
+    Component, ComponentId, ComponentParser, ComponentParsers, CoresightComponent, CustomComponent,
+    PeripheralType, RomTable,
+};
+
+/// The width of the addresses a memory access port can access.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressWidth {
+    /// Only 32 bit addresses are supported.
+    Bits32,
+    /// 64 bit addresses are supported, using the large physical address extension.
+    Bits64,
+}
+
+impl AddressWidth {
+    /// Returns `true` if `address` can be accessed with this address width.
+    pub fn contains(&self, address: u64) -> bool {
+        match self {
+            AddressWidth::Bits32 => address <= u64::from(u32::MAX),
+            AddressWidth::Bits64 => true,
+        }
+    }
+}
+
+/// The security state used for memory accesses, on targets with the ARMv8-M Security Extension.
+///
+/// The same address can map to different memory in the secure and the non-secure view.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecurityState {
+    /// Access the secure view of memory.
+    Secure,
+    /// Access the non-secure view of memory.
+    NonSecure,
+}
+
+/// Bus attributes for memory accesses through a MEM-AP.
+///
+/// Attributes which are `None` keep the current setting of the access port, so the
+/// default value doesn't change anything.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemAccessAttributes {
+    /// Perform privileged accesses.
+    pub privileged: Option<bool>,
+    /// Perform secure accesses.
+    pub secure: Option<bool>,
+    /// Perform cacheable accesses.
+    pub cacheable: Option<bool>,
+}
+
+impl MemAccessAttributes {
+    /// Applies the attributes to the `Prot` field of the CSW of an access port of the given type.
+    pub(crate) fn apply(&self, ap_type: ApType, mut csw: CSW) -> Result<CSW, ArmError> {
+        // Bits within CSW.Prot, which starts at CSW[24].
+        let (privileged, non_secure, cacheable) = match ap_type {
+            ApType::AmbaAhb3 | ApType::AmbaAhb5 | ApType::AmbaAhb5Hprot => (1 << 1, 1 << 6, 1 << 3),
+            ApType::AmbaAxi3Axi4 | ApType::AmbaAxi5 => (1 << 4, 1 << 5, 0b1111),
+            _ if *self == Self::default() => return Ok(csw),
+            _ => return Err(ArmError::WrongApType),
+        };
+
+        let mut set = |mask: u8, enable: bool| {
+            if enable {
+                csw.Prot |= mask;
+            } else {
+                csw.Prot &= !mask;
+            }
+        };
+
+        if let Some(enable) = self.privileged {
+            set(privileged, enable);
+        }
+        if let Some(enable) = self.secure {
+            set(non_secure, !enable);
+        }
+        if let Some(enable) = self.cacheable {
+            set(cacheable, enable);
+        }
+
+        Ok(csw)
+    }
+}
 
 /// Trait for accessing memory behind a memory access port,
 /// as defined in the ARM Debug Interface Specification.
@@ -19,6 +104,92 @@ pub trait ArmMemoryInterface: MemoryInterface<ArmError> {
     /// The underlying memory AP’s base address.
     fn base_address(&mut self) -> Result<u64, ArmError>;
 
+    /// The width of the addresses the underlying memory AP supports.
+    ///
+    /// Accessing an address which doesn't fit into this width returns
+    /// [`ArmError::AddressOutOfRange`].
+    fn address_width(&self) -> AddressWidth {
+        AddressWidth::Bits32
+    }
+
+    /// Reads a block of bytes at `address`.
+    ///
+    /// Where supported by the access port, the word-aligned part of the block is read with
+    /// packed 8-bit transfers, so that the bus only sees byte accesses. Otherwise, and for
+    /// the unaligned bytes at the start and the end of the block, word reads are used.
+    fn read_memory_8_block(&mut self, address: u64, data: &mut [u8]) -> Result<(), ArmError> {
+        self.read(address, data)
+    }
+
+    /// Polls the 32-bit word at `address` until the bits in `mask` are all set, or all cleared
+    /// if `set` is `false`.
+    ///
+    /// Returns [`ArmError::Timeout`] if the bits do not reach the requested state within
+    /// `timeout`. The word is always read at least once.
+    fn wait_for_bit(
+        &mut self,
+        address: u64,
+        mask: u32,
+        set: bool,
+        timeout: Duration,
+    ) -> Result<(), ArmError> {
+        let expected = if set { mask } else { 0 };
+        let start = Instant::now();
+
+        loop {
+            let value = self.read_word_32(address)?;
+            if value & mask == expected {
+                return Ok(());
+            }
+
+            if start.elapsed() > timeout {
+                tracing::debug!(
+                    "Timed out waiting for {mask:#010x} at {address:#010x} to be {}, last value {value:#010x}",
+                    if set { "set" } else { "cleared" }
+                );
+                return Err(ArmError::Timeout);
+            }
+
+            std::thread::sleep(Duration::from_micros(100));
+        }
+    }
+
+    /// Updates the bits in `mask` of the 32-bit word at `address` to the corresponding bits of
+    /// `value`, and leaves all other bits unchanged.
+    ///
+    /// The word is read and written back without any other access in between, so that reserved
+    /// bits are preserved.
+    fn modify_32(&mut self, address: u64, mask: u32, value: u32) -> Result<(), ArmError> {
+        let old = self.read_word_32(address)?;
+        self.write_word_32(address, (old & !mask) | (value & mask))
+    }
+
+    /// Updates the bits in `mask` of the 16-bit half word at `address`, see
+    /// [`ArmMemoryInterface::modify_32`].
+    fn modify_16(&mut self, address: u64, mask: u16, value: u16) -> Result<(), ArmError> {
+        let old = self.read_word_16(address)?;
+        self.write_word_16(address, (old & !mask) | (value & mask))
+    }
+
+    /// Updates the bits in `mask` of the byte at `address`, see
+    /// [`ArmMemoryInterface::modify_32`].
+    fn modify_8(&mut self, address: u64, mask: u8, value: u8) -> Result<(), ArmError> {
+        let old = self.read_word_8(address)?;
+        self.write_word_8(address, (old & !mask) | (value & mask))
+    }
+
+    /// Selects whether the following accesses use the secure or the non-secure view of memory,
+    /// by setting the corresponding bus attribute in the CSW of the access port.
+    ///
+    /// Secure accesses only succeed if secure debug is enabled for the access port.
+    ///
+    /// The selection is kept in the CSW of the access port, so it also applies to memory
+    /// interfaces opened later for the same access port, until it is changed again or the
+    /// interface is reinitialized.
+    fn set_security(&mut self, _security: SecurityState) -> Result<(), ArmError> {
+        Err(ArmError::NotImplemented("set_security"))
+    }
+
     /// Get this interface as a [`ArmDebugInterface`] object.
     fn get_arm_debug_interface(&mut self) -> Result<&mut dyn ArmDebugInterface, DebugProbeError>;
 
@@ -32,3 +203,35 @@ pub trait ArmMemoryInterface: MemoryInterface<ArmError> {
     // a visual indication.
     fn update_core_status(&mut self, _state: CoreStatus) {}
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use test_log::test;
+
+    use crate::architecture::arm::{
+        ArmDebugInterface, FullyQualifiedApAddress, ap::memory_ap::mock::MockMemoryAp,
+        communication_interface::tests::mock_memory_interface,
+    };
+
+    #[test]
+    fn modify_only_changes_masked_bits() {
+        let mut memory_ap = MockMemoryAp::with_pattern_and_size(8);
+        memory_ap.memory.fill(0xAA);
+        let mut interface = mock_memory_interface(Arc::new(Mutex::new(memory_ap)));
+        let mut memory = interface
+            .memory_interface(&FullyQualifiedApAddress::v1_with_default_dp(0))
+            .unwrap();
+
+        memory.modify_32(0, 0x0000_FF00, 0x1234_5678).unwrap();
+        assert_eq!(memory.read_word_32(0).unwrap(), 0xAAAA_56AA);
+
+        memory.modify_16(4, 0x00F0, 0xFFFF).unwrap();
+        assert_eq!(memory.read_word_16(4).unwrap(), 0xAAFA);
+
+        memory.modify_8(6, 0x0F, 0x00).unwrap();
+        assert_eq!(memory.read_word_8(6).unwrap(), 0xA0);
+        assert_eq!(memory.read_word_8(7).unwrap(), 0xAA);
+    }
+}
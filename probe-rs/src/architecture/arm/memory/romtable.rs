@@ -1,5 +1,7 @@
 //! CoreSight ROM table parsing and handling.
 
+use std::{fmt, ops::Range, sync::Arc, time::Duration};
+
 use crate::architecture::arm::{
     ArmError, FullyQualifiedApAddress, ap::AccessPortError,
     communication_interface::ArmDebugInterface, memory::ArmMemoryInterface,
@@ -8,6 +10,19 @@ use crate::architecture::arm::{
 /// The ARCHID associated with all CoreSight ROM tables.
 pub const CORESIGHT_ROM_TABLE_ARCHID: u16 = 0x0af7;
 
+/// Offset of the CIDR1 register of a CoreSight component.
+const CIDR1_OFFSET: u64 = 0xFF4;
+/// Offset of the DEVID register of a CoreSight component.
+const DEVID_OFFSET: u64 = 0xFC8;
+/// Offset of the first DBGPCR register of a Class 0x9 ROM table.
+const DBGPCR_OFFSET: u64 = 0xA00;
+/// Offset of the first DBGPSR register of a Class 0x9 ROM table.
+const DBGPSR_OFFSET: u64 = 0xA80;
+/// How long to wait for a power domain to power up after requesting it.
+const POWER_UP_TIMEOUT: Duration = Duration::from_millis(100);
+/// The size of the blocks occupied by a CoreSight component.
+const BLOCK_SIZE: u64 = 0x1000;
+
 /// An error to report any errors that are romtable discovery specific.
 #[derive(thiserror::Error, Debug, docsplay::Display)]
 pub enum RomTableError {
@@ -124,12 +139,27 @@ impl RomTable {
     pub fn try_parse(
         memory: &mut dyn ArmMemoryInterface,
         base_address: u64,
+    ) -> Result<RomTable, RomTableError> {
+        Self::try_parse_with(memory, base_address, &ComponentParsers::default())
+    }
+
+    /// Tries to parse a CoreSight component table, recognizing proprietary components with
+    /// the given `parsers`.
+    pub fn try_parse_with(
+        memory: &mut dyn ArmMemoryInterface,
+        base_address: u64,
+        parsers: &ComponentParsers,
     ) -> Result<RomTable, RomTableError> {
         // This is required for the collect down below.
-        let mut entries = vec![];
+        let mut entries: Vec<RomTableEntry> = vec![];
+        // The blocks of components spanning multiple 4 KB blocks, except the block with the
+        // ID registers.
+        let mut other_blocks: Vec<Range<u64>> = vec![];
 
         tracing::debug!("Parsing romtable at base_address {:#010x}", base_address);
 
+        let power_requests = supports_power_requests(memory, base_address)?;
+
         // Read all the raw romtable entries and flatten them.
 
         // This is not a needless collect! It fixes the borrowing issue with &mut Memory that clippy cannot detect!
@@ -145,7 +175,39 @@ impl RomTable {
             tracing::debug!("Parsing entry at {:#010x}", entry_base_addr);
 
             if raw_entry.entry_present {
-                let component = Component::try_parse(memory, u64::from(entry_base_addr))?;
+                let address = u64::from(entry_base_addr);
+                if other_blocks.iter().any(|blocks| blocks.contains(&address)) {
+                    tracing::debug!(
+                        "Entry #{} at {:#010x} is part of a larger component, skipping.",
+                        i,
+                        address
+                    );
+                    continue;
+                }
+
+                // Components in a powered down domain read as all zeroes.
+                if power_requests && raw_entry.power_domain_valid {
+                    if let Err(error) =
+                        power_up_domain(memory, base_address, raw_entry.power_domain_id)
+                    {
+                        tracing::warn!(
+                            "Failed to power up domain {} of the component at {:#010x}: {}",
+                            raw_entry.power_domain_id,
+                            entry_base_addr,
+                            error
+                        );
+                    }
+                }
+
+                let component = Component::try_parse_with(memory, address, parsers)?;
+
+                // The ID registers of a component are in its last block, so entries parsed
+                // before may point into the other blocks of this component.
+                let blocks = component.id().address_range().start..address;
+                entries.retain(|entry| {
+                    !blocks.contains(&entry.component.component.id().component_address())
+                });
+                other_blocks.push(blocks);
 
                 // Finally remember the entry.
                 entries.push(RomTableEntry {
@@ -168,6 +230,53 @@ impl RomTable {
     }
 }
 
+/// Returns `true` if the ROM table at `base_address` is a Class 0x9 ROM table which implements
+/// the power request registers.
+fn supports_power_requests(
+    memory: &mut dyn ArmMemoryInterface,
+    base_address: u64,
+) -> Result<bool, RomTableError> {
+    let cidr1 = memory
+        .read_word_32(base_address + CIDR1_OFFSET)
+        .map_err(RomTableError::memory)?;
+    if (cidr1 >> 4) & 0xF != RawComponent::CoreSightComponent as u32 {
+        return Ok(false);
+    }
+
+    // DEVID.PRR
+    let devid = memory
+        .read_word_32(base_address + DEVID_OFFSET)
+        .map_err(RomTableError::memory)?;
+
+    Ok(devid & (1 << 5) != 0)
+}
+
+/// Requests power for `power_domain` through the power request registers of the Class 0x9 ROM
+/// table at `rom_base`, and waits until the domain is powered up.
+///
+/// Nothing is done if the power domain can't be controlled. If the domain doesn't power up in
+/// time, [`ArmError::Timeout`] is returned.
+pub(crate) fn power_up_domain(
+    memory: &mut dyn ArmMemoryInterface,
+    rom_base: u64,
+    power_domain: u8,
+) -> Result<(), ArmError> {
+    let dbgpcr = rom_base + DBGPCR_OFFSET + u64::from(power_domain) * 4;
+    let dbgpsr = rom_base + DBGPSR_OFFSET + u64::from(power_domain) * 4;
+
+    // DBGPCR.PRESENT indicates whether the power domain can be controlled at all.
+    if memory.read_word_32(dbgpcr)? & 1 == 0 {
+        return Ok(());
+    }
+
+    tracing::debug!("Requesting power for domain {}", power_domain);
+    // DBGPCR.PR
+    memory.write_word_32(dbgpcr, 1 << 1)?;
+
+    // DBGPSR.PS is 0b01 or 0b11 once the domain is powered up.
+    memory.wait_for_bit(dbgpsr, 1, true, POWER_UP_TIMEOUT)
+}
+
 /// A ROM table entry with raw information parsed.
 ///
 /// Described in section D3.4.4 of the ADIv5.2 specification.
@@ -201,7 +310,7 @@ impl RomTableEntryRaw {
         tracing::debug!("Parsing raw rom table entry: {:#07x}", raw);
 
         let address_offset = ((raw >> 12) & 0xf_ff_ff) as i32;
-        let power_domain_id = ((raw >> 4) & 0xf) as u8;
+        let power_domain_id = ((raw >> 4) & 0x1f) as u8;
         let power_domain_valid = (raw & 4) == 4;
         let format = (raw & 2) == 2;
         let entry_present = (raw & 1) == 1;
@@ -266,6 +375,17 @@ impl ComponentId {
     pub fn peripheral_id(&self) -> &PeripheralID {
         &self.peripheral_id
     }
+
+    /// Retrieve the memory range occupied by the component.
+    ///
+    /// Components spanning multiple 4 KB blocks have their ID registers in the last block, so
+    /// the range starts below the component address.
+    pub fn address_range(&self) -> Range<u64> {
+        let blocks = u64::from(self.peripheral_id.size_in_blocks());
+
+        self.component_address
+            .saturating_sub((blocks - 1) * BLOCK_SIZE)..self.component_address + BLOCK_SIZE
+    }
 }
 
 /// A reader to extract information from a CoreSight component table.
@@ -439,6 +559,8 @@ pub enum Component {
     GenericIPComponent(ComponentId),
     /// CoreLink, PrimeCell, or system component with no standardized register layout, for backwards compatibility.
     CoreLinkOrPrimeCellOrSystemComponent(ComponentId),
+    /// Proprietary component, recognized by one of the [`ComponentParsers`].
+    Custom(ComponentId, CustomComponent),
 }
 
 impl Component {
@@ -446,6 +568,18 @@ impl Component {
     pub fn try_parse<'probe: 'memory, 'memory>(
         memory: &'memory mut (dyn ArmMemoryInterface + 'probe),
         baseaddr: u64,
+    ) -> Result<Component, RomTableError> {
+        Self::try_parse_with(memory, baseaddr, &ComponentParsers::default())
+    }
+
+    /// Tries to parse a CoreSight component table.
+    ///
+    /// Components with a peripheral ID which is not known are passed to `parsers`, also in
+    /// nested ROM tables.
+    pub fn try_parse_with<'probe: 'memory, 'memory>(
+        memory: &'memory mut (dyn ArmMemoryInterface + 'probe),
+        baseaddr: u64,
+        parsers: &ComponentParsers,
     ) -> Result<Component, RomTableError> {
         tracing::debug!("\tReading component data at: {:#010x}", baseaddr);
 
@@ -470,8 +604,18 @@ impl Component {
             component_id.peripheral_id
         );
 
-        if let Some(info) = component_id.peripheral_id.determine_part() {
-            tracing::debug!("\tComponent is known: {}", info);
+        let custom = match component_id.peripheral_id.determine_part() {
+            Some(info) => {
+                tracing::debug!("\tComponent is known: {}", info);
+                None
+            }
+            None if component_id.class != RawComponent::RomTable => parsers.parse(&component_id),
+            None => None,
+        };
+
+        if let Some(custom) = custom {
+            tracing::debug!("\tComponent is a custom component: {}", custom.name);
+            return Ok(Component::Custom(component_id, custom));
         }
 
         let class = match component_id.class {
@@ -479,7 +623,8 @@ impl Component {
                 Component::GenericVerificationComponent(component_id)
             }
             RawComponent::RomTable => {
-                let rom_table = RomTable::try_parse(memory, component_id.component_address)?;
+                let rom_table =
+                    RomTable::try_parse_with(memory, component_id.component_address, parsers)?;
 
                 Component::Class1RomTable(component_id, rom_table)
             }
@@ -503,10 +648,63 @@ impl Component {
             Component::PeripheralTestBlock(component_id) => component_id,
             Component::GenericIPComponent(component_id) => component_id,
             Component::CoreLinkOrPrimeCellOrSystemComponent(component_id) => component_id,
+            Component::Custom(component_id, _) => component_id,
         }
     }
 }
 
+/// A proprietary component, which is not part of the standard CoreSight tables.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CustomComponent {
+    /// The name of the component.
+    pub name: String,
+}
+
+/// A parser for proprietary components, see [`ComponentParsers`].
+///
+/// It is called with the ID and base address of a component with an unknown peripheral ID.
+pub type ComponentParser = dyn Fn(&ComponentId, u64) -> Option<CustomComponent> + Send + Sync;
+
+/// Parsers for proprietary components, which are consulted while walking the ROM tables.
+///
+/// This lets vendors recognize their own peripherals during discovery. Parsers are called in
+/// the order they were registered, the first component returned is used. ROM tables are never
+/// passed to the parsers.
+#[derive(Clone, Default)]
+pub struct ComponentParsers {
+    parsers: Vec<Arc<ComponentParser>>,
+}
+
+impl ComponentParsers {
+    /// Creates an empty set of parsers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers another parser.
+    pub fn register(
+        mut self,
+        parser: impl Fn(&ComponentId, u64) -> Option<CustomComponent> + Send + Sync + 'static,
+    ) -> Self {
+        self.parsers.push(Arc::new(parser));
+        self
+    }
+
+    fn parse(&self, id: &ComponentId) -> Option<CustomComponent> {
+        self.parsers
+            .iter()
+            .find_map(|parser| parser(id, id.component_address))
+    }
+}
+
+impl fmt::Debug for ComponentParsers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ComponentParsers")
+            .field("parsers", &self.parsers.len())
+            .finish()
+    }
+}
+
 /// A Coresight debug component that can be configured with the Probe.
 #[derive(Clone, Debug)]
 pub struct CoresightComponent {
@@ -654,7 +852,7 @@ pub struct PeripheralID {
     JEP106: Option<jep106::JEP106Code>,
     /// Indicates the specific component with an ID unique to this component.
     PART: u16,
-    /// The SIZE is indicated as a multiple of 4k blocks the peripheral occupies.
+    /// The number of 4 KB blocks the peripheral occupies, as log2.
     SIZE: u8,
     /// The dev_type of the peripheral
     dev_type: u8,
@@ -678,7 +876,7 @@ impl PeripheralID {
             REVISION: ((data[2] >> 4) & 0x0F) as u8,
             JEP106: if legacy { Some(jep106) } else { None },
             PART: (((data[1] & 0x0F) << 8) | (data[0] & 0xFF)) as u16,
-            SIZE: 2u32.pow((data[4] >> 4) & 0x0F) as u8,
+            SIZE: ((data[4] >> 4) & 0x0F) as u8,
             dev_type,
             arch_id,
         }
@@ -721,6 +919,11 @@ impl PeripheralID {
         self.REVISION
     }
 
+    /// The number of 4 KB blocks the peripheral occupies, from the SIZE field of PIDR4.
+    pub fn size_in_blocks(&self) -> u32 {
+        1 << self.SIZE
+    }
+
     /// Uses the available data to match it against a table of known components.
     /// If the component is known, some info about it is returned.
     /// If it is not known, None is returned.
@@ -898,3 +1101,128 @@ impl std::fmt::Display for PeripheralType {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::{Component, ComponentParsers, CustomComponent, RomTable};
+    use crate::architecture::arm::{
+        ArmDebugInterface, FullyQualifiedApAddress, ap::memory_ap::mock::MockMemoryAp,
+        communication_interface::tests::mock_memory_interface,
+    };
+
+    const ROM_BASE: u64 = 0x1000;
+    const COMPONENT_BASE: u64 = 0x2000;
+    const POWER_DOMAIN: u64 = 2;
+    const DBGPCR: u64 = ROM_BASE + 0xA00 + POWER_DOMAIN * 4;
+
+    /// A Class 0x9 ROM table with a single component in a power domain, which powers up as soon
+    /// as it is requested.
+    fn power_controlled_rom_table() -> MockMemoryAp {
+        let mut memory_ap = MockMemoryAp::with_pattern_and_size(0);
+        memory_ap.registers.extend([
+            // Entry 0: present, POWERIDVALID, component at offset 0x1000
+            (ROM_BASE, 0x1000 | ((POWER_DOMAIN as u32) << 4) | 0b111),
+            // Class 0x9
+            (ROM_BASE + 0xFF4, 0x90),
+            // DEVID.PRR
+            (ROM_BASE + 0xFC8, 1 << 5),
+            // DBGPCR.PRESENT
+            (DBGPCR, 1),
+            // CIDR of a Class 0x9 component
+            (COMPONENT_BASE + 0xFF0, 0x0D),
+            (COMPONENT_BASE + 0xFF4, 0x90),
+            (COMPONENT_BASE + 0xFF8, 0x05),
+            (COMPONENT_BASE + 0xFFC, 0xB1),
+        ]);
+        // DBGPSR.PS
+        memory_ap
+            .read_only_registers
+            .insert(ROM_BASE + 0xA80 + POWER_DOMAIN * 4, 1);
+
+        memory_ap
+    }
+
+    #[test]
+    fn power_domain_is_powered_up_before_parsing_entries() {
+        let memory_ap = Arc::new(Mutex::new(power_controlled_rom_table()));
+        let mut interface = mock_memory_interface(memory_ap.clone());
+        let mut memory = interface
+            .memory_interface(&FullyQualifiedApAddress::v1_with_default_dp(0))
+            .unwrap();
+
+        let rom_table = RomTable::try_parse(&mut *memory, ROM_BASE).unwrap();
+
+        // DBGPCR.PR
+        assert_eq!(
+            memory_ap.lock().unwrap().register_writes,
+            [(DBGPCR, 1 << 1)]
+        );
+        let entries: Vec<_> = rom_table.entries().collect();
+        assert_eq!(entries.len(), 1);
+        assert!(matches!(
+            entries[0].component(),
+            Component::CoresightComponent(id) if id.component_address() == COMPONENT_BASE
+        ));
+    }
+
+    #[test]
+    fn blocks_of_larger_components_are_skipped() {
+        const LARGE_COMPONENT: u64 = COMPONENT_BASE + 0x1000;
+
+        let mut memory_ap = power_controlled_rom_table();
+        memory_ap.registers.extend([
+            // Entry 1: present, component at offset 0x2000
+            (ROM_BASE + 4, 0x2000 | 0b11),
+            // CIDR of a Class 0x9 component
+            (LARGE_COMPONENT + 0xFF0, 0x0D),
+            (LARGE_COMPONENT + 0xFF4, 0x90),
+            (LARGE_COMPONENT + 0xFF8, 0x05),
+            (LARGE_COMPONENT + 0xFFC, 0xB1),
+            // PIDR4.SIZE: 8 KB, starting at the component of entry 0
+            (LARGE_COMPONENT + 0xFD0, 1 << 4),
+        ]);
+        let mut interface = mock_memory_interface(Arc::new(Mutex::new(memory_ap)));
+        let mut memory = interface
+            .memory_interface(&FullyQualifiedApAddress::v1_with_default_dp(0))
+            .unwrap();
+
+        let rom_table = RomTable::try_parse(&mut *memory, ROM_BASE).unwrap();
+
+        let entries: Vec<_> = rom_table.entries().collect();
+        assert_eq!(entries.len(), 1);
+
+        let id = entries[0].component().id();
+        assert_eq!(id.component_address(), LARGE_COMPONENT);
+        assert_eq!(id.peripheral_id().size_in_blocks(), 2);
+        assert_eq!(id.address_range(), COMPONENT_BASE..LARGE_COMPONENT + 0x1000);
+    }
+
+    #[test]
+    fn unknown_components_are_passed_to_custom_parsers() {
+        let mut interface =
+            mock_memory_interface(Arc::new(Mutex::new(power_controlled_rom_table())));
+        let mut memory = interface
+            .memory_interface(&FullyQualifiedApAddress::v1_with_default_dp(0))
+            .unwrap();
+
+        let parsers = ComponentParsers::new().register(|id, base_address| {
+            (id.peripheral_id().part() == 0 && base_address == COMPONENT_BASE).then(|| {
+                CustomComponent {
+                    name: "Trace widget".to_string(),
+                }
+            })
+        });
+
+        let component = Component::try_parse_with(&mut *memory, COMPONENT_BASE, &parsers).unwrap();
+        assert!(matches!(
+            component,
+            Component::Custom(_, CustomComponent { name }) if name == "Trace widget"
+        ));
+
+        // Without the parser, the component stays a generic CoreSight component.
+        let component = Component::try_parse(&mut *memory, COMPONENT_BASE).unwrap();
+        assert!(matches!(component, Component::CoresightComponent(_)));
+    }
+}
@@ -9,14 +9,20 @@ pub mod component;
 pub mod core;
 pub mod dp;
 pub mod memory;
+pub mod rtt;
 pub mod sequences;
+mod shared_interface;
 pub mod swo;
 pub(crate) mod traits;
 
-pub use self::core::{Dump, armv6m, armv7a, armv7m, armv8a, armv8m};
+pub use self::core::{
+    Dump, armv6m, armv7a, armv7m, armv8a, armv8m,
+    cortex_m::{BreakpointUnitInfo, CortexMDebug, ResetVector, VectorCatchFlags},
+};
 use self::{
     ap::AccessPortError,
     dp::DebugPortError,
+    dp::{DebugPortVersion, DpAddress},
     memory::romtable::RomTableError,
     sequences::ArmDebugSequenceError,
     {armv7a::Armv7aError, armv8a::Armv8aError},
@@ -24,12 +30,19 @@ use self::{
 use crate::{
     core::memory_mapped_registers::RegisterAddressOutOfBounds,
     memory::{InvalidDataLengthError, MemoryNotAlignedError},
-    probe::DebugProbeError,
+    probe::{DebugProbeError, WireProtocol},
 };
 pub use communication_interface::{
-    ArmChipInfo, ArmCommunicationInterface, ArmDebugInterface, DapError, DapProbe,
+    ApInfo, ArmChipInfo, ArmCommunicationInterface, ArmCommunicationInterfaceBuilder,
+    ArmDebugInterface, ConnectDiagnostics, DapError, DapProbe, DpTopology, FaultedRange, RateLimit,
+    RateUnit, RetryPolicy, SelectCache, SwdPhase, TransferStats, identify,
+};
+pub use rtt::{RttChannels, RttWriteMode};
+pub use shared_interface::{KeepAlivePause, SharedArmInterface, SharedMemoryInterface};
+pub use swo::{
+    ParallelTraceConfig, SwoAccess, SwoConfig, SwoMode, SwoReader, TracePortWidth,
+    itm::{ItmDecoder, ItmPacket, PcSample},
 };
-pub use swo::{SwoAccess, SwoConfig, SwoMode, SwoReader};
 pub use traits::*;
 
 /// A error that occured while parsing a raw register value.
@@ -62,6 +75,14 @@ pub enum ArmError {
     /// The current target device is not an ARM device.
     NoArmTarget,
 
+    /// No debug port responded at {dp:x?}.
+    NoDebugPort {
+        /// The address of the debug port.
+        dp: DpAddress,
+        /// The error which occurred while connecting to the debug port.
+        source: Box<ArmError>,
+    },
+
     /// Error using access port {address:?}.
     AccessPort {
         /// Address of the access port
@@ -76,6 +97,14 @@ pub enum ArmError {
     /// The core has to be halted for the operation, but was not.
     CoreNotHalted,
 
+    /// The cores with DHCSR at {cores:x?} did not {action} in time.
+    CoresNotResponding {
+        /// What the cores were requested to do.
+        action: &'static str,
+        /// The access port and DHCSR address of each core which did not respond.
+        cores: Vec<(FullyQualifiedApAddress, u64)>,
+    },
+
     /// Performing certain operations (e.g device unlock or Chip-Erase) can leave the device in a
     /// state that requires a probe re-attach to resolve.
     ReAttachRequired,
@@ -91,9 +120,44 @@ pub enum ArmError {
     /// An error occurred in the communication with an access port or debug port.
     Dap(#[from] DapError),
 
+    /// The target did not respond using {configured} nor using {alternative}.
+    ///
+    /// The target might be configured for a different wire protocol than the probe, or
+    /// it might not be connected or powered.
+    #[ignore_extra_doc_attributes]
+    WireProtocolMismatch {
+        /// The wire protocol the probe was configured for.
+        configured: WireProtocol,
+        /// The wire protocol which was attempted as a fallback.
+        alternative: WireProtocol,
+    },
+
     /// The debug probe encountered an error.
     Probe(#[from] DebugProbeError),
 
+    /// The debug probe does not support the {0} wire protocol.
+    ProtocolNotSupported(WireProtocol),
+
+    /// The {0} wire protocol is only used after the interface is reinitialized.
+    ///
+    /// A debug port is connected using the current wire protocol, so it has to be stopped and
+    /// connected again to switch to the new one.
+    ReinitializeRequired(WireProtocol),
+
+    /// The transaction count {0} does not fit into CTRL/STAT.TRNCNT.
+    TransactionCountOutOfRange(u16),
+
+    /// The debug probe was disconnected and could not be re-acquired.
+    ProbeDisconnected,
+
+    /// Verifying a register write failed: wrote {wrote:#010x}, but read back {read:#010x}.
+    WriteVerifyMismatch {
+        /// The value which was written.
+        wrote: u32,
+        /// The value which was read back.
+        read: u32,
+    },
+
     /// Failed to access address 0x{0.address:08x} as it is not aligned to the requirement of
     /// {0.alignment} bytes for this platform and API call.
     MemoryNotAligned(#[from] MemoryNotAlignedError),
@@ -101,15 +165,33 @@ pub enum ArmError {
     /// A region outside of the AP address space was accessed.
     OutOfBounds,
 
+    /// The address {0:#x} is too large for the address width of the access port.
+    AddressOutOfRange(u64),
+
     /// {0} bit is not a supported memory transfer width on the current core.
     UnsupportedTransferWidth(usize),
 
     /// The AP with address {0:?} does not exist.
     ApDoesNotExist(FullyQualifiedApAddress),
 
+    /// The power domain of the AP with address {0:?} could not be powered up.
+    ApPowerDomainUnavailable(FullyQualifiedApAddress),
+
     /// The AP has the wrong version for the operation.
     WrongApVersion,
 
+    /// The AP {ap:x?} can't be accessed through a {debug_port_version} debug port.
+    ///
+    /// APv1 addresses can only be used with debug ports before DPv3, and APv2 addresses only with
+    /// DPv3 debug ports.
+    #[ignore_extra_doc_attributes]
+    ApVersionMismatch {
+        /// The address of the access port.
+        ap: FullyQualifiedApAddress,
+        /// The version of the debug port.
+        debug_port_version: DebugPortVersion,
+    },
+
     /// The AP has the wrong type for the operation.
     WrongApType,
 
@@ -117,6 +199,46 @@ pub enum ArmError {
     /// at addresses < 0x2000_0000.
     UnsupportedBreakpointAddress(u32),
 
+    /// The hardware breakpoint {index} does not exist, only {available} are available.
+    BreakpointIndexOutOfRange {
+        /// The index of the requested breakpoint.
+        index: usize,
+        /// The number of hardware breakpoints.
+        available: u32,
+    },
+
+    /// The watchpoint {index} does not exist, only {available} DWT comparators are available.
+    WatchpointIndexOutOfRange {
+        /// The index of the requested watchpoint.
+        index: usize,
+        /// The number of DWT comparators.
+        available: u32,
+    },
+
+    /// The CTI channel {index} does not exist, only {available} are available.
+    CtiChannelIndexOutOfRange {
+        /// The index of the requested channel.
+        index: usize,
+        /// The number of channels of the CTI.
+        available: u32,
+    },
+
+    /// The RTT control block at {address:#010x} is invalid: {reason}
+    RttControlBlockInvalid {
+        /// The address of the control block.
+        address: u64,
+        /// What is wrong with the control block.
+        reason: String,
+    },
+
+    /// The RTT channel {index} does not exist, only {available} are available.
+    RttChannelIndexOutOfRange {
+        /// The index of the requested channel.
+        index: usize,
+        /// The number of channels in this direction.
+        available: usize,
+    },
+
     /// ARMv8a specific error occurred.
     Armv8a(#[from] Armv8aError),
 
@@ -129,6 +251,9 @@ pub enum ArmError {
     /// Tracing has not been configured.
     TracingUnconfigured,
 
+    /// Failed to write the SWO capture file.
+    SwoCapture(#[source] std::io::Error),
+
     /// Error parsing a register.
     RegisterParse(#[from] RegisterParseError),
 
@@ -147,6 +272,9 @@ pub enum ArmError {
     /// Some required functionality is not implemented: {0}
     NotImplemented(&'static str),
 
+    /// The target does not implement {0}.
+    FeatureNotImplemented(&'static str),
+
     /// Invalid data length error: {0}
     InvalidDataLength(#[from] InvalidDataLengthError),
 
@@ -0,0 +1,495 @@
+//! Access to RTT channels through the memory interface of an access port.
+//!
+//! Unlike [`Rtt`], this doesn't need a [`Core`](crate::Core), so RTT can be used over SWD or
+//! JTAG without SWO, e.g. to read the log output of a running target. Only the 32-bit control
+//! block layout is supported.
+
+use std::{
+    ops::Range,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::rtt::Rtt;
+
+use super::{ArmError, memory::ArmMemoryInterface};
+
+/// The most channels accepted in each direction. Control blocks with more channels are assumed
+/// to be corrupted.
+const MAX_CHANNELS: u32 = 255;
+
+/// The size of the ID and the channel counts at the start of the control block.
+const HEADER_SIZE: u64 = 24;
+
+/// The size of a channel descriptor in the control block.
+//
+// struct Channel {
+//     const char *name;
+//     char *buffer;
+//     unsigned int size;
+//     unsigned int write;
+//     unsigned int read;
+//     unsigned int flags;
+// }
+const DESCRIPTOR_SIZE: u64 = 24;
+
+/// The offset of the write offset in a channel descriptor, which is followed by the read offset.
+const WRITE_OFFSET: u64 = 12;
+
+/// The offset of the read offset in a channel descriptor.
+const READ_OFFSET: u64 = 16;
+
+/// Searches `search_range` of the memory behind `memory` for the ID of an RTT control block,
+/// and returns the address of the first match.
+///
+/// The memory is read in blocks of 4 KiB, so large RAM regions can be searched without reading
+/// them word by word. An ID which spans two blocks is found as well.
+pub fn find_control_block(
+    memory: &mut dyn ArmMemoryInterface,
+    search_range: Range<u64>,
+) -> Result<Option<u64>, ArmError> {
+    const CHUNK_SIZE: u64 = 4096;
+
+    let id = &Rtt::RTT_ID;
+
+    // The searched bytes, starting with the end of the previous block, in case the ID starts
+    // there.
+    let mut window = Vec::with_capacity(CHUNK_SIZE as usize + id.len() - 1);
+    let mut window_start = search_range.start;
+    let mut address = search_range.start;
+
+    while address < search_range.end {
+        let len = (search_range.end - address).min(CHUNK_SIZE) as usize;
+        let carried = window.len();
+        window.resize(carried + len, 0);
+        memory.read(address, &mut window[carried..])?;
+        address += len as u64;
+
+        if let Some(offset) = window.windows(id.len()).position(|w| w == id) {
+            return Ok(Some(window_start + offset as u64));
+        }
+
+        let keep = window.len().min(id.len() - 1);
+        window.drain(..window.len() - keep);
+        window_start = address - keep as u64;
+    }
+
+    Ok(None)
+}
+
+/// How [`RttChannels::write_down_channel`] handles a down channel without enough free space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RttWriteMode {
+    /// Write as much as fits, and return the number of bytes written.
+    NonBlocking,
+    /// Wait until the target has read enough data to write everything.
+    Blocking {
+        /// How long to wait for free space, in total.
+        timeout: Duration,
+    },
+}
+
+/// The ring buffer of a channel, as described by its descriptor.
+#[derive(Debug, Clone, Copy)]
+struct RingBuffer {
+    /// The address of the channel descriptor.
+    descriptor: u64,
+    /// The address of the buffer.
+    address: u64,
+    /// The size of the buffer. One byte less is usable, so that a full buffer can be told apart
+    /// from an empty one.
+    size: u32,
+}
+
+impl RingBuffer {
+    fn from_descriptor(descriptor: u64, words: &[u32]) -> Self {
+        Self {
+            descriptor,
+            address: u64::from(words[1]),
+            size: words[2],
+        }
+    }
+}
+
+/// The RTT channels of a control block in target memory.
+///
+/// Data is transferred by reading and writing the ring buffers and their offsets through the
+/// memory interface, while the target keeps running.
+pub struct RttChannels<'a> {
+    memory: &'a mut dyn ArmMemoryInterface,
+    control_block: u64,
+    up_channels: Vec<RingBuffer>,
+    down_channels: Vec<RingBuffer>,
+}
+
+impl<'a> RttChannels<'a> {
+    /// Parses the channel descriptors of the control block at `control_block`.
+    ///
+    /// The control block is usually found with [`find_control_block`].
+    pub fn attach(
+        memory: &'a mut dyn ArmMemoryInterface,
+        control_block: u64,
+    ) -> Result<Self, ArmError> {
+        let invalid = |reason: String| ArmError::RttControlBlockInvalid {
+            address: control_block,
+            reason,
+        };
+
+        let mut id = [0; Rtt::RTT_ID.len()];
+        memory.read(control_block, &mut id)?;
+        if id != Rtt::RTT_ID {
+            return Err(invalid(format!(
+                "expected the ID {:?}, found {:?}",
+                String::from_utf8_lossy(&Rtt::RTT_ID),
+                String::from_utf8_lossy(&id)
+            )));
+        }
+
+        let mut counts = [0; 2];
+        memory.read_32(control_block + id.len() as u64, &mut counts)?;
+        let [max_up_channels, max_down_channels] = counts;
+        if max_up_channels > MAX_CHANNELS || max_down_channels > MAX_CHANNELS {
+            return Err(invalid(format!(
+                "unexpected channel counts: MaxNumUpBuffers={max_up_channels} MaxNumDownBuffers={max_down_channels}"
+            )));
+        }
+
+        let channel_count = (max_up_channels + max_down_channels) as usize;
+        let words_per_descriptor = DESCRIPTOR_SIZE as usize / 4;
+        let mut descriptors = vec![0; channel_count * words_per_descriptor];
+        memory.read_32(control_block + HEADER_SIZE, &mut descriptors)?;
+
+        let mut channels = descriptors
+            .chunks_exact(words_per_descriptor)
+            .enumerate()
+            .map(|(index, words)| {
+                let descriptor = control_block + HEADER_SIZE + index as u64 * DESCRIPTOR_SIZE;
+                RingBuffer::from_descriptor(descriptor, words)
+            })
+            .collect::<Vec<_>>();
+        let down_channels = channels.split_off(max_up_channels as usize);
+
+        Ok(Self {
+            memory,
+            control_block,
+            up_channels: channels,
+            down_channels,
+        })
+    }
+
+    /// Returns the number of up (target to host) channels.
+    pub fn up_channel_count(&self) -> usize {
+        self.up_channels.len()
+    }
+
+    /// Returns the number of down (host to target) channels.
+    pub fn down_channel_count(&self) -> usize {
+        self.down_channels.len()
+    }
+
+    /// Reads the data available in the up channel `index` into `buf`, and returns the number of
+    /// bytes read.
+    ///
+    /// This doesn't wait for data, and reads at most `buf.len()` bytes. The read data is removed
+    /// from the channel.
+    pub fn read_up_channel(&mut self, index: usize, buf: &mut [u8]) -> Result<usize, ArmError> {
+        let channel = self.channel(&self.up_channels, index)?;
+        let (write, mut read) = self.offsets(index, channel)?;
+
+        let mut total = 0;
+
+        // The data wraps around at most once, so this takes at most two reads.
+        while total < buf.len() {
+            let end = if read > write { channel.size } else { write };
+            let count = ((end - read) as usize).min(buf.len() - total);
+            if count == 0 {
+                break;
+            }
+
+            self.memory.read(
+                channel.address + u64::from(read),
+                &mut buf[total..][..count],
+            )?;
+
+            total += count;
+            read += count as u32;
+            if read == channel.size {
+                read = 0;
+            }
+        }
+
+        if total > 0 {
+            self.memory
+                .write_word_32(channel.descriptor + READ_OFFSET, read)?;
+            self.memory.flush()?;
+        }
+
+        Ok(total)
+    }
+
+    /// Writes `data` into the down channel `index`, and returns the number of bytes written.
+    ///
+    /// With [`RttWriteMode::NonBlocking`], only the part of `data` which fits into the channel is
+    /// written. With [`RttWriteMode::Blocking`], the read offset of the channel is polled until
+    /// all of `data` is written, and [`ArmError::Timeout`] is returned if the target doesn't
+    /// read the channel in time. The data written until then stays in the channel.
+    pub fn write_down_channel(
+        &mut self,
+        index: usize,
+        data: &[u8],
+        mode: RttWriteMode,
+    ) -> Result<usize, ArmError> {
+        let RttWriteMode::Blocking { timeout } = mode else {
+            return self.write_available(index, data);
+        };
+
+        let start = Instant::now();
+        let mut total = 0;
+
+        loop {
+            total += self.write_available(index, &data[total..])?;
+            if total == data.len() {
+                return Ok(total);
+            }
+
+            if start.elapsed() > timeout {
+                tracing::debug!(
+                    "Timed out writing to RTT down channel {index}, {total} of {} bytes written",
+                    data.len()
+                );
+                return Err(ArmError::Timeout);
+            }
+
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    /// Writes as much of `data` as fits into the down channel `index`.
+    fn write_available(&mut self, index: usize, data: &[u8]) -> Result<usize, ArmError> {
+        let channel = self.channel(&self.down_channels, index)?;
+        let (mut write, read) = self.offsets(index, channel)?;
+
+        let mut total = 0;
+
+        // The free space wraps around at most once, so this takes at most two writes.
+        while total < data.len() {
+            // The byte before the read offset is always kept free.
+            let end = if read > write {
+                read - 1
+            } else if read == 0 {
+                channel.size - 1
+            } else {
+                channel.size
+            };
+            let count = ((end - write) as usize).min(data.len() - total);
+            if count == 0 {
+                break;
+            }
+
+            self.memory
+                .write(channel.address + u64::from(write), &data[total..][..count])?;
+
+            total += count;
+            write += count as u32;
+            if write == channel.size {
+                write = 0;
+            }
+        }
+
+        if total > 0 {
+            // The data has to be written before the offset, so the target never reads stale data.
+            self.memory
+                .write_word_32(channel.descriptor + WRITE_OFFSET, write)?;
+            self.memory.flush()?;
+        }
+
+        Ok(total)
+    }
+
+    fn channel(&self, channels: &[RingBuffer], index: usize) -> Result<RingBuffer, ArmError> {
+        let channel = *channels
+            .get(index)
+            .ok_or(ArmError::RttChannelIndexOutOfRange {
+                index,
+                available: channels.len(),
+            })?;
+
+        if channel.address == 0 || channel.size == 0 {
+            return Err(ArmError::RttControlBlockInvalid {
+                address: self.control_block,
+                reason: format!("channel {index} is not initialized"),
+            });
+        }
+
+        Ok(channel)
+    }
+
+    /// Reads the write and read offsets of `channel`, and checks that they are within its buffer.
+    fn offsets(&mut self, index: usize, channel: RingBuffer) -> Result<(u32, u32), ArmError> {
+        let mut offsets = [0; 2];
+        self.memory
+            .read_32(channel.descriptor + WRITE_OFFSET, &mut offsets)?;
+        let [write, read] = offsets;
+
+        if write >= channel.size || read >= channel.size {
+            return Err(ArmError::RttControlBlockInvalid {
+                address: self.control_block,
+                reason: format!(
+                    "the offsets write={write} read={read} of channel {index} are outside of its buffer of {} bytes",
+                    channel.size
+                ),
+            });
+        }
+
+        Ok((write, read))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use super::{RttChannels, RttWriteMode, find_control_block};
+    use crate::{
+        architecture::arm::{
+            ArmCommunicationInterface, ArmDebugInterface, ArmError, FullyQualifiedApAddress,
+            ap::memory_ap::mock::MockMemoryAp, communication_interface::tests::mock_target,
+        },
+        rtt::Rtt,
+    };
+
+    /// An interface whose memory holds a control block at 0 with one up and one down channel.
+    /// Both have a 16 byte buffer, at 0x100 and 0x200.
+    fn control_block() -> (Arc<Mutex<MockMemoryAp>>, ArmCommunicationInterface) {
+        let mut memory_ap = MockMemoryAp::with_pattern_and_size(0x300);
+        memory_ap.memory.fill(0);
+        let (memory_ap, mut interface) = mock_target(memory_ap);
+
+        {
+            let mut memory = interface.memory_interface(&ap()).unwrap();
+            memory.write(0, &Rtt::RTT_ID).unwrap();
+            memory.write_32(16, &[1, 1]).unwrap();
+            memory.write_32(24, &[0, 0x100, 16, 0, 0, 0]).unwrap();
+            memory.write_32(48, &[0, 0x200, 16, 0, 0, 0]).unwrap();
+        }
+
+        (memory_ap, interface)
+    }
+
+    fn ap() -> FullyQualifiedApAddress {
+        FullyQualifiedApAddress::v1_with_default_dp(0)
+    }
+
+    #[test]
+    fn up_channel_data_wraps_around() {
+        let (_memory_ap, mut interface) = control_block();
+        let mut memory = interface.memory_interface(&ap()).unwrap();
+        memory.write(0x100, b"ghijklmnopqrstuv").unwrap();
+        // write = 6, read = 10
+        memory.write_32(24 + 12, &[6, 10]).unwrap();
+
+        let mut channels = RttChannels::attach(&mut *memory, 0).unwrap();
+        let mut buf = [0; 32];
+        let count = channels.read_up_channel(0, &mut buf).unwrap();
+        assert_eq!(&buf[..count], b"qrstuvghijkl");
+        assert_eq!(channels.read_up_channel(0, &mut buf).unwrap(), 0);
+
+        assert_eq!(memory.read_word_32(24 + 16).unwrap(), 6);
+    }
+
+    #[test]
+    fn down_channel_keeps_one_byte_free() {
+        let (memory_ap, mut interface) = control_block();
+        let mut memory = interface.memory_interface(&ap()).unwrap();
+        // write = 12, read = 4
+        memory.write_32(48 + 12, &[12, 4]).unwrap();
+
+        let mut channels = RttChannels::attach(&mut *memory, 0).unwrap();
+        let mode = RttWriteMode::NonBlocking;
+        assert_eq!(
+            channels.write_down_channel(0, b"0123456789", mode).unwrap(),
+            7
+        );
+        assert_eq!(channels.write_down_channel(0, b"x", mode).unwrap(), 0);
+        assert!(matches!(
+            channels.write_down_channel(
+                0,
+                b"x",
+                RttWriteMode::Blocking {
+                    timeout: Duration::from_millis(5)
+                }
+            ),
+            Err(ArmError::Timeout)
+        ));
+        assert!(matches!(
+            channels.write_down_channel(1, b"x", mode),
+            Err(ArmError::RttChannelIndexOutOfRange {
+                index: 1,
+                available: 1
+            })
+        ));
+
+        assert_eq!(memory.read_word_32(48 + 12).unwrap(), 3);
+        let memory_ap = memory_ap.lock().unwrap();
+        assert_eq!(&memory_ap.memory[0x200..0x203], b"456");
+        assert_eq!(&memory_ap.memory[0x20C..0x210], b"0123");
+    }
+
+    #[test]
+    fn channel_counts_are_validated() {
+        let (_memory_ap, mut interface) = control_block();
+        let mut memory = interface.memory_interface(&ap()).unwrap();
+        memory.write_32(16, &[1, 1000]).unwrap();
+
+        assert!(matches!(
+            RttChannels::attach(&mut *memory, 0),
+            Err(ArmError::RttControlBlockInvalid { address: 0, .. })
+        ));
+    }
+
+    /// Returns 8 KiB of memory, with the ID of an RTT control block at `address`.
+    fn rtt_id_at(address: usize) -> MockMemoryAp {
+        let mut memory_ap = MockMemoryAp::with_pattern_and_size(0x2000);
+        memory_ap.memory[address..address + Rtt::RTT_ID.len()].copy_from_slice(&Rtt::RTT_ID);
+        memory_ap
+    }
+
+    #[test]
+    fn control_block_spanning_two_chunks_is_found() {
+        // The first 4 KiB chunk of the search ends 5 bytes into the ID.
+        const ADDRESS: u64 = 0x800 + 0x1000 - 5;
+
+        let (_memory_ap, mut interface) = mock_target(rtt_id_at(ADDRESS as usize));
+
+        let mut memory = interface.memory_interface(&ap()).unwrap();
+        assert_eq!(
+            find_control_block(&mut *memory, 0x800..0x2000).unwrap(),
+            Some(ADDRESS)
+        );
+    }
+
+    #[test]
+    fn control_block_has_to_be_inside_the_range() {
+        const ADDRESS: u64 = 0x1000 - 5;
+
+        let (_memory_ap, mut interface) = mock_target(rtt_id_at(ADDRESS as usize));
+
+        let mut memory = interface.memory_interface(&ap()).unwrap();
+        // The last byte of the ID is outside of the range.
+        assert_eq!(
+            find_control_block(&mut *memory, 0..ADDRESS + 15).unwrap(),
+            None
+        );
+        assert_eq!(
+            find_control_block(&mut *memory, ADDRESS + 1..0x2000).unwrap(),
+            None
+        );
+        assert_eq!(
+            find_control_block(&mut *memory, 0..ADDRESS + 16).unwrap(),
+            Some(ADDRESS)
+        );
+    }
+}
@@ -56,6 +56,18 @@ impl ArmDebugSequenceError {
     }
 }
 
+/// Whether debug access to a device is locked, see [`ArmDebugInterface::check_debug_lock`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugLockStatus {
+    /// Debug access is not locked.
+    Unlocked,
+    /// Debug access is locked, e.g. by readout protection. Usually, the device has to be
+    /// mass-erased to unlock it.
+    Locked,
+    /// The debug sequence doesn't know how to detect whether the device is locked.
+    Unknown,
+}
+
 /// The default sequences that is used for ARM chips that do not specify a specific sequence.
 #[derive(Debug)]
 pub struct DefaultArmSequence(pub(crate) ());
@@ -67,7 +79,11 @@ impl DefaultArmSequence {
     }
 }
 
-impl ArmDebugSequence for DefaultArmSequence {}
+impl ArmDebugSequence for DefaultArmSequence {
+    fn name(&self) -> &str {
+        "default"
+    }
+}
 
 /// ResetCatchSet for Cortex-A devices
 fn armv7a_reset_catch_set(
@@ -370,7 +386,9 @@ fn cortex_m_reset_catch_set(core: &mut dyn ArmMemoryInterface) -> Result<(), Arm
 }
 
 /// ResetSystem for Cortex-M devices
-fn cortex_m_reset_system(interface: &mut dyn ArmMemoryInterface) -> Result<(), ArmError> {
+pub(crate) fn cortex_m_reset_system(
+    interface: &mut dyn ArmMemoryInterface,
+) -> Result<(), ArmError> {
     use crate::architecture::arm::core::armv7m::Aircr;
 
     let mut aircr = Aircr(0);
@@ -856,6 +874,19 @@ pub trait ArmDebugSequence: Send + Sync + Debug {
         Ok(())
     }
 
+    /// Checks whether debug access to the device behind the debug port `dp` is locked, using
+    /// knowledge about the device family.
+    ///
+    /// Executed after having powered up the debug port. Returns `None` if the lock state can't be
+    /// detected, which is the default.
+    fn is_debug_locked(
+        &self,
+        _interface: &mut dyn ArmDebugInterface,
+        _dp: DpAddress,
+    ) -> Result<Option<bool>, ArmError> {
+        Ok(None)
+    }
+
     /// Executed before step or run command to support recovery from a lost target connection, e.g. after a low power mode.
     /// This is based on the `RecoverSupportStart` function from the [ARM SVD Debug Description].
     ///
@@ -1123,6 +1154,24 @@ pub trait ArmDebugSequence: Send + Sync + Debug {
     fn allowed_access_ports(&self) -> Vec<u8> {
         (0..=255).collect()
     }
+
+    /// Reads the unique ID of the device, if the family has one at a known location.
+    ///
+    /// The default implementation returns `None`.
+    fn read_unique_id(
+        &self,
+        _memory: &mut dyn ArmMemoryInterface,
+    ) -> Result<Option<Vec<u8>>, ArmError> {
+        Ok(None)
+    }
+
+    /// Returns a short name of the sequence, used for diagnostics.
+    ///
+    /// The default implementation returns the name of the implementing type.
+    fn name(&self) -> &str {
+        let name = std::any::type_name::<Self>();
+        name.rsplit("::").next().unwrap_or(name)
+    }
 }
 
 /// Chip-Erase Handling via the Device's Debug Interface
@@ -0,0 +1,1092 @@
+//! Sharing an [`ArmDebugInterface`] between threads.
+
+use std::{
+    fmt::Debug,
+    path::Path,
+    sync::{
+        Arc, Mutex, MutexGuard, PoisonError, TryLockError, Weak,
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, RecvTimeoutError},
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use bitvec::vec::BitVec;
+
+use crate::{
+    CoreStatus, MemoryInterface,
+    architecture::arm::{
+        ArmError, DapAccess, FullyQualifiedApAddress, SwoAccess, SwoConfig,
+        ap::{self, MemApCfg, MemApRegister},
+        communication_interface::{
+            ArmDebugInterface, ConnectDiagnostics, DapProbe, SelectCache, SwdPhase, SwdSequence,
+            TransferStats,
+        },
+        dp::{DPIDR, DebugPortId, DebugPortVersion, DpAddress, DpRegister, DpRegisterAddress},
+        memory::{AddressWidth, ArmMemoryInterface, SecurityState},
+        sequences::DebugLockStatus,
+    },
+    probe::{DebugProbeError, Probe, ProbeInfo, WireProtocol},
+};
+
+/// A thread-safe handle to an [`ArmDebugInterface`], which can be cloned and handed to other
+/// threads.
+///
+/// The handle itself implements [`ArmDebugInterface`]. Every call made through it locks the
+/// underlying interface for the duration of that call only, so a single call (e.g. one
+/// register access, or one block transfer through a memory interface) is never interleaved
+/// with calls from other threads. A sequence of calls is not atomic, though: another thread
+/// may access the target between two calls. Use [`SharedArmInterface::lock`] to perform
+/// several accesses without interruption.
+///
+/// The lock is never held while user code runs. Default trait methods which take a
+/// callback, like [`ArmDebugInterface::dump_memory_with_progress`], are not forwarded as a
+/// whole, but are built from individually locked calls.
+///
+/// Memory interfaces returned by this handle don't borrow the interface. Each access opens a
+/// memory interface on the locked interface and closes it again, so it is safe to keep them
+/// around while other threads use the interface. The configuration of the access port is
+/// read when it is opened first, and reused for later accesses until the interface is
+/// reinitialized.
+///
+/// Direct access to the probe is not possible through the shared handle, so
+/// [`DapAccess::try_dap_probe`] and [`DapAccess::try_dap_probe_mut`] always return `None`.
+#[derive(Clone)]
+pub struct SharedArmInterface {
+    // Declared first, so the keep-alive thread is stopped before the interface is dropped.
+    keep_alive: Option<Arc<KeepAlive>>,
+    interface: Arc<Mutex<Box<dyn ArmDebugInterface>>>,
+    // Cached, because the name can't be borrowed from behind the lock.
+    sequence_name: Arc<str>,
+    activity: Arc<Activity>,
+}
+
+impl SharedArmInterface {
+    /// Creates a new shared handle for the given interface.
+    pub fn new(interface: Box<dyn ArmDebugInterface>) -> Self {
+        Self {
+            keep_alive: None,
+            sequence_name: interface.sequence_name().into(),
+            interface: Arc::new(Mutex::new(interface)),
+            activity: Arc::new(Activity::new()),
+        }
+    }
+
+    /// Locks the interface for exclusive use by the current thread.
+    ///
+    /// All other handles block until the returned guard is dropped.
+    pub fn lock(&self) -> MutexGuard<'_, Box<dyn ArmDebugInterface>> {
+        // A panic while holding the lock doesn't leave the interface in a state which is
+        // worse than any other error would, so the poison flag is ignored.
+        let interface = self
+            .interface
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        self.activity.touch();
+
+        interface
+    }
+
+    /// Starts reading DPIDR of the current debug port whenever the interface was idle for
+    /// `interval`.
+    ///
+    /// Some hosts suspend USB devices which are idle for a few seconds, and some probes lose
+    /// their connection to the target when that happens, e.g. while a core is halted at a
+    /// breakpoint for a long time. The harmless DPIDR read keeps the probe busy.
+    ///
+    /// The reads happen on a background thread, which holds the lock of the interface for the
+    /// duration of a read, so they are never interleaved with other accesses. A read is skipped
+    /// if the interface is locked by another thread. Use [`SharedArmInterface::pause_keep_alive`]
+    /// to suspend the reads during a burst of transfers made through several calls.
+    ///
+    /// Only clones created after this call share the keep-alive. The thread is stopped when the
+    /// last of them is dropped. Calling this again replaces the previous interval.
+    pub fn start_keep_alive(&mut self, interval: Duration) {
+        self.keep_alive = Some(Arc::new(KeepAlive::spawn(
+            Arc::downgrade(&self.interface),
+            self.activity.clone(),
+            interval,
+        )));
+    }
+
+    /// Suspends the reads started by [`SharedArmInterface::start_keep_alive`] until the returned
+    /// guard is dropped.
+    ///
+    /// Guards can be nested, the reads resume once all guards are dropped.
+    pub fn pause_keep_alive(&self) -> KeepAlivePause {
+        self.activity.paused.fetch_add(1, Ordering::SeqCst);
+
+        KeepAlivePause {
+            activity: self.activity.clone(),
+        }
+    }
+
+    /// Returns the underlying interface, if this is the last handle to it.
+    ///
+    /// Otherwise, the handle is returned unchanged.
+    pub fn try_into_inner(mut self) -> Result<Box<dyn ArmDebugInterface>, Self> {
+        let mut restart_keep_alive = None;
+
+        if let Some(keep_alive) = self.keep_alive.take() {
+            match Arc::try_unwrap(keep_alive) {
+                Ok(keep_alive) => {
+                    // Stop and join the keep-alive thread, so it can't hold on to the interface
+                    // while it is unwrapped.
+                    restart_keep_alive = Some(keep_alive.interval);
+                    drop(keep_alive);
+                }
+                Err(keep_alive) => {
+                    // Other handles share the keep-alive, so they share the interface as well.
+                    self.keep_alive = Some(keep_alive);
+                    return Err(self);
+                }
+            }
+        }
+
+        let Self {
+            keep_alive,
+            interface,
+            sequence_name,
+            activity,
+        } = self;
+
+        Arc::try_unwrap(interface)
+            .map(|mutex| mutex.into_inner().unwrap_or_else(PoisonError::into_inner))
+            .map_err(|interface| {
+                let mut shared = Self {
+                    keep_alive,
+                    interface,
+                    sequence_name,
+                    activity,
+                };
+                if let Some(interval) = restart_keep_alive {
+                    shared.start_keep_alive(interval);
+                }
+                shared
+            })
+    }
+}
+
+/// Tracks when the interface was last used, shared between all handles and the keep-alive
+/// thread.
+#[derive(Debug)]
+struct Activity {
+    last_access: Mutex<Instant>,
+    /// Number of [`KeepAlivePause`] guards currently alive.
+    paused: AtomicUsize,
+    /// Number of times the keep-alive thread woke up, for tests to wait on.
+    #[cfg(test)]
+    ticks: (Mutex<usize>, std::sync::Condvar),
+}
+
+impl Activity {
+    fn new() -> Self {
+        Self {
+            last_access: Mutex::new(Instant::now()),
+            paused: AtomicUsize::new(0),
+            #[cfg(test)]
+            ticks: (Mutex::new(0), std::sync::Condvar::new()),
+        }
+    }
+
+    fn touch(&self) {
+        *self
+            .last_access
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Instant::now();
+    }
+
+    fn idle_time(&self) -> Duration {
+        self.last_access
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .elapsed()
+    }
+
+    #[cfg(test)]
+    fn tick(&self) {
+        let (ticks, ticked) = &self.ticks;
+        *ticks.lock().unwrap() += 1;
+        ticked.notify_all();
+    }
+
+    /// Blocks until the keep-alive thread woke up `count` more times.
+    #[cfg(test)]
+    fn wait_for_ticks(&self, count: usize) {
+        let (ticks, ticked) = &self.ticks;
+        let ticks = ticks.lock().unwrap();
+        let target = *ticks + count;
+        let (_ticks, timeout) = ticked
+            .wait_timeout_while(ticks, Duration::from_secs(10), |ticks| *ticks < target)
+            .unwrap();
+        assert!(!timeout.timed_out(), "The keep-alive thread is not running");
+    }
+}
+
+/// Suspends the keep-alive reads while alive, see [`SharedArmInterface::pause_keep_alive`].
+#[derive(Debug)]
+pub struct KeepAlivePause {
+    activity: Arc<Activity>,
+}
+
+impl Drop for KeepAlivePause {
+    fn drop(&mut self) {
+        // The interval starts again once the burst is over.
+        self.activity.touch();
+        self.activity.paused.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// The background thread started by [`SharedArmInterface::start_keep_alive`].
+struct KeepAlive {
+    interval: Duration,
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl KeepAlive {
+    fn spawn(
+        interface: Weak<Mutex<Box<dyn ArmDebugInterface>>>,
+        activity: Arc<Activity>,
+        interval: Duration,
+    ) -> Self {
+        let (stop, stopped) = mpsc::channel();
+
+        let thread = std::thread::Builder::new()
+            .name("probe-rs keep-alive".into())
+            .spawn(move || {
+                let mut wait = interval;
+
+                // The sender is only dropped, never used, so any result but a timeout means stop.
+                while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(wait) {
+                    let Some(next) = Self::tick(&interface, &activity, interval) else {
+                        break;
+                    };
+                    wait = next;
+
+                    #[cfg(test)]
+                    activity.tick();
+                }
+            })
+            .expect("Failed to spawn the keep-alive thread");
+
+        Self {
+            interval,
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+
+    /// Reads DPIDR if the interface is idle, and returns how long to wait for the next read.
+    ///
+    /// Returns `None` once the interface was dropped.
+    fn tick(
+        interface: &Weak<Mutex<Box<dyn ArmDebugInterface>>>,
+        activity: &Activity,
+        interval: Duration,
+    ) -> Option<Duration> {
+        if activity.paused.load(Ordering::SeqCst) > 0 {
+            return Some(interval);
+        }
+
+        let idle = activity.idle_time();
+        if idle < interval {
+            return Some(interval - idle);
+        }
+
+        let shared = interface.upgrade()?;
+        let mut locked = match shared.try_lock() {
+            Ok(locked) => locked,
+            Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+            // Someone else is using the interface, so it's not idle.
+            Err(TryLockError::WouldBlock) => return Some(interval),
+        };
+
+        let result = match locked.current_debug_port() {
+            Some(dp) => locked.read_raw_dp_register(dp, DPIDR::ADDRESS).map(drop),
+            None => Ok(()),
+        };
+        drop(locked);
+        activity.touch();
+
+        if let Err(error) = result {
+            tracing::debug!("Keep-alive read of DPIDR failed: {error}");
+        }
+
+        Some(interval)
+    }
+}
+
+impl Drop for KeepAlive {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Debug for SharedArmInterface {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SharedArmInterface")
+            .field("sequence_name", &self.sequence_name)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SwdSequence for SharedArmInterface {
+    fn swj_sequence(&mut self, bit_len: u8, bits: u64) -> Result<(), DebugProbeError> {
+        self.lock().swj_sequence(bit_len, bits)
+    }
+
+    fn swj_pins(
+        &mut self,
+        pin_out: u32,
+        pin_select: u32,
+        pin_wait: u32,
+    ) -> Result<u32, DebugProbeError> {
+        self.lock().swj_pins(pin_out, pin_select, pin_wait)
+    }
+
+    fn swd_sequence(&mut self, phases: &[SwdPhase]) -> Result<Vec<bool>, DebugProbeError> {
+        self.lock().swd_sequence(phases)
+    }
+}
+
+impl SwoAccess for SharedArmInterface {
+    fn enable_swo(&mut self, config: &SwoConfig) -> Result<(), ArmError> {
+        self.lock().enable_swo(config)
+    }
+
+    fn disable_swo(&mut self) -> Result<(), ArmError> {
+        self.lock().disable_swo()
+    }
+
+    fn read_swo(&mut self) -> Result<Vec<u8>, ArmError> {
+        self.lock().read_swo()
+    }
+
+    fn read_swo_timeout(&mut self, timeout: Duration) -> Result<Vec<u8>, ArmError> {
+        self.lock().read_swo_timeout(timeout)
+    }
+
+    fn swo_poll_interval_hint(&mut self, config: &SwoConfig) -> Option<Duration> {
+        self.lock().swo_poll_interval_hint(config)
+    }
+
+    fn swo_buffer_size(&mut self) -> Option<usize> {
+        self.lock().swo_buffer_size()
+    }
+
+    fn swo_buffer_available(&mut self) -> Result<usize, ArmError> {
+        self.lock().swo_buffer_available()
+    }
+
+    fn flush_swo(&mut self) -> Result<Vec<u8>, ArmError> {
+        self.lock().flush_swo()
+    }
+
+    fn start_swo_capture(&mut self, path: &Path) -> Result<(), ArmError> {
+        self.lock().start_swo_capture(path)
+    }
+
+    fn stop_swo_capture(&mut self) -> Result<(), ArmError> {
+        self.lock().stop_swo_capture()
+    }
+}
+
+impl DapAccess for SharedArmInterface {
+    fn read_raw_dp_register(
+        &mut self,
+        dp: DpAddress,
+        addr: DpRegisterAddress,
+    ) -> Result<u32, ArmError> {
+        self.lock().read_raw_dp_register(dp, addr)
+    }
+
+    fn write_raw_dp_register(
+        &mut self,
+        dp: DpAddress,
+        addr: DpRegisterAddress,
+        value: u32,
+    ) -> Result<(), ArmError> {
+        self.lock().write_raw_dp_register(dp, addr, value)
+    }
+
+    fn read_raw_ap_register(
+        &mut self,
+        ap: &FullyQualifiedApAddress,
+        addr: u64,
+    ) -> Result<u32, ArmError> {
+        self.lock().read_raw_ap_register(ap, addr)
+    }
+
+    fn read_raw_ap_register_repeated(
+        &mut self,
+        ap: &FullyQualifiedApAddress,
+        addr: u64,
+        values: &mut [u32],
+    ) -> Result<(), ArmError> {
+        self.lock().read_raw_ap_register_repeated(ap, addr, values)
+    }
+
+    fn write_raw_ap_register(
+        &mut self,
+        ap: &FullyQualifiedApAddress,
+        addr: u64,
+        value: u32,
+    ) -> Result<(), ArmError> {
+        self.lock().write_raw_ap_register(ap, addr, value)
+    }
+
+    fn write_raw_ap_register_repeated(
+        &mut self,
+        ap: &FullyQualifiedApAddress,
+        addr: u64,
+        values: &[u32],
+    ) -> Result<(), ArmError> {
+        self.lock().write_raw_ap_register_repeated(ap, addr, values)
+    }
+
+    fn read_mem_ap_register(
+        &mut self,
+        ap: &FullyQualifiedApAddress,
+        register: MemApRegister,
+    ) -> Result<u32, ArmError> {
+        self.lock().read_mem_ap_register(ap, register)
+    }
+
+    fn write_mem_ap_register(
+        &mut self,
+        ap: &FullyQualifiedApAddress,
+        register: MemApRegister,
+        value: u32,
+    ) -> Result<(), ArmError> {
+        self.lock().write_mem_ap_register(ap, register, value)
+    }
+
+    fn read_mem_ap_cfg(&mut self, ap: &FullyQualifiedApAddress) -> Result<MemApCfg, ArmError> {
+        self.lock().read_mem_ap_cfg(ap)
+    }
+
+    fn read_mem_ap_tar64(&mut self, ap: &FullyQualifiedApAddress) -> Result<u64, ArmError> {
+        self.lock().read_mem_ap_tar64(ap)
+    }
+
+    fn write_mem_ap_tar64(
+        &mut self,
+        ap: &FullyQualifiedApAddress,
+        address: u64,
+    ) -> Result<(), ArmError> {
+        self.lock().write_mem_ap_tar64(ap, address)
+    }
+
+    fn set_transaction_count(&mut self, dp: DpAddress, count: u16) -> Result<(), ArmError> {
+        self.lock().set_transaction_count(dp, count)
+    }
+
+    // TRNMODE must not be changed by other threads during a compare, so it is locked as a whole.
+    fn push_compare(
+        &mut self,
+        ap: &FullyQualifiedApAddress,
+        address: u64,
+        expected: u32,
+    ) -> Result<bool, ArmError> {
+        self.lock().push_compare(ap, address, expected)
+    }
+
+    fn push_compare_repeated(
+        &mut self,
+        ap: &FullyQualifiedApAddress,
+        address: u64,
+        expected: u32,
+        count: usize,
+    ) -> Result<bool, ArmError> {
+        self.lock()
+            .push_compare_repeated(ap, address, expected, count)
+    }
+
+    fn flush(&mut self) -> Result<(), ArmError> {
+        DapAccess::flush(&mut **self.lock())
+    }
+
+    fn try_dap_probe(&self) -> Option<&dyn DapProbe> {
+        None
+    }
+
+    fn try_dap_probe_mut(&mut self) -> Option<&mut dyn DapProbe> {
+        None
+    }
+}
+
+impl ArmDebugInterface for SharedArmInterface {
+    fn reinitialize(&mut self) -> Result<(), ArmError> {
+        self.lock().reinitialize()
+    }
+
+    fn resync(&mut self, dp: DpAddress) -> Result<(), ArmError> {
+        self.lock().resync(dp)
+    }
+
+    fn access_ports(
+        &mut self,
+        dp: DpAddress,
+    ) -> Result<std::collections::BTreeSet<FullyQualifiedApAddress>, ArmError> {
+        self.lock().access_ports(dp)
+    }
+
+    fn access_ports_bounded(
+        &mut self,
+        dp: DpAddress,
+        max_apsel: u8,
+        stop_after_gaps: usize,
+    ) -> Result<std::collections::BTreeSet<FullyQualifiedApAddress>, ArmError> {
+        self.lock()
+            .access_ports_bounded(dp, max_apsel, stop_after_gaps)
+    }
+
+    /// Closes the interface and returns back the generic probe it consumed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if other handles to the interface still exist.
+    fn close(self: Box<Self>) -> Probe {
+        match (*self).try_into_inner() {
+            Ok(interface) => interface.close(),
+            Err(_) => panic!("Cannot close a shared ARM interface which is still in use"),
+        }
+    }
+
+    fn select_debug_port(&mut self, dp: DpAddress) -> Result<(), ArmError> {
+        self.lock().select_debug_port(dp)
+    }
+
+    fn disconnect_gracefully(&mut self) -> Result<(), ArmError> {
+        self.lock().disconnect_gracefully()
+    }
+
+    fn current_debug_port(&self) -> Option<DpAddress> {
+        self.lock().current_debug_port()
+    }
+
+    fn known_debug_ports(&self) -> Vec<DpAddress> {
+        self.lock().known_debug_ports()
+    }
+
+    fn debug_port_version(&self, dp: DpAddress) -> Option<DebugPortVersion> {
+        self.lock().debug_port_version(dp)
+    }
+
+    fn debug_port_id(&self, dp: DpAddress) -> Option<DebugPortId> {
+        self.lock().debug_port_id(dp)
+    }
+
+    fn read_resend(&mut self, dp: DpAddress) -> Result<u32, ArmError> {
+        self.lock().read_resend(dp)
+    }
+
+    fn save_select_state(&self, dp: DpAddress) -> Option<SelectCache> {
+        self.lock().save_select_state(dp)
+    }
+
+    fn restore_select_state(&mut self, dp: DpAddress, state: SelectCache) -> Result<(), ArmError> {
+        self.lock().restore_select_state(dp, state)
+    }
+
+    fn resync_select(&mut self, dp: DpAddress) -> Result<(), ArmError> {
+        self.lock().resync_select(dp)
+    }
+
+    fn probe_info(&self) -> ProbeInfo {
+        self.lock().probe_info()
+    }
+
+    fn sequence_name(&self) -> &str {
+        &self.sequence_name
+    }
+
+    fn check_debug_lock(&mut self, dp: DpAddress) -> Result<DebugLockStatus, ArmError> {
+        self.lock().check_debug_lock(dp)
+    }
+
+    fn set_idle_cycles(&mut self, cycles: u8) -> Result<(), ArmError> {
+        self.lock().set_idle_cycles(cycles)
+    }
+
+    fn last_connect_diagnostics(&self) -> Option<ConnectDiagnostics> {
+        self.lock().last_connect_diagnostics()
+    }
+
+    fn transfer_stats(&self) -> Result<TransferStats, ArmError> {
+        self.lock().transfer_stats()
+    }
+
+    fn reset_transfer_stats(&mut self) -> Result<(), ArmError> {
+        self.lock().reset_transfer_stats()
+    }
+
+    fn take_disconnect_errors(&mut self) -> Result<Vec<(DpAddress, ArmError)>, ArmError> {
+        self.lock().take_disconnect_errors()
+    }
+
+    fn invalidate_write_cache(&mut self) {
+        self.lock().invalidate_write_cache()
+    }
+
+    fn read_target_uid(
+        &mut self,
+        ap: &FullyQualifiedApAddress,
+    ) -> Result<Option<Vec<u8>>, ArmError> {
+        self.lock().read_target_uid(ap)
+    }
+
+    fn set_wire_protocol(&mut self, protocol: WireProtocol) -> Result<(), ArmError> {
+        self.lock().set_wire_protocol(protocol)
+    }
+
+    fn memory_interface(
+        &mut self,
+        access_port: &FullyQualifiedApAddress,
+    ) -> Result<Box<dyn ArmMemoryInterface + '_>, ArmError> {
+        let memory = SharedMemoryInterface::new(self.clone(), access_port, MemoryAccess::Default)?;
+
+        Ok(Box::new(memory) as _)
+    }
+
+    fn read_word_32(
+        &mut self,
+        access_port: &FullyQualifiedApAddress,
+        address: u64,
+    ) -> Result<u32, ArmError> {
+        self.lock().read_word_32(access_port, address)
+    }
+
+    fn supports_swo(&self) -> bool {
+        self.lock().supports_swo()
+    }
+
+    fn set_swd_clock(&mut self, hz: u32) -> Result<u32, DebugProbeError> {
+        self.lock().set_swd_clock(hz)
+    }
+
+    fn memory_interface_with_csw(
+        &mut self,
+        access_port: &FullyQualifiedApAddress,
+        csw: ap::CSW,
+    ) -> Result<Box<dyn ArmMemoryInterface + '_>, ArmError> {
+        let memory = SharedMemoryInterface::new(self.clone(), access_port, MemoryAccess::Csw(csw))?;
+
+        Ok(Box::new(memory) as _)
+    }
+
+    fn supports_jtag_scan(&mut self) -> bool {
+        self.lock().supports_jtag_scan()
+    }
+
+    fn jtag_scan_ir(&mut self, bits: &[u8], len: u32) -> Result<BitVec, ArmError> {
+        self.lock().jtag_scan_ir(bits, len)
+    }
+
+    fn jtag_scan_dr(&mut self, bits: &[u8], len: u32) -> Result<BitVec, ArmError> {
+        self.lock().jtag_scan_dr(bits, len)
+    }
+
+    fn debug_reset(&mut self, dp: DpAddress) -> Result<(), ArmError> {
+        self.lock().debug_reset(dp)
+    }
+}
+
+/// How a [`SharedMemoryInterface`] opens the memory interface of the underlying interface.
+#[derive(Debug, Clone, Copy)]
+enum MemoryAccess {
+    Default,
+    Csw(ap::CSW),
+}
+
+/// A memory interface of a [`SharedArmInterface`].
+///
+/// Each access locks the shared interface, so a single block transfer is atomic, but
+/// accesses from other threads may happen between two transfers.
+#[derive(Debug)]
+pub struct SharedMemoryInterface {
+    shared: SharedArmInterface,
+    address: FullyQualifiedApAddress,
+    access: MemoryAccess,
+    address_width: AddressWidth,
+    /// The security state selected with [`ArmMemoryInterface::set_security`], which is applied
+    /// to each memory interface opened for an access. CSW is only written if another memory
+    /// interface selected a different state in the meantime.
+    security: Option<SecurityState>,
+}
+
+impl SharedMemoryInterface {
+    fn new(
+        shared: SharedArmInterface,
+        address: &FullyQualifiedApAddress,
+        access: MemoryAccess,
+    ) -> Result<Self, ArmError> {
+        let mut this = Self {
+            shared,
+            address: address.clone(),
+            access,
+            address_width: AddressWidth::Bits32,
+            security: None,
+        };
+
+        // Open the memory interface once, so that errors are reported here instead of on
+        // the first access.
+        this.address_width = this.with_memory(|memory| Ok(memory.address_width()))?;
+
+        Ok(this)
+    }
+
+    /// Locks the shared interface and runs `f` with a memory interface opened on it.
+    fn with_memory<T>(
+        &self,
+        f: impl FnOnce(&mut dyn ArmMemoryInterface) -> Result<T, ArmError>,
+    ) -> Result<T, ArmError> {
+        let mut interface = self.shared.lock();
+        let mut memory = match self.access {
+            MemoryAccess::Default => interface.memory_interface(&self.address)?,
+            MemoryAccess::Csw(csw) => interface.memory_interface_with_csw(&self.address, csw)?,
+        };
+
+        if let Some(security) = self.security {
+            memory.set_security(security)?;
+        }
+
+        f(&mut *memory)
+    }
+}
+
+impl MemoryInterface<ArmError> for SharedMemoryInterface {
+    fn supports_native_64bit_access(&mut self) -> bool {
+        self.with_memory(|memory| Ok(memory.supports_native_64bit_access()))
+            .unwrap_or(false)
+    }
+
+    fn read_64(&mut self, address: u64, data: &mut [u64]) -> Result<(), ArmError> {
+        self.with_memory(|memory| memory.read_64(address, data))
+    }
+
+    fn read_32(&mut self, address: u64, data: &mut [u32]) -> Result<(), ArmError> {
+        self.with_memory(|memory| memory.read_32(address, data))
+    }
+
+    fn read_16(&mut self, address: u64, data: &mut [u16]) -> Result<(), ArmError> {
+        self.with_memory(|memory| memory.read_16(address, data))
+    }
+
+    fn read_8(&mut self, address: u64, data: &mut [u8]) -> Result<(), ArmError> {
+        self.with_memory(|memory| memory.read_8(address, data))
+    }
+
+    fn read(&mut self, address: u64, data: &mut [u8]) -> Result<(), ArmError> {
+        self.with_memory(|memory| memory.read(address, data))
+    }
+
+    fn write_64(&mut self, address: u64, data: &[u64]) -> Result<(), ArmError> {
+        self.with_memory(|memory| memory.write_64(address, data))
+    }
+
+    fn write_32(&mut self, address: u64, data: &[u32]) -> Result<(), ArmError> {
+        self.with_memory(|memory| memory.write_32(address, data))
+    }
+
+    fn write_16(&mut self, address: u64, data: &[u16]) -> Result<(), ArmError> {
+        self.with_memory(|memory| memory.write_16(address, data))
+    }
+
+    fn write_8(&mut self, address: u64, data: &[u8]) -> Result<(), ArmError> {
+        self.with_memory(|memory| memory.write_8(address, data))
+    }
+
+    fn write(&mut self, address: u64, data: &[u8]) -> Result<(), ArmError> {
+        self.with_memory(|memory| memory.write(address, data))
+    }
+
+    fn supports_8bit_transfers(&self) -> Result<bool, ArmError> {
+        self.with_memory(|memory| memory.supports_8bit_transfers())
+    }
+
+    fn flush(&mut self) -> Result<(), ArmError> {
+        self.with_memory(|memory| memory.flush())
+    }
+}
+
+impl ArmMemoryInterface for SharedMemoryInterface {
+    fn fully_qualified_address(&self) -> FullyQualifiedApAddress {
+        self.address.clone()
+    }
+
+    fn base_address(&mut self) -> Result<u64, ArmError> {
+        self.with_memory(|memory| memory.base_address())
+    }
+
+    fn address_width(&self) -> AddressWidth {
+        self.address_width
+    }
+
+    fn read_memory_8_block(&mut self, address: u64, data: &mut [u8]) -> Result<(), ArmError> {
+        self.with_memory(|memory| memory.read_memory_8_block(address, data))
+    }
+
+    // The read-modify-write accesses happen under a single lock, so that no other thread can
+    // write the location in between.
+    fn modify_32(&mut self, address: u64, mask: u32, value: u32) -> Result<(), ArmError> {
+        self.with_memory(|memory| memory.modify_32(address, mask, value))
+    }
+
+    fn modify_16(&mut self, address: u64, mask: u16, value: u16) -> Result<(), ArmError> {
+        self.with_memory(|memory| memory.modify_16(address, mask, value))
+    }
+
+    fn modify_8(&mut self, address: u64, mask: u8, value: u8) -> Result<(), ArmError> {
+        self.with_memory(|memory| memory.modify_8(address, mask, value))
+    }
+
+    fn get_arm_debug_interface(&mut self) -> Result<&mut dyn ArmDebugInterface, DebugProbeError> {
+        Ok(&mut self.shared)
+    }
+
+    fn generic_status(&mut self) -> Result<ap::CSW, ArmError> {
+        self.with_memory(|memory| memory.generic_status())
+    }
+
+    fn set_security(&mut self, security: SecurityState) -> Result<(), ArmError> {
+        let previous = self.security.replace(security);
+
+        // Open a memory interface, so that unsupported security states are reported here.
+        let result = self.with_memory(|_| Ok(()));
+        if result.is_err() {
+            self.security = previous;
+        }
+
+        result
+    }
+
+    fn update_core_status(&mut self, state: CoreStatus) {
+        let result = self.with_memory(|memory| {
+            memory.update_core_status(state);
+            Ok(())
+        });
+
+        if let Err(error) = result {
+            tracing::debug!("Failed to update the core status: {error}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use super::*;
+    use crate::{
+        MemoryMappedRegister,
+        architecture::arm::{
+            ArmCommunicationInterfaceBuilder, RegisterAddress, core::armv7m::Dhcsr,
+            sequences::DefaultArmSequence,
+        },
+        probe::{
+            DebugProbe,
+            fake_probe::FakeProbe,
+            recording_probe::{RecordedTransfer, RecordingProbe},
+        },
+    };
+
+    #[test]
+    fn memory_accesses_from_other_threads_are_visible() {
+        let probe = Box::new(FakeProbe::with_mocked_core());
+        let interface = probe
+            .try_get_arm_debug_interface(DefaultArmSequence::create())
+            .map_err(|(_, error)| error)
+            .unwrap();
+        let mut shared = SharedArmInterface::new(interface);
+        let ap = FullyQualifiedApAddress::v1_with_default_dp(0);
+
+        let mut other = shared.clone();
+        let other_ap = ap.clone();
+        std::thread::spawn(move || {
+            let mut memory = other.memory_interface(&other_ap).unwrap();
+            // C_HALT | C_DEBUGEN
+            memory
+                .write_word_32(Dhcsr::get_mmio_address(), 0xA05F_0003)
+                .unwrap();
+        })
+        .join()
+        .unwrap();
+
+        let mut memory = shared.memory_interface(&ap).unwrap();
+        let dhcsr = memory.read_word_32(Dhcsr::get_mmio_address()).unwrap();
+        assert_ne!(dhcsr & (1 << 17), 0, "S_HALT should be set");
+
+        drop(memory);
+        assert!(shared.try_into_inner().is_ok());
+    }
+
+    #[test]
+    fn memory_ap_is_set_up_once() {
+        const IDR_AHB3: u32 = 0x2477_0011;
+        const CSW_VALUE: u32 = 0x2300_0002;
+        const WORD: u32 = 0x1234_5678;
+
+        let recorder = RecordingProbe::new([IDR_AHB3, CSW_VALUE, 0, WORD, IDR_AHB3]);
+        let interface = ArmCommunicationInterfaceBuilder::new()
+            .adopt_connected(
+                Box::new(recorder.clone()),
+                DpAddress::Default,
+                DebugPortVersion::DPv1,
+            )
+            .unwrap();
+        let mut shared = SharedArmInterface::new(interface);
+        let ap = FullyQualifiedApAddress::v1_with_default_dp(0);
+
+        let mut handle = shared.clone();
+        let mut memory = handle.memory_interface(&ap).unwrap();
+        recorder.clear_transfers();
+
+        // IDR, CSW and CFG were read when the memory interface was created, and are not read
+        // again for the access.
+        assert_eq!(memory.read_word_32(0x2000_0000).unwrap(), WORD);
+        assert_eq!(
+            recorder.transfers(),
+            [
+                RecordedTransfer::Write {
+                    address: RegisterAddress::ApRegister(0x04),
+                    value: 0x2000_0000,
+                },
+                RecordedTransfer::Read {
+                    address: RegisterAddress::ApRegister(0x0C),
+                    value: WORD,
+                },
+            ]
+        );
+
+        // The access port is set up again after a reset.
+        shared.invalidate_write_cache();
+        recorder.clear_transfers();
+        memory.read_word_32(0x2000_0000).unwrap();
+        assert!(recorder.transfers().contains(&RecordedTransfer::Read {
+            address: RegisterAddress::ApRegister(0xFC),
+            value: IDR_AHB3,
+        }));
+
+        drop(memory);
+        drop(handle);
+        assert!(shared.try_into_inner().is_ok());
+    }
+
+    #[test]
+    fn security_state_is_applied_once() {
+        const IDR_AHB3: u32 = 0x2477_0011;
+        const CSW_VALUE: u32 = 0x2300_0002;
+
+        let recorder = RecordingProbe::new([IDR_AHB3, CSW_VALUE, 0]);
+        let interface = ArmCommunicationInterfaceBuilder::new()
+            .adopt_connected(
+                Box::new(recorder.clone()),
+                DpAddress::Default,
+                DebugPortVersion::DPv1,
+            )
+            .unwrap();
+        let shared = SharedArmInterface::new(interface);
+        let ap = FullyQualifiedApAddress::v1_with_default_dp(0);
+
+        let mut handle = shared.clone();
+        let mut memory = handle.memory_interface(&ap).unwrap();
+        memory.set_security(SecurityState::Secure).unwrap();
+        recorder.clear_transfers();
+
+        memory.read_word_32(0x2000_0000).unwrap();
+        memory.read_word_32(0x2000_0004).unwrap();
+
+        // CSW already selects the secure view, and is neither read nor written again.
+        let csw_accesses = recorder
+            .transfers()
+            .into_iter()
+            .filter(|transfer| {
+                matches!(
+                    transfer,
+                    RecordedTransfer::Read {
+                        address: RegisterAddress::ApRegister(0x00),
+                        ..
+                    } | RecordedTransfer::Write {
+                        address: RegisterAddress::ApRegister(0x00),
+                        ..
+                    }
+                )
+            })
+            .count();
+        assert_eq!(csw_accesses, 0);
+
+        drop(memory);
+        drop(handle);
+        assert!(shared.try_into_inner().is_ok());
+    }
+
+    #[test]
+    fn keep_alive_reads_dpidr_while_idle() {
+        let recorder = RecordingProbe::new([]);
+        let interface = ArmCommunicationInterfaceBuilder::new()
+            .adopt_connected(
+                Box::new(recorder.clone()),
+                DpAddress::Default,
+                DebugPortVersion::DPv1,
+            )
+            .unwrap();
+        let mut shared = SharedArmInterface::new(interface);
+        recorder.clear_transfers();
+
+        let is_keep_alive = |transfer: &RecordedTransfer| {
+            matches!(
+                transfer,
+                RecordedTransfer::Read {
+                    address: RegisterAddress::DpRegister(DPIDR::ADDRESS),
+                    ..
+                }
+            )
+        };
+
+        shared.start_keep_alive(Duration::from_millis(1));
+        while !recorder.transfers().iter().any(is_keep_alive) {
+            shared.activity.wait_for_ticks(1);
+        }
+
+        let pause = shared.pause_keep_alive();
+        // Let a read which is already in progress finish.
+        shared.activity.wait_for_ticks(1);
+        recorder.clear_transfers();
+        shared.activity.wait_for_ticks(3);
+        assert!(recorder.transfers().is_empty());
+        drop(pause);
+
+        // The thread is joined, so it can't hold on to the interface during the unwrap.
+        assert!(shared.try_into_inner().is_ok());
+    }
+
+    #[test]
+    fn keep_alive_survives_a_failed_unwrap() {
+        let interface = ArmCommunicationInterfaceBuilder::new()
+            .adopt_connected(
+                Box::new(RecordingProbe::new([])),
+                DpAddress::Default,
+                DebugPortVersion::DPv1,
+            )
+            .unwrap();
+        let mut shared = SharedArmInterface::new(interface);
+        // Created before the keep-alive is started, so it doesn't share it.
+        let other = shared.clone();
+
+        shared.start_keep_alive(Duration::from_millis(1));
+        let Err(shared) = shared.try_into_inner() else {
+            panic!("the interface is still shared");
+        };
+        shared.activity.wait_for_ticks(1);
+
+        drop(shared);
+        assert!(other.try_into_inner().is_ok());
+    }
+}
@@ -0,0 +1,134 @@
+//! Capturing raw SWO data into a file, for offline analysis.
+//!
+//! A capture file starts with the 8 byte magic [`CAPTURE_MAGIC`], followed by one record per
+//! chunk of SWO data read from the probe. Each record consists of
+//!
+//! - the time since the capture was started, in microseconds, as little endian `u64`,
+//! - the number of data bytes, as little endian `u32`,
+//! - the raw SWO bytes.
+//!
+//! Timestamps are monotonic, so external ITM decoders can reconstruct the timing of the trace.
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Read, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+/// The magic at the start of every SWO capture file.
+pub const CAPTURE_MAGIC: [u8; 8] = *b"PRSWO\0\0\x01";
+
+/// Writes raw SWO data into a capture file.
+#[derive(Debug)]
+pub struct SwoCapture<W: Write = BufWriter<File>> {
+    writer: W,
+    started: Instant,
+}
+
+impl SwoCapture {
+    /// Creates the capture file at `path`, replacing any existing file.
+    pub fn create(path: &Path) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> SwoCapture<W> {
+    /// Starts a capture which is written to `writer`.
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        writer.write_all(&CAPTURE_MAGIC)?;
+
+        Ok(Self {
+            writer,
+            started: Instant::now(),
+        })
+    }
+
+    /// Appends a record with `data`, timestamped with the current time.
+    ///
+    /// Nothing is written for empty reads.
+    pub fn record(&mut self, data: &[u8]) -> io::Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        let timestamp = self.started.elapsed().as_micros() as u64;
+        let len = u32::try_from(data.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "SWO read too large"))?;
+
+        self.writer.write_all(&timestamp.to_le_bytes())?;
+        self.writer.write_all(&len.to_le_bytes())?;
+        self.writer.write_all(data)
+    }
+
+    /// Writes all buffered records, and returns the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.flush()?;
+
+        Ok(self.writer)
+    }
+}
+
+/// A record read back from a capture file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SwoCaptureRecord {
+    /// The time since the capture was started.
+    pub timestamp: Duration,
+    /// The raw SWO bytes.
+    pub data: Vec<u8>,
+}
+
+/// Reads all records of a capture file.
+pub fn read_capture(mut reader: impl Read) -> io::Result<Vec<SwoCaptureRecord>> {
+    let mut magic = [0; 8];
+    reader.read_exact(&mut magic)?;
+    if magic != CAPTURE_MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a SWO capture file",
+        ));
+    }
+
+    let mut records = Vec::new();
+    loop {
+        let mut timestamp = [0; 8];
+        match reader.read_exact(&mut timestamp) {
+            Ok(()) => {}
+            Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(error) => return Err(error),
+        }
+
+        let mut len = [0; 4];
+        reader.read_exact(&mut len)?;
+
+        let mut data = vec![0; u32::from_le_bytes(len) as usize];
+        reader.read_exact(&mut data)?;
+
+        records.push(SwoCaptureRecord {
+            timestamp: Duration::from_micros(u64::from_le_bytes(timestamp)),
+            data,
+        });
+    }
+
+    Ok(records)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{SwoCapture, read_capture};
+
+    #[test]
+    fn capture_can_be_read_back() {
+        let mut capture = SwoCapture::new(Vec::new()).unwrap();
+        capture.record(&[0x01, 0x02]).unwrap();
+        capture.record(&[]).unwrap();
+        capture.record(&[0x03]).unwrap();
+        let file = capture.finish().unwrap();
+
+        let records = read_capture(file.as_slice()).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].data, [0x01, 0x02]);
+        assert_eq!(records[1].data, [0x03]);
+        assert!(records[0].timestamp <= records[1].timestamp);
+    }
+}
@@ -0,0 +1,303 @@
+//! Decoding of ITM packets from SWO data.
+//!
+//! The packet format is described in the ARMv7-M Architecture Reference Manual, appendix D4.
+//! Only the packets most useful for software tracing are decoded: instrumentation (stimulus
+//! port) packets, hardware source packets, local timestamps, overflow and synchronization
+//! packets. Extension and global timestamp packets are skipped.
+
+/// The relation between a local timestamp and the packet it belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimestampRelation {
+    /// The timestamp was generated in sync with the data.
+    Synchronous,
+    /// The timestamp was delayed relative to the data.
+    TimestampDelayed,
+    /// The data was delayed relative to the timestamp.
+    DataDelayed,
+    /// Both the timestamp and the data were delayed.
+    BothDelayed,
+}
+
+/// A packet decoded by an [`ItmDecoder`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ItmPacket {
+    /// A synchronization packet.
+    Sync,
+    /// The ITM dropped packets because its FIFO overflowed.
+    Overflow,
+    /// A write to a stimulus port.
+    Instrumentation {
+        /// The number of the stimulus port.
+        port: u8,
+        /// The written data, 1, 2 or 4 bytes in little endian order.
+        payload: Vec<u8>,
+    },
+    /// A packet generated by the DWT, e.g. for a data trace or PC sample.
+    HardwareSource {
+        /// The discriminator, which identifies the kind of packet.
+        discriminator: u8,
+        /// The packet data, 1, 2 or 4 bytes in little endian order.
+        payload: Vec<u8>,
+    },
+    /// A local timestamp.
+    LocalTimestamp {
+        /// The number of timestamp clock cycles since the previous local timestamp.
+        delta: u32,
+        /// How the timestamp relates to the packet it follows.
+        relation: TimestampRelation,
+    },
+}
+
+/// A periodic PC sample, taken by the DWT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PcSample {
+    /// The program counter of the core when the sample was taken.
+    Pc(u32),
+    /// The core was sleeping when the sample was taken.
+    Sleep,
+}
+
+impl ItmPacket {
+    /// Returns the PC sample carried by this packet, or `None` if this isn't a periodic PC
+    /// sample packet.
+    pub fn pc_sample(&self) -> Option<PcSample> {
+        match self {
+            // Periodic PC samples use discriminator 2, with a 4 byte PC or a single zero byte
+            // while the core is sleeping.
+            ItmPacket::HardwareSource {
+                discriminator: 2,
+                payload,
+            } => match payload.as_slice() {
+                [a, b, c, d] => Some(PcSample::Pc(u32::from_le_bytes([*a, *b, *c, *d]))),
+                [0] => Some(PcSample::Sleep),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// The result of decoding the data at the start of the buffer.
+enum Step {
+    Packet(ItmPacket, usize),
+    Skip(usize),
+    Incomplete,
+}
+
+/// Decodes ITM packets from raw SWO data.
+///
+/// SWO data is read in chunks which don't respect packet boundaries, e.g. with
+/// [`SwoAccess::read_swo_timeout`](super::SwoAccess::read_swo_timeout) or a
+/// [`SwoReader`](super::SwoReader). The decoder buffers incomplete packets until the rest of
+/// their data is passed to [`ItmDecoder::decode`].
+#[derive(Debug, Default)]
+pub struct ItmDecoder {
+    buf: Vec<u8>,
+    /// Number of zero bytes seen since the last packet, which may be the start of a
+    /// synchronization packet.
+    sync_zeros: usize,
+}
+
+impl ItmDecoder {
+    /// Creates a decoder which has not seen any data yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decodes `data`, and returns all packets which are complete now.
+    ///
+    /// Malformed packets are skipped.
+    pub fn decode(&mut self, data: &[u8]) -> Vec<ItmPacket> {
+        self.buf.extend_from_slice(data);
+
+        let mut packets = Vec::new();
+        let mut position = 0;
+
+        while position < self.buf.len() {
+            let byte = self.buf[position];
+
+            if byte == 0x00 {
+                self.sync_zeros += 1;
+                position += 1;
+                continue;
+            }
+
+            // A synchronization packet is at least 47 zero bits, followed by a one bit.
+            if self.sync_zeros > 0 && byte == 0x80 {
+                if self.sync_zeros >= 5 {
+                    packets.push(ItmPacket::Sync);
+                }
+                self.sync_zeros = 0;
+                position += 1;
+                continue;
+            }
+            self.sync_zeros = 0;
+
+            match decode_packet(&self.buf[position..]) {
+                Step::Packet(packet, len) => {
+                    packets.push(packet);
+                    position += len;
+                }
+                Step::Skip(len) => {
+                    tracing::trace!("Skipping ITM packet with header {byte:#04x}");
+                    position += len;
+                }
+                Step::Incomplete => break,
+            }
+        }
+
+        self.buf.drain(..position);
+
+        packets
+    }
+
+    /// Returns the number of bytes of incomplete packets which are buffered.
+    pub fn pending(&self) -> usize {
+        self.buf.len()
+    }
+}
+
+/// Decodes the packet at the start of `data`, which must not start with a zero byte.
+fn decode_packet(data: &[u8]) -> Step {
+    let header = data[0];
+
+    match header {
+        0x70 => Step::Packet(ItmPacket::Overflow, 1),
+        // Local timestamp, format 2: the timestamp is part of the header.
+        _ if header & 0x8F == 0x00 => Step::Packet(
+            ItmPacket::LocalTimestamp {
+                delta: u32::from(header >> 4),
+                relation: TimestampRelation::Synchronous,
+            },
+            1,
+        ),
+        // Local timestamp, format 1: up to 4 payload bytes follow.
+        _ if header & 0xCF == 0xC0 => {
+            let Some(len) = continuation_len(&data[1..]) else {
+                return Step::Incomplete;
+            };
+            if len > 4 {
+                return Step::Skip(1 + len);
+            }
+
+            let delta = data[1..=len]
+                .iter()
+                .enumerate()
+                .fold(0, |delta, (i, byte)| {
+                    delta | (u32::from(byte & 0x7F) << (7 * i))
+                });
+            let relation = match (header >> 4) & 0b11 {
+                0b00 => TimestampRelation::Synchronous,
+                0b01 => TimestampRelation::TimestampDelayed,
+                0b10 => TimestampRelation::DataDelayed,
+                _ => TimestampRelation::BothDelayed,
+            };
+
+            Step::Packet(ItmPacket::LocalTimestamp { delta, relation }, 1 + len)
+        }
+        // Extension packets and global timestamps, which may be followed by payload bytes.
+        _ if header & 0x0B == 0x08 || header == 0x94 || header == 0xB4 => {
+            if header & 0x80 == 0 {
+                return Step::Skip(1);
+            }
+
+            match continuation_len(&data[1..]) {
+                Some(len) => Step::Skip(1 + len),
+                None => Step::Incomplete,
+            }
+        }
+        // Source packets, with a 1, 2 or 4 byte payload.
+        _ if header & 0x03 != 0 => {
+            let size = match header & 0x03 {
+                1 => 1,
+                2 => 2,
+                _ => 4,
+            };
+            if data.len() < 1 + size {
+                return Step::Incomplete;
+            }
+
+            let payload = data[1..=size].to_vec();
+            let packet = if header & 0x04 == 0 {
+                ItmPacket::Instrumentation {
+                    port: header >> 3,
+                    payload,
+                }
+            } else {
+                ItmPacket::HardwareSource {
+                    discriminator: header >> 3,
+                    payload,
+                }
+            };
+
+            Step::Packet(packet, 1 + size)
+        }
+        // Reserved headers.
+        _ => Step::Skip(1),
+    }
+}
+
+/// Returns the number of payload bytes in `data`, where every byte but the last has the
+/// continuation bit set, or `None` if the last payload byte is missing.
+fn continuation_len(data: &[u8]) -> Option<usize> {
+    data.iter()
+        .position(|byte| byte & 0x80 == 0)
+        .map(|last| last + 1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ItmDecoder, ItmPacket, PcSample, TimestampRelation};
+
+    #[test]
+    fn packets_are_decoded_across_reads() {
+        let mut decoder = ItmDecoder::new();
+
+        // Sync, then a 4 byte write to stimulus port 1, split over two reads.
+        let packets = decoder.decode(&[0x00, 0x00, 0x00, 0x00, 0x00, 0x80, 0x0B, 0x01, 0x02]);
+        assert_eq!(packets, [ItmPacket::Sync]);
+        assert_eq!(decoder.pending(), 3);
+
+        // The rest of the write, an overflow, and a local timestamp with a 2 byte payload.
+        let packets = decoder.decode(&[0x03, 0x04, 0x70, 0xD0, 0x85]);
+        assert_eq!(
+            packets,
+            [
+                ItmPacket::Instrumentation {
+                    port: 1,
+                    payload: vec![0x01, 0x02, 0x03, 0x04],
+                },
+                ItmPacket::Overflow,
+            ]
+        );
+
+        let packets = decoder.decode(&[0x01, 0x30]);
+        assert_eq!(
+            packets,
+            [
+                ItmPacket::LocalTimestamp {
+                    delta: 0x85,
+                    relation: TimestampRelation::TimestampDelayed,
+                },
+                ItmPacket::LocalTimestamp {
+                    delta: 3,
+                    relation: TimestampRelation::Synchronous,
+                },
+            ]
+        );
+        assert_eq!(decoder.pending(), 0);
+    }
+
+    #[test]
+    fn pc_samples_are_extracted() {
+        let mut decoder = ItmDecoder::new();
+
+        // A PC sample, a sleep sample, and a write to stimulus port 2, which is no sample.
+        let packets = decoder.decode(&[0x17, 0x78, 0x56, 0x34, 0x12, 0x15, 0x00, 0x11, 0x41]);
+        let samples = packets.iter().map(ItmPacket::pc_sample).collect::<Vec<_>>();
+        assert_eq!(
+            samples,
+            [Some(PcSample::Pc(0x1234_5678)), Some(PcSample::Sleep), None]
+        );
+    }
+}
@@ -1,11 +1,14 @@
 //! SWO tracing related functions.
 
-use std::time::Duration;
+use std::{path::Path, time::Duration};
 
 use crate::architecture::arm::communication_interface::ArmDebugInterface;
 
 use super::ArmError;
 
+pub mod capture;
+pub mod itm;
+
 /// The protocol the SWO pin should use for data transmission.
 #[derive(Debug, Copy, Clone)]
 pub enum SwoMode {
@@ -149,6 +152,96 @@ pub trait SwoAccess {
     fn swo_buffer_size(&mut self) -> Option<usize> {
         None
     }
+
+    /// Returns the number of SWO bytes buffered in the probe, which were not read yet.
+    ///
+    /// Probes which can't report this return 0.
+    fn swo_buffer_available(&mut self) -> Result<usize, ArmError> {
+        Ok(0)
+    }
+
+    /// Reads all SWO data which is still buffered in the probe.
+    ///
+    /// Call this before `disable_swo` to avoid losing the end of a trace capture.
+    ///
+    /// The default implementation reads any available data using `read_swo`.
+    fn flush_swo(&mut self) -> Result<Vec<u8>, ArmError> {
+        self.read_swo()
+    }
+
+    /// Starts writing all SWO data read from now on into the file at `path`, in addition to
+    /// returning it.
+    ///
+    /// The file format is described in [`capture`]. A capture which is already running is
+    /// stopped first.
+    fn start_swo_capture(&mut self, _path: &Path) -> Result<(), ArmError> {
+        Err(ArmError::NotImplemented("start_swo_capture"))
+    }
+
+    /// Stops the capture started with [`SwoAccess::start_swo_capture`], and writes the
+    /// remaining data to the file.
+    ///
+    /// Does nothing if no capture is running.
+    fn stop_swo_capture(&mut self) -> Result<(), ArmError> {
+        Ok(())
+    }
+}
+
+/// The number of TRACEDATA pins used for parallel trace.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TracePortWidth {
+    /// TRACEDATA\[0\]
+    Bits1,
+    /// TRACEDATA\[1:0\]
+    Bits2,
+    /// TRACEDATA\[3:0\]
+    Bits4,
+}
+
+impl TracePortWidth {
+    /// The number of data pins.
+    pub fn bits(self) -> u32 {
+        match self {
+            TracePortWidth::Bits1 => 1,
+            TracePortWidth::Bits2 => 2,
+            TracePortWidth::Bits4 => 4,
+        }
+    }
+}
+
+/// The config for parallel trace over the TRACEDATA pins.
+#[derive(Debug, Copy, Clone)]
+pub struct ParallelTraceConfig {
+    /// The number of data pins.
+    port_width: TracePortWidth,
+}
+
+impl ParallelTraceConfig {
+    /// Create a new ParallelTraceConfig.
+    ///
+    /// By default all four data pins are used.
+    pub fn new() -> Self {
+        ParallelTraceConfig {
+            port_width: TracePortWidth::Bits4,
+        }
+    }
+
+    /// Set the number of data pins.
+    pub fn set_port_width(mut self, port_width: TracePortWidth) -> Self {
+        self.port_width = port_width;
+        self
+    }
+
+    /// The number of data pins.
+    pub fn port_width(&self) -> TracePortWidth {
+        self.port_width
+    }
+}
+
+impl Default for ParallelTraceConfig {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 /// Helper function to compute a poll interval from a SwoConfig and SWO buffer size.
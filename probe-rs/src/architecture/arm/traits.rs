@@ -1,16 +1,38 @@
 use crate::{
     CoreStatus,
-    probe::{DebugProbe, DebugProbeError},
+    probe::{DebugProbe, DebugProbeError, WireProtocol},
 };
 
 use super::{
     ArmError,
-    communication_interface::DapProbe,
-    dp::{DpAddress, DpRegisterAddress},
+    ap::{CFG, MemApCfg, MemApRegister},
+    communication_interface::{DapProbe, SwdPhase},
+    dp::{Abort, Ctrl, DpAccess, DpAddress, DpRegister, DpRegisterAddress},
 };
 
 pub(crate) mod polyfill;
 
+/// The SIZE and AddrInc fields of CSW.
+const CSW_SIZE_ADDRINC_MASK: u32 = 0x37;
+/// The SIZE field of CSW.
+pub(crate) const CSW_SIZE_MASK: u32 = 0x7;
+/// CSW.SIZE for 32 bit transfers, with AddrInc off.
+pub(crate) const CSW_SIZE_32BIT: u32 = 0b010;
+/// The sticky flags of CTRL/STAT, which are write-one-to-clear on DPv0.
+const CTRL_STICKY_FLAGS: u32 = (1 << 7) | (1 << 5) | CTRL_STICKYCMP | (1 << 1);
+/// CTRL/STAT.STICKYCMP, which is set when a pushed-compare transfer doesn't match.
+const CTRL_STICKYCMP: u32 = 1 << 4;
+/// CSW.AddrInc for single auto-increment.
+const CSW_ADDRINC_SINGLE: u32 = 0b01 << 4;
+/// TAR is only guaranteed to auto-increment within blocks of this size.
+const TAR_AUTOINCREMENT_BLOCK: u64 = 0x400;
+/// The largest value CTRL/STAT.TRNCNT can hold.
+const TRNCNT_MAX: u16 = 0xFFF;
+/// CTRL/STAT.TRNMODE for normal operation.
+const TRNMODE_NORMAL: u8 = 0b00;
+/// CTRL/STAT.TRNMODE for pushed-compare operation.
+const TRNMODE_PUSHED_COMPARE: u8 = 0b10;
+
 /// Specifies the address of register to access in a debug or access port.
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum RegisterAddress {
@@ -100,7 +122,7 @@ impl ApV2Address {
     }
 
     /// Create a new ApV2 address at `base` within the DP root memory space.
-    pub fn new(base: u64) -> Self {
+    pub const fn new(base: u64) -> Self {
         Self(Some(base))
     }
 }
@@ -146,6 +168,20 @@ pub struct FullyQualifiedApAddress {
 }
 
 impl FullyQualifiedApAddress {
+    /// Create the address of the APv1 with the number `apsel` on the debug port `dp`.
+    ///
+    /// APv1 addresses can only be used with debug ports before DPv3.
+    pub const fn v1(dp: DpAddress, apsel: u8) -> Self {
+        Self::v1_with_dp(dp, apsel)
+    }
+
+    /// Create the address of the APv2 at `base` in the root memory space of the debug port `dp`.
+    ///
+    /// APv2 addresses can only be used with DPv3 debug ports.
+    pub const fn v2(dp: DpAddress, base: u64) -> Self {
+        Self::v2_with_dp(dp, ApV2Address::new(base))
+    }
+
     /// Create a new `FullyQualifiedApAddress` belonging to the default debug port.
     pub const fn v1_with_default_dp(ap: u8) -> Self {
         Self {
@@ -267,6 +303,18 @@ pub trait RawDapAccess {
         Ok(())
     }
 
+    /// Sets the number of idle clock cycles which the probe appends after each SWD transfer.
+    ///
+    /// Some targets with slow internal logic need extra idle cycles, or they intermittently don't
+    /// acknowledge the next transfer. Every idle cycle adds one clock period to each transfer, so
+    /// this reduces throughput. The default is 0.
+    fn set_swd_idle_cycles(&mut self, _cycles: u8) -> Result<(), ArmError> {
+        Err(DebugProbeError::CommandNotSupportedByProbe {
+            command_name: "set_swd_idle_cycles",
+        }
+        .into())
+    }
+
     /// Configures the probe for JTAG use (specifying IR lengths of each DAP).
     fn configure_jtag(&mut self, _skip_scan: bool) -> Result<(), DebugProbeError> {
         Ok(())
@@ -284,6 +332,16 @@ pub trait RawDapAccess {
     /// the initial reset sequence, for example.
     fn swj_sequence(&mut self, bit_len: u8, bits: u64) -> Result<(), DebugProbeError>;
 
+    /// Send a sequence over SWD, in which SWDIO is driven or captured depending on the phase.
+    ///
+    /// Returns the bits captured in all input phases, see
+    /// [`SwdSequence::swd_sequence`](super::communication_interface::SwdSequence::swd_sequence).
+    fn swd_sequence(&mut self, _phases: &[SwdPhase]) -> Result<Vec<bool>, DebugProbeError> {
+        Err(DebugProbeError::NotImplemented {
+            function_name: "swd_sequence",
+        })
+    }
+
     /// Set the state of debugger output pins directly.
     ///
     /// The bits have the following meaning:
@@ -378,6 +436,25 @@ pub trait DapAccess {
         Ok(())
     }
 
+    /// Reads the same Access Port register `count` times, pipelining the reads.
+    ///
+    /// On the wire, the result of an AP read is only returned by the following transfer.
+    /// Instead of reading RDBUFF after every single read, the reads are issued back to back
+    /// and only the result of the last one is fetched from RDBUFF. The returned values are
+    /// already in the order of the reads.
+    ///
+    /// Probes which don't support batched transfers fall back to individual reads.
+    fn read_ap_pipelined(
+        &mut self,
+        ap: &FullyQualifiedApAddress,
+        addr: u64,
+        count: usize,
+    ) -> Result<Vec<u32>, ArmError> {
+        let mut values = vec![0; count];
+        self.read_raw_ap_register_repeated(ap, addr, &mut values)?;
+        Ok(values)
+    }
+
     /// Write an AP register.
     ///
     /// # Note
@@ -414,6 +491,201 @@ pub trait DapAccess {
         Ok(())
     }
 
+    /// Read a register of a memory access port by name.
+    ///
+    /// This selects the register bank as necessary, like [`DapAccess::read_raw_ap_register`].
+    fn read_mem_ap_register(
+        &mut self,
+        ap: &FullyQualifiedApAddress,
+        register: MemApRegister,
+    ) -> Result<u32, ArmError> {
+        self.read_raw_ap_register(ap, register.address())
+    }
+
+    /// Write a register of a memory access port by name.
+    ///
+    /// This selects the register bank as necessary, like [`DapAccess::write_raw_ap_register`].
+    fn write_mem_ap_register(
+        &mut self,
+        ap: &FullyQualifiedApAddress,
+        register: MemApRegister,
+        value: u32,
+    ) -> Result<(), ArmError> {
+        self.write_raw_ap_register(ap, register.address(), value)
+    }
+
+    /// Read the CFG register of a memory access port, which tells which extensions it implements.
+    ///
+    /// This should be checked before using 64-bit transfers or addresses, as memory APs without
+    /// the respective extension ignore them. CFG is read-only, so implementations may return the
+    /// value read when the memory AP was set up.
+    fn read_mem_ap_cfg(&mut self, ap: &FullyQualifiedApAddress) -> Result<MemApCfg, ArmError> {
+        let cfg = CFG::try_from(self.read_mem_ap_register(ap, MemApRegister::Cfg)?)?;
+
+        Ok(cfg.into())
+    }
+
+    /// Read the 64-bit transfer address of a memory access port, combining TAR2 and TAR.
+    ///
+    /// TAR2 is only implemented by memory APs with the large address extension; on other APs
+    /// it reads as zero.
+    fn read_mem_ap_tar64(&mut self, ap: &FullyQualifiedApAddress) -> Result<u64, ArmError> {
+        let upper = self.read_mem_ap_register(ap, MemApRegister::Tar2)?;
+        let lower = self.read_mem_ap_register(ap, MemApRegister::Tar)?;
+
+        Ok((u64::from(upper) << 32) | u64::from(lower))
+    }
+
+    /// Write the 64-bit transfer address of a memory access port, splitting it into TAR2 and TAR.
+    ///
+    /// TAR2 is ignored by memory APs without the large address extension, so only 32-bit
+    /// addresses can be used with those. For larger addresses, CFG is checked first, and
+    /// [`ArmError::AddressOutOfRange`] is returned if the extension is not implemented.
+    fn write_mem_ap_tar64(
+        &mut self,
+        ap: &FullyQualifiedApAddress,
+        address: u64,
+    ) -> Result<(), ArmError> {
+        if address > u64::from(u32::MAX) && !self.read_mem_ap_cfg(ap)?.long_address {
+            return Err(ArmError::AddressOutOfRange(address));
+        }
+
+        self.write_mem_ap_register(ap, MemApRegister::Tar2, (address >> 32) as u32)?;
+        self.write_mem_ap_register(ap, MemApRegister::Tar, address as u32)
+    }
+
+    /// Sets CTRL/STAT.TRNCNT of the debug port `dp`, the number of times a pushed operation is
+    /// repeated.
+    ///
+    /// TRNCNT only has 12 bits, so [`ArmError::TransactionCountOutOfRange`] is returned for larger
+    /// counts. It is IMPLEMENTATION DEFINED whether TRNCNT is implemented, and it is not
+    /// supported by debug ports implementing MINDP. Set it back to 0 afterwards, so normal
+    /// transfers aren't repeated.
+    fn set_transaction_count(&mut self, dp: DpAddress, count: u16) -> Result<(), ArmError> {
+        if count > TRNCNT_MAX {
+            return Err(ArmError::TransactionCountOutOfRange(count));
+        }
+
+        // Writing the sticky flags back would clear them on DPv0.
+        let ctrl = self.read_raw_dp_register(dp, Ctrl::ADDRESS)? & !CTRL_STICKY_FLAGS;
+        let mut ctrl = Ctrl::try_from(ctrl)?;
+        ctrl.set_trn_cnt(count);
+
+        self.write_dp_register(dp, ctrl)
+    }
+
+    /// Compare the word at `address` with `expected` using a pushed-compare transfer, and
+    /// return whether they match.
+    ///
+    /// The debug port does the comparison itself, so no data has to be read back. This is much
+    /// faster than reading and comparing when checking large regions, e.g. to verify flash.
+    /// Pushed-compare is optional, it is not supported by debug ports implementing MINDP.
+    ///
+    /// CSW and CTRL/STAT.TRNMODE are restored afterwards, and STICKYCMP is cleared.
+    fn push_compare(
+        &mut self,
+        ap: &FullyQualifiedApAddress,
+        address: u64,
+        expected: u32,
+    ) -> Result<bool, ArmError> {
+        self.push_compare_repeated(ap, address, expected, 1)
+    }
+
+    /// Compare the `count` words starting at `address` with `expected`, and return whether all
+    /// of them match.
+    ///
+    /// Like [`DapAccess::push_compare`], but the transfer is repeated by the debug port using
+    /// CTRL/STAT.TRNCNT, so a whole block is checked with a single write and a single check of
+    /// STICKYCMP. This is e.g. useful to check that flash was erased. The block is split at
+    /// 1 KiB boundaries, as TAR is only guaranteed to auto-increment within those.
+    ///
+    /// CSW, CTRL/STAT.TRNMODE and CTRL/STAT.TRNCNT are restored afterwards, and STICKYCMP is
+    /// cleared.
+    fn push_compare_repeated(
+        &mut self,
+        ap: &FullyQualifiedApAddress,
+        address: u64,
+        expected: u32,
+        count: usize,
+    ) -> Result<bool, ArmError> {
+        if address % 4 != 0 {
+            return Err(ArmError::alignment_error(address, 4));
+        }
+        if count == 0 {
+            return Ok(true);
+        }
+
+        let dp = ap.dp();
+
+        let csw = self.read_mem_ap_register(ap, MemApRegister::Csw)?;
+        self.write_mem_ap_register(
+            ap,
+            MemApRegister::Csw,
+            (csw & !CSW_SIZE_ADDRINC_MASK) | CSW_SIZE_32BIT | CSW_ADDRINC_SINGLE,
+        )?;
+
+        // Writing the sticky flags back would clear them on DPv0.
+        let ctrl = self.read_raw_dp_register(dp, Ctrl::ADDRESS)? & !CTRL_STICKY_FLAGS;
+        let mut normal_ctrl = Ctrl::try_from(ctrl)?;
+        normal_ctrl.set_trn_mode(TRNMODE_NORMAL);
+        normal_ctrl.set_trn_cnt(0);
+
+        let compare = |this: &mut Self| -> Result<bool, ArmError> {
+            let mut address = address;
+            let mut remaining = count;
+
+            while remaining > 0 {
+                let to_boundary =
+                    (TAR_AUTOINCREMENT_BLOCK - (address % TAR_AUTOINCREMENT_BLOCK)) / 4;
+                let words = remaining.min(to_boundary as usize);
+
+                this.write_mem_ap_tar64(ap, address)?;
+
+                let mut compare_ctrl = normal_ctrl.clone();
+                compare_ctrl.set_trn_mode(TRNMODE_PUSHED_COMPARE);
+                // The transfer is performed once, and then repeated TRNCNT times.
+                compare_ctrl.set_trn_cnt((words - 1) as u16);
+                this.write_dp_register(dp, compare_ctrl)?;
+
+                // In pushed-compare mode, the write to DRW reads the words and compares them instead.
+                this.write_mem_ap_register(ap, MemApRegister::Drw, expected)?;
+
+                // STICKYCMP is set on a mismatch.
+                if this.read_dp_register::<Ctrl>(dp)?.stick_cmp() {
+                    return Ok(false);
+                }
+
+                address = address.wrapping_add(words as u64 * 4);
+                remaining -= words;
+            }
+
+            Ok(true)
+        };
+        let result = compare(self);
+
+        // Return to normal operation even if the compare failed.
+        self.write_dp_register(dp, normal_ctrl.clone())?;
+        self.write_mem_ap_register(ap, MemApRegister::Csw, csw)?;
+
+        let matches = result?;
+        if !matches {
+            let protocol = self
+                .try_dap_probe()
+                .and_then(|probe| probe.active_protocol());
+            if protocol == Some(WireProtocol::Jtag) {
+                // ABORT.STKCMPCLR is reserved on JTAG-DPs, STICKYCMP is cleared by writing 1 to it.
+                let ctrl = u32::from(normal_ctrl) | CTRL_STICKYCMP;
+                self.write_raw_dp_register(dp, Ctrl::ADDRESS, ctrl)?;
+            } else {
+                let mut abort = Abort::default();
+                abort.set_stkcmpclr(true);
+                self.write_dp_register(dp, abort)?;
+            }
+        }
+
+        Ok(matches)
+    }
+
     /// Flush any outstanding operations.
     ///
     /// For performance, debug probe implementations may choose to batch writes;
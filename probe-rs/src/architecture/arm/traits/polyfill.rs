@@ -1609,6 +1609,27 @@ mod test {
         assert_eq!(result, read_value);
     }
 
+    #[test]
+    fn read_block_is_pipelined() {
+        let read_values = [0x11, 0x22, 0x33];
+
+        let mut mock = MockJaylink::new();
+
+        // The first read returns the (stale) result of the previous AP read, every following
+        // read returns the result of the one before it. The last value is read from RDBUFF.
+        mock.add_read_response(DapAcknowledge::Ok, 0);
+        for value in read_values {
+            mock.add_read_response(DapAcknowledge::Ok, value);
+        }
+        mock.add_idle_cycles(mock.swd_settings.idle_cycles_after_transfer);
+
+        let mut result = [0; 3];
+        mock.raw_read_block(ApAddress::V1(0xC).into(), &mut result)
+            .unwrap();
+
+        assert_eq!(result, read_values);
+    }
+
     #[test]
     fn read_register_jtag() {
         let read_value = 12;
@@ -5,14 +5,18 @@ pub(crate) mod usb_util;
 pub mod blackmagic;
 pub mod ch347usbjtag;
 pub mod cmsisdap;
+#[cfg(any(test, feature = "test"))]
+pub mod connect_capture;
 pub mod espusbjtag;
 pub mod fake_probe;
 pub mod ftdi;
 pub mod glasgow;
 pub mod jlink;
 pub mod list;
+pub mod recording_probe;
 pub mod sifliuart;
 pub mod stlink;
+pub mod transfer_log;
 pub mod wlink;
 
 use crate::architecture::arm::sequences::{ArmDebugSequence, DefaultArmSequence};
@@ -634,6 +638,36 @@ pub trait ProbeFactory: std::any::Any + std::fmt::Display + std::fmt::Debug + Sy
     }
 }
 
+/// Optional features of a debug probe, as reported by [`DebugProbe::probe_info`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ProbeCapabilities {
+    /// The probe can capture SWO trace data.
+    pub swo: bool,
+    /// The probe supports the JTAG wire protocol.
+    pub jtag: bool,
+    /// The probe can select targets on a SWD multidrop bus.
+    pub swd_multidrop: bool,
+    /// The probe can perform multiple DAP transfers in a single command.
+    pub block_transfer: bool,
+}
+
+/// Information about an opened debug probe, as returned by [`DebugProbe::probe_info`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ProbeInfo {
+    /// The human readable name of the probe.
+    pub name: String,
+    /// The USB vendor ID of the probe, if known.
+    pub vendor_id: Option<u16>,
+    /// The USB product ID of the probe, if known.
+    pub product_id: Option<u16>,
+    /// The serial number of the probe, if known.
+    pub serial_number: Option<String>,
+    /// The firmware version reported by the probe, if known.
+    pub firmware_version: Option<String>,
+    /// The optional features supported by the probe.
+    pub capabilities: ProbeCapabilities,
+}
+
 /// An abstraction over a general debug probe.
 ///
 /// This trait has to be implemented by ever debug probe driver.
@@ -690,6 +724,13 @@ pub trait DebugProbe: Any + Send + fmt::Debug {
     /// Get the transport protocol currently in active use by the debug probe.
     fn active_protocol(&self) -> Option<WireProtocol>;
 
+    /// Check if the probe can use the given transport protocol, without selecting it.
+    ///
+    /// Probes which can only tell by selecting the protocol return `true`.
+    fn supports_protocol(&self, _protocol: WireProtocol) -> bool {
+        true
+    }
+
     /// Check if the probe offers an interface to debug ARM chips.
     fn has_arm_interface(&self) -> bool {
         false
@@ -780,6 +821,20 @@ pub trait DebugProbe: Any + Send + fmt::Debug {
     fn get_target_voltage(&mut self) -> Result<Option<f32>, DebugProbeError> {
         Ok(None)
     }
+
+    /// Returns information about the probe, such as its firmware version and capabilities.
+    ///
+    /// The default implementation only reports the name and SWO support.
+    fn probe_info(&self) -> ProbeInfo {
+        ProbeInfo {
+            name: self.get_name().to_string(),
+            capabilities: ProbeCapabilities {
+                swo: self.get_swo_interface().is_some(),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
 }
 
 impl PartialEq for dyn ProbeFactory {
@@ -1346,6 +1401,15 @@ pub trait JtagAccess: DebugProbe {
     /// The data shifted out of the DR register will be returned.
     fn write_dr(&mut self, data: &[u8], len: u32) -> Result<BitVec, DebugProbeError>;
 
+    /// Shift a value into the IR JTAG register
+    ///
+    /// The data shifted out of the IR register will be returned.
+    fn write_ir(&mut self, _data: &[u8], _len: u32) -> Result<BitVec, DebugProbeError> {
+        Err(DebugProbeError::NotImplemented {
+            function_name: "write_ir",
+        })
+    }
+
     /// Executes a sequence of JTAG commands.
     fn write_register_batch(
         &mut self,
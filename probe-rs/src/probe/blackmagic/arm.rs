@@ -15,7 +15,7 @@ use crate::architecture::arm::{
     sequences::ArmDebugSequence,
 };
 use crate::probe::blackmagic::{Align, BlackMagicProbe, ProtocolVersion, RemoteCommand};
-use crate::probe::{ArmError, DebugProbeError, Probe};
+use crate::probe::{ArmError, DebugProbe, DebugProbeError, Probe, ProbeInfo};
 use std::collections::BTreeSet;
 use std::collections::hash_map;
 use std::{collections::HashMap, sync::Arc};
@@ -203,6 +203,13 @@ impl BlackMagicProbeArmDebug {
 }
 
 impl ArmDebugInterface for BlackMagicProbeArmDebug {
+    fn probe_info(&self) -> ProbeInfo {
+        self.probe.probe_info()
+    }
+
+    fn sequence_name(&self) -> &str {
+        self.sequence.name()
+    }
     fn access_ports(
         &mut self,
         dp: DpAddress,
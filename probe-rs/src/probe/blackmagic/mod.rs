@@ -10,7 +10,7 @@ use std::{
 use crate::{
     architecture::{
         arm::{
-            ArmCommunicationInterface, ArmDebugInterface, ArmError,
+            ArmCommunicationInterfaceBuilder, ArmDebugInterface, ArmError,
             communication_interface::DapProbe, sequences::ArmDebugSequence,
         },
         riscv::{
@@ -1167,7 +1167,10 @@ impl DebugProbe for BlackMagicProbe {
                 Err((probe, err)) => Err((probe.into_probe(), err)),
             }
         } else {
-            Ok(ArmCommunicationInterface::create(self, sequence, true)) // TODO: Fixup the error type here
+            Ok(ArmCommunicationInterfaceBuilder::new()
+                .sequence(sequence)
+                .overrun_detect(true)
+                .build(self)) // TODO: Fixup the error type here
         }
     }
 
@@ -5,7 +5,7 @@ use protocol::Ch347UsbJtagDevice;
 
 use crate::{
     architecture::{
-        arm::{ArmCommunicationInterface, communication_interface::DapProbe},
+        arm::{ArmCommunicationInterfaceBuilder, communication_interface::DapProbe},
         riscv::dtm::jtag_dtm::JtagDtmBuilder,
         xtensa::communication_interface::XtensaCommunicationInterface,
     },
@@ -175,6 +175,10 @@ impl DebugProbe for Ch347UsbJtag {
         Some(super::WireProtocol::Jtag)
     }
 
+    fn supports_protocol(&self, protocol: super::WireProtocol) -> bool {
+        protocol == super::WireProtocol::Jtag
+    }
+
     fn into_probe(self: Box<Self>) -> Box<dyn DebugProbe> {
         self
     }
@@ -194,7 +198,10 @@ impl DebugProbe for Ch347UsbJtag {
         Box<dyn crate::architecture::arm::ArmDebugInterface + 'probe>,
         (Box<dyn DebugProbe>, crate::architecture::arm::ArmError),
     > {
-        Ok(ArmCommunicationInterface::create(self, sequence, true))
+        Ok(ArmCommunicationInterfaceBuilder::new()
+            .sequence(sequence)
+            .overrun_detect(true)
+            .build(self))
     }
 
     fn has_riscv_interface(&self) -> bool {
@@ -127,6 +127,11 @@ pub enum RequestError {
         request: swj::sequence::SequenceRequest,
     },
 
+    /// Failed to send the bidirectional SWD sequence to the probe with the following request: {request:?}
+    SwdSequence {
+        request: swd::sequence::SequenceRequest,
+    },
+
     /// Failed to send the JTAG sequence to the probe with the following request: {request:?}
     JtagSequence {
         request: jtag::sequence::SequenceRequest,
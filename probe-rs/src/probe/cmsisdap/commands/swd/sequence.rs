@@ -0,0 +1,228 @@
+/// Implementation of the DAP_SWD_Sequence command
+use super::super::{CommandId, Request, SendError, Status};
+
+#[derive(Clone, Copy, Debug)]
+pub struct Sequence {
+    /// Number of SWCLK cycles: 1..64 (64 encoded as 0)
+    cycles: u8,
+
+    /// Whether SWDIO is captured instead of driven
+    input: bool,
+
+    /// Data to generate on SWDIO
+    data: [u8; 8],
+}
+
+impl Sequence {
+    /// Create a sequence which drives the lowest `cycles` bits of `bits` on SWDIO, LSB first.
+    pub(crate) fn output(cycles: u8, bits: u64) -> Self {
+        assert!(
+            cycles > 0 && cycles <= 64,
+            "cycles = {cycles}, but expected [1,64]"
+        );
+
+        Self {
+            cycles,
+            input: false,
+            data: bits.to_le_bytes(),
+        }
+    }
+
+    /// Create a sequence which captures SWDIO for `cycles` clock cycles.
+    pub(crate) fn input(cycles: u8) -> Self {
+        assert!(
+            cycles > 0 && cycles <= 64,
+            "cycles = {cycles}, but expected [1,64]"
+        );
+
+        Self {
+            cycles,
+            input: true,
+            data: [0; 8],
+        }
+    }
+
+    fn data_len(&self) -> usize {
+        (self.cycles as usize).div_ceil(8)
+    }
+
+    /// The number of bytes this sequence takes up in the request.
+    pub(crate) fn request_len(&self) -> usize {
+        if self.input { 1 } else { 1 + self.data_len() }
+    }
+
+    /// The number of bytes this sequence takes up in the response.
+    pub(crate) fn response_len(&self) -> usize {
+        if self.input { self.data_len() } else { 0 }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SequenceRequest {
+    sequences: Vec<Sequence>,
+}
+
+impl SequenceRequest {
+    pub(crate) fn new(sequences: Vec<Sequence>) -> Self {
+        assert!(
+            !sequences.is_empty() && sequences.len() <= (u8::MAX as usize),
+            "sequences.len() == {}, but expected [1,255]",
+            sequences.len()
+        );
+        SequenceRequest { sequences }
+    }
+}
+
+impl Request for SequenceRequest {
+    const COMMAND_ID: CommandId = CommandId::SwdSequence;
+
+    type Response = SequenceResponse;
+
+    /*
+    | BYTE | BYTE **********| BYTE *********| BYTE *****|
+    > 0x1D | Sequence Count | Sequence Info | SWDIO Data|
+    |******|****************|///////////////|///////////|
+     */
+    fn to_bytes(&self, buffer: &mut [u8]) -> Result<usize, SendError> {
+        let mut transfer_len_bytes = 0;
+        buffer[transfer_len_bytes] = self.sequences.len() as u8;
+        transfer_len_bytes += 1;
+
+        for sequence in &self.sequences {
+            // Clock cycle count has been checked when creating the sequence
+            let mut sequence_info = if sequence.cycles == 64 {
+                0
+            } else {
+                sequence.cycles
+            };
+            sequence_info |= (sequence.input as u8) << 7;
+            buffer[transfer_len_bytes] = sequence_info;
+            transfer_len_bytes += 1;
+
+            // Only output sequences carry data.
+            if !sequence.input {
+                let byte_count = sequence.data_len();
+                buffer[transfer_len_bytes..][..byte_count]
+                    .copy_from_slice(&sequence.data[..byte_count]);
+                transfer_len_bytes += byte_count;
+            }
+        }
+
+        Ok(transfer_len_bytes)
+    }
+
+    /*
+    | BYTE | BYTE **| BYTE *****|
+    < 0x1D | Status | SWDIO Data|
+    |******|********|///////////|
+     */
+    fn parse_response(&self, buffer: &[u8]) -> Result<Self::Response, SendError> {
+        let status = Status::from_byte(buffer[0])?;
+
+        let expected_len = 1 + self
+            .sequences
+            .iter()
+            .map(Sequence::response_len)
+            .sum::<usize>();
+        if status == Status::DapOk && buffer.len() < expected_len {
+            return Err(SendError::NotEnoughData);
+        }
+
+        let mut bits = Vec::new();
+        if status == Status::DapOk {
+            let mut received_len_bytes = 1;
+            for sequence in self.sequences.iter().filter(|sequence| sequence.input) {
+                let byte_count = sequence.data_len();
+                let bytes = &buffer[received_len_bytes..][..byte_count];
+                bits.extend(
+                    (0..sequence.cycles as usize).map(|bit| bytes[bit / 8] & (1 << (bit % 8)) != 0),
+                );
+                received_len_bytes += byte_count;
+            }
+        }
+
+        Ok(SequenceResponse(status, bits))
+    }
+}
+
+#[derive(Debug)]
+pub struct SequenceResponse(pub(crate) Status, pub(crate) Vec<bool>);
+
+#[cfg(test)]
+mod tests {
+    use super::{Sequence, SequenceRequest};
+    use crate::probe::cmsisdap::commands::{Request, SendError, Status};
+
+    #[test]
+    fn sequences_are_encoded() {
+        let request = SequenceRequest::new(vec![
+            Sequence::output(12, 0x0ABC),
+            Sequence::input(3),
+            Sequence::output(64, 0x0102_0304_0506_0708),
+        ]);
+
+        let mut buffer = [0; 32];
+        let len = request.to_bytes(&mut buffer).unwrap();
+
+        let expected = [
+            // Sequence count
+            &[3][..],
+            // 12 cycles, output, with two bytes of data
+            &[12, 0xBC, 0x0A],
+            // 3 cycles, input, without data
+            &[0x80 | 3],
+            // 64 cycles are encoded as 0
+            &[0, 0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01],
+        ]
+        .concat();
+        assert_eq!(&buffer[..len], expected);
+    }
+
+    #[test]
+    fn sequence_lengths() {
+        assert_eq!(Sequence::output(12, 0).request_len(), 3);
+        assert_eq!(Sequence::output(12, 0).response_len(), 0);
+        assert_eq!(Sequence::input(10).request_len(), 1);
+        assert_eq!(Sequence::input(10).response_len(), 2);
+    }
+
+    #[test]
+    fn captured_bits_are_parsed() {
+        let request = SequenceRequest::new(vec![
+            Sequence::input(3),
+            Sequence::output(8, 0xFF),
+            Sequence::input(10),
+        ]);
+
+        let response = request.parse_response(&[0x00, 0b101, 0x81, 0x02]).unwrap();
+
+        assert_eq!(response.0, Status::DapOk);
+        // The 3 bits of the first and the 10 bits of the second input sequence, LSB first.
+        let bits: String = response
+            .1
+            .iter()
+            .map(|&bit| if bit { '1' } else { '0' })
+            .collect();
+        assert_eq!(bits, "1011000000101");
+    }
+
+    #[test]
+    fn short_response_is_rejected() {
+        let request = SequenceRequest::new(vec![Sequence::input(3), Sequence::input(10)]);
+
+        assert!(matches!(
+            request.parse_response(&[0x00, 0b101]),
+            Err(SendError::NotEnoughData)
+        ));
+    }
+
+    #[test]
+    fn failed_sequence_has_no_data() {
+        let request = SequenceRequest::new(vec![Sequence::input(3)]);
+
+        let response = request.parse_response(&[0xFF]).unwrap();
+
+        assert_eq!(response.0, Status::DapError);
+        assert!(response.1.is_empty());
+    }
+}
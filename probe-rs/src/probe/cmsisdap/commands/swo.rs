@@ -146,7 +146,7 @@ impl Request for StatusRequest {
         );
         Ok(StatusResponse {
             _status: status,
-            _count: count,
+            count,
         })
     }
 }
@@ -171,7 +171,7 @@ impl From<u8> for TraceStatus {
 #[derive(Debug)]
 pub struct StatusResponse {
     pub(crate) _status: TraceStatus,
-    pub(crate) _count: u32,
+    pub(crate) count: u32,
 }
 
 #[derive(Debug)]
@@ -6,8 +6,8 @@ use crate::{
     CoreStatus,
     architecture::{
         arm::{
-            ArmCommunicationInterface, ArmDebugInterface, ArmError, DapError, Pins, RawDapAccess,
-            RegisterAddress, SwoAccess, SwoConfig, SwoMode,
+            ArmCommunicationInterfaceBuilder, ArmDebugInterface, ArmError, DapError, Pins,
+            RawDapAccess, RegisterAddress, SwdPhase, SwoAccess, SwoConfig, SwoMode,
             communication_interface::DapProbe,
             dp::{Abort, Ctrl, DpRegister},
             sequences::ArmDebugSequence,
@@ -23,10 +23,14 @@ use crate::{
     },
     probe::{
         AutoImplementJtagAccess, BatchCommand, DebugProbe, DebugProbeError, DebugProbeInfo,
-        DebugProbeSelector, JtagAccess, JtagDriverState, ProbeFactory, WireProtocol,
+        DebugProbeSelector, JtagAccess, JtagDriverState, ProbeCapabilities, ProbeFactory,
+        ProbeInfo, WireProtocol,
         cmsisdap::commands::{
             CmsisDapError, RequestError,
-            general::info::{CapabilitiesCommand, PacketCountCommand, SWOTraceBufferSizeCommand},
+            general::info::{
+                CapabilitiesCommand, FirmwareVersionCommand, PacketCountCommand,
+                SWOTraceBufferSizeCommand, SerialNumberCommand,
+            },
         },
     },
 };
@@ -48,7 +52,13 @@ use commands::{
             SequenceResponse as JtagSequenceResponse,
         },
     },
-    swd,
+    swd::{
+        self,
+        sequence::{
+            Sequence as SwdSequenceCommand, SequenceRequest as SwdSequenceRequest,
+            SequenceResponse as SwdSequenceResponse,
+        },
+    },
     swj::{
         clock::SWJClockRequest,
         pins::{SWJPinsRequest, SWJPinsRequestBuilder, SWJPinsResponse},
@@ -100,6 +110,8 @@ pub struct CmsisDap {
     packet_size: u16,
     packet_count: u8,
     capabilities: Capabilities,
+    firmware_version: Option<String>,
+    serial_number: Option<String>,
     swo_buffer_size: Option<usize>,
     swo_active: bool,
     swo_streaming: bool,
@@ -107,6 +119,8 @@ pub struct CmsisDap {
 
     /// Speed in kHz
     speed_khz: u32,
+    /// Idle cycles appended after each transfer.
+    idle_cycles: u8,
 
     batch: Vec<BatchCommand>,
 
@@ -150,6 +164,18 @@ impl CmsisDap {
             tracing::debug!("Probe SWO buffer size: {}", swo_size);
         }
 
+        // These strings are optional, so don't fail to open the probe if they can't be read.
+        let firmware_version = commands::send_command(&mut device, &FirmwareVersionCommand {})
+            .unwrap_or_else(|error| {
+                tracing::debug!("Failed to read probe firmware version: {error}");
+                None
+            });
+        let serial_number = commands::send_command(&mut device, &SerialNumberCommand {})
+            .unwrap_or_else(|error| {
+                tracing::debug!("Failed to read probe serial number: {error}");
+                None
+            });
+
         Ok(Self {
             device,
             _hw_version: 0,
@@ -158,11 +184,14 @@ impl CmsisDap {
             packet_count,
             packet_size,
             capabilities: caps,
+            firmware_version,
+            serial_number,
             swo_buffer_size,
             swo_active: false,
             swo_streaming: false,
             connected: false,
             speed_khz: 1_000,
+            idle_cycles: 0,
             batch: Vec::new(),
             jtag_state: JtagDriverState::default(),
             jtag_buffer: JtagBuffer::new(packet_size - 1),
@@ -417,6 +446,23 @@ impl CmsisDap {
         })
     }
 
+    /// Sends the sequences with DAP_SWD_Sequence, and returns the captured bits.
+    fn send_swd_sequences(
+        &mut self,
+        sequences: Vec<SwdSequenceCommand>,
+    ) -> Result<Vec<bool>, CmsisDapError> {
+        let request = SwdSequenceRequest::new(sequences);
+
+        commands::send_command(&mut self.device, &request).and_then(|v| match v {
+            SwdSequenceResponse(Status::DapOk, bits) => Ok(bits),
+            SwdSequenceResponse(Status::DapError, _) => {
+                Err(CmsisDapError::ErrorResponse(RequestError::SwdSequence {
+                    request,
+                }))
+            }
+        })
+    }
+
     fn send_swj_sequences(&mut self, request: SequenceRequest) -> Result<(), CmsisDapError> {
         // Ensure all pending commands are processed.
         //self.process_batch()?;
@@ -713,7 +759,6 @@ impl CmsisDap {
     }
 
     /// Fetch current SWO trace status.
-    #[expect(dead_code)]
     fn get_swo_status(&mut self) -> Result<swo::StatusResponse, DebugProbeError> {
         Ok(commands::send_command(
             &mut self.device,
@@ -833,7 +878,7 @@ impl DebugProbe for CmsisDap {
         self.set_speed(self.speed_khz)?;
 
         self.transfer_configure(ConfigureRequest {
-            idle_cycles: 0,
+            idle_cycles: self.idle_cycles,
             wait_retry: 0xffff,
             match_retry: 0,
         })?;
@@ -881,23 +926,38 @@ impl DebugProbe for CmsisDap {
     }
 
     fn select_protocol(&mut self, protocol: WireProtocol) -> Result<(), DebugProbeError> {
-        match protocol {
-            WireProtocol::Jtag if self.capabilities.jtag_implemented => {
-                self.protocol = Some(WireProtocol::Jtag);
-                Ok(())
-            }
-            WireProtocol::Swd if self.capabilities.swd_implemented => {
-                self.protocol = Some(WireProtocol::Swd);
-                Ok(())
-            }
-            _ => Err(DebugProbeError::UnsupportedProtocol(protocol)),
+        if !self.supports_protocol(protocol) {
+            return Err(DebugProbeError::UnsupportedProtocol(protocol));
+        }
+
+        let changed = self.protocol != Some(protocol);
+        self.protocol = Some(protocol);
+
+        // The protocol is only sent to the probe with DAP_Connect when attaching, so an attached
+        // probe has to be attached again to switch to the new protocol.
+        if changed && self.connected {
+            self.process_batch().map_err(|error| match error {
+                ArmError::Probe(error) => error,
+                other => DebugProbeError::Other(other.to_string()),
+            })?;
+            self.connected = false;
+            self.attach()?;
         }
+
+        Ok(())
     }
 
     fn active_protocol(&self) -> Option<WireProtocol> {
         self.protocol
     }
 
+    fn supports_protocol(&self, protocol: WireProtocol) -> bool {
+        match protocol {
+            WireProtocol::Jtag => self.capabilities.jtag_implemented,
+            WireProtocol::Swd => self.capabilities.swd_implemented,
+        }
+    }
+
     /// Asserts the nRESET pin.
     fn target_reset(&mut self) -> Result<(), DebugProbeError> {
         commands::send_command(&mut self.device, &ResetRequest).map(|v: ResetResponse| {
@@ -936,7 +996,10 @@ impl DebugProbe for CmsisDap {
         self: Box<Self>,
         sequence: Arc<dyn ArmDebugSequence>,
     ) -> Result<Box<dyn ArmDebugInterface + 'probe>, (Box<dyn DebugProbe>, ArmError)> {
-        Ok(ArmCommunicationInterface::create(self, sequence, false))
+        Ok(ArmCommunicationInterfaceBuilder::new()
+            .sequence(sequence)
+            .overrun_detect(false)
+            .build(self))
     }
 
     fn has_arm_interface(&self) -> bool {
@@ -976,6 +1039,24 @@ impl DebugProbe for CmsisDap {
     fn has_xtensa_interface(&self) -> bool {
         true
     }
+
+    fn probe_info(&self) -> ProbeInfo {
+        ProbeInfo {
+            name: self.get_name().to_string(),
+            vendor_id: None,
+            product_id: None,
+            serial_number: self.serial_number.clone(),
+            firmware_version: self.firmware_version.clone(),
+            capabilities: ProbeCapabilities {
+                swo: self.capabilities.swo_uart_implemented
+                    || self.capabilities.swo_manchester_implemented,
+                jtag: self.capabilities.jtag_implemented,
+                // Multidrop only needs raw SWD sequences, which every CMSIS-DAP probe supports.
+                swd_multidrop: self.capabilities.swd_implemented,
+                block_transfer: true,
+            },
+        }
+    }
 }
 
 // TODO: we will want to replace the default implementation with one that can use vendor extensions.
@@ -988,6 +1069,24 @@ impl RawDapAccess for CmsisDap {
         Ok(())
     }
 
+    fn set_swd_idle_cycles(&mut self, cycles: u8) -> Result<(), ArmError> {
+        // Transfers which are already queued still use the previous setting.
+        self.process_batch()?;
+        self.idle_cycles = cycles;
+
+        // Otherwise, the setting is sent when attaching.
+        if self.connected {
+            self.transfer_configure(ConfigureRequest {
+                idle_cycles: cycles,
+                wait_retry: 0xffff,
+                match_retry: 0,
+            })
+            .map_err(DebugProbeError::from)?;
+        }
+
+        Ok(())
+    }
+
     /// Reads the DAP register on the specified port and address.
     fn raw_read_register(&mut self, address: RegisterAddress) -> Result<u32, ArmError> {
         let res = self.batch_add(BatchCommand::Read(address))?;
@@ -1170,6 +1269,61 @@ impl RawDapAccess for CmsisDap {
         Ok(())
     }
 
+    fn swd_sequence(&mut self, phases: &[SwdPhase]) -> Result<Vec<bool>, DebugProbeError> {
+        self.connect_if_needed()?;
+
+        if let Some(phase) = phases
+            .iter()
+            .find(|phase| !(1..=64).contains(&phase.bit_len()))
+        {
+            return Err(DebugProbeError::Other(format!(
+                "SWD sequence phases must be 1 to 64 bits long, not {}",
+                phase.bit_len()
+            )));
+        }
+
+        // Queued transfers have to be sent before the sequence.
+        self.process_batch().map_err(|error| match error {
+            ArmError::Probe(error) => error,
+            other => DebugProbeError::Other(other.to_string()),
+        })?;
+
+        // One byte of each packet is taken up by the command ID, and another one by the sequence
+        // count or the status.
+        let max_len = self.packet_size as usize - 2;
+
+        let mut captured = Vec::new();
+        let mut sequences: Vec<SwdSequenceCommand> = Vec::new();
+        let mut request_len = 0;
+        let mut response_len = 0;
+
+        for phase in phases {
+            let sequence = match *phase {
+                SwdPhase::Output { bit_len, bits } => SwdSequenceCommand::output(bit_len, bits),
+                SwdPhase::Input { bit_len } => SwdSequenceCommand::input(bit_len),
+            };
+
+            if request_len + sequence.request_len() > max_len
+                || response_len + sequence.response_len() > max_len
+                || sequences.len() == u8::MAX as usize
+            {
+                captured.extend(self.send_swd_sequences(std::mem::take(&mut sequences))?);
+                request_len = 0;
+                response_len = 0;
+            }
+
+            request_len += sequence.request_len();
+            response_len += sequence.response_len();
+            sequences.push(sequence);
+        }
+
+        if !sequences.is_empty() {
+            captured.extend(self.send_swd_sequences(sequences)?);
+        }
+
+        Ok(captured)
+    }
+
     fn swj_pins(
         &mut self,
         pin_out: u32,
@@ -1288,6 +1442,36 @@ impl SwoAccess for CmsisDap {
     fn swo_buffer_size(&mut self) -> Option<usize> {
         self.swo_buffer_size
     }
+
+    fn swo_buffer_available(&mut self) -> Result<usize, ArmError> {
+        if !self.swo_active {
+            return Ok(0);
+        }
+
+        Ok(self.get_swo_status()?.count as usize)
+    }
+
+    fn flush_swo(&mut self) -> Result<Vec<u8>, ArmError> {
+        if !self.swo_active || self.swo_streaming {
+            return self.read_swo();
+        }
+
+        // Only read the data which is buffered now, so that a running trace
+        // can't keep us reading forever.
+        let mut remaining = self.swo_buffer_available()?;
+        let mut data = Vec::with_capacity(remaining);
+        while remaining > 0 {
+            let chunk = self.get_swo_data()?;
+            if chunk.is_empty() {
+                break;
+            }
+
+            remaining = remaining.saturating_sub(chunk.len());
+            data.extend(chunk);
+        }
+
+        Ok(data)
+    }
 }
 
 impl Drop for CmsisDap {
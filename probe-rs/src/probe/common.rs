@@ -705,6 +705,15 @@ impl<Probe: AutoImplementJtagAccess> JtagAccess for Probe {
         Ok(response)
     }
 
+    fn write_ir(&mut self, data: &[u8], len: u32) -> Result<BitVec, DebugProbeError> {
+        shift_ir(self, data, len as usize, true)?;
+
+        let response = self.read_captured_bits()?;
+
+        tracing::trace!("write_ir result: {:?}", response);
+        Ok(response)
+    }
+
     #[tracing::instrument(skip(self, writes))]
     fn write_register_batch(
         &mut self,
@@ -0,0 +1,167 @@
+//! Capturing the transfers used to connect to a debug port.
+//!
+//! A [`ConnectCapture`] attached to an
+//! [`ArmCommunicationInterfaceBuilder`](crate::architecture::arm::ArmCommunicationInterfaceBuilder)
+//! records every transfer performed while the interface connects to a debug port, i.e. while the
+//! `debug_port_setup`, `debug_port_connect` and `debug_port_start` sequences run. All other
+//! transfers are ignored.
+//!
+//! The captured [`trace`](ConnectCapture::trace) can be compared against a trace recorded earlier,
+//! to catch unintended changes to the connect sequence.
+
+use std::{
+    fmt::Write,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use crate::{
+    architecture::arm::communication_interface::DapProbe,
+    probe::{
+        recording_probe::RecordedTransfer,
+        transfer_log::{RecordedProbe, TransferLogEntry, TransferResult, TransferSink},
+    },
+};
+
+#[derive(Debug, Default)]
+struct CaptureState {
+    /// Number of nested connects currently in progress.
+    depth: usize,
+    entries: Vec<TransferLogEntry>,
+}
+
+/// Captures the transfers used to connect to a debug port.
+///
+/// Attach it to an
+/// [`ArmCommunicationInterfaceBuilder`](crate::architecture::arm::ArmCommunicationInterfaceBuilder)
+/// with
+/// [`ArmCommunicationInterfaceBuilder::capture_connect`](crate::architecture::arm::ArmCommunicationInterfaceBuilder::capture_connect).
+/// Clones of a capture share the captured transfers.
+#[derive(Debug, Clone, Default)]
+pub struct ConnectCapture {
+    state: Arc<Mutex<CaptureState>>,
+}
+
+impl ConnectCapture {
+    /// Creates an empty capture.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns all transfers captured so far.
+    ///
+    /// The timestamps of the entries are always zero, so that captures can be compared.
+    pub fn entries(&self) -> Vec<TransferLogEntry> {
+        self.state().entries.clone()
+    }
+
+    /// Returns the captured transfers in the format of a transfer log, one transfer per line.
+    ///
+    /// The trace can be read back by a
+    /// [`TransferReplayer`](crate::probe::transfer_log::TransferReplayer).
+    pub fn trace(&self) -> String {
+        let mut trace = String::new();
+
+        for entry in &self.state().entries {
+            // note(unwrap): Writing to a String can't fail.
+            writeln!(trace, "{entry}").unwrap();
+        }
+
+        trace
+    }
+
+    /// Removes all transfers captured so far.
+    pub fn clear(&self) {
+        self.state().entries.clear();
+    }
+
+    /// Wraps `probe`, so that its transfers can be captured.
+    pub(crate) fn wrap(&self, probe: Box<dyn DapProbe>) -> Box<dyn DapProbe> {
+        Box::new(RecordedProbe::new(probe, self.clone()))
+    }
+
+    /// Captures transfers until the returned guard is dropped.
+    pub(crate) fn capture(&self) -> CaptureGuard {
+        self.state().depth += 1;
+
+        CaptureGuard {
+            capture: self.clone(),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, CaptureState> {
+        // A panic while holding the lock can't leave the state inconsistent.
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl TransferSink for ConnectCapture {
+    fn record(&self, transfer: RecordedTransfer, result: TransferResult) {
+        let mut state = self.state();
+
+        if state.depth > 0 {
+            state.entries.push(TransferLogEntry {
+                timestamp: Duration::ZERO,
+                transfer,
+                result,
+            });
+        }
+    }
+}
+
+/// Stops capturing when dropped, see [`ConnectCapture::capture`].
+pub(crate) struct CaptureGuard {
+    capture: ConnectCapture,
+}
+
+impl Drop for CaptureGuard {
+    fn drop(&mut self) {
+        self.capture.state().depth -= 1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ConnectCapture;
+    use crate::{
+        architecture::arm::{
+            ArmCommunicationInterfaceBuilder,
+            communication_interface::tests::CONNECT_READS,
+            dp::{Ctrl, DpAddress, DpRegister},
+        },
+        probe::{
+            recording_probe::{RecordedTransfer, RecordingProbe},
+            transfer_log::TransferReplayer,
+        },
+    };
+
+    #[test]
+    fn only_connect_transfers_are_captured() {
+        let recorder = RecordingProbe::new(CONNECT_READS);
+        let capture = ConnectCapture::new();
+
+        let mut interface = ArmCommunicationInterfaceBuilder::new()
+            .capture_connect(capture.clone())
+            .build(Box::new(recorder.clone()));
+
+        interface.select_debug_port(DpAddress::Default).unwrap();
+
+        let entries = capture.entries();
+        assert_eq!(entries.len(), recorder.transfers().len());
+        assert!(matches!(
+            entries[0].transfer,
+            RecordedTransfer::SwjSequence { .. }
+        ));
+
+        // The trace is a valid transfer log.
+        let replayer = TransferReplayer::from_reader(capture.trace().as_bytes()).unwrap();
+        assert_eq!(replayer.remaining(), entries.len());
+
+        interface
+            .read_raw_dp_register(DpAddress::Default, Ctrl::ADDRESS)
+            .unwrap();
+        assert_eq!(capture.entries(), entries);
+    }
+}
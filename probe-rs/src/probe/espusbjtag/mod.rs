@@ -93,6 +93,10 @@ impl DebugProbe for EspUsbJtag {
         Some(WireProtocol::Jtag)
     }
 
+    fn supports_protocol(&self, protocol: WireProtocol) -> bool {
+        protocol == WireProtocol::Jtag
+    }
+
     fn get_name(&self) -> &'static str {
         "Esp USB JTAG"
     }
@@ -31,6 +31,7 @@ use std::{
 #[expect(clippy::type_complexity)]
 pub struct FakeProbe {
     protocol: WireProtocol,
+    supported_protocols: Vec<WireProtocol>,
     speed: u32,
 
     dap_register_read_handler: Option<Box<dyn Fn(RegisterAddress) -> Result<u32, ArmError> + Send>>,
@@ -45,7 +46,7 @@ pub struct FakeProbe {
 
 enum MockedAp {
     /// Mock a memory AP
-    MemoryAp(MockMemoryAp),
+    MemoryAp(Box<MockMemoryAp>),
     /// Mock an ARM core behind a memory AP
     Core(MockCore),
 }
@@ -306,6 +307,7 @@ impl FakeProbe {
     pub fn new() -> Self {
         FakeProbe {
             protocol: WireProtocol::Swd,
+            supported_protocols: vec![WireProtocol::Swd, WireProtocol::Jtag],
             speed: 1000,
 
             dap_register_read_handler: None,
@@ -313,7 +315,7 @@ impl FakeProbe {
 
             operations: RefCell::new(VecDeque::new()),
 
-            memory_ap: MockedAp::MemoryAp(MockMemoryAp::with_pattern()),
+            memory_ap: MockedAp::MemoryAp(Box::new(MockMemoryAp::with_pattern())),
         }
     }
 
@@ -370,6 +372,11 @@ impl FakeProbe {
         self.dap_register_write_handler = Some(handler);
     }
 
+    /// Restricts the wire protocols the probe can select, by default it supports all of them.
+    pub fn set_supported_protocols(&mut self, protocols: &[WireProtocol]) {
+        self.supported_protocols = protocols.to_vec();
+    }
+
     /// Makes a generic probe out of the [`FakeProbe`]
     pub fn into_probe(self) -> Probe {
         Probe::from_specific_probe(Box::new(self))
@@ -488,6 +495,10 @@ impl DebugProbe for FakeProbe {
     }
 
     fn select_protocol(&mut self, protocol: WireProtocol) -> Result<(), DebugProbeError> {
+        if !self.supports_protocol(protocol) {
+            return Err(DebugProbeError::UnsupportedProtocol(protocol));
+        }
+
         self.protocol = protocol;
 
         Ok(())
@@ -497,6 +508,10 @@ impl DebugProbe for FakeProbe {
         Some(self.protocol)
     }
 
+    fn supports_protocol(&self, protocol: WireProtocol) -> bool {
+        self.supported_protocols.contains(&protocol)
+    }
+
     /// Leave debug mode
     fn detach(&mut self) -> Result<(), crate::Error> {
         Ok(())
@@ -574,6 +589,8 @@ impl RawDapAccess for FakeProbe {
     }
 }
 
+impl DapProbe for FakeProbe {}
+
 #[derive(Debug)]
 struct FakeArmInterface {
     probe: Box<FakeProbe>,
@@ -727,11 +744,38 @@ impl DapAccess for FakeArmInterface {
     }
 }
 
-#[cfg(all(test, feature = "builtin-targets"))]
+#[cfg(test)]
 mod test {
-    use super::FakeProbe;
+    use super::{FakeArmInterface, FakeProbe};
+    use crate::{
+        MemoryMappedRegister,
+        architecture::arm::{
+            ArmDebugInterface, FullyQualifiedApAddress, armv8m::Dhcsr,
+            sequences::DefaultArmSequence,
+        },
+    };
+
+    #[cfg(feature = "builtin-targets")]
     use crate::Permissions;
 
+    #[test]
+    fn read_word_32_uses_the_memory_interface() {
+        // The fake interface has no raw access to the access port registers.
+        let mut interface = FakeArmInterface::new(
+            Box::new(FakeProbe::with_mocked_core()),
+            DefaultArmSequence::create(),
+        );
+
+        let ap = FullyQualifiedApAddress::v1_with_default_dp(0);
+        let dhcsr = interface
+            .read_word_32(&ap, Dhcsr::get_mmio_address())
+            .unwrap();
+
+        // S_REGRDY is always set by the mocked core.
+        assert_eq!(dhcsr, 1 << 16);
+    }
+
+    #[cfg(feature = "builtin-targets")]
     #[test]
     fn create_session_with_fake_probe() {
         let fake_probe = FakeProbe::with_mocked_core();
@@ -2,7 +2,7 @@
 use crate::{
     architecture::{
         arm::{
-            ArmCommunicationInterface, ArmDebugInterface, ArmError,
+            ArmCommunicationInterfaceBuilder, ArmDebugInterface, ArmError,
             communication_interface::DapProbe, sequences::ArmDebugSequence,
         },
         riscv::{
@@ -375,6 +375,10 @@ impl DebugProbe for FtdiProbe {
         Some(WireProtocol::Jtag)
     }
 
+    fn supports_protocol(&self, protocol: WireProtocol) -> bool {
+        protocol == WireProtocol::Jtag
+    }
+
     fn try_as_jtag_probe(&mut self) -> Option<&mut dyn JtagAccess> {
         Some(self)
     }
@@ -397,7 +401,10 @@ impl DebugProbe for FtdiProbe {
         self: Box<Self>,
         sequence: Arc<dyn ArmDebugSequence>,
     ) -> Result<Box<dyn ArmDebugInterface + 'probe>, (Box<dyn DebugProbe>, ArmError)> {
-        Ok(ArmCommunicationInterface::create(self, sequence, true))
+        Ok(ArmCommunicationInterfaceBuilder::new()
+            .sequence(sequence)
+            .overrun_detect(true)
+            .build(self))
     }
 
     fn has_arm_interface(&self) -> bool {
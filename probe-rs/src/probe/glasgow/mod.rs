@@ -6,7 +6,7 @@
 use std::sync::Arc;
 
 use crate::architecture::arm::{
-    ArmCommunicationInterface, ArmDebugInterface, ArmError, DapError, RawDapAccess,
+    ArmCommunicationInterfaceBuilder, ArmDebugInterface, ArmError, DapError, RawDapAccess,
     RegisterAddress,
     communication_interface::DapProbe,
     dp::{DpRegister, RdBuff},
@@ -254,6 +254,10 @@ impl DebugProbe for Glasgow {
         }
     }
 
+    fn supports_protocol(&self, protocol: WireProtocol) -> bool {
+        protocol == WireProtocol::Swd
+    }
+
     fn into_probe(self: Box<Self>) -> Box<dyn DebugProbe> {
         self
     }
@@ -271,9 +275,10 @@ impl DebugProbe for Glasgow {
         sequence: Arc<dyn ArmDebugSequence>,
     ) -> Result<Box<dyn ArmDebugInterface + 'probe>, (Box<dyn DebugProbe>, ArmError)> {
         // The Glasgow applet handles FAULT/WAIT states promptly.
-        Ok(ArmCommunicationInterface::create(
-            self, sequence, /*use_overrun_detect=*/ false,
-        ))
+        Ok(ArmCommunicationInterfaceBuilder::new()
+            .sequence(sequence)
+            .overrun_detect(false)
+            .build(self))
     }
 }
 
@@ -39,7 +39,8 @@ use crate::probe::{AutoImplementJtagAccess, JtagAccess};
 use crate::{
     architecture::{
         arm::{
-            ArmCommunicationInterface, SwoAccess, communication_interface::DapProbe, swo::SwoConfig,
+            ArmCommunicationInterfaceBuilder, SwoAccess, communication_interface::DapProbe,
+            swo::SwoConfig,
         },
         riscv::{communication_interface::RiscvInterfaceBuilder, dtm::jtag_dtm::JtagDtmBuilder},
     },
@@ -942,20 +943,8 @@ impl JLink {
 
 impl DebugProbe for JLink {
     fn select_protocol(&mut self, protocol: WireProtocol) -> Result<(), DebugProbeError> {
-        if self.caps.contains(Capability::SelectIf) {
-            let jlink_interface = match protocol {
-                WireProtocol::Swd => Interface::Swd,
-                WireProtocol::Jtag => Interface::Jtag,
-            };
-
-            if !self.interfaces.contains(jlink_interface) {
-                return Err(DebugProbeError::UnsupportedProtocol(protocol));
-            }
-        } else {
-            // Assume JTAG protocol if the probe does not support switching interfaces
-            if protocol != WireProtocol::Jtag {
-                return Err(DebugProbeError::UnsupportedProtocol(protocol));
-            }
+        if !self.supports_protocol(protocol) {
+            return Err(DebugProbeError::UnsupportedProtocol(protocol));
         }
 
         self.protocol = protocol;
@@ -967,6 +956,20 @@ impl DebugProbe for JLink {
         Some(self.protocol)
     }
 
+    fn supports_protocol(&self, protocol: WireProtocol) -> bool {
+        if self.caps.contains(Capability::SelectIf) {
+            let jlink_interface = match protocol {
+                WireProtocol::Swd => Interface::Swd,
+                WireProtocol::Jtag => Interface::Jtag,
+            };
+
+            self.interfaces.contains(jlink_interface)
+        } else {
+            // Assume JTAG protocol if the probe does not support switching interfaces
+            protocol == WireProtocol::Jtag
+        }
+    }
+
     fn get_name(&self) -> &'static str {
         "J-Link"
     }
@@ -1128,7 +1131,10 @@ impl DebugProbe for JLink {
         self: Box<Self>,
         sequence: Arc<dyn ArmDebugSequence>,
     ) -> Result<Box<dyn ArmDebugInterface + 'probe>, (Box<dyn DebugProbe>, ArmError)> {
-        Ok(ArmCommunicationInterface::create(self, sequence, true))
+        Ok(ArmCommunicationInterfaceBuilder::new()
+            .sequence(sequence)
+            .overrun_detect(true)
+            .build(self))
     }
 
     fn get_target_voltage(&mut self) -> Result<Option<f32>, DebugProbeError> {
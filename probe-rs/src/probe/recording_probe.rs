@@ -0,0 +1,284 @@
+//! A probe which records all transfers instead of talking to hardware.
+//!
+//! This is useful for developing and testing debug sequences, as it allows checking
+//! that a sequence issues exactly the register accesses it is expected to.
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    CoreStatus,
+    architecture::arm::{
+        ArmCommunicationInterfaceBuilder, ArmDebugInterface, ArmError, RawDapAccess,
+        RegisterAddress, communication_interface::DapProbe, sequences::ArmDebugSequence,
+    },
+    probe::{DebugProbe, DebugProbeError, WireProtocol},
+};
+
+/// A single transfer recorded by the [`RecordingProbe`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordedTransfer {
+    /// A DP or AP register was read, and the given value was returned.
+    Read {
+        /// The register which was read.
+        address: RegisterAddress,
+        /// The canned value returned for the read.
+        value: u32,
+    },
+    /// A DP or AP register was written.
+    Write {
+        /// The register which was written.
+        address: RegisterAddress,
+        /// The value written to the register.
+        value: u32,
+    },
+    /// A SWJ sequence was sent.
+    SwjSequence {
+        /// Number of bits in the sequence.
+        bit_len: u8,
+        /// The bits of the sequence, LSB first.
+        bits: u64,
+    },
+    /// A JTAG sequence was sent.
+    JtagSequence {
+        /// Number of clock cycles.
+        cycles: u8,
+        /// The TMS value during the sequence.
+        tms: bool,
+        /// The TDI bits of the sequence, LSB first.
+        tdi: u64,
+    },
+}
+
+#[derive(Debug, Default)]
+struct RecordingState {
+    canned_reads: VecDeque<u32>,
+    transfers: Vec<RecordedTransfer>,
+}
+
+/// A [`DapProbe`] which records every transfer instead of touching any hardware.
+///
+/// Register reads return the canned values passed to [`RecordingProbe::new`] in order.
+/// Once all canned values are used up, reads return zero.
+///
+/// The recorded transfers are shared between all clones of a probe, so a clone can be
+/// handed to an [`ArmCommunicationInterface`](crate::architecture::arm::ArmCommunicationInterface) while the original is kept to inspect the
+/// transfers with [`RecordingProbe::transfers`].
+#[derive(Debug, Clone)]
+pub struct RecordingProbe {
+    protocol: WireProtocol,
+    speed_khz: u32,
+    state: Arc<Mutex<RecordingState>>,
+}
+
+impl RecordingProbe {
+    /// Creates a new recording probe, which returns `canned_reads` for register reads, in order.
+    pub fn new(canned_reads: impl IntoIterator<Item = u32>) -> Self {
+        Self {
+            protocol: WireProtocol::Swd,
+            speed_khz: 1000,
+            state: Arc::new(Mutex::new(RecordingState {
+                canned_reads: canned_reads.into_iter().collect(),
+                transfers: Vec::new(),
+            })),
+        }
+    }
+
+    /// Returns all transfers recorded so far.
+    pub fn transfers(&self) -> Vec<RecordedTransfer> {
+        self.state().transfers.clone()
+    }
+
+    /// Removes all transfers recorded so far.
+    pub fn clear_transfers(&self) {
+        self.state().transfers.clear();
+    }
+
+    /// Creates an [`ArmCommunicationInterface`](crate::architecture::arm::ArmCommunicationInterface) which uses a clone of this probe.
+    pub fn arm_interface(&self, sequence: Arc<dyn ArmDebugSequence>) -> Box<dyn ArmDebugInterface> {
+        ArmCommunicationInterfaceBuilder::new()
+            .sequence(sequence)
+            .build(Box::new(self.clone()))
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, RecordingState> {
+        // A panic while holding the lock can't leave the state inconsistent.
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn record(&self, transfer: RecordedTransfer) {
+        tracing::trace!("Recorded transfer: {:x?}", transfer);
+        self.state().transfers.push(transfer);
+    }
+}
+
+impl DebugProbe for RecordingProbe {
+    fn get_name(&self) -> &str {
+        "Recording probe"
+    }
+
+    fn speed_khz(&self) -> u32 {
+        self.speed_khz
+    }
+
+    fn set_speed(&mut self, speed_khz: u32) -> Result<u32, DebugProbeError> {
+        self.speed_khz = speed_khz;
+
+        Ok(speed_khz)
+    }
+
+    fn attach(&mut self) -> Result<(), DebugProbeError> {
+        Ok(())
+    }
+
+    fn detach(&mut self) -> Result<(), crate::Error> {
+        Ok(())
+    }
+
+    fn target_reset(&mut self) -> Result<(), DebugProbeError> {
+        Ok(())
+    }
+
+    fn target_reset_assert(&mut self) -> Result<(), DebugProbeError> {
+        Ok(())
+    }
+
+    fn target_reset_deassert(&mut self) -> Result<(), DebugProbeError> {
+        Ok(())
+    }
+
+    fn select_protocol(&mut self, protocol: WireProtocol) -> Result<(), DebugProbeError> {
+        self.protocol = protocol;
+
+        Ok(())
+    }
+
+    fn active_protocol(&self) -> Option<WireProtocol> {
+        Some(self.protocol)
+    }
+
+    fn has_arm_interface(&self) -> bool {
+        true
+    }
+
+    fn try_get_arm_debug_interface<'probe>(
+        self: Box<Self>,
+        sequence: Arc<dyn ArmDebugSequence>,
+    ) -> Result<Box<dyn ArmDebugInterface + 'probe>, (Box<dyn DebugProbe>, ArmError)> {
+        Ok(ArmCommunicationInterfaceBuilder::new()
+            .sequence(sequence)
+            .overrun_detect(false)
+            .build(self))
+    }
+
+    fn into_probe(self: Box<Self>) -> Box<dyn DebugProbe> {
+        self
+    }
+
+    fn try_as_dap_probe(&mut self) -> Option<&mut dyn DapProbe> {
+        Some(self)
+    }
+}
+
+impl RawDapAccess for RecordingProbe {
+    fn raw_read_register(&mut self, address: RegisterAddress) -> Result<u32, ArmError> {
+        let value = self.state().canned_reads.pop_front().unwrap_or(0);
+        self.record(RecordedTransfer::Read { address, value });
+
+        Ok(value)
+    }
+
+    fn raw_write_register(&mut self, address: RegisterAddress, value: u32) -> Result<(), ArmError> {
+        self.record(RecordedTransfer::Write { address, value });
+
+        Ok(())
+    }
+
+    fn jtag_sequence(&mut self, cycles: u8, tms: bool, tdi: u64) -> Result<(), DebugProbeError> {
+        self.record(RecordedTransfer::JtagSequence { cycles, tms, tdi });
+
+        Ok(())
+    }
+
+    fn swj_sequence(&mut self, bit_len: u8, bits: u64) -> Result<(), DebugProbeError> {
+        self.record(RecordedTransfer::SwjSequence { bit_len, bits });
+
+        Ok(())
+    }
+
+    fn swj_pins(
+        &mut self,
+        _pin_out: u32,
+        _pin_select: u32,
+        _pin_wait: u32,
+    ) -> Result<u32, DebugProbeError> {
+        Ok(0)
+    }
+
+    fn into_probe(self: Box<Self>) -> Box<dyn DebugProbe> {
+        self
+    }
+
+    fn core_status_notification(&mut self, _state: CoreStatus) -> Result<(), DebugProbeError> {
+        Ok(())
+    }
+}
+
+impl DapProbe for RecordingProbe {}
+
+#[cfg(test)]
+mod test {
+    use super::{RecordedTransfer, RecordingProbe};
+    use crate::architecture::arm::{RawDapAccess, RegisterAddress, dp::DpRegisterAddress};
+
+    #[test]
+    fn records_transfers_and_returns_canned_reads() {
+        let recorder = RecordingProbe::new([0x2ba0_1477]);
+        let mut probe = recorder.clone();
+
+        let dpidr = RegisterAddress::DpRegister(DpRegisterAddress {
+            bank: None,
+            address: 0x0,
+        });
+        let select = RegisterAddress::DpRegister(DpRegisterAddress {
+            bank: None,
+            address: 0x8,
+        });
+
+        probe.swj_sequence(8, 0xe7).unwrap();
+        assert_eq!(probe.raw_read_register(dpidr).unwrap(), 0x2ba0_1477);
+        probe.raw_write_register(select, 0x0100_0000).unwrap();
+        assert_eq!(
+            probe
+                .raw_read_register(RegisterAddress::ApRegister(0xFC))
+                .unwrap(),
+            0
+        );
+
+        assert_eq!(
+            recorder.transfers(),
+            vec![
+                RecordedTransfer::SwjSequence {
+                    bit_len: 8,
+                    bits: 0xe7
+                },
+                RecordedTransfer::Read {
+                    address: dpidr,
+                    value: 0x2ba0_1477
+                },
+                RecordedTransfer::Write {
+                    address: select,
+                    value: 0x0100_0000
+                },
+                RecordedTransfer::Read {
+                    address: RegisterAddress::ApRegister(0xFC),
+                    value: 0
+                },
+            ]
+        );
+    }
+}
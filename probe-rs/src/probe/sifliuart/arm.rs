@@ -11,7 +11,7 @@ use crate::architecture::arm::{
     ArmDebugInterface, ArmError, DapAccess, FullyQualifiedApAddress, SwoAccess, SwoConfig,
 };
 use crate::probe::sifliuart::{SifliUart, SifliUartCommand, SifliUartResponse};
-use crate::probe::{DebugProbeError, Probe};
+use crate::probe::{DebugProbe, DebugProbeError, Probe, ProbeInfo};
 use std::cmp::{max, min};
 use std::collections::BTreeSet;
 use std::sync::Arc;
@@ -31,7 +31,7 @@ pub(crate) struct SifliUartArmDebug {
     pub _access_ports: BTreeSet<FullyQualifiedApAddress>,
 
     /// A copy of the sequence that was passed during initialization
-    _sequence: Arc<dyn ArmDebugSequence>,
+    sequence: Arc<dyn ArmDebugSequence>,
 }
 
 impl SifliUartArmDebug {
@@ -40,7 +40,7 @@ impl SifliUartArmDebug {
             probe,
             is_connected_to_dp: false,
             _access_ports: BTreeSet::new(),
-            _sequence: sequence,
+            sequence,
         }
     }
 }
@@ -159,6 +159,12 @@ impl ArmMemoryInterface for SifliUartMemoryInterface<'_> {
 }
 
 impl ArmDebugInterface for SifliUartArmDebug {
+    fn probe_info(&self) -> ProbeInfo {
+        self.probe.probe_info()
+    }
+    fn sequence_name(&self) -> &str {
+        self.sequence.name()
+    }
     fn reinitialize(&mut self) -> Result<(), ArmError> {
         Ok(())
     }
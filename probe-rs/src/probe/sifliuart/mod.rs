@@ -348,6 +348,10 @@ impl DebugProbe for SifliUart {
         Some(WireProtocol::Swd)
     }
 
+    fn supports_protocol(&self, protocol: WireProtocol) -> bool {
+        protocol == WireProtocol::Swd
+    }
+
     fn has_arm_interface(&self) -> bool {
         true
     }
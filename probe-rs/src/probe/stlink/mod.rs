@@ -20,8 +20,8 @@ use crate::{
         valid_32bit_arm_address,
     },
     probe::{
-        DebugProbe, DebugProbeError, DebugProbeInfo, DebugProbeSelector, Probe, ProbeError,
-        ProbeFactory, WireProtocol,
+        DebugProbe, DebugProbeError, DebugProbeInfo, DebugProbeSelector, Probe, ProbeCapabilities,
+        ProbeError, ProbeFactory, ProbeInfo, WireProtocol,
     },
 };
 
@@ -329,6 +329,23 @@ impl DebugProbe for StLink<StLinkUsbDevice> {
             })
             .map_err(|e| e.into())
     }
+
+    fn probe_info(&self) -> ProbeInfo {
+        ProbeInfo {
+            name: self.name.clone(),
+            vendor_id: None,
+            product_id: None,
+            serial_number: None,
+            // Use the same format as the ST tools, e.g. `V2J37`.
+            firmware_version: Some(format!("V{}J{}", self.hw_version, self.jtag_version)),
+            capabilities: ProbeCapabilities {
+                swo: true,
+                jtag: true,
+                swd_multidrop: false,
+                block_transfer: false,
+            },
+        }
+    }
 }
 
 impl<D: StLinkUsb> Drop for StLink<D> {
@@ -1201,6 +1218,14 @@ impl<D: StLinkUsb> SwoAccess for StLink<D> {
         let data = self.read_swo_data(timeout)?;
         Ok(data)
     }
+
+    fn swo_buffer_available(&mut self) -> Result<usize, ArmError> {
+        if !self.swo_enabled {
+            return Ok(0);
+        }
+
+        Ok(self.read_swo_available_byte_count()?)
+    }
 }
 
 /// ST-Link specific errors.
@@ -1411,6 +1436,9 @@ impl DapAccess for StlinkArmDebug {
 }
 
 impl ArmDebugInterface for StlinkArmDebug {
+    fn probe_info(&self) -> ProbeInfo {
+        self.probe.probe_info()
+    }
     fn memory_interface(
         &mut self,
         access_port: &FullyQualifiedApAddress,
@@ -1453,6 +1481,11 @@ impl ArmDebugInterface for StlinkArmDebug {
     fn reinitialize(&mut self) -> Result<(), ArmError> {
         Ok(())
     }
+
+    fn supports_swo(&self) -> bool {
+        self.probe.get_swo_interface().is_some()
+            && self.probe.active_protocol() == Some(WireProtocol::Swd)
+    }
 }
 
 impl SwdSequence for StlinkArmDebug {
@@ -1485,6 +1518,14 @@ impl SwoAccess for StlinkArmDebug {
     fn read_swo_timeout(&mut self, timeout: Duration) -> Result<Vec<u8>, ArmError> {
         self.probe.read_swo_timeout(timeout)
     }
+
+    fn swo_buffer_available(&mut self) -> Result<usize, ArmError> {
+        self.probe.swo_buffer_available()
+    }
+
+    fn flush_swo(&mut self) -> Result<Vec<u8>, ArmError> {
+        self.probe.flush_swo()
+    }
 }
 
 #[derive(Debug)]
@@ -0,0 +1,928 @@
+//! Recording and replaying of debug port and access port transfers.
+//!
+//! A [`TransferRecorder`] attached to an
+//! [`ArmCommunicationInterfaceBuilder`](crate::architecture::arm::ArmCommunicationInterfaceBuilder)
+//! writes every transfer of a session to a log, one transfer per line. Such a log can be attached
+//! to a bug report, and fed into a [`TransferReplayer`], which answers the transfers exactly like
+//! the target did, including any errors.
+//!
+//! Each line of the log has the following format, with all numbers except the timestamp in hex:
+//!
+//! ```text
+//! <microseconds> R <register> <value> <result>
+//! <microseconds> W <register> <value> <result>
+//! <microseconds> SWJ <bit length> <bits> <result>
+//! <microseconds> JTAG <cycles> <tms> <tdi> <result>
+//! ```
+//!
+//! Registers are written as `DP:0x4`, `DP[2]:0x4` for banked DP registers, or `AP:0xc`.
+//! The result is one of `OK`, `WAIT`, `FAULT`, `NOACK`, `PARITY`, `PROTOCOL-SWD`,
+//! `PROTOCOL-JTAG` or `ERROR`.
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    CoreStatus,
+    architecture::arm::{
+        ArmCommunicationInterfaceBuilder, ArmDebugInterface, ArmError, DapError, RawDapAccess,
+        RegisterAddress, SwdPhase, SwoAccess, communication_interface::DapProbe,
+        dp::DpRegisterAddress, sequences::ArmDebugSequence,
+    },
+    probe::{
+        DebugProbe, DebugProbeError, JtagAccess, WireProtocol, recording_probe::RecordedTransfer,
+    },
+};
+
+/// The result of a logged transfer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferResult {
+    /// The transfer succeeded.
+    Ok,
+    /// The transfer failed with an error in the communication with the target.
+    Dap(DapError),
+    /// The transfer failed with another error, e.g. a probe error.
+    Other,
+}
+
+impl TransferResult {
+    fn of<T, E>(result: &Result<T, E>) -> Self
+    where
+        E: AsDapError,
+    {
+        match result {
+            Ok(_) => TransferResult::Ok,
+            Err(error) => error
+                .as_dap_error()
+                .map_or(TransferResult::Other, TransferResult::Dap),
+        }
+    }
+}
+
+trait AsDapError {
+    fn as_dap_error(&self) -> Option<DapError>;
+}
+
+impl AsDapError for ArmError {
+    fn as_dap_error(&self) -> Option<DapError> {
+        match self {
+            ArmError::Dap(error) => Some(*error),
+            _ => None,
+        }
+    }
+}
+
+impl AsDapError for DebugProbeError {
+    fn as_dap_error(&self) -> Option<DapError> {
+        None
+    }
+}
+
+impl fmt::Display for TransferResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            TransferResult::Ok => "OK",
+            TransferResult::Dap(DapError::WaitResponse) => "WAIT",
+            TransferResult::Dap(DapError::FaultResponse) => "FAULT",
+            TransferResult::Dap(DapError::NoAcknowledge) => "NOACK",
+            TransferResult::Dap(DapError::IncorrectParity) => "PARITY",
+            TransferResult::Dap(DapError::Protocol(WireProtocol::Swd)) => "PROTOCOL-SWD",
+            TransferResult::Dap(DapError::Protocol(WireProtocol::Jtag)) => "PROTOCOL-JTAG",
+            TransferResult::Other => "ERROR",
+        };
+
+        f.write_str(name)
+    }
+}
+
+impl FromStr for TransferResult {
+    type Err = ParseTransferLogError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "OK" => TransferResult::Ok,
+            "WAIT" => TransferResult::Dap(DapError::WaitResponse),
+            "FAULT" => TransferResult::Dap(DapError::FaultResponse),
+            "NOACK" => TransferResult::Dap(DapError::NoAcknowledge),
+            "PARITY" => TransferResult::Dap(DapError::IncorrectParity),
+            "PROTOCOL-SWD" => TransferResult::Dap(DapError::Protocol(WireProtocol::Swd)),
+            "PROTOCOL-JTAG" => TransferResult::Dap(DapError::Protocol(WireProtocol::Jtag)),
+            "ERROR" => TransferResult::Other,
+            other => return Err(ParseTransferLogError::InvalidField(other.to_string())),
+        })
+    }
+}
+
+/// A single line of a transfer log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransferLogEntry {
+    /// Time since the start of the recording.
+    pub timestamp: Duration,
+    /// The transfer which was performed.
+    pub transfer: RecordedTransfer,
+    /// The result of the transfer.
+    pub result: TransferResult,
+}
+
+impl fmt::Display for TransferLogEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ", self.timestamp.as_micros())?;
+
+        match self.transfer {
+            RecordedTransfer::Read { address, value } => {
+                write!(f, "R {} {value:#x}", DisplayRegister(address))?
+            }
+            RecordedTransfer::Write { address, value } => {
+                write!(f, "W {} {value:#x}", DisplayRegister(address))?
+            }
+            RecordedTransfer::SwjSequence { bit_len, bits } => {
+                write!(f, "SWJ {bit_len:#x} {bits:#x}")?
+            }
+            RecordedTransfer::JtagSequence { cycles, tms, tdi } => {
+                write!(f, "JTAG {cycles:#x} {} {tdi:#x}", u8::from(tms))?
+            }
+        }
+
+        write!(f, " {}", self.result)
+    }
+}
+
+impl FromStr for TransferLogEntry {
+    type Err = ParseTransferLogError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let mut fields = line.split_whitespace();
+        let mut next = || fields.next().ok_or(ParseTransferLogError::MissingField);
+
+        let timestamp = Duration::from_micros(
+            next()?
+                .parse()
+                .map_err(|_| ParseTransferLogError::InvalidField(line.to_string()))?,
+        );
+
+        let transfer = match next()? {
+            "R" => RecordedTransfer::Read {
+                address: parse_register(next()?)?,
+                value: parse_hex(next()?)?,
+            },
+            "W" => RecordedTransfer::Write {
+                address: parse_register(next()?)?,
+                value: parse_hex(next()?)?,
+            },
+            "SWJ" => RecordedTransfer::SwjSequence {
+                bit_len: parse_hex(next()?)?,
+                bits: parse_hex(next()?)?,
+            },
+            "JTAG" => RecordedTransfer::JtagSequence {
+                cycles: parse_hex(next()?)?,
+                tms: parse_hex::<u8>(next()?)? != 0,
+                tdi: parse_hex(next()?)?,
+            },
+            other => return Err(ParseTransferLogError::InvalidField(other.to_string())),
+        };
+
+        let result = next()?.parse()?;
+
+        Ok(TransferLogEntry {
+            timestamp,
+            transfer,
+            result,
+        })
+    }
+}
+
+struct DisplayRegister(RegisterAddress);
+
+impl fmt::Display for DisplayRegister {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            RegisterAddress::DpRegister(DpRegisterAddress {
+                bank: Some(bank),
+                address,
+            }) => write!(f, "DP[{bank:#x}]:{address:#x}"),
+            RegisterAddress::DpRegister(DpRegisterAddress {
+                bank: None,
+                address,
+            }) => write!(f, "DP:{address:#x}"),
+            RegisterAddress::ApRegister(address) => write!(f, "AP:{address:#x}"),
+        }
+    }
+}
+
+fn parse_register(field: &str) -> Result<RegisterAddress, ParseTransferLogError> {
+    let invalid = || ParseTransferLogError::InvalidField(field.to_string());
+
+    let (port, address) = field.split_once(':').ok_or_else(invalid)?;
+    let address = parse_hex(address)?;
+
+    match port {
+        "AP" => Ok(RegisterAddress::ApRegister(address)),
+        "DP" => Ok(RegisterAddress::DpRegister(DpRegisterAddress {
+            bank: None,
+            address,
+        })),
+        banked => {
+            let bank = banked
+                .strip_prefix("DP[")
+                .and_then(|bank| bank.strip_suffix(']'))
+                .ok_or_else(invalid)?;
+
+            Ok(RegisterAddress::DpRegister(DpRegisterAddress {
+                bank: Some(parse_hex(bank)?),
+                address,
+            }))
+        }
+    }
+}
+
+fn parse_hex<T: TryFrom<u64>>(field: &str) -> Result<T, ParseTransferLogError> {
+    field
+        .strip_prefix("0x")
+        .and_then(|digits| u64::from_str_radix(digits, 16).ok())
+        .and_then(|value| T::try_from(value).ok())
+        .ok_or_else(|| ParseTransferLogError::InvalidField(field.to_string()))
+}
+
+/// An error which occurred while reading a transfer log.
+#[derive(Debug, thiserror::Error, docsplay::Display)]
+pub enum ParseTransferLogError {
+    /// A line of the transfer log is incomplete.
+    MissingField,
+
+    /// The transfer log contains the invalid field '{0}'.
+    InvalidField(String),
+
+    /// Failed to read the transfer log.
+    Io(#[from] std::io::Error),
+}
+
+/// How often the log is flushed while transfers are recorded.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Writes every transfer of a debug probe to a log.
+///
+/// Attach it to an [`ArmCommunicationInterfaceBuilder`] with
+/// [`ArmCommunicationInterfaceBuilder::record_transfers`]. Clones of a recorder write to the same log.
+///
+/// The log is flushed periodically, and when the last clone of the recorder is dropped. Use
+/// [`TransferRecorder::flush`] to flush it at other times.
+#[derive(Clone)]
+pub struct TransferRecorder {
+    writer: Arc<Mutex<LogWriter>>,
+    start: Instant,
+}
+
+/// The writer of a [`TransferRecorder`], which is flushed when dropped.
+struct LogWriter {
+    writer: Box<dyn Write + Send>,
+    last_flush: Instant,
+}
+
+impl LogWriter {
+    fn write_entry(&mut self, entry: &TransferLogEntry) -> std::io::Result<()> {
+        writeln!(self.writer, "{entry}")?;
+
+        if self.last_flush.elapsed() >= FLUSH_INTERVAL {
+            self.flush()?;
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.last_flush = Instant::now();
+        self.writer.flush()
+    }
+}
+
+impl Drop for LogWriter {
+    fn drop(&mut self) {
+        if let Err(error) = self.flush() {
+            tracing::warn!("Failed to flush transfer log: {}", error);
+        }
+    }
+}
+
+impl fmt::Debug for TransferRecorder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TransferRecorder")
+            .field("start", &self.start)
+            .finish_non_exhaustive()
+    }
+}
+
+impl TransferRecorder {
+    /// Creates a recorder which writes the log to `writer`.
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        let start = Instant::now();
+
+        Self {
+            writer: Arc::new(Mutex::new(LogWriter {
+                writer: Box::new(writer),
+                last_flush: start,
+            })),
+            start,
+        }
+    }
+
+    /// Creates a recorder which writes the log to a new file at `path`.
+    pub fn create(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let file = File::create(path)?;
+
+        Ok(Self::new(BufWriter::new(file)))
+    }
+
+    /// Writes all transfers recorded so far to the underlying writer.
+    pub fn flush(&self) -> std::io::Result<()> {
+        self.lock().flush()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, LogWriter> {
+        // A panic while holding the lock can't leave the writer inconsistent.
+        self.writer
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Wraps `probe`, so that all its transfers are recorded.
+    pub(crate) fn wrap(&self, probe: Box<dyn DapProbe>) -> Box<dyn DapProbe> {
+        Box::new(RecordedProbe::new(probe, self.clone()))
+    }
+}
+
+/// Receives the transfers of a [`RecordedProbe`].
+pub(crate) trait TransferSink: fmt::Debug + Send + 'static {
+    /// Called after every transfer of the probe.
+    fn record(&self, transfer: RecordedTransfer, result: TransferResult);
+}
+
+impl TransferSink for TransferRecorder {
+    fn record(&self, transfer: RecordedTransfer, result: TransferResult) {
+        let entry = TransferLogEntry {
+            timestamp: self.start.elapsed(),
+            transfer,
+            result,
+        };
+
+        if let Err(error) = self.lock().write_entry(&entry) {
+            tracing::warn!("Failed to write transfer log: {}", error);
+        }
+    }
+}
+
+/// A probe whose transfers are passed to a [`TransferSink`], e.g. a [`TransferRecorder`].
+#[derive(Debug)]
+pub(crate) struct RecordedProbe<S> {
+    inner: Box<dyn DapProbe>,
+    recorder: S,
+}
+
+impl<S: TransferSink> RecordedProbe<S> {
+    /// Wraps `inner`, passing all its transfers to `recorder`.
+    pub(crate) fn new(inner: Box<dyn DapProbe>, recorder: S) -> Self {
+        Self { inner, recorder }
+    }
+}
+
+impl<S: TransferSink> DebugProbe for RecordedProbe<S> {
+    fn get_name(&self) -> &str {
+        self.inner.get_name()
+    }
+
+    fn speed_khz(&self) -> u32 {
+        self.inner.speed_khz()
+    }
+
+    fn set_speed(&mut self, speed_khz: u32) -> Result<u32, DebugProbeError> {
+        self.inner.set_speed(speed_khz)
+    }
+
+    fn attach(&mut self) -> Result<(), DebugProbeError> {
+        self.inner.attach()
+    }
+
+    fn detach(&mut self) -> Result<(), crate::Error> {
+        self.inner.detach()
+    }
+
+    fn target_reset(&mut self) -> Result<(), DebugProbeError> {
+        self.inner.target_reset()
+    }
+
+    fn target_reset_assert(&mut self) -> Result<(), DebugProbeError> {
+        self.inner.target_reset_assert()
+    }
+
+    fn target_reset_deassert(&mut self) -> Result<(), DebugProbeError> {
+        self.inner.target_reset_deassert()
+    }
+
+    fn select_protocol(&mut self, protocol: WireProtocol) -> Result<(), DebugProbeError> {
+        self.inner.select_protocol(protocol)
+    }
+
+    fn active_protocol(&self) -> Option<WireProtocol> {
+        self.inner.active_protocol()
+    }
+
+    fn supports_protocol(&self, protocol: WireProtocol) -> bool {
+        self.inner.supports_protocol(protocol)
+    }
+
+    fn has_arm_interface(&self) -> bool {
+        true
+    }
+
+    fn try_as_jtag_probe(&mut self) -> Option<&mut dyn JtagAccess> {
+        self.inner.try_as_jtag_probe()
+    }
+
+    fn try_get_arm_debug_interface<'probe>(
+        self: Box<Self>,
+        sequence: Arc<dyn ArmDebugSequence>,
+    ) -> Result<Box<dyn ArmDebugInterface + 'probe>, (Box<dyn DebugProbe>, ArmError)> {
+        Ok(ArmCommunicationInterfaceBuilder::new()
+            .sequence(sequence)
+            .build(self))
+    }
+
+    fn get_swo_interface(&self) -> Option<&dyn SwoAccess> {
+        self.inner.get_swo_interface()
+    }
+
+    fn get_swo_interface_mut(&mut self) -> Option<&mut dyn SwoAccess> {
+        self.inner.get_swo_interface_mut()
+    }
+
+    fn into_probe(self: Box<Self>) -> Box<dyn DebugProbe> {
+        RawDapAccess::into_probe(self.inner)
+    }
+
+    fn try_as_dap_probe(&mut self) -> Option<&mut dyn DapProbe> {
+        Some(self)
+    }
+
+    fn get_target_voltage(&mut self) -> Result<Option<f32>, DebugProbeError> {
+        self.inner.get_target_voltage()
+    }
+}
+
+impl<S: TransferSink> RawDapAccess for RecordedProbe<S> {
+    fn raw_read_register(&mut self, address: RegisterAddress) -> Result<u32, ArmError> {
+        let result = self.inner.raw_read_register(address);
+        let value = *result.as_ref().unwrap_or(&0);
+        self.recorder.record(
+            RecordedTransfer::Read { address, value },
+            TransferResult::of(&result),
+        );
+
+        result
+    }
+
+    fn raw_read_block(
+        &mut self,
+        address: RegisterAddress,
+        values: &mut [u32],
+    ) -> Result<(), ArmError> {
+        let result = self.inner.raw_read_block(address, values);
+
+        // The values of a failed block read are unknown, so only the error is recorded.
+        if result.is_ok() {
+            for &value in values.iter() {
+                self.recorder.record(
+                    RecordedTransfer::Read { address, value },
+                    TransferResult::Ok,
+                );
+            }
+        } else {
+            self.recorder.record(
+                RecordedTransfer::Read { address, value: 0 },
+                TransferResult::of(&result),
+            );
+        }
+
+        result
+    }
+
+    fn raw_write_register(&mut self, address: RegisterAddress, value: u32) -> Result<(), ArmError> {
+        let result = self.inner.raw_write_register(address, value);
+        self.recorder.record(
+            RecordedTransfer::Write { address, value },
+            TransferResult::of(&result),
+        );
+
+        result
+    }
+
+    fn raw_write_block(
+        &mut self,
+        address: RegisterAddress,
+        values: &[u32],
+    ) -> Result<(), ArmError> {
+        let result = self.inner.raw_write_block(address, values);
+
+        // The result of a block write is recorded for its last value.
+        if let Some((&last, values)) = values.split_last() {
+            for &value in values {
+                self.recorder.record(
+                    RecordedTransfer::Write { address, value },
+                    TransferResult::Ok,
+                );
+            }
+            self.recorder.record(
+                RecordedTransfer::Write {
+                    address,
+                    value: last,
+                },
+                TransferResult::of(&result),
+            );
+        }
+
+        result
+    }
+
+    fn raw_flush(&mut self) -> Result<(), ArmError> {
+        self.inner.raw_flush()
+    }
+
+    fn configure_jtag(&mut self, skip_scan: bool) -> Result<(), DebugProbeError> {
+        self.inner.configure_jtag(skip_scan)
+    }
+
+    fn jtag_sequence(&mut self, cycles: u8, tms: bool, tdi: u64) -> Result<(), DebugProbeError> {
+        let result = self.inner.jtag_sequence(cycles, tms, tdi);
+        self.recorder.record(
+            RecordedTransfer::JtagSequence { cycles, tms, tdi },
+            TransferResult::of(&result),
+        );
+
+        result
+    }
+
+    fn swj_sequence(&mut self, bit_len: u8, bits: u64) -> Result<(), DebugProbeError> {
+        let result = self.inner.swj_sequence(bit_len, bits);
+        self.recorder.record(
+            RecordedTransfer::SwjSequence { bit_len, bits },
+            TransferResult::of(&result),
+        );
+
+        result
+    }
+
+    fn swj_pins(
+        &mut self,
+        pin_out: u32,
+        pin_select: u32,
+        pin_wait: u32,
+    ) -> Result<u32, DebugProbeError> {
+        self.inner.swj_pins(pin_out, pin_select, pin_wait)
+    }
+
+    fn into_probe(self: Box<Self>) -> Box<dyn DebugProbe> {
+        RawDapAccess::into_probe(self.inner)
+    }
+
+    fn core_status_notification(&mut self, state: CoreStatus) -> Result<(), DebugProbeError> {
+        self.inner.core_status_notification(state)
+    }
+
+    fn set_swd_idle_cycles(&mut self, cycles: u8) -> Result<(), ArmError> {
+        self.inner.set_swd_idle_cycles(cycles)
+    }
+
+    // Not recorded, as the captured bits can't be replayed.
+    fn swd_sequence(&mut self, phases: &[SwdPhase]) -> Result<Vec<bool>, DebugProbeError> {
+        self.inner.swd_sequence(phases)
+    }
+}
+
+impl<S: TransferSink> DapProbe for RecordedProbe<S> {}
+
+/// A [`DapProbe`] which replays a transfer log recorded with a [`TransferRecorder`].
+///
+/// Every transfer is answered with the recorded value and result, so that a session can be
+/// reproduced without the original hardware. If the transfers diverge from the log, an error is
+/// returned.
+#[derive(Debug)]
+pub struct TransferReplayer {
+    protocol: WireProtocol,
+    speed_khz: u32,
+    entries: VecDeque<TransferLogEntry>,
+}
+
+impl TransferReplayer {
+    /// Creates a replayer for the given log entries.
+    pub fn new(entries: impl IntoIterator<Item = TransferLogEntry>) -> Self {
+        Self {
+            protocol: WireProtocol::Swd,
+            speed_khz: 1000,
+            entries: entries.into_iter().collect(),
+        }
+    }
+
+    /// Reads a transfer log from `reader`.
+    pub fn from_reader(reader: impl BufRead) -> Result<Self, ParseTransferLogError> {
+        let mut entries = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                entries.push(line.parse()?);
+            }
+        }
+
+        Ok(Self::new(entries))
+    }
+
+    /// Reads a transfer log from the file at `path`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, ParseTransferLogError> {
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    /// Returns the number of log entries which were not replayed yet.
+    pub fn remaining(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Replays the next log entry, which has to match `expected`.
+    fn replay(
+        &mut self,
+        expected: RecordedTransfer,
+    ) -> Result<(RecordedTransfer, TransferResult), ArmError> {
+        let Some(entry) = self.entries.pop_front() else {
+            return Err(ArmError::Other(format!(
+                "Replay diverged: {expected:x?} is not in the transfer log"
+            )));
+        };
+
+        // The value of a read is only known once it is replayed.
+        let matches = match (expected, entry.transfer) {
+            (
+                RecordedTransfer::Read { address, .. },
+                RecordedTransfer::Read {
+                    address: recorded, ..
+                },
+            ) => address == recorded,
+            (expected, recorded) => expected == recorded,
+        };
+
+        if !matches {
+            return Err(ArmError::Other(format!(
+                "Replay diverged: {expected:x?} was performed, but the transfer log contains {:x?}",
+                entry.transfer
+            )));
+        }
+
+        Ok((entry.transfer, entry.result))
+    }
+
+    fn replay_sequence(&mut self, expected: RecordedTransfer) -> Result<(), DebugProbeError> {
+        match self.replay(expected) {
+            Ok((_, TransferResult::Ok)) => Ok(()),
+            Ok((_, _)) => Err(DebugProbeError::Other(
+                "Recorded sequence failed".to_string(),
+            )),
+            Err(error) => Err(DebugProbeError::Other(error.to_string())),
+        }
+    }
+}
+
+fn replayed_error(result: TransferResult) -> Result<(), ArmError> {
+    match result {
+        TransferResult::Ok => Ok(()),
+        TransferResult::Dap(error) => Err(error.into()),
+        TransferResult::Other => Err(ArmError::Other("Recorded transfer failed".to_string())),
+    }
+}
+
+impl DebugProbe for TransferReplayer {
+    fn get_name(&self) -> &str {
+        "Transfer replayer"
+    }
+
+    fn speed_khz(&self) -> u32 {
+        self.speed_khz
+    }
+
+    fn set_speed(&mut self, speed_khz: u32) -> Result<u32, DebugProbeError> {
+        self.speed_khz = speed_khz;
+
+        Ok(speed_khz)
+    }
+
+    fn attach(&mut self) -> Result<(), DebugProbeError> {
+        Ok(())
+    }
+
+    fn detach(&mut self) -> Result<(), crate::Error> {
+        Ok(())
+    }
+
+    fn target_reset(&mut self) -> Result<(), DebugProbeError> {
+        Ok(())
+    }
+
+    fn target_reset_assert(&mut self) -> Result<(), DebugProbeError> {
+        Ok(())
+    }
+
+    fn target_reset_deassert(&mut self) -> Result<(), DebugProbeError> {
+        Ok(())
+    }
+
+    fn select_protocol(&mut self, protocol: WireProtocol) -> Result<(), DebugProbeError> {
+        self.protocol = protocol;
+
+        Ok(())
+    }
+
+    fn active_protocol(&self) -> Option<WireProtocol> {
+        Some(self.protocol)
+    }
+
+    fn has_arm_interface(&self) -> bool {
+        true
+    }
+
+    fn try_get_arm_debug_interface<'probe>(
+        self: Box<Self>,
+        sequence: Arc<dyn ArmDebugSequence>,
+    ) -> Result<Box<dyn ArmDebugInterface + 'probe>, (Box<dyn DebugProbe>, ArmError)> {
+        Ok(ArmCommunicationInterfaceBuilder::new()
+            .sequence(sequence)
+            .build(self))
+    }
+
+    fn into_probe(self: Box<Self>) -> Box<dyn DebugProbe> {
+        self
+    }
+
+    fn try_as_dap_probe(&mut self) -> Option<&mut dyn DapProbe> {
+        Some(self)
+    }
+}
+
+impl RawDapAccess for TransferReplayer {
+    fn raw_read_register(&mut self, address: RegisterAddress) -> Result<u32, ArmError> {
+        let (transfer, result) = self.replay(RecordedTransfer::Read { address, value: 0 })?;
+        replayed_error(result)?;
+
+        match transfer {
+            RecordedTransfer::Read { value, .. } => Ok(value),
+            _ => unreachable!("replay only returns matching transfers"),
+        }
+    }
+
+    fn raw_write_register(&mut self, address: RegisterAddress, value: u32) -> Result<(), ArmError> {
+        let (_, result) = self.replay(RecordedTransfer::Write { address, value })?;
+        replayed_error(result)
+    }
+
+    fn jtag_sequence(&mut self, cycles: u8, tms: bool, tdi: u64) -> Result<(), DebugProbeError> {
+        self.replay_sequence(RecordedTransfer::JtagSequence { cycles, tms, tdi })
+    }
+
+    fn swj_sequence(&mut self, bit_len: u8, bits: u64) -> Result<(), DebugProbeError> {
+        self.replay_sequence(RecordedTransfer::SwjSequence { bit_len, bits })
+    }
+
+    fn swj_pins(
+        &mut self,
+        _pin_out: u32,
+        _pin_select: u32,
+        _pin_wait: u32,
+    ) -> Result<u32, DebugProbeError> {
+        Ok(0)
+    }
+
+    fn into_probe(self: Box<Self>) -> Box<dyn DebugProbe> {
+        self
+    }
+
+    fn core_status_notification(&mut self, _state: CoreStatus) -> Result<(), DebugProbeError> {
+        Ok(())
+    }
+}
+
+impl DapProbe for TransferReplayer {}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::Write,
+        sync::{
+            Arc, Mutex,
+            atomic::{AtomicUsize, Ordering},
+        },
+    };
+
+    use super::{TransferRecorder, TransferReplayer};
+    use crate::{
+        architecture::arm::{
+            ArmError, DapError, RawDapAccess, RegisterAddress, dp::DpRegisterAddress,
+        },
+        probe::recording_probe::RecordingProbe,
+    };
+
+    /// A log buffer, which counts how often it was flushed.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>, Arc<AtomicUsize>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.1.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn log_is_flushed_when_the_recorder_is_dropped() {
+        let log = SharedBuffer::default();
+        let recorder = TransferRecorder::new(log.clone());
+        let mut probe = recorder.wrap(Box::new(RecordingProbe::new([])));
+
+        for _ in 0..100 {
+            probe
+                .raw_read_register(RegisterAddress::ApRegister(0xC))
+                .unwrap();
+        }
+        assert_eq!(log.1.load(Ordering::SeqCst), 0);
+
+        // The recorder itself still holds the log.
+        drop(probe);
+        assert_eq!(log.1.load(Ordering::SeqCst), 0);
+
+        recorder.flush().unwrap();
+        assert_eq!(log.1.load(Ordering::SeqCst), 1);
+
+        drop(recorder);
+        assert_eq!(log.1.load(Ordering::SeqCst), 2);
+        assert_eq!(log.0.lock().unwrap().split(|&b| b == b'\n').count(), 101);
+    }
+
+    #[test]
+    fn recorded_session_is_replayed() {
+        let dpidr = RegisterAddress::DpRegister(DpRegisterAddress {
+            bank: None,
+            address: 0x0,
+        });
+        let select = RegisterAddress::DpRegister(DpRegisterAddress {
+            bank: Some(2),
+            address: 0x8,
+        });
+
+        let log = SharedBuffer::default();
+        let recorder = TransferRecorder::new(log.clone());
+        let mut probe = recorder.wrap(Box::new(RecordingProbe::new([0x2ba0_1477, 1, 2])));
+
+        probe.swj_sequence(8, 0xe7).unwrap();
+        assert_eq!(probe.raw_read_register(dpidr).unwrap(), 0x2ba0_1477);
+        probe.raw_write_register(select, 0x0100_0000).unwrap();
+        let mut values = [0; 2];
+        probe
+            .raw_read_block(RegisterAddress::ApRegister(0xC), &mut values)
+            .unwrap();
+
+        let log = log.0.lock().unwrap().clone();
+        let mut replayer = TransferReplayer::from_reader(log.as_slice()).unwrap();
+        assert_eq!(replayer.remaining(), 5);
+
+        replayer.swj_sequence(8, 0xe7).unwrap();
+        assert_eq!(replayer.raw_read_register(dpidr).unwrap(), 0x2ba0_1477);
+        replayer.raw_write_register(select, 0x0100_0000).unwrap();
+        let mut replayed = [0; 2];
+        replayer
+            .raw_read_block(RegisterAddress::ApRegister(0xC), &mut replayed)
+            .unwrap();
+        assert_eq!(replayed, [1, 2]);
+        assert_eq!(replayer.remaining(), 0);
+    }
+
+    #[test]
+    fn recorded_faults_are_replayed() {
+        let log = "10 R AP:0xc 0x0 FAULT\n20 W DP:0x4 0x50000000 OK\n";
+        let mut replayer = TransferReplayer::from_reader(log.as_bytes()).unwrap();
+
+        assert!(matches!(
+            replayer.raw_read_register(RegisterAddress::ApRegister(0xC)),
+            Err(ArmError::Dap(DapError::FaultResponse))
+        ));
+
+        // A different transfer than the recorded one is reported as divergence.
+        assert!(matches!(
+            replayer.raw_write_register(RegisterAddress::ApRegister(0x4), 0x5000_0000),
+            Err(ArmError::Other(_))
+        ));
+    }
+}
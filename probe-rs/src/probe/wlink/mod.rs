@@ -361,6 +361,10 @@ impl DebugProbe for WchLink {
         Some(WireProtocol::Jtag)
     }
 
+    fn supports_protocol(&self, protocol: WireProtocol) -> bool {
+        protocol == WireProtocol::Jtag
+    }
+
     fn into_probe(self: Box<Self>) -> Box<dyn DebugProbe> {
         self
     }